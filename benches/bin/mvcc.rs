@@ -64,7 +64,9 @@ fn bench_tombstone_scan() -> BenchSamples {
         let (k, _) = kvs.next().unwrap();
         assert!(store.scan(Context::new(),
                            Key::from_raw(&k),
+                           None,
                            1,
+                           false,
                            ts_generator.next().unwrap())
                      .unwrap()
                      .is_empty())
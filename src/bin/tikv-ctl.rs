@@ -0,0 +1,110 @@
+// Copyright 2016 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![feature(plugin)]
+#![cfg_attr(feature = "dev", plugin(clippy))]
+
+extern crate tikv;
+extern crate getopts;
+extern crate protobuf;
+extern crate kvproto;
+
+use std::env;
+use std::net::TcpStream;
+use std::process;
+use getopts::Options;
+use kvproto::msgpb::{Message, MessageType};
+use kvproto::backuppb::BackupRequest;
+use tikv::util::codec::rpc;
+use tikv::util::escape;
+
+/// # Admin tool
+///
+/// A simple tool that sends admin requests to a running TiKV server.
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options] <command>\n\nCommands:\n    backup    backup the \
+                         regions led by the server",
+                        program);
+    print!("{}", opts.usage(&brief));
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+    let mut opts = Options::new();
+    opts.optopt("A", "addr", "set the server address", "default is 127.0.0.1:20160");
+    opts.optopt("", "backup-ts", "set the ts to backup at, required by backup", "");
+    opts.optopt("",
+                "path",
+                "set the backup directory on the server, required by backup",
+                "");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = opts.parse(&args[1..]).expect("opts parse failed");
+    if matches.opt_present("h") || matches.free.is_empty() {
+        print_usage(&program, opts);
+        return;
+    }
+
+    let addr = matches.opt_str("A").unwrap_or_else(|| "127.0.0.1:20160".to_owned());
+    match matches.free[0].as_str() {
+        "backup" => {
+            let backup_ts = matches.opt_str("backup-ts").unwrap().parse().unwrap();
+            let path = matches.opt_str("path").unwrap();
+            backup(&addr, backup_ts, path);
+        }
+        cmd => panic!("unknown command {}", cmd),
+    }
+}
+
+fn backup(addr: &str, backup_ts: u64, path: String) {
+    let mut req = BackupRequest::new();
+    req.set_backup_ts(backup_ts);
+    req.set_path(path);
+    let mut msg = Message::new();
+    msg.set_msg_type(MessageType::BackupReq);
+    msg.set_backup_req(req);
+
+    let mut conn = TcpStream::connect(addr).unwrap();
+    rpc::encode_msg(&mut conn, 1, &msg).unwrap();
+    let mut resp_msg = Message::new();
+    rpc::decode_msg(&mut conn, &mut resp_msg).unwrap();
+    let resp = resp_msg.take_backup_resp();
+    if resp.has_error() {
+        println!("backup failed: {}", resp.get_error());
+        process::exit(1);
+    }
+
+    let meta = resp.get_meta();
+    println!("backup at {} finished, {} files",
+             meta.get_backup_ts(),
+             meta.get_files().len());
+    for f in meta.get_files() {
+        let region = f.get_region();
+        println!("region {} [{}, {}) cf: {}, file: {}, kvs: {}, size: {}, crc32: {}",
+                 region.get_id(),
+                 escape(region.get_start_key()),
+                 escape(region.get_end_key()),
+                 f.get_cf(),
+                 f.get_name(),
+                 f.get_total_kvs(),
+                 f.get_size(),
+                 f.get_crc32());
+    }
+    if !meta.get_failed_regions().is_empty() {
+        for f in meta.get_failed_regions() {
+            println!("region {} failed: {}", f.get_region().get_id(), f.get_error());
+        }
+        process::exit(1);
+    }
+}
@@ -39,7 +39,8 @@ use mio::tcp::TcpListener;
 use fs2::FileExt;
 use cadence::{StatsdClient, NopMetricSink};
 
-use tikv::storage::{Storage, TEMP_DIR, DEFAULT_CFS};
+use tikv::storage::{Storage, TEMP_DIR, DEFAULT_CFS, CF_WRITE};
+use tikv::storage::mvcc::{GcContext, WriteCompactionFilterFactory};
 use tikv::util::{self, logger, file_log, panic_hook, rocksdb as rocksdb_util};
 use tikv::util::metric::{self, BufferedUdpMetricSink};
 use tikv::util::transport::SendCh;
@@ -436,6 +437,36 @@ fn build_cfg(matches: &Matches, config: &toml::Value, cluster_id: u64, addr: &st
                           Some(10000),
                           |v| v.as_integer()) as u64;
 
+    cfg.raft_store.mvcc_gc_tick_interval =
+        get_integer_value("",
+                          "raftstore.mvcc-gc-tick-interval",
+                          matches,
+                          config,
+                          Some(600_000),
+                          |v| v.as_integer()) as u64;
+
+    cfg.raft_store.mvcc_gc_batch_interval =
+        get_integer_value("",
+                          "raftstore.mvcc-gc-batch-interval",
+                          matches,
+                          config,
+                          Some(10),
+                          |v| v.as_integer()) as u64;
+
+    cfg.raft_store.cdc_resolved_ts_interval =
+        get_integer_value("",
+                          "raftstore.cdc-resolved-ts-interval",
+                          matches,
+                          config,
+                          Some(1000),
+                          |v| v.as_integer()) as u64;
+
+    cfg.raft_store.mvcc_gc_compaction_filter =
+        config.lookup("raftstore.mvcc-gc-compaction-filter")
+            .unwrap_or(&toml::Value::Boolean(false))
+            .as_bool()
+            .unwrap_or(false);
+
     cfg.storage.sched_notify_capacity =
         get_integer_value("",
                           "storage.scheduler-notify-capacity",
@@ -464,6 +495,41 @@ fn build_cfg(matches: &Matches, config: &toml::Value, cluster_id: u64, addr: &st
                           config,
                           Some(4),
                           |v| v.as_integer()) as usize;
+    cfg.storage.sched_lock_wait_timeout =
+        get_integer_value("",
+                          "storage.scheduler-lock-wait-timeout",
+                          matches,
+                          config,
+                          Some(0),
+                          |v| v.as_integer()) as u64;
+    cfg.storage.sched_too_busy_threshold =
+        get_integer_value("",
+                          "storage.scheduler-too-busy-threshold",
+                          matches,
+                          config,
+                          Some(1000),
+                          |v| v.as_integer()) as usize;
+    cfg.storage.sched_pending_write_threshold =
+        get_integer_value("",
+                          "storage.scheduler-pending-write-threshold",
+                          matches,
+                          config,
+                          Some(100 * 1024 * 1024),
+                          |v| v.as_integer()) as usize;
+    cfg.storage.read_pool_high_concurrency =
+        get_integer_value("",
+                          "storage.read-pool-high-concurrency",
+                          matches,
+                          config,
+                          Some(4),
+                          |v| v.as_integer()) as usize;
+    cfg.storage.read_pool_low_concurrency =
+        get_integer_value("",
+                          "storage.read-pool-low-concurrency",
+                          matches,
+                          config,
+                          Some(4),
+                          |v| v.as_integer()) as usize;
     cfg
 }
 
@@ -481,11 +547,18 @@ fn build_raftkv(matches: &Matches,
                         get_rocksdb_write_cf_option(matches, config)];
     let mut db_path = path.clone();
     db_path.push("db");
+    let gc_ctx = Arc::new(GcContext::new());
+    let mut filters: Vec<(&str, Box<rocksdb::CompactionFilterFactory>)> = vec![];
+    if cfg.raft_store.mvcc_gc_compaction_filter {
+        filters.push((CF_WRITE, Box::new(WriteCompactionFilterFactory::new(gc_ctx.clone()))));
+    }
     let engine = Arc::new(rocksdb_util::new_engine_opt(opts,
                                                        db_path.to_str().unwrap(),
                                                        DEFAULT_CFS,
-                                                       cfs_opts)
+                                                       cfs_opts,
+                                                       filters)
         .unwrap());
+    gc_ctx.set_db(&engine).unwrap();
 
     let mut event_loop = store::create_event_loop(&cfg.raft_store).unwrap();
     let mut node = Node::new(&mut event_loop, cfg, pd_client);
@@ -498,7 +571,8 @@ fn build_raftkv(matches: &Matches,
     node.start(event_loop, engine.clone(), trans, snap_mgr.clone()).unwrap();
     let router = ServerRaftStoreRouter::new(node.get_sendch());
 
-    (node, create_raft_storage(router.clone(), engine, cfg).unwrap(), router, snap_mgr)
+    let storage = create_raft_storage(router.clone(), engine, node.max_read_ts(), cfg).unwrap();
+    (node, storage, router, snap_mgr)
 }
 
 fn get_store_path(matches: &Matches, config: &toml::Value) -> String {
@@ -538,7 +612,8 @@ fn run_local_server(listener: TcpListener, config: &Config) {
                               store,
                               MockRaftStoreRouter,
                               MockStoreAddrResolver,
-                              snap_mgr)
+                              snap_mgr,
+                              None)
         .unwrap();
     svr.run(&mut event_loop).unwrap();
 }
@@ -580,7 +655,8 @@ fn run_raft_server(listener: TcpListener, matches: &Matches, config: &toml::Valu
                               store,
                               raft_router,
                               resolver,
-                              snap_mgr)
+                              snap_mgr,
+                              Some(node.cdc_scheduler()))
         .unwrap();
     svr.run(&mut event_loop).unwrap();
     node.stop().unwrap();
@@ -99,4 +99,12 @@ pub trait PdClient: Send + Sync {
 
     // Report pd the split region.
     fn report_split(&self, left: metapb::Region, right: metapb::Region) -> Result<()>;
+
+    // Get the cluster wide GC safe point, versions older than it can be
+    // deleted by MVCC GC. 0 means no safe point has been set yet.
+    fn get_gc_safe_point(&self) -> Result<u64>;
+
+    // Get a timestamp from the timestamp oracle, it's larger than all the
+    // timestamps allocated before.
+    fn get_ts(&self) -> Result<u64>;
 }
@@ -14,6 +14,7 @@
 use uuid::Uuid;
 use kvproto::{metapb, pdpb};
 use protobuf::RepeatedField;
+use storage::mvcc::compose_ts;
 use super::{Error, Result, RpcClient};
 
 impl super::PdClient for RpcClient {
@@ -143,6 +144,31 @@ impl super::PdClient for RpcClient {
         let resp = try!(self.send(&req));
         check_resp(&resp)
     }
+
+    fn get_gc_safe_point(&self) -> Result<u64> {
+        let mut req = self.new_request(pdpb::CommandType::GetGCSafePoint);
+        req.set_get_gc_safe_point(pdpb::GetGCSafePointRequest::new());
+
+        let resp = try!(self.send(&req));
+        try!(check_resp(&resp));
+        Ok(resp.get_get_gc_safe_point().get_safe_point())
+    }
+
+    fn get_ts(&self) -> Result<u64> {
+        let mut tso = pdpb::TsoRequest::new();
+        tso.set_count(1);
+
+        let mut req = self.new_request(pdpb::CommandType::Tso);
+        req.set_tso(tso);
+
+        let resp = try!(self.send(&req));
+        try!(check_resp(&resp));
+        let ts = match resp.get_tso().get_timestamps().first() {
+            Some(ts) => ts,
+            None => return Err(box_err!("pd returns no timestamp")),
+        };
+        Ok(compose_ts(ts.get_physical() as u64, ts.get_logical() as u64))
+    }
 }
 
 impl RpcClient {
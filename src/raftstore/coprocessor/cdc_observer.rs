@@ -0,0 +1,185 @@
+// Copyright 2016 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::{Arc, RwLock};
+use std::collections::{HashMap, HashSet};
+
+use kvproto::raft_cmdpb::{AdminRequest, Request, AdminResponse, Response, AdminCmdType,
+                          CmdType};
+use protobuf::RepeatedField;
+
+use raftstore::store::{CdcTask, CdcChange, Peekable};
+use storage::{Key, CF_DEFAULT, CF_LOCK, CF_WRITE};
+use storage::mvcc::{Lock, Write, WriteType};
+use util::HandyRwLock;
+use util::worker::Scheduler;
+use super::{Coprocessor, RegionObserver, ObserverContext, Result};
+
+/// `CdcObserver` decodes the MVCC changes of the applied commands in the
+/// observed regions and sends them to the cdc worker.
+pub struct CdcObserver {
+    scheduler: Scheduler<CdcTask>,
+    observed: Arc<RwLock<HashSet<u64>>>,
+}
+
+impl CdcObserver {
+    pub fn new(scheduler: Scheduler<CdcTask>,
+               observed: Arc<RwLock<HashSet<u64>>>)
+               -> CdcObserver {
+        CdcObserver {
+            scheduler: scheduler,
+            observed: observed,
+        }
+    }
+
+    fn is_observed(&self, region_id: u64) -> bool {
+        self.observed.rl().contains(&region_id)
+    }
+}
+
+fn load_value(ctx: &ObserverContext,
+              values: &HashMap<&[u8], &[u8]>,
+              data_key: &Key)
+              -> Result<Vec<u8>> {
+    if let Some(v) = values.get(data_key.encoded().as_slice()) {
+        return Ok(v.to_vec());
+    }
+    let v = box_try!(ctx.snap.get_value(data_key.encoded()));
+    Ok(v.map(|v| v.to_vec()).unwrap_or_default())
+}
+
+/// Decode the changes of `CF_LOCK` and `CF_WRITE` in the requests, the value of
+/// a commit is inlined in the write record, written by the same batch or loaded
+/// from the snapshot.
+fn decode_changes(ctx: &ObserverContext, reqs: &[Request]) -> Result<Vec<CdcChange>> {
+    let mut values = HashMap::new();
+    for req in reqs {
+        let put = req.get_put();
+        // The cf of `CF_DEFAULT` is left empty.
+        if req.get_cmd_type() == CmdType::Put &&
+           (!put.has_cf() || put.get_cf() == CF_DEFAULT) {
+            values.insert(put.get_key(), put.get_value());
+        }
+    }
+
+    let mut changes = vec![];
+    for req in reqs {
+        match req.get_cmd_type() {
+            CmdType::Put => {
+                let put = req.get_put();
+                let cf = put.get_cf();
+                if cf == CF_LOCK {
+                    let lock = box_try!(Lock::parse(put.get_value()));
+                    changes.push(CdcChange::Lock {
+                        key: Key::from_encoded(put.get_key().to_vec()),
+                        start_ts: lock.ts,
+                    });
+                } else if cf == CF_WRITE {
+                    let key = Key::from_encoded(put.get_key().to_vec());
+                    let commit_ts = box_try!(key.decode_ts());
+                    let key = box_try!(key.truncate_ts());
+                    let write = box_try!(Write::parse(put.get_value()));
+                    let value = match write.write_type {
+                        WriteType::Put => {
+                            match write.short_value {
+                                Some(v) => Some(v),
+                                None => {
+                                    let data_key = key.append_ts(write.start_ts);
+                                    Some(try!(load_value(ctx, &values, &data_key)))
+                                }
+                            }
+                        }
+                        WriteType::Delete => None,
+                        WriteType::Lock | WriteType::Rollback => continue,
+                    };
+                    changes.push(CdcChange::Commit {
+                        key: key,
+                        start_ts: write.start_ts,
+                        commit_ts: commit_ts,
+                        value: value,
+                    });
+                }
+            }
+            CmdType::Delete => {
+                let delete = req.get_delete();
+                if delete.get_cf() == CF_LOCK {
+                    changes.push(CdcChange::Unlock {
+                        key: Key::from_encoded(delete.get_key().to_vec()),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(changes)
+}
+
+impl Coprocessor for CdcObserver {
+    fn start(&mut self) {}
+    fn stop(&mut self) {}
+}
+
+impl RegionObserver for CdcObserver {
+    fn pre_admin(&mut self, _: &mut ObserverContext, _: &mut AdminRequest) -> Result<()> {
+        Ok(())
+    }
+
+    fn pre_query(&mut self,
+                 _: &mut ObserverContext,
+                 _: &mut RepeatedField<Request>)
+                 -> Result<()> {
+        Ok(())
+    }
+
+    fn post_admin(&mut self,
+                  ctx: &mut ObserverContext,
+                  req: &AdminRequest,
+                  resp: &mut AdminResponse) {
+        let region_id = ctx.snap.get_region().get_id();
+        if req.get_cmd_type() != AdminCmdType::Split || !resp.has_split() ||
+           !self.is_observed(region_id) {
+            return;
+        }
+        if let Err(e) = self.scheduler.schedule(CdcTask::RegionSplit { region_id: region_id }) {
+            error!("[region {}] failed to schedule cdc task: {}", region_id, e);
+        }
+    }
+
+    fn post_query(&mut self,
+                  ctx: &mut ObserverContext,
+                  reqs: &[Request],
+                  resps: &mut RepeatedField<Response>) {
+        let region_id = ctx.snap.get_region().get_id();
+        // A failed command has no responses.
+        if reqs.len() != resps.len() || !self.is_observed(region_id) {
+            return;
+        }
+        let changes = match decode_changes(ctx, reqs) {
+            Ok(changes) => changes,
+            Err(e) => {
+                error!("[region {}] failed to decode changes: {:?}", region_id, e);
+                return;
+            }
+        };
+        if changes.is_empty() {
+            return;
+        }
+        let task = CdcTask::Apply {
+            region_id: region_id,
+            changes: changes,
+        };
+        if let Err(e) = self.scheduler.schedule(task) {
+            error!("[region {}] failed to schedule cdc task: {}", region_id, e);
+        }
+    }
+}
@@ -174,7 +174,8 @@ impl<'a> RegionIterator<'a> {
     }
 
     pub fn seek(&mut self, key: &[u8]) -> Result<bool> {
-        let key = try!(self.should_seekable(key));
+        try!(self.should_seekable(key));
+        let key = keys::data_key(key);
         if key == self.end_key {
             self.valid = false;
         } else {
@@ -219,13 +220,15 @@ impl<'a> RegionIterator<'a> {
         self.valid
     }
 
+    /// Check whether the key can be sought, the end key of the region is
+    /// allowed and positions the iterator at the end.
     #[inline]
-    fn should_seekable(&self, key: &[u8]) -> Result<Vec<u8>> {
-        let key = keys::data_key(key);
-        if key < self.start_key || key > self.end_key {
+    pub fn should_seekable(&self, key: &[u8]) -> Result<()> {
+        let end_key = self.region.get_end_key();
+        if key < self.region.get_start_key() || (!end_key.is_empty() && key > end_key) {
             return Err(Error::KeyNotInRegion(key.to_vec(), self.region.clone()));
         }
-        Ok(key)
+        Ok(())
     }
 }
 
@@ -442,4 +445,33 @@ mod tests {
         expect.reverse();
         assert_eq!(res, expect);
     }
+
+    #[test]
+    fn test_iterate_in_range() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let engine = new_temp_engine(&path);
+        let (store, _) = load_default_dataset(engine.clone());
+
+        let snap = RegionSnapshot::new(&store);
+        let mut iter = snap.iter();
+        // a1 and a7 are out of the region, the iterator never reaches them.
+        assert!(iter.seek(b"a5").unwrap());
+        assert!(!iter.next());
+        assert!(!iter.prev());
+        assert!(iter.seek_to_first());
+        assert_eq!(iter.key(), b"a3");
+        assert!(!iter.prev());
+        assert!(iter.seek_to_last());
+        assert_eq!(iter.key(), b"a5");
+
+        // The range is checked even if the iterator is near the key.
+        let key = |k: &[u8]| Key::from_encoded(k.to_vec());
+        assert!(Cursor::near_seek(&mut iter, &key(b"a8")).is_err());
+        assert!(iter.seek_to_first());
+        assert!(Cursor::near_seek(&mut iter, &key(b"a1")).is_err());
+        assert!(Cursor::near_seek(&mut iter, &key(b"a4")).unwrap());
+        assert_eq!(iter.key(), b"a5");
+        assert!(Cursor::get(&mut iter, &key(b"a7")).unwrap().is_none());
+        assert!(Cursor::get(&mut iter, &key(b"a7\x00")).is_err());
+    }
 }
@@ -36,6 +36,9 @@ const DEFAULT_MGR_GC_TICK_INTERVAL_MS: u64 = 60000;
 const DEFAULT_SNAP_GC_TIMEOUT_SECS: u64 = 60 * 10;
 const DEFAULT_MESSAGES_PER_TICK: usize = 256;
 const DEFAULT_MAX_PEER_DOWN_SECS: u64 = 300;
+const DEFAULT_MVCC_GC_TICK_INTERVAL_MS: u64 = 10 * 60 * 1000;
+const DEFAULT_MVCC_GC_BATCH_INTERVAL_MS: u64 = 10;
+const DEFAULT_CDC_RESOLVED_TS_INTERVAL_MS: u64 = 1000;
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -79,6 +82,17 @@ pub struct Config {
     /// When a peer hasn't been active for max_peer_down_duration,
     /// we will consider this peer to be down and report it to pd.
     pub max_peer_down_duration: Duration,
+
+    // Interval (ms) to gc stale MVCC versions of the leader regions.
+    pub mvcc_gc_tick_interval: u64,
+    // Interval (ms) to sleep between two gc batches.
+    pub mvcc_gc_batch_interval: u64,
+    // If true, stale versions are dropped by compaction filters instead of
+    // being deleted through raft, and the gc worker only persists the safe point.
+    pub mvcc_gc_compaction_filter: bool,
+
+    // Interval (ms) to advance the resolved ts of the regions subscribed by cdc.
+    pub cdc_resolved_ts_interval: u64,
 }
 
 impl Default for Config {
@@ -104,6 +118,10 @@ impl Default for Config {
             snap_gc_timeout: DEFAULT_SNAP_GC_TIMEOUT_SECS,
             messages_per_tick: DEFAULT_MESSAGES_PER_TICK,
             max_peer_down_duration: Duration::from_secs(DEFAULT_MAX_PEER_DOWN_SECS),
+            mvcc_gc_tick_interval: DEFAULT_MVCC_GC_TICK_INTERVAL_MS,
+            mvcc_gc_batch_interval: DEFAULT_MVCC_GC_BATCH_INTERVAL_MS,
+            mvcc_gc_compaction_filter: false,
+            cdc_resolved_ts_interval: DEFAULT_CDC_RESOLVED_TS_INTERVAL_MS,
         }
     }
 }
@@ -0,0 +1,304 @@
+// Copyright 2016 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crc::crc32;
+use rocksdb::{DB, IngestExternalFileOptions, SstFileReader, SeekKey, Options};
+use uuid::Uuid;
+use kvproto::metapb;
+use kvproto::importpb::SstMeta;
+
+use raftstore::Result;
+use storage::DEFAULT_CFS;
+use util::{escape, rocksdb};
+use super::keys;
+use super::util::check_key_in_region;
+use super::worker::file_checksum;
+
+/// `SstImporter` manages the uploaded SST files waiting to be ingested.
+///
+/// A file must be uploaded to every store of the region before the `IngestSst`
+/// command is proposed, the leader verifies its own copy before proposing. Keys
+/// in the files must be data keys.
+pub struct SstImporter {
+    dir: PathBuf,
+}
+
+impl SstImporter {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> SstImporter {
+        SstImporter { dir: dir.into() }
+    }
+
+    fn path(&self, meta: &SstMeta) -> Result<PathBuf> {
+        let uuid = match Uuid::from_bytes(meta.get_uuid()) {
+            Ok(uuid) => uuid,
+            Err(e) => return Err(box_err!("invalid uuid {:?}: {:?}", meta.get_uuid(), e)),
+        };
+        if !DEFAULT_CFS.contains(&meta.get_cf_name()) {
+            return Err(box_err!("invalid cf {}", meta.get_cf_name()));
+        }
+        let name = format!("{}_{}_{}_{}.sst",
+                           uuid.to_simple_string(),
+                           meta.get_region_id(),
+                           meta.get_region_epoch().get_version(),
+                           meta.get_cf_name());
+        Ok(self.dir.join(name))
+    }
+
+    /// Save the content of a SST file, the length and the checksum must match the meta.
+    pub fn upload(&self, meta: &SstMeta, data: &[u8]) -> Result<()> {
+        if data.len() as u64 != meta.get_length() {
+            return Err(box_err!("sst length mismatch: {} != {}", data.len(), meta.get_length()));
+        }
+        let checksum = crc32::checksum_ieee(data);
+        if checksum != meta.get_crc32() {
+            return Err(box_err!("sst crc32 mismatch: {} != {}", checksum, meta.get_crc32()));
+        }
+
+        let path = try!(self.path(meta));
+        if path.exists() {
+            return Err(box_err!("sst {} already exists", path.display()));
+        }
+        try!(fs::create_dir_all(&self.dir));
+        // Write to a temporary file first, so a partial file is never ingested.
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut f = try!(OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&tmp_path));
+            try!(f.write_all(data));
+            try!(f.sync_all());
+        }
+        try!(fs::rename(&tmp_path, &path));
+        info!("sst {} is uploaded", path.display());
+        Ok(())
+    }
+
+    /// Check that the file is uploaded and matches the meta, return its path.
+    pub fn verify(&self, meta: &SstMeta) -> Result<PathBuf> {
+        let path = try!(self.path(meta));
+        if !path.exists() {
+            return Err(box_err!("sst {} is not uploaded", path.display()));
+        }
+        let (size, checksum) = try!(file_checksum(&path));
+        if size != meta.get_length() || checksum != meta.get_crc32() {
+            return Err(box_err!("sst {} is corrupted, length {}, crc32 {}",
+                                path.display(),
+                                size,
+                                checksum));
+        }
+        Ok(path)
+    }
+
+    /// Ingest an uploaded SST file into its column family.
+    ///
+    /// A missing or corrupted file fails the command on this peer only, the error
+    /// is returned instead of crashing the store. The file is kept since the
+    /// command is applied again if the store crashes before the apply state is
+    /// persisted, `delete` it after that.
+    pub fn ingest(&self, meta: &SstMeta, region: &metapb::Region, db: &DB) -> Result<()> {
+        let path = try!(self.verify(meta));
+
+        // The range in the meta is not trusted, check the keys in the file.
+        let (first_key, last_key) = try!(key_range(&path));
+        let range = meta.get_range();
+        if first_key.as_slice() < range.get_start() ||
+           !range.get_end().is_empty() && last_key.as_slice() >= range.get_end() {
+            return Err(box_err!("keys [{}, {}] of sst {} are out of range [{}, {})",
+                                escape(&first_key),
+                                escape(&last_key),
+                                path.display(),
+                                escape(range.get_start()),
+                                escape(range.get_end())));
+        }
+        try!(check_key_in_region(&first_key, region));
+        try!(check_key_in_region(&last_key, region));
+
+        let handle = try!(rocksdb::get_cf_handle(db, meta.get_cf_name()));
+        let mut opts = IngestExternalFileOptions::new();
+        // Ingest a copy, the file can be ingested again.
+        opts.move_files(false);
+        if let Err(e) = db.ingest_external_file_cf(*handle, &opts, &[path.to_str().unwrap()]) {
+            return Err(box_err!("failed to ingest sst {}: {:?}", path.display(), e));
+        }
+        info!("sst {} is ingested", path.display());
+        Ok(())
+    }
+
+    /// Delete an ingested SST file.
+    pub fn delete(&self, meta: &SstMeta) {
+        let path = match self.path(meta) {
+            Ok(path) => path,
+            Err(_) => return,
+        };
+        if let Err(e) = fs::remove_file(&path) {
+            error!("failed to delete sst {}: {:?}", path.display(), e);
+        }
+    }
+}
+
+/// Get the first and the last keys in a SST file without the data prefix.
+fn key_range(path: &Path) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut reader = SstFileReader::new(Options::new());
+    box_try!(reader.open(path.to_str().unwrap()));
+    let mut iter = reader.iter();
+    if !iter.seek(SeekKey::Start) {
+        return Err(box_err!("sst {} is empty", path.display()));
+    }
+    let first_key = iter.key().to_vec();
+    iter.seek(SeekKey::End);
+    let last_key = iter.key().to_vec();
+    // Keys are sorted, all the keys have the data prefix if both of them have.
+    try!(keys::validate_data_key(&first_key));
+    try!(keys::validate_data_key(&last_key));
+    Ok((keys::origin_key(&first_key).to_vec(), keys::origin_key(&last_key).to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Read, Write};
+
+    use crc::crc32;
+    use rocksdb::{SstFileWriter, EnvOptions, Options};
+    use tempdir::TempDir;
+    use uuid::Uuid;
+    use kvproto::metapb;
+    use kvproto::importpb::SstMeta;
+
+    use raftstore::store::keys;
+    use raftstore::store::engine::Peekable;
+    use storage::DEFAULT_CFS;
+    use util::rocksdb;
+    use super::SstImporter;
+
+    fn new_sst(dir: &TempDir, keys: &[&[u8]]) -> Vec<u8> {
+        let path = dir.path().join("gen.sst");
+        let mut writer = SstFileWriter::new(EnvOptions::new(), Options::new());
+        writer.open(path.to_str().unwrap()).unwrap();
+        for k in keys {
+            writer.add(k, b"v").unwrap();
+        }
+        writer.finish().unwrap();
+        let mut data = vec![];
+        File::open(&path).unwrap().read_to_end(&mut data).unwrap();
+        data
+    }
+
+    fn new_meta(data: &[u8], start: &[u8], end: &[u8]) -> SstMeta {
+        let mut meta = SstMeta::new();
+        meta.set_uuid(Uuid::new_v4().as_bytes().to_vec());
+        meta.set_region_id(1);
+        meta.set_cf_name("default".to_owned());
+        meta.set_length(data.len() as u64);
+        meta.set_crc32(crc32::checksum_ieee(data));
+        meta.mut_range().set_start(start.to_vec());
+        meta.mut_range().set_end(end.to_vec());
+        meta
+    }
+
+    #[test]
+    fn test_upload() {
+        let dir = TempDir::new("test-sst-import").unwrap();
+        let importer = SstImporter::new(dir.path().join("import"));
+
+        let data = b"sst data".to_vec();
+        let mut meta = SstMeta::new();
+        meta.set_uuid(Uuid::new_v4().as_bytes().to_vec());
+        meta.set_region_id(1);
+        meta.set_cf_name("default".to_owned());
+        meta.set_length(data.len() as u64);
+        meta.set_crc32(crc32::checksum_ieee(&data));
+
+        // Corrupted data is rejected.
+        assert!(importer.upload(&meta, b"sst dat").is_err());
+        assert!(importer.upload(&meta, b"sst dat?").is_err());
+
+        importer.upload(&meta, &data).unwrap();
+        assert!(importer.path(&meta).unwrap().exists());
+        // A file can't be uploaded twice.
+        assert!(importer.upload(&meta, &data).is_err());
+
+        // Invalid cf and uuid.
+        meta.set_cf_name("raft".to_owned());
+        assert!(importer.upload(&meta, &data).is_err());
+        meta.set_cf_name("default".to_owned());
+        meta.set_uuid(b"uuid".to_vec());
+        assert!(importer.upload(&meta, &data).is_err());
+    }
+
+    #[test]
+    fn test_ingest() {
+        let dir = TempDir::new("test-sst-ingest").unwrap();
+        let importer = SstImporter::new(dir.path().join("import"));
+        let db = rocksdb::new_engine(dir.path().join("db").to_str().unwrap(), DEFAULT_CFS)
+            .unwrap();
+        let mut region = metapb::Region::new();
+        region.set_id(1);
+        region.set_start_key(b"a".to_vec());
+        region.set_end_key(b"c".to_vec());
+
+        let data = new_sst(&dir, &[b"za", b"zb"]);
+        let meta = new_meta(&data, b"a", b"c");
+        importer.upload(&meta, &data).unwrap();
+        importer.ingest(&meta, &region, &db).unwrap();
+        assert_eq!(&*db.get_value(&keys::data_key(b"b")).unwrap().unwrap(), b"v");
+        // The file is kept until it's deleted explicitly, and can be ingested again.
+        importer.ingest(&meta, &region, &db).unwrap();
+        importer.delete(&meta);
+        assert!(!importer.path(&meta).unwrap().exists());
+
+        // The keys in the file must be in the range of the meta and the region.
+        let data = new_sst(&dir, &[b"zb", b"zc"]);
+        for &(start, end) in &[(b"a", b"c"), (b"b", b"d")] {
+            let meta = new_meta(&data, start, end);
+            importer.upload(&meta, &data).unwrap();
+            assert!(importer.ingest(&meta, &region, &db).is_err());
+        }
+        assert!(db.get_value(&keys::data_key(b"c")).unwrap().is_none());
+
+        // Keys must have the data prefix.
+        let data = new_sst(&dir, &[b"a", b"b"]);
+        let meta = new_meta(&data, b"", b"");
+        importer.upload(&meta, &data).unwrap();
+        assert!(importer.ingest(&meta, &region, &db).is_err());
+    }
+
+    #[test]
+    fn test_ingest_invalid_sst() {
+        let dir = TempDir::new("test-sst-ingest").unwrap();
+        let importer = SstImporter::new(dir.path().join("import"));
+        let db = rocksdb::new_engine(dir.path().join("db").to_str().unwrap(), DEFAULT_CFS)
+            .unwrap();
+        let region = metapb::Region::new();
+
+        // The file is not uploaded.
+        let data = new_sst(&dir, &[b"za", b"zb"]);
+        let meta = new_meta(&data, b"", b"");
+        assert!(importer.verify(&meta).is_err());
+        assert!(importer.ingest(&meta, &region, &db).is_err());
+
+        // The file is corrupted after it's uploaded.
+        importer.upload(&meta, &data).unwrap();
+        importer.verify(&meta).unwrap();
+        File::create(importer.path(&meta).unwrap()).unwrap().write_all(b"sst data").unwrap();
+        assert!(importer.verify(&meta).is_err());
+        assert!(importer.ingest(&meta, &region, &db).is_err());
+        assert!(db.get_value(&keys::data_key(b"a")).unwrap().is_none());
+    }
+}
@@ -23,10 +23,11 @@ mod store;
 mod peer;
 mod peer_storage;
 mod snap;
+mod import;
 pub mod util;
 mod worker;
 
-pub use self::msg::{Msg, Callback, Tick};
+pub use self::msg::{Msg, Callback, BackupCallback, Tick};
 pub use self::store::{Store, create_event_loop};
 pub use self::config::Config;
 pub use self::transport::Transport;
@@ -35,4 +36,6 @@ pub use self::bootstrap::{bootstrap_store, bootstrap_region, write_region, clear
 pub use self::engine::{Peekable, Iterable, Mutable};
 pub use self::peer_storage::{PeerStorage, do_snapshot, SnapState, RAFT_INIT_LOG_TERM,
                              RAFT_INIT_LOG_INDEX};
+pub use self::import::SstImporter;
 pub use self::snap::{SnapFile, SnapKey, SnapManager, new_snap_mgr, SnapEntry};
+pub use self::worker::{CdcTask, CdcChange, OnChangeData};
@@ -19,9 +19,11 @@ use kvproto::eraftpb::Snapshot;
 use kvproto::raft_serverpb::RaftMessage;
 use kvproto::raft_cmdpb::{RaftCmdRequest, RaftCmdResponse};
 use kvproto::metapb::RegionEpoch;
+use kvproto::backuppb::BackupMeta;
 use raft::SnapshotStatus;
 
 pub type Callback = Box<FnBox(RaftCmdResponse) -> Result<()> + Send>;
+pub type BackupCallback = Box<FnBox(Result<BackupMeta>) + Send>;
 
 #[derive(Debug)]
 pub enum Tick {
@@ -31,6 +33,8 @@ pub enum Tick {
     PdHeartbeat,
     PdStoreHeartbeat,
     SnapGc,
+    MvccGc,
+    CdcResolvedTs,
 }
 
 pub enum Msg {
@@ -71,6 +75,13 @@ pub enum Msg {
         region_id: u64,
         snap: Option<Snapshot>,
     },
+
+    // Backup the regions led by this store.
+    Backup {
+        backup_ts: u64,
+        path: String,
+        callback: BackupCallback,
+    },
 }
 
 impl fmt::Debug for Msg {
@@ -106,6 +117,9 @@ impl fmt::Debug for Msg {
                        region_id,
                        snap.is_some())
             }
+            Msg::Backup { backup_ts, ref path, .. } => {
+                write!(fmt, "Backup [backup_ts: {}, path: {}]", backup_ts, path)
+            }
         }
     }
 }
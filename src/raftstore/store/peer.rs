@@ -17,7 +17,7 @@ use std::vec::Vec;
 use std::default::Default;
 use std::time::{Instant, Duration};
 
-use rocksdb::{DB, WriteBatch, Writable};
+use rocksdb::{DB, WriteBatch, Writable, WriteOptions};
 use protobuf::{self, Message};
 use uuid::Uuid;
 
@@ -29,10 +29,12 @@ use kvproto::raft_cmdpb::{RaftCmdRequest, RaftCmdResponse, ChangePeerRequest, Cm
 use kvproto::raft_serverpb::{RaftMessage, RaftApplyState, RaftTruncatedState, PeerState,
                              RegionLocalState};
 use kvproto::pdpb::PeerStats;
+use kvproto::importpb::SstMeta;
 use raft::{self, RawNode, StateRole, SnapshotStatus, Ready, ProgressState};
 use raftstore::{Result, Error};
 use raftstore::coprocessor::CoprocessorHost;
 use raftstore::coprocessor::split_observer::SplitObserver;
+use raftstore::coprocessor::cdc_observer::CdcObserver;
 use util::{escape, HandyRwLock, SlowTimer, rocksdb};
 use pd::{PdClient, INVALID_ID};
 use super::store::Store;
@@ -42,7 +44,8 @@ use super::msg::Callback;
 use super::cmd_resp;
 use super::transport::Transport;
 use super::keys;
-use super::engine::{Snapshot, Peekable, Mutable};
+use super::engine::{Snapshot, Peekable, Iterable, Mutable};
+use super::import::SstImporter;
 
 const TRANSFER_LEADER_ALLOW_LOG_LAG: u64 = 10;
 
@@ -78,6 +81,8 @@ pub struct ReadyResult {
     pub exec_results: Vec<ExecResult>,
     // apply_snap_result is set after snapshot applied.
     pub apply_snap_result: Option<ApplySnapResult>,
+    // role_change is set if the role of the peer has changed.
+    pub role_change: Option<StateRole>,
 }
 
 #[derive(Default)]
@@ -153,6 +158,12 @@ pub struct Peer {
     // Record the last instant of each peer's heartbeat response.
     pub peer_heartbeats: HashMap<u64, Instant>,
     coprocessor_host: CoprocessorHost,
+    // regions captured by cdc, whose applied commands are all observed
+    cdc_regions: Arc<RwLock<HashSet<u64>>>,
+    importer: SstImporter,
+    // the sst ingested by the command being applied, it's deleted after the
+    // apply state is persisted.
+    ingested_sst: Option<SstMeta>,
     /// an inaccurate difference in region size since last reset.
     pub size_diff_hint: u64,
     // if we remove ourself in ChangePeer remove, we should set this flag, then
@@ -210,6 +221,8 @@ impl Peer {
 
         let store_id = store.store_id();
         let sched = store.snap_scheduler();
+        let cdc_observer = store.cdc_observer();
+        let importer = SstImporter::new(store.get_snap_mgr().rl().import_dir());
         let tag = format!("[region {}] {}", region.get_id(), peer_id);
 
         let ps = try!(PeerStorage::new(store.engine(), &region, sched, tag.clone()));
@@ -239,12 +252,15 @@ impl Peer {
             peer_cache: store.peer_cache(),
             peer_heartbeats: HashMap::new(),
             coprocessor_host: CoprocessorHost::new(),
+            cdc_regions: store.cdc_regions(),
+            importer: importer,
+            ingested_sst: None,
             size_diff_hint: 0,
             pending_remove: false,
             tag: tag,
         };
 
-        peer.load_all_coprocessors();
+        peer.load_all_coprocessors(cdc_observer);
 
         // If this region has only one peer and I am the one, campaign directly.
         if region.get_peers().len() == 1 && region.get_peers()[0].get_store_id() == store_id {
@@ -287,9 +303,10 @@ impl Peer {
         self.get_store().is_initialized()
     }
 
-    pub fn load_all_coprocessors(&mut self) {
+    pub fn load_all_coprocessors(&mut self, cdc_observer: CdcObserver) {
         // TODO load coprocessors from configuation
         self.coprocessor_host.registry.register_observer(100, box SplitObserver);
+        self.coprocessor_host.registry.register_observer(200, box cdc_observer);
     }
 
     pub fn region(&self) -> &metapb::Region {
@@ -445,10 +462,12 @@ impl Peer {
             ready.hs.take();
         }
 
+        let role_change = ready.ss.as_ref().map(|ss| ss.raft_state);
         self.raft_group.advance(ready);
         Ok(Some(ReadyResult {
             apply_snap_result: apply_result,
             exec_results: exec_results,
+            role_change: role_change,
         }))
     }
 
@@ -573,6 +592,13 @@ impl Peer {
 
     fn propose_normal(&mut self, mut cmd: RaftCmdRequest) -> Result<()> {
         // TODO: validate request for unexpected changes.
+        for req in cmd.get_requests() {
+            // The file must have been uploaded to every store, reject the command
+            // early if the leader doesn't have a valid copy.
+            if req.get_cmd_type() == CmdType::IngestSst {
+                try!(self.importer.verify(req.get_ingest_sst().get_sst()));
+            }
+        }
         try!(self.coprocessor_host.pre_propose(&self.raft_group.get_store(), &mut cmd));
         let data = try!(cmd.write_to_bytes());
         try!(self.raft_group.propose(data));
@@ -873,12 +899,18 @@ impl Peer {
                uuid,
                resp.get_header());
 
+        // Observers see every applied command in the regions captured by cdc, no
+        // matter who proposed it. Other commands are only observed by the proposer
+        // to save the snapshot taken for observers.
+        if cb.is_some() || self.cdc_regions.rl().contains(&self.region_id) {
+            self.coprocessor_host.post_apply(self.raft_group.get_store(), &cmd, &mut resp);
+        }
+
         if cb.is_none() {
             return Ok(exec_result);
         }
 
         let cb = cb.unwrap();
-        self.coprocessor_host.post_apply(self.raft_group.get_store(), &cmd, &mut resp);
         // TODO: if we have exec_result, maybe we should return this callback too. Outer
         // store will call it after handing exec result.
         // Bind uuid here.
@@ -932,29 +964,47 @@ impl Peer {
         ctx.save(self.region_id).expect("save state must not fail");
 
         // Commit write and change storage fields atomically.
-        let mut storage = self.mut_store();
-        match storage.engine.write(ctx.wb) {
-            Ok(_) => {
-                storage.apply_state = ctx.apply_state;
-                storage.applied_index_term = term;
-
-                if let Some(ref exec_result) = exec_result {
-                    match *exec_result {
-                        ExecResult::ChangePeer { ref region, .. } => {
-                            storage.region = region.clone();
-                        }
-                        ExecResult::CompactLog { .. } => {}
-                        ExecResult::SplitRegion { ref left, .. } => {
-                            storage.region = left.clone();
+        let ingested_sst = self.ingested_sst.take();
+        let persisted = {
+            let mut storage = self.mut_store();
+            let res = if ingested_sst.is_some() {
+                // The ingested file can't be deleted until the apply state is synced.
+                let mut opts = WriteOptions::new();
+                opts.set_sync(true);
+                storage.engine.write_opt(ctx.wb, &opts)
+            } else {
+                storage.engine.write(ctx.wb)
+            };
+            match res {
+                Ok(_) => {
+                    storage.apply_state = ctx.apply_state;
+                    storage.applied_index_term = term;
+
+                    if let Some(ref exec_result) = exec_result {
+                        match *exec_result {
+                            ExecResult::ChangePeer { ref region, .. } => {
+                                storage.region = region.clone();
+                            }
+                            ExecResult::CompactLog { .. } => {}
+                            ExecResult::SplitRegion { ref left, .. } => {
+                                storage.region = left.clone();
+                            }
                         }
-                    }
-                };
-            }
-            Err(e) => {
-                error!("{} commit batch failed err {:?}", storage.tag, e);
-                resp = cmd_resp::message_error(e);
+                    };
+                    true
+                }
+                Err(e) => {
+                    error!("{} commit batch failed err {:?}", storage.tag, e);
+                    resp = cmd_resp::message_error(e);
+                    false
+                }
             }
         };
+        if let Some(meta) = ingested_sst {
+            if persisted {
+                self.importer.delete(&meta);
+            }
+        }
 
         Ok((resp, exec_result))
     }
@@ -1227,6 +1277,8 @@ impl Peer {
                 CmdType::Get => self.do_get(ctx, req),
                 CmdType::Put => self.do_put(ctx, req),
                 CmdType::Delete => self.do_delete(ctx, req),
+                CmdType::DeleteRange => self.do_delete_range(ctx, req),
+                CmdType::IngestSst => self.do_ingest_sst(ctx, req),
                 CmdType::Snap => self.do_snap(ctx, req),
                 CmdType::Invalid => Err(box_err!("invalid cmd type, message maybe currupted")),
             });
@@ -1315,6 +1367,71 @@ impl Peer {
         Ok(resp)
     }
 
+    fn do_delete_range(&mut self, ctx: &ExecContext, req: &Request) -> Result<Response> {
+        let (start_key, end_key) = (req.get_delete_range().get_start_key(),
+                                    req.get_delete_range().get_end_key());
+        if start_key >= end_key {
+            return Err(box_err!("invalid delete range command, start_key: {:?}, end_key: {:?}",
+                                escape(start_key),
+                                escape(end_key)));
+        }
+        try!(self.check_data_key(start_key));
+        let region = self.get_store().get_region();
+        if !region.get_end_key().is_empty() && end_key > region.get_end_key() {
+            return Err(Error::KeyNotInRegion(end_key.to_vec(), region.clone()));
+        }
+
+        // `delete_file_in_range` is not used here: it can't be undone if the following
+        // write batch fails, and it drops data which may still be read through snapshots.
+        // The range deletion is applied atomically with the write batch instead.
+        let (start_key, end_key) = (keys::data_key(start_key), keys::data_key(end_key));
+        for cf in self.engine.cf_names() {
+            let handle = try!(rocksdb::get_cf_handle(&self.engine, cf));
+            try!(ctx.wb.delete_range_cf(*handle, &start_key, &end_key));
+        }
+        // The region shrinks a lot, there is no need to check whether it should be split.
+        self.size_diff_hint = 0;
+
+        Ok(Response::new())
+    }
+
+    fn do_ingest_sst(&mut self, ctx: &ExecContext, req: &Request) -> Result<Response> {
+        // The file is ingested before the write batch is committed, it must not be
+        // mixed with other writes.
+        if ctx.req.get_requests().len() != 1 {
+            return Err(box_err!("ingest sst must be the only request in a command"));
+        }
+        let meta = req.get_ingest_sst().get_sst();
+        let region = self.get_store().get_region().clone();
+        if meta.get_region_id() != region.get_id() {
+            return Err(box_err!("sst of region {} can't be ingested into region {}",
+                                meta.get_region_id(),
+                                region.get_id()));
+        }
+        if meta.get_region_epoch().get_version() != region.get_region_epoch().get_version() {
+            return Err(Error::StaleEpoch(format!("sst epoch {:?} is stale, current epoch {:?}",
+                                                 meta.get_region_epoch(),
+                                                 region.get_region_epoch())));
+        }
+        let (start_key, end_key) = (meta.get_range().get_start(), meta.get_range().get_end());
+        try!(self.check_data_key(start_key));
+        if end_key.is_empty() && !region.get_end_key().is_empty() ||
+           !region.get_end_key().is_empty() && end_key > region.get_end_key() {
+            return Err(Error::KeyNotInRegion(end_key.to_vec(), region));
+        }
+
+        // The file is deleted after the apply state is persisted, whether it's
+        // ingested or not. Ingesting it again after a crash has the same result,
+        // since the later commands are not applied yet.
+        self.ingested_sst = Some(meta.clone());
+        try!(self.importer.ingest(meta, &region, &self.engine));
+        if let Some(diff) = self.size_diff_hint.checked_add(meta.get_length()) {
+            self.size_diff_hint = diff;
+        }
+
+        Ok(Response::new())
+    }
+
     fn do_snap(&mut self, _: &ExecContext, _: &Request) -> Result<Response> {
         let mut resp = Response::new();
         resp.mut_snap().set_region(self.get_store().get_region().clone());
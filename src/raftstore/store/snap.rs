@@ -59,6 +59,8 @@ impl Display for SnapKey {
 const SNAP_GEN_PREFIX: &'static str = "gen";
 /// Name prefix for the received snapshot file.
 const SNAP_REV_PREFIX: &'static str = "rev";
+/// Sub directory for the uploaded SST files.
+const IMPORT_DIR_NAME: &'static str = "import";
 
 /// A structure represents the snapshot file.
 ///
@@ -246,6 +248,11 @@ impl SnapManagerCore {
         Ok(())
     }
 
+    /// The directory of the uploaded SST files waiting to be ingested.
+    pub fn import_dir(&self) -> PathBuf {
+        Path::new(&self.base).join(IMPORT_DIR_NAME)
+    }
+
     pub fn list_snap(&self) -> io::Result<Vec<(SnapKey, bool)>> {
         let path = Path::new(&self.base);
         let read_dir = try!(fs::read_dir(path));
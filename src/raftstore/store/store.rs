@@ -33,21 +33,24 @@ use pd::PdClient;
 use kvproto::raft_cmdpb::{AdminCmdType, AdminRequest, StatusCmdType, StatusResponse,
                           RaftCmdRequest, RaftCmdResponse};
 use protobuf::Message;
-use raft::SnapshotStatus;
+use raft::{SnapshotStatus, StateRole};
 use raftstore::{Result, Error};
+use raftstore::coprocessor::cdc_observer::CdcObserver;
 use kvproto::metapb;
-use util::worker::{Worker, Scheduler};
+use util::worker::{Worker, Scheduler, Stopped};
 use util::transport::SendCh;
 use util::get_disk_stat;
+use storage::{Engine, MaxReadTs};
 use super::worker::{SplitCheckRunner, SplitCheckTask, SnapTask, SnapRunner, CompactTask,
-                    CompactRunner, PdRunner, PdTask};
+                    CompactRunner, PdRunner, PdTask, GcRunner, GcTask, CdcRunner, CdcTask,
+                    BackupRunner, BackupTask};
 use super::{util, Msg, Tick, SnapManager};
 use super::keys::{self, enc_start_key, enc_end_key};
 use super::engine::{Iterable, Peekable, delete_all_in_range};
 use super::config::Config;
 use super::peer::{Peer, PendingCmd, ReadyResult, ExecResult};
 use super::peer_storage::{ApplySnapResult, SnapState};
-use super::msg::Callback;
+use super::msg::{Callback, BackupCallback};
 use super::cmd_resp::{bind_uuid, bind_term, bind_error};
 use super::transport::Transport;
 
@@ -59,6 +62,8 @@ pub struct Store<T: Transport, C: PdClient + 'static> {
     cfg: Config,
     store: metapb::Store,
     engine: Arc<DB>,
+    // The transactional engine used by the workers, whose writes go through raft.
+    kv_engine: Box<Engine>,
     sendch: SendCh<Msg>,
 
     // region_id -> peers
@@ -71,6 +76,13 @@ pub struct Store<T: Transport, C: PdClient + 'static> {
     snap_worker: Worker<SnapTask>,
     compact_worker: Worker<CompactTask>,
     pd_worker: Worker<PdTask>,
+    gc_worker: Worker<GcTask>,
+    cdc_worker: Worker<CdcTask>,
+    backup_worker: Worker<BackupTask>,
+    // The regions subscribed by cdc, shared by the cdc observers and worker.
+    cdc_regions: Arc<RwLock<HashSet<u64>>>,
+    // Shared with the storage, seeded for the regions led by this store.
+    max_read_ts: MaxReadTs,
 
     trans: T,
     pd_client: Arc<C>,
@@ -98,9 +110,12 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                meta: metapb::Store,
                cfg: Config,
                engine: Arc<DB>,
+               kv_engine: Box<Engine>,
                trans: T,
                pd_client: Arc<C>,
-               mgr: SnapManager)
+               mgr: SnapManager,
+               cdc_worker: Worker<CdcTask>,
+               max_read_ts: MaxReadTs)
                -> Result<Store<T, C>> {
         // TODO: we can get cluster meta regularly too later.
         try!(cfg.validate());
@@ -113,6 +128,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             cfg: cfg,
             store: meta,
             engine: engine,
+            kv_engine: kv_engine,
             sendch: sendch,
             region_peers: HashMap::new(),
             pending_raft_groups: HashSet::new(),
@@ -120,6 +136,11 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             snap_worker: Worker::new("snapshot worker"),
             compact_worker: Worker::new("compact worker"),
             pd_worker: Worker::new("pd worker"),
+            gc_worker: Worker::new("mvcc gc worker"),
+            cdc_worker: cdc_worker,
+            backup_worker: Worker::new("backup worker"),
+            cdc_regions: Arc::new(RwLock::new(HashSet::new())),
+            max_read_ts: max_read_ts,
             region_ranges: BTreeMap::new(),
             pending_regions: vec![],
             trans: trans,
@@ -198,6 +219,8 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         self.register_pd_heartbeat_tick(event_loop);
         self.register_pd_store_heartbeat_tick(event_loop);
         self.register_snap_mgr_gc_tick(event_loop);
+        self.register_mvcc_gc_tick(event_loop);
+        self.register_cdc_resolved_ts_tick(event_loop);
 
         let split_check_runner = SplitCheckRunner::new(self.sendch.clone(),
                                                        self.cfg.region_max_size,
@@ -211,9 +234,29 @@ impl<T: Transport, C: PdClient> Store<T, C> {
 
         box_try!(self.compact_worker.start(CompactRunner));
 
-        let pd_runner = PdRunner::new(self.pd_client.clone(), self.sendch.clone());
+        let pd_runner = PdRunner::new(self.pd_client.clone(),
+                                      self.sendch.clone(),
+                                      self.max_read_ts.clone());
         box_try!(self.pd_worker.start(pd_runner));
 
+        let gc_runner = GcRunner::new(self.pd_client.clone(),
+                                      self.kv_engine.clone(),
+                                      self.engine.clone(),
+                                      self.max_read_ts.clone(),
+                                      self.cfg.mvcc_gc_batch_interval,
+                                      self.cfg.mvcc_gc_compaction_filter);
+        box_try!(self.gc_worker.start(gc_runner));
+
+        let cdc_runner = CdcRunner::new(self.pd_client.clone(),
+                                        self.kv_engine.clone(),
+                                        self.cdc_regions.clone(),
+                                        self.max_read_ts.clone(),
+                                        self.cdc_worker.scheduler());
+        box_try!(self.cdc_worker.start(cdc_runner));
+
+        let backup_runner = BackupRunner::new(self.kv_engine.clone(), self.max_read_ts.clone());
+        box_try!(self.backup_worker.start(backup_runner));
+
         try!(event_loop.run(self));
         Ok(())
     }
@@ -231,6 +274,14 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         self.snap_worker.scheduler()
     }
 
+    pub fn cdc_observer(&self) -> CdcObserver {
+        CdcObserver::new(self.cdc_worker.scheduler(), self.cdc_regions.clone())
+    }
+
+    pub fn cdc_regions(&self) -> Arc<RwLock<HashSet<u64>>> {
+        self.cdc_regions.clone()
+    }
+
     pub fn engine(&self) -> Arc<DB> {
         self.engine.clone()
     }
@@ -572,6 +623,8 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         let mut p = self.region_peers.remove(&region_id).unwrap();
         // We can't destroy a peer which is applying snapshot.
         assert!(!p.is_applying_snap());
+        self.max_read_ts.remove(region_id);
+        self.stop_cdc(region_id, CdcTask::RegionDestroyed { region_id: region_id });
 
         let is_initialized = p.is_initialized();
         let end_key = enc_end_key(p.region());
@@ -728,7 +781,46 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         self.region_ranges.insert(enc_end_key(&region), region.get_id());
     }
 
+    fn on_role_changed(&mut self, region_id: u64, role: StateRole) {
+        let term = match self.region_peers.get(&region_id) {
+            Some(peer) => peer.term(),
+            None => return,
+        };
+        if role == StateRole::Leader {
+            self.max_read_ts.on_leader(region_id, term);
+            self.seed_max_read_ts(region_id, term);
+        } else {
+            self.max_read_ts.on_follower(region_id);
+            self.stop_cdc(region_id, CdcTask::LeaderLost { region_id: region_id });
+        }
+    }
+
+    // The changes are only sent by the leader, the subscribers of the region
+    // must register again with the new leader.
+    fn stop_cdc(&self, region_id: u64, task: CdcTask) {
+        if !self.cdc_regions.rl().contains(&region_id) {
+            return;
+        }
+        if let Err(e) = self.cdc_worker.schedule(task) {
+            error!("[region {}] failed to schedule cdc task: {}", region_id, e);
+        }
+    }
+
+    fn seed_max_read_ts(&self, region_id: u64, term: u64) {
+        let task = PdTask::SeedMaxReadTs {
+            region_id: region_id,
+            term: term,
+        };
+        if let Err(e) = self.pd_worker.schedule(task) {
+            error!("[region {}] failed to seed max read ts: {}", region_id, e);
+        }
+    }
+
     fn on_ready_result(&mut self, region_id: u64, ready_result: ReadyResult) -> Result<()> {
+        if let Some(role) = ready_result.role_change {
+            self.on_role_changed(region_id, role);
+        }
+
         if let Some(apply_result) = ready_result.apply_snap_result {
             self.on_ready_apply_snapshot(apply_result);
         }
@@ -989,6 +1081,11 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             if peer.is_leader() {
                 leader_count += 1;
                 self.heartbeat_pd(peer);
+                // Retry the seeding failed before.
+                let region_id = peer.region().get_id();
+                if self.max_read_ts.need_seed(region_id, peer.term()) {
+                    self.seed_max_read_ts(region_id, peer.term());
+                }
             }
         }
 
@@ -1140,6 +1237,80 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         }
     }
 
+    fn on_mvcc_gc_tick(&mut self, event_loop: &mut EventLoop<Self>) {
+        // To avoid frequent scan, we only add new gc task when the last one is handled.
+        if self.gc_worker.is_busy() {
+            self.register_mvcc_gc_tick(event_loop);
+            return;
+        }
+
+        let regions: Vec<_> = self.region_peers
+            .values()
+            .filter(|peer| peer.is_leader())
+            .map(|peer| (peer.region().clone(), peer.peer.clone()))
+            .collect();
+        if let Err(e) = self.gc_worker.schedule(GcTask::new(regions)) {
+            error!("failed to schedule mvcc gc task: {}", e);
+        }
+
+        self.register_mvcc_gc_tick(event_loop);
+    }
+
+    fn on_cdc_resolved_ts_tick(&mut self, event_loop: &mut EventLoop<Self>) {
+        // Skip the tick if the last one is not handled, the ts got later is larger.
+        if !self.cdc_regions.rl().is_empty() && !self.cdc_worker.is_busy() {
+            // Only the leaders that have applied the logs of the former leaders, so
+            // the locks written before are all sent to the cdc worker.
+            let regions: Vec<_> = self.cdc_regions
+                .rl()
+                .iter()
+                .filter(|&region_id| {
+                    self.region_peers.get(region_id).map_or(false, |p| {
+                        p.is_leader() && p.get_store().applied_index_term == p.term()
+                    })
+                })
+                .cloned()
+                .collect();
+            if !regions.is_empty() {
+                let task = CdcTask::AdvanceResolvedTs { regions: regions };
+                if let Err(e) = self.cdc_worker.schedule(task) {
+                    error!("failed to schedule cdc resolved ts task: {}", e);
+                }
+            }
+        }
+
+        self.register_cdc_resolved_ts_tick(event_loop);
+    }
+
+    fn register_cdc_resolved_ts_tick(&self, event_loop: &mut EventLoop<Self>) {
+        if let Err(e) = register_timer(event_loop,
+                                       Tick::CdcResolvedTs,
+                                       self.cfg.cdc_resolved_ts_interval) {
+            error!("register cdc resolved ts tick err: {:?}", e);
+        }
+    }
+
+    fn on_backup(&mut self, backup_ts: u64, path: String, callback: BackupCallback) {
+        let regions: Vec<_> = self.region_peers
+            .values()
+            .filter(|peer| peer.is_leader())
+            .map(|peer| (peer.region().clone(), peer.peer.clone()))
+            .collect();
+        let task = BackupTask::new(backup_ts, path, regions, callback);
+        if let Err(Stopped(task)) = self.backup_worker.schedule(task) {
+            error!("failed to schedule backup task: {}", task);
+            task.fail(box_err!("backup worker is stopped"));
+        }
+    }
+
+    fn register_mvcc_gc_tick(&self, event_loop: &mut EventLoop<Self>) {
+        if let Err(e) = register_timer(event_loop,
+                                       Tick::MvccGc,
+                                       self.cfg.mvcc_gc_tick_interval) {
+            error!("register mvcc gc tick err: {:?}", e);
+        }
+    }
+
     fn on_report_snapshot(&mut self, region_id: u64, to_peer_id: u64, status: SnapshotStatus) {
         if let Some(mut peer) = self.region_peers.get_mut(&region_id) {
             // The peer must exist in peer_cache.
@@ -1268,6 +1439,7 @@ impl<T: Transport, C: PdClient> mio::Handler for Store<T, C> {
             Msg::SnapGenRes { region_id, snap } => {
                 self.on_snap_gen_res(region_id, snap);
             }
+            Msg::Backup { backup_ts, path, callback } => self.on_backup(backup_ts, path, callback),
         }
         slow_log!(t, "handle {:?}", msg_str);
     }
@@ -1281,6 +1453,8 @@ impl<T: Transport, C: PdClient> mio::Handler for Store<T, C> {
             Tick::PdHeartbeat => self.on_pd_heartbeat_tick(event_loop),
             Tick::PdStoreHeartbeat => self.on_pd_store_heartbeat_tick(event_loop),
             Tick::SnapGc => self.on_snap_mgr_gc(event_loop),
+            Tick::MvccGc => self.on_mvcc_gc_tick(event_loop),
+            Tick::CdcResolvedTs => self.on_cdc_resolved_ts_tick(event_loop),
         }
         slow_log!(t, "handle timeout {:?}", timeout);
     }
@@ -1291,7 +1465,10 @@ impl<T: Transport, C: PdClient> mio::Handler for Store<T, C> {
                                         self.split_check_worker.name()),
                                        (self.snap_worker.stop(), self.snap_worker.name()),
                                        (self.compact_worker.stop(), self.compact_worker.name()),
-                                       (self.pd_worker.stop(), self.pd_worker.name())] {
+                                       (self.pd_worker.stop(), self.pd_worker.name()),
+                                       (self.gc_worker.stop(), self.gc_worker.name()),
+                                       (self.cdc_worker.stop(), self.cdc_worker.name()),
+                                       (self.backup_worker.stop(), self.backup_worker.name())] {
                 if let Some(Err(e)) = handle.map(|h| h.join()) {
                     error!("failed to stop {}: {:?}", name, e);
                 }
@@ -0,0 +1,417 @@
+// Copyright 2016 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::{self, Formatter, Display};
+use std::fs::{self, File};
+use std::io::{Read, Write as IoWrite};
+use std::path::{Path, PathBuf};
+
+use crc::crc32::{self, Digest, Hasher32};
+use rocksdb::{SstFileWriter, EnvOptions, Options};
+use protobuf::{Message, RepeatedField};
+use kvproto::metapb;
+use kvproto::kvrpcpb::Context;
+use kvproto::backuppb::{BackupMeta, File as BackupFile, FailedRegion};
+
+use raftstore::{Result, Error};
+use raftstore::store::keys;
+use raftstore::store::msg::BackupCallback;
+use storage::{Engine, Key, SnapshotStore, MaxReadTs, CfName, CF_DEFAULT, CF_WRITE};
+use storage::mvcc::{Write, WriteType, SHORT_VALUE_MAX_LEN};
+use util::worker::Runnable;
+
+/// The name of the manifest in the backup directory.
+pub const BACKUP_META_NAME: &'static str = "backupmeta";
+
+/// Backup task, it contains all the regions led by this store.
+pub struct Task {
+    backup_ts: u64,
+    path: String,
+    regions: Vec<(metapb::Region, metapb::Peer)>,
+    cb: BackupCallback,
+}
+
+impl Task {
+    pub fn new(backup_ts: u64,
+               path: String,
+               regions: Vec<(metapb::Region, metapb::Peer)>,
+               cb: BackupCallback)
+               -> Task {
+        Task {
+            backup_ts: backup_ts,
+            path: path,
+            regions: regions,
+            cb: cb,
+        }
+    }
+
+    /// Finish the task with an error without running it.
+    pub fn fail(self, e: Error) {
+        self.cb.call_box((Err(e),));
+    }
+}
+
+impl Display for Task {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f,
+               "Backup Task [backup ts: {}, path: {}, regions: {}]",
+               self.backup_ts,
+               self.path,
+               self.regions.len())
+    }
+}
+
+/// Get the size and the crc32 checksum of a file.
+pub fn file_checksum(path: &Path) -> Result<(u64, u32)> {
+    let mut f = try!(File::open(path));
+    let mut digest = Digest::new(crc32::IEEE);
+    let mut buf = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let n = try!(f.read(&mut buf));
+        if n == 0 {
+            return Ok((size, digest.sum32()));
+        }
+        digest.write(&buf[..n]);
+        size += n as u64;
+    }
+}
+
+fn sst_name(region_id: u64, cf: CfName) -> String {
+    format!("{}_{}.sst", region_id, cf)
+}
+
+/// Writes the records of a column family in a region into a SST file,
+/// the file is created on the first record.
+struct CfWriter {
+    cf: CfName,
+    name: String,
+    path: PathBuf,
+    writer: Option<SstFileWriter>,
+    total_kvs: u64,
+}
+
+impl CfWriter {
+    fn new(dir: &Path, region_id: u64, cf: CfName) -> CfWriter {
+        let name = sst_name(region_id, cf);
+        CfWriter {
+            cf: cf,
+            path: dir.join(&name),
+            name: name,
+            writer: None,
+            total_kvs: 0,
+        }
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        if self.writer.is_none() {
+            let mut writer = SstFileWriter::new(EnvOptions::new(), Options::new());
+            try!(writer.open(self.path.to_str().unwrap()));
+            self.writer = Some(writer);
+        }
+        try!(self.writer.as_mut().unwrap().add(key, value));
+        self.total_kvs += 1;
+        Ok(())
+    }
+
+    /// Finish the file and return its meta, None if nothing is written.
+    fn finish(self, region: &metapb::Region) -> Result<Option<BackupFile>> {
+        let mut writer = match self.writer {
+            Some(writer) => writer,
+            None => return Ok(None),
+        };
+        try!(writer.finish());
+        let (size, checksum) = try!(file_checksum(&self.path));
+        let mut file = BackupFile::new();
+        file.set_name(self.name);
+        file.set_cf(self.cf.to_owned());
+        file.set_region(region.clone());
+        file.set_size(size);
+        file.set_crc32(checksum);
+        file.set_total_kvs(self.total_kvs);
+        Ok(Some(file))
+    }
+}
+
+pub struct Runner {
+    engine: Box<Engine>,
+    max_read_ts: MaxReadTs,
+}
+
+impl Runner {
+    pub fn new(engine: Box<Engine>, max_read_ts: MaxReadTs) -> Runner {
+        Runner {
+            engine: engine,
+            max_read_ts: max_read_ts,
+        }
+    }
+
+    /// Write the data visible at `backup_ts` in the region into SST files.
+    ///
+    /// The files keep the MVCC layout so they can be ingested directly, every
+    /// key has a single version committed at `backup_ts`.
+    fn backup_region(&self,
+                     dir: &Path,
+                     region: &metapb::Region,
+                     peer: metapb::Peer,
+                     backup_ts: u64)
+                     -> Result<Vec<BackupFile>> {
+        let mut ctx = Context::new();
+        ctx.set_region_id(region.get_id());
+        ctx.set_region_epoch(region.get_region_epoch().clone());
+        ctx.set_peer(peer);
+
+        // A one-phase commit at or before `backup_ts` being written fails the
+        // region, and later ones are committed after it.
+        box_try!(self.max_read_ts.on_read(region.get_id(), backup_ts));
+        let snapshot = box_try!(self.engine.snapshot(&ctx));
+        let store = SnapshotStore::new(snapshot.as_ref(), backup_ts);
+        let mut scanner = box_try!(store.scanner());
+
+        let mut default_writer = CfWriter::new(dir, region.get_id(), CF_DEFAULT);
+        let mut write_writer = CfWriter::new(dir, region.get_id(), CF_WRITE);
+        let end_key = region.get_end_key();
+        let mut key = Key::from_encoded(region.get_start_key().to_vec());
+        // A key locked before `backup_ts` fails the region, the backup must not
+        // miss a transaction that may be committed before `backup_ts`.
+        while let Some((k, v)) = box_try!(scanner.seek(key)) {
+            // The scan is not bounded by the region, stop at its end key.
+            if !end_key.is_empty() && k.encoded().as_slice() >= end_key {
+                break;
+            }
+            let data_key = keys::data_key(k.append_ts(backup_ts).encoded());
+            let write = if v.len() <= SHORT_VALUE_MAX_LEN {
+                Write::new(WriteType::Put, backup_ts, Some(v))
+            } else {
+                try!(default_writer.put(&data_key, &v));
+                Write::new(WriteType::Put, backup_ts, None)
+            };
+            try!(write_writer.put(&data_key, &write.to_bytes()));
+            key = k.append_ts(0);
+        }
+
+        let mut files = vec![];
+        for writer in vec![default_writer, write_writer] {
+            if let Some(file) = try!(writer.finish(region)) {
+                files.push(file);
+            }
+        }
+        Ok(files)
+    }
+
+    fn backup(&self,
+              backup_ts: u64,
+              path: &str,
+              regions: Vec<(metapb::Region, metapb::Peer)>)
+              -> Result<BackupMeta> {
+        let dir = Path::new(path);
+        let meta_path = dir.join(BACKUP_META_NAME);
+        if meta_path.exists() {
+            return Err(box_err!("backup {} already exists", meta_path.display()));
+        }
+        try!(fs::create_dir_all(dir));
+
+        let mut files = vec![];
+        let mut failed_regions = vec![];
+        for (region, peer) in regions {
+            match self.backup_region(dir, &region, peer, backup_ts) {
+                Ok(region_files) => {
+                    info!("[region {}] backup {} files at {}",
+                          region.get_id(),
+                          region_files.len(),
+                          backup_ts);
+                    files.extend(region_files);
+                }
+                // Other regions are still backed up, the failed ones are
+                // recorded in the manifest and can be backed up again.
+                Err(e) => {
+                    error!("[region {}] backup at {} failed: {:?}",
+                           region.get_id(),
+                           backup_ts,
+                           e);
+                    for cf in &[CF_DEFAULT, CF_WRITE] {
+                        let path = dir.join(sst_name(region.get_id(), *cf));
+                        if path.exists() {
+                            try!(fs::remove_file(&path));
+                        }
+                    }
+                    let mut failed = FailedRegion::new();
+                    failed.set_region(region);
+                    failed.set_error(format!("{:?}", e));
+                    failed_regions.push(failed);
+                }
+            }
+        }
+
+        let mut meta = BackupMeta::new();
+        meta.set_backup_ts(backup_ts);
+        meta.set_files(RepeatedField::from_vec(files));
+        meta.set_failed_regions(RepeatedField::from_vec(failed_regions));
+        // The manifest is written last, a backup without it is incomplete.
+        let data = try!(meta.write_to_bytes());
+        let mut f = try!(File::create(&meta_path));
+        try!(f.write_all(&data));
+        try!(f.sync_all());
+        Ok(meta)
+    }
+}
+
+impl Runnable<Task> for Runner {
+    fn run(&mut self, task: Task) {
+        info!("executing task {}", task);
+
+        let res = self.backup(task.backup_ts, &task.path, task.regions);
+        if let Err(ref e) = res {
+            error!("backup to {} at {} failed: {:?}", task.path, task.backup_ts, e);
+        }
+        task.cb.call_box((res,));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Read, Write as IoWrite};
+
+    use crc::crc32;
+    use protobuf;
+    use rocksdb::IngestExternalFileOptions;
+    use tempdir::TempDir;
+    use kvproto::metapb;
+    use kvproto::kvrpcpb::Context;
+    use kvproto::backuppb::BackupMeta;
+
+    use raftstore::store::keys;
+    use raftstore::store::engine::Peekable;
+    use storage::{Engine, Dsn, Mutation, Options, MaxReadTs, make_key, new_engine, CF_DEFAULT,
+                  CF_WRITE, DEFAULT_CFS};
+    use storage::mvcc::{MvccTxn, Write, WriteType, SHORT_VALUE_MAX_LEN};
+    use util::rocksdb;
+    use super::{Runner, BACKUP_META_NAME, file_checksum, sst_name};
+
+    fn must_prewrite(engine: &Engine, m: Mutation, start_ts: u64) {
+        let snapshot = engine.snapshot(&Context::new()).unwrap();
+        let mut txn = MvccTxn::new(snapshot.as_ref(), start_ts);
+        let primary = m.key().raw().unwrap();
+        txn.prewrite(m, &primary, &Options::default()).unwrap();
+        engine.write(&Context::new(), txn.modifies()).unwrap();
+    }
+
+    fn must_commit(engine: &Engine, key: &[u8], start_ts: u64, commit_ts: u64) {
+        let snapshot = engine.snapshot(&Context::new()).unwrap();
+        let mut txn = MvccTxn::new(snapshot.as_ref(), start_ts);
+        txn.commit(&make_key(key), commit_ts).unwrap();
+        engine.write(&Context::new(), txn.modifies()).unwrap();
+    }
+
+    fn must_put(engine: &Engine, key: &[u8], value: &[u8], start_ts: u64, commit_ts: u64) {
+        must_prewrite(engine,
+                      Mutation::Put((make_key(key), value.to_vec())),
+                      start_ts);
+        must_commit(engine, key, start_ts, commit_ts);
+    }
+
+    fn new_region(id: u64, start_key: &[u8], end_key: &[u8]) -> metapb::Region {
+        let mut region = metapb::Region::new();
+        region.set_id(id);
+        if !start_key.is_empty() {
+            region.set_start_key(make_key(start_key).encoded().clone());
+        }
+        if !end_key.is_empty() {
+            region.set_end_key(make_key(end_key).encoded().clone());
+        }
+        region
+    }
+
+    fn data_key(key: &[u8], ts: u64) -> Vec<u8> {
+        keys::data_key(make_key(key).append_ts(ts).encoded())
+    }
+
+    #[test]
+    fn test_backup() {
+        let engine = new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
+        let long_value = vec![b'v'; SHORT_VALUE_MAX_LEN + 1];
+        must_put(engine.as_ref(), b"a", &long_value, 1, 2);
+        must_put(engine.as_ref(), b"b", b"v", 1, 2);
+        must_put(engine.as_ref(), b"c", b"v", 1, 2);
+        must_prewrite(engine.as_ref(), Mutation::Delete(make_key(b"c")), 3);
+        must_commit(engine.as_ref(), b"c", 3, 4);
+        // Committed after the backup ts.
+        must_put(engine.as_ref(), b"d", b"v", 11, 12);
+        // Region 2 has a lock before the backup ts.
+        must_put(engine.as_ref(), b"n", b"v", 1, 2);
+        must_prewrite(engine.as_ref(),
+                      Mutation::Put((make_key(b"x"), b"v".to_vec())),
+                      5);
+
+        let dir = TempDir::new("test-backup").unwrap();
+        let path = dir.path().join("backup");
+        let regions = vec![(new_region(1, b"", b"m"), metapb::Peer::new()),
+                           (new_region(2, b"m", b""), metapb::Peer::new())];
+        let runner = Runner::new(engine, MaxReadTs::new());
+        let meta = runner.backup(10, path.to_str().unwrap(), regions).unwrap();
+        assert_eq!(meta.get_backup_ts(), 10);
+
+        // Only the locked region fails, and its files are removed.
+        assert_eq!(meta.get_failed_regions().len(), 1);
+        assert_eq!(meta.get_failed_regions()[0].get_region().get_id(), 2);
+        assert!(!path.join(sst_name(2, CF_WRITE)).exists());
+
+        let mut data = vec![];
+        File::open(path.join(BACKUP_META_NAME)).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(protobuf::parse_from_bytes::<BackupMeta>(&data).unwrap(), meta);
+        // An existing backup is never overwritten.
+        assert!(runner.backup(10, path.to_str().unwrap(), vec![]).is_err());
+
+        let db_dir = TempDir::new("test-backup-db").unwrap();
+        let db = rocksdb::new_engine(db_dir.path().to_str().unwrap(), DEFAULT_CFS).unwrap();
+        let mut cfs = vec![];
+        for f in meta.get_files() {
+            assert_eq!(f.get_region().get_id(), 1);
+            let file_path = path.join(f.get_name());
+            assert_eq!(file_checksum(&file_path).unwrap(),
+                       (f.get_size(), f.get_crc32()));
+            let handle = rocksdb::get_cf_handle(&db, f.get_cf()).unwrap();
+            db.ingest_external_file_cf(*handle,
+                                        &IngestExternalFileOptions::new(),
+                                        &[file_path.to_str().unwrap()])
+                .unwrap();
+            cfs.push((f.get_cf().to_owned(), f.get_total_kvs()));
+        }
+        cfs.sort();
+        assert_eq!(cfs,
+                   vec![(CF_DEFAULT.to_owned(), 1), (CF_WRITE.to_owned(), 2)]);
+
+        // Every key visible at the backup ts is committed at the backup ts.
+        let write = db.get_value_cf(CF_WRITE, &data_key(b"a", 10)).unwrap().unwrap();
+        assert_eq!(&*write, Write::new(WriteType::Put, 10, None).to_bytes().as_slice());
+        let value = db.get_value_cf(CF_DEFAULT, &data_key(b"a", 10)).unwrap().unwrap();
+        assert_eq!(&*value, long_value.as_slice());
+        let write = db.get_value_cf(CF_WRITE, &data_key(b"b", 10)).unwrap().unwrap();
+        assert_eq!(&*write,
+                   Write::new(WriteType::Put, 10, Some(b"v".to_vec())).to_bytes().as_slice());
+        for key in &[b"c", b"d"] {
+            assert!(db.get_value_cf(CF_WRITE, &data_key(*key, 10)).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_file_checksum() {
+        let dir = TempDir::new("test-backup-checksum").unwrap();
+        let path = dir.path().join("f");
+        let data = vec![b'a'; 100 * 1024];
+        File::create(&path).unwrap().write_all(&data).unwrap();
+        assert_eq!(file_checksum(&path).unwrap(),
+                   (data.len() as u64, crc32::checksum_ieee(&data)));
+    }
+}
@@ -0,0 +1,567 @@
+// Copyright 2016 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::{Arc, RwLock};
+use std::fmt::{self, Formatter, Display};
+use std::collections::{HashMap, HashSet};
+use std::{cmp, error, mem};
+
+use kvproto::kvrpcpb::{Context, Op};
+use kvproto::cdcpb::{ChangeDataEvent, Event};
+use protobuf::RepeatedField;
+
+use pd::PdClient;
+use raftstore::Error as RaftStoreError;
+use storage::{Engine, Key, Value, MaxReadTs, CF_WRITE};
+use storage::engine::Error as EngineError;
+use storage::mvcc::{MvccReader, Write, WriteType};
+use util::HandyRwLock;
+use util::worker::{Runnable, Scheduler};
+
+const SCAN_BATCH_SIZE: usize = 256;
+
+/// The callback to send events to a subscriber.
+pub type OnChangeData = Box<Fn(ChangeDataEvent) + Send>;
+
+/// A change decoded from an applied raft command.
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    /// A lock is written by prewrite.
+    Lock { key: Key, start_ts: u64 },
+    /// A lock is removed by commit or rollback.
+    Unlock { key: Key },
+    /// A Put or Delete is committed, `value` is None for a Delete.
+    Commit {
+        key: Key,
+        start_ts: u64,
+        commit_ts: u64,
+        value: Option<Value>,
+    },
+}
+
+pub enum Task {
+    /// Subscribe the changes committed after `checkpoint_ts` in the region.
+    Register {
+        ctx: Context,
+        checkpoint_ts: u64,
+        conn_id: usize,
+        on_change: OnChangeData,
+    },
+    /// Drop all the subscriptions of the connection.
+    Deregister { conn_id: usize },
+    /// Changes applied to an observed region.
+    Apply { region_id: u64, changes: Vec<Change> },
+    /// The region is split, subscribers must register again with the new regions.
+    RegionSplit { region_id: u64 },
+    /// The local peer is not the leader of the region any more.
+    LeaderLost { region_id: u64 },
+    /// The local peer of the region is destroyed.
+    RegionDestroyed { region_id: u64 },
+    /// Get a ts from pd to advance the resolved ts of the observed `regions`, which
+    /// are led by the local peers and have applied the logs of the former leaders.
+    AdvanceResolvedTs { regions: Vec<u64> },
+    /// Advance the resolved ts of the observed `regions` up to `ts`.
+    ResolvedTs { ts: u64, regions: Vec<u64> },
+}
+
+impl Display for Task {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Task::Register { ref ctx, checkpoint_ts, conn_id, .. } => {
+                write!(f,
+                       "CDC Register [region {}, conn {}, checkpoint ts {}]",
+                       ctx.get_region_id(),
+                       conn_id,
+                       checkpoint_ts)
+            }
+            Task::Deregister { conn_id } => write!(f, "CDC Deregister [conn {}]", conn_id),
+            Task::Apply { region_id, ref changes } => {
+                write!(f, "CDC Apply [region {}, changes {}]", region_id, changes.len())
+            }
+            Task::RegionSplit { region_id } => write!(f, "CDC RegionSplit [region {}]", region_id),
+            Task::LeaderLost { region_id } => write!(f, "CDC LeaderLost [region {}]", region_id),
+            Task::RegionDestroyed { region_id } => {
+                write!(f, "CDC RegionDestroyed [region {}]", region_id)
+            }
+            Task::AdvanceResolvedTs { ref regions } => {
+                write!(f, "CDC AdvanceResolvedTs [regions {}]", regions.len())
+            }
+            Task::ResolvedTs { ts, ref regions } => {
+                write!(f, "CDC ResolvedTs [ts {}, regions {}]", ts, regions.len())
+            }
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    enum Error {
+        Engine(err: EngineError) {
+            from()
+            cause(err)
+            description(err.description())
+            display("engine {:?}", err)
+        }
+        Other(err: Box<error::Error + Sync + Send>) {
+            from()
+            cause(err.as_ref())
+            description(err.description())
+            display("cdc failed {:?}", err)
+        }
+    }
+}
+
+fn new_event(key: &Key,
+             start_ts: u64,
+             commit_ts: u64,
+             value: Option<Value>)
+             -> Result<Event, Error> {
+    let mut event = Event::new();
+    event.set_key(box_try!(key.raw()));
+    event.set_start_ts(start_ts);
+    event.set_commit_ts(commit_ts);
+    match value {
+        Some(v) => {
+            event.set_op_type(Op::Put);
+            event.set_value(v);
+        }
+        None => event.set_op_type(Op::Del),
+    }
+    Ok(event)
+}
+
+fn new_error_event(region_id: u64, err: Error) -> ChangeDataEvent {
+    let mut resp = ChangeDataEvent::new();
+    resp.set_region_id(region_id);
+    match err {
+        Error::Engine(EngineError::Request(header)) => resp.set_region_error(header),
+        e => resp.set_error(format!("{:?}", e)),
+    }
+    resp
+}
+
+struct Subscriber {
+    conn_id: usize,
+    checkpoint_ts: u64,
+    on_change: OnChangeData,
+}
+
+impl Subscriber {
+    /// Send the events committed after the checkpoint and the resolved ts if any.
+    fn notify(&self, region_id: u64, events: &[Event], resolved_ts: Option<u64>) {
+        let events: Vec<_> = events.iter()
+            .filter(|e| e.get_commit_ts() > self.checkpoint_ts)
+            .cloned()
+            .collect();
+        if events.is_empty() && resolved_ts.is_none() {
+            return;
+        }
+        let mut resp = ChangeDataEvent::new();
+        resp.set_region_id(region_id);
+        resp.set_events(RepeatedField::from_vec(events));
+        if let Some(ts) = resolved_ts {
+            resp.set_resolved_ts(ts);
+        }
+        (self.on_change)(resp);
+    }
+}
+
+/// The subscribers and the lock state of an observed region.
+struct Delegate {
+    subscribers: Vec<Subscriber>,
+    // encoded key -> start ts of the locks in the region.
+    locks: HashMap<Key, u64>,
+    // The max ts got from pd, no change can be committed at or before it later
+    // except the transactions holding the locks.
+    max_ts: u64,
+    resolved_ts: u64,
+}
+
+impl Delegate {
+    fn new(locks: HashMap<Key, u64>) -> Delegate {
+        Delegate {
+            subscribers: vec![],
+            locks: locks,
+            max_ts: 0,
+            resolved_ts: 0,
+        }
+    }
+
+    /// Track the locks of the changes and convert the commits to events.
+    fn apply(&mut self, changes: Vec<Change>) -> Result<Vec<Event>, Error> {
+        let mut events = vec![];
+        for change in changes {
+            match change {
+                Change::Lock { key, start_ts } => {
+                    self.locks.insert(key, start_ts);
+                }
+                Change::Unlock { key } => {
+                    self.locks.remove(&key);
+                }
+                Change::Commit { key, start_ts, commit_ts, value } => {
+                    events.push(try!(new_event(&key, start_ts, commit_ts, value)));
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    /// All the changes committed before or at the resolved ts have been sent.
+    /// A transaction can't commit before its locks are resolved, so the resolved ts
+    /// stays below the smallest start ts of the locks. It never goes backward and
+    /// is advanced by the ts got from pd.
+    ///
+    /// The commits observed don't advance it, a one-phase commit at a smaller ts
+    /// leaves no lock and may still be applied later.
+    fn advance_resolved_ts(&mut self) -> Option<u64> {
+        let mut ts = self.max_ts;
+        if let Some(min_lock_ts) = self.locks.values().min() {
+            ts = cmp::min(ts, min_lock_ts - 1);
+        }
+        if ts > self.resolved_ts {
+            self.resolved_ts = ts;
+            return Some(ts);
+        }
+        None
+    }
+
+    /// Advance the resolved ts up to `ts`, which must be got from pd after all the
+    /// locks tracked so far are applied. Transactions that haven't written their
+    /// locks yet will commit at a larger ts, and so will the one-phase commits
+    /// once `ts` is recorded as read.
+    fn resolve(&mut self, ts: u64) -> Option<u64> {
+        self.max_ts = cmp::max(self.max_ts, ts);
+        self.advance_resolved_ts()
+    }
+
+    fn notify(&self, region_id: u64, events: &[Event], resolved_ts: Option<u64>) {
+        for s in &self.subscribers {
+            s.notify(region_id, events, resolved_ts);
+        }
+    }
+}
+
+/// Runner sends the committed changes of the observed regions to the subscribers.
+///
+/// A new subscriber gets the changes committed after its checkpoint ts by an initial
+/// scan, the changes applied during the scan may be sent twice, so the events are
+/// delivered at least once and subscribers should dedup them by (key, commit ts).
+///
+/// The resolved ts is advanced periodically by a ts got from pd, in the regions led
+/// by the local peers. Subscriptions are dropped when the local peer loses the
+/// leadership or is destroyed.
+pub struct Runner<C: PdClient> {
+    pd_client: Arc<C>,
+    engine: Box<Engine>,
+    // The regions that have subscribers, shared with the observers.
+    observed: Arc<RwLock<HashSet<u64>>>,
+    max_read_ts: MaxReadTs,
+    delegates: HashMap<u64, Delegate>,
+    sched: Scheduler<Task>,
+}
+
+impl<C: PdClient> Runner<C> {
+    pub fn new(pd_client: Arc<C>,
+               engine: Box<Engine>,
+               observed: Arc<RwLock<HashSet<u64>>>,
+               max_read_ts: MaxReadTs,
+               sched: Scheduler<Task>)
+               -> Runner<C> {
+        Runner {
+            pd_client: pd_client,
+            engine: engine,
+            observed: observed,
+            max_read_ts: max_read_ts,
+            delegates: HashMap::new(),
+            sched: sched,
+        }
+    }
+
+    /// Scan the locks and the changes committed after `checkpoint_ts` in the region,
+    /// return the locks.
+    fn initial_scan(&self,
+                    ctx: &Context,
+                    checkpoint_ts: u64,
+                    on_change: &OnChangeData)
+                    -> Result<HashMap<Key, u64>, Error> {
+        let region_id = ctx.get_region_id();
+        // The subscriber has seen the changes committed before or at the checkpoint,
+        // no one-phase commit may be written at or before it later.
+        box_try!(self.max_read_ts.on_read(region_id, checkpoint_ts));
+        let snapshot = try!(self.engine.snapshot(ctx));
+        let locks = {
+            let mut reader = MvccReader::new(snapshot.as_ref());
+            box_try!(reader.scan_lock(|_| true))
+        };
+        let locks = locks.into_iter().map(|(k, lock)| (k, lock.ts)).collect();
+
+        let mut events = vec![];
+        let mut cursor = try!(snapshot.iter_cf(CF_WRITE));
+        let mut valid = cursor.seek_to_first();
+        while valid {
+            let key = Key::from_encoded(cursor.key().to_vec());
+            let commit_ts = box_try!(key.decode_ts());
+            if commit_ts > checkpoint_ts {
+                let write = box_try!(Write::parse(cursor.value()));
+                let key = box_try!(key.truncate_ts());
+                let value = match write.write_type {
+                    WriteType::Put => {
+                        match write.short_value {
+                            Some(v) => Some(v),
+                            None => {
+                                let v = try!(snapshot.get(&key.append_ts(write.start_ts)));
+                                Some(v.unwrap_or_default())
+                            }
+                        }
+                    }
+                    WriteType::Delete => None,
+                    WriteType::Lock | WriteType::Rollback => {
+                        valid = cursor.next();
+                        continue;
+                    }
+                };
+                events.push(try!(new_event(&key, write.start_ts, commit_ts, value)));
+                if events.len() >= SCAN_BATCH_SIZE {
+                    send_events(region_id, mem::replace(&mut events, vec![]), on_change);
+                }
+            }
+            valid = cursor.next();
+        }
+        if !events.is_empty() {
+            send_events(region_id, events, on_change);
+        }
+        Ok(locks)
+    }
+
+    fn register(&mut self,
+                ctx: Context,
+                checkpoint_ts: u64,
+                conn_id: usize,
+                on_change: OnChangeData) {
+        let region_id = ctx.get_region_id();
+        // Observe the region before the scan, so no change is missed.
+        self.observed.wl().insert(region_id);
+        let locks = match self.initial_scan(&ctx, checkpoint_ts, &on_change) {
+            Ok(res) => res,
+            Err(e) => {
+                error!("[region {}] cdc initial scan failed: {:?}", region_id, e);
+                on_change(new_error_event(region_id, e));
+                if !self.delegates.contains_key(&region_id) {
+                    self.observed.wl().remove(&region_id);
+                }
+                return;
+            }
+        };
+        info!("[region {}] conn {} subscribes changes after {}",
+              region_id,
+              conn_id,
+              checkpoint_ts);
+        let delegate = self.delegates.entry(region_id).or_insert_with(|| Delegate::new(locks));
+        delegate.subscribers.push(Subscriber {
+            conn_id: conn_id,
+            checkpoint_ts: checkpoint_ts,
+            on_change: on_change,
+        });
+        if delegate.resolved_ts > 0 {
+            let s = delegate.subscribers.last().unwrap();
+            s.notify(region_id, &[], Some(delegate.resolved_ts));
+        }
+    }
+
+    fn deregister(&mut self, conn_id: usize) {
+        let mut removed = vec![];
+        for (region_id, delegate) in &mut self.delegates {
+            delegate.subscribers.retain(|s| s.conn_id != conn_id);
+            if delegate.subscribers.is_empty() {
+                removed.push(*region_id);
+            }
+        }
+        let mut observed = self.observed.wl();
+        for region_id in removed {
+            self.delegates.remove(&region_id);
+            observed.remove(&region_id);
+        }
+    }
+
+    fn apply(&mut self, region_id: u64, changes: Vec<Change>) {
+        let res = match self.delegates.get_mut(&region_id) {
+            None => return,
+            Some(delegate) => {
+                delegate.apply(changes).map(|events| {
+                    let resolved_ts = delegate.advance_resolved_ts();
+                    delegate.notify(region_id, &events, resolved_ts);
+                })
+            }
+        };
+        if let Err(e) = res {
+            error!("[region {}] failed to apply changes: {:?}", region_id, e);
+            self.stop_region(region_id, e);
+        }
+    }
+
+    /// The ts is applied by another task, so the changes scheduled before the ts is
+    /// got, which include all the locks written before, are applied first.
+    ///
+    /// The ts is recorded as read in each region before it's applied, so one-phase
+    /// commits at or before it are either written and scheduled before the task, or
+    /// rejected. Regions with such commits being written are skipped this time.
+    fn advance_resolved_ts(&self, mut regions: Vec<u64>) {
+        regions.retain(|region_id| self.delegates.contains_key(region_id));
+        if regions.is_empty() {
+            return;
+        }
+        let ts = match self.pd_client.get_ts() {
+            Ok(ts) => ts,
+            Err(e) => {
+                error!("failed to get ts from pd: {:?}", e);
+                return;
+            }
+        };
+        regions.retain(|&region_id| {
+            if let Err(e) = self.max_read_ts.on_read(region_id, ts) {
+                debug!("[region {}] skip resolving ts: {}", region_id, e);
+                return false;
+            }
+            true
+        });
+        let task = Task::ResolvedTs {
+            ts: ts,
+            regions: regions,
+        };
+        if let Err(e) = self.sched.schedule(task) {
+            error!("failed to schedule cdc resolved ts task: {}", e);
+        }
+    }
+
+    fn resolve(&mut self, ts: u64, regions: Vec<u64>) {
+        for region_id in regions {
+            if let Some(delegate) = self.delegates.get_mut(&region_id) {
+                if let Some(resolved_ts) = delegate.resolve(ts) {
+                    delegate.notify(region_id, &[], Some(resolved_ts));
+                }
+            }
+        }
+    }
+
+    /// Drop all the subscribers of the region with an error.
+    fn stop_region(&mut self, region_id: u64, err: Error) {
+        self.observed.wl().remove(&region_id);
+        if let Some(delegate) = self.delegates.remove(&region_id) {
+            let resp = new_error_event(region_id, err);
+            for s in delegate.subscribers {
+                (s.on_change)(resp.clone());
+            }
+        }
+    }
+}
+
+fn send_events(region_id: u64, events: Vec<Event>, on_change: &OnChangeData) {
+    let mut resp = ChangeDataEvent::new();
+    resp.set_region_id(region_id);
+    resp.set_events(RepeatedField::from_vec(events));
+    on_change(resp);
+}
+
+impl<C: PdClient> Runnable<Task> for Runner<C> {
+    fn run(&mut self, task: Task) {
+        debug!("executing task {}", task);
+
+        match task {
+            Task::Register { ctx, checkpoint_ts, conn_id, on_change } => {
+                self.register(ctx, checkpoint_ts, conn_id, on_change)
+            }
+            Task::Deregister { conn_id } => self.deregister(conn_id),
+            Task::Apply { region_id, changes } => self.apply(region_id, changes),
+            Task::RegionSplit { region_id } => {
+                let err = RaftStoreError::StaleEpoch(format!("region {} is split", region_id));
+                let header = err.into();
+                self.stop_region(region_id, Error::Engine(EngineError::Request(header)));
+            }
+            Task::LeaderLost { region_id } => {
+                let header = RaftStoreError::NotLeader(region_id, None).into();
+                self.stop_region(region_id, Error::Engine(EngineError::Request(header)));
+            }
+            Task::RegionDestroyed { region_id } => {
+                let header = RaftStoreError::RegionNotFound(region_id).into();
+                self.stop_region(region_id, Error::Engine(EngineError::Request(header)));
+            }
+            Task::AdvanceResolvedTs { regions } => self.advance_resolved_ts(regions),
+            Task::ResolvedTs { ts, regions } => self.resolve(ts, regions),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use kvproto::kvrpcpb::Op;
+    use storage::make_key;
+    use super::{Change, Delegate};
+
+    #[test]
+    fn test_resolved_ts() {
+        let mut locks = HashMap::new();
+        locks.insert(make_key(b"a"), 10);
+        let mut delegate = Delegate::new(locks);
+        assert_eq!(delegate.advance_resolved_ts(), None);
+
+        // Blocked by the lock of a.
+        let events = delegate.apply(vec![Change::Lock {
+                                              key: make_key(b"b"),
+                                              start_ts: 12,
+                                          },
+                                          Change::Commit {
+                                              key: make_key(b"c"),
+                                              start_ts: 6,
+                                              commit_ts: 20,
+                                              value: Some(b"v".to_vec()),
+                                          }])
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].get_key(), b"c");
+        assert_eq!(events[0].get_value(), b"v");
+        assert_eq!(events[0].get_op_type(), Op::Put);
+        // The commits observed don't advance the resolved ts.
+        assert_eq!(delegate.advance_resolved_ts(), None);
+        assert_eq!(delegate.resolve(20), Some(9));
+        assert_eq!(delegate.resolve(20), None);
+
+        let events = delegate.apply(vec![Change::Unlock { key: make_key(b"a") },
+                                          Change::Commit {
+                                              key: make_key(b"a"),
+                                              start_ts: 10,
+                                              commit_ts: 21,
+                                              value: None,
+                                          }])
+            .unwrap();
+        assert_eq!(events[0].get_op_type(), Op::Del);
+        assert_eq!(delegate.advance_resolved_ts(), Some(11));
+
+        delegate.apply(vec![Change::Unlock { key: make_key(b"b") }]).unwrap();
+        assert_eq!(delegate.advance_resolved_ts(), Some(20));
+        assert_eq!(delegate.advance_resolved_ts(), None);
+
+        // The ts from pd advances idle regions, bounded by the locks.
+        assert_eq!(delegate.resolve(30), Some(30));
+        assert_eq!(delegate.resolve(25), None);
+        delegate.apply(vec![Change::Lock {
+                                 key: make_key(b"d"),
+                                 start_ts: 35,
+                             }])
+            .unwrap();
+        assert_eq!(delegate.resolve(40), Some(34));
+        delegate.apply(vec![Change::Unlock { key: make_key(b"d") }]).unwrap();
+        assert_eq!(delegate.resolve(41), Some(41));
+    }
+}
@@ -0,0 +1,441 @@
+// Copyright 2016 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::fmt::{self, Formatter, Display};
+use std::collections::HashMap;
+use std::time::Duration;
+use std::thread;
+use std::error;
+
+use rocksdb::DB;
+use kvproto::metapb;
+use kvproto::kvrpcpb::Context;
+
+use pd::PdClient;
+use raftstore::store::keys;
+use raftstore::store::engine::{Peekable, Mutable};
+use storage::{Engine, Key, MaxReadTs};
+use storage::mvcc::{MvccReader, MvccTxn};
+use util::worker::Runnable;
+
+const GC_BATCH_SIZE: usize = 512;
+
+/// MVCC GC task, it contains all the regions led by this store.
+pub struct Task {
+    regions: Vec<(metapb::Region, metapb::Peer)>,
+}
+
+impl Task {
+    pub fn new(regions: Vec<(metapb::Region, metapb::Peer)>) -> Task {
+        Task { regions: regions }
+    }
+}
+
+impl Display for Task {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "MVCC GC Task [regions: {}]", self.regions.len())
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    enum Error {
+        Other(err: Box<error::Error + Sync + Send>) {
+            from()
+            cause(err.as_ref())
+            description(err.description())
+            display("gc failed {:?}", err)
+        }
+    }
+}
+
+pub struct Runner<C: PdClient> {
+    pd_client: Arc<C>,
+    engine: Box<Engine>,
+    db: Arc<DB>,
+    max_read_ts: MaxReadTs,
+    // Interval to sleep between two batches, to limit the impact on foreground requests.
+    batch_interval: Duration,
+    // region id -> the safe point the region has been GCed to.
+    safe_points: HashMap<u64, u64>,
+    // Stale versions are dropped by compaction filters, only the safe point is persisted.
+    compaction_filter: bool,
+}
+
+impl<C: PdClient> Runner<C> {
+    pub fn new(pd_client: Arc<C>,
+               engine: Box<Engine>,
+               db: Arc<DB>,
+               max_read_ts: MaxReadTs,
+               batch_interval: u64,
+               compaction_filter: bool)
+               -> Runner<C> {
+        Runner {
+            pd_client: pd_client,
+            engine: engine,
+            db: db,
+            max_read_ts: max_read_ts,
+            batch_interval: Duration::from_millis(batch_interval),
+            safe_points: HashMap::new(),
+            compaction_filter: compaction_filter,
+        }
+    }
+
+    /// Persist the safe point for compaction filters, it never goes backward.
+    fn save_safe_point(&self, safe_point: u64) -> Result<(), Error> {
+        let last_safe_point = box_try!(self.db.get_u64(keys::GC_SAFE_POINT_KEY)).unwrap_or(0);
+        if last_safe_point < safe_point {
+            box_try!(self.db.put_u64(keys::GC_SAFE_POINT_KEY, safe_point));
+        }
+        Ok(())
+    }
+
+    /// GC the region to `safe_point` in batches and return the count of versions deleted.
+    fn gc_region(&self,
+                 region: &metapb::Region,
+                 peer: metapb::Peer,
+                 safe_point: u64)
+                 -> Result<usize, Error> {
+        let mut ctx = Context::new();
+        ctx.set_region_id(region.get_id());
+        ctx.set_region_epoch(region.get_region_epoch().clone());
+        ctx.set_peer(peer);
+
+        // The versions are read at the safe point, no one-phase commit may be
+        // written at or before it later.
+        box_try!(self.max_read_ts.on_read(region.get_id(), safe_point));
+        let end_key = region.get_end_key();
+        let mut next_key = if region.get_start_key().is_empty() {
+            None
+        } else {
+            Some(Key::from_encoded(region.get_start_key().to_vec()))
+        };
+        let mut deleted = 0;
+        loop {
+            let snapshot = box_try!(self.engine.snapshot(&ctx));
+            let (mut keys, next) = {
+                let mut reader = MvccReader::new(snapshot.as_ref());
+                box_try!(reader.scan_keys(next_key.take(), GC_BATCH_SIZE))
+            };
+            // The scan is not bounded by the region, stop at its end key.
+            let finished = match keys.iter()
+                .position(|k| !end_key.is_empty() && k.encoded().as_slice() >= end_key) {
+                Some(pos) => {
+                    keys.truncate(pos);
+                    true
+                }
+                None => next.is_none(),
+            };
+
+            let mut txn = MvccTxn::new(snapshot.as_ref(), 0);
+            for k in &keys {
+                deleted += box_try!(txn.gc(k, safe_point));
+            }
+            let modifies = txn.modifies();
+            if !modifies.is_empty() {
+                box_try!(self.engine.write(&ctx, modifies));
+            }
+
+            if finished {
+                return Ok(deleted);
+            }
+            next_key = next;
+            thread::sleep(self.batch_interval);
+        }
+    }
+}
+
+impl<C: PdClient> Runnable<Task> for Runner<C> {
+    fn run(&mut self, task: Task) {
+        debug!("executing task {}", task);
+
+        let safe_point = match self.pd_client.get_gc_safe_point() {
+            Ok(safe_point) => safe_point,
+            Err(e) => {
+                error!("failed to get gc safe point: {:?}", e);
+                return;
+            }
+        };
+        metric_gauge!("storage.gc.safe_point", safe_point);
+        if safe_point == 0 {
+            debug!("gc safe point is not set yet, skip gc");
+            return;
+        }
+        if self.compaction_filter {
+            if let Err(e) = self.save_safe_point(safe_point) {
+                error!("failed to save gc safe point {}: {:?}", safe_point, e);
+            }
+            return;
+        }
+
+        let total = task.regions.len();
+        let mut safe_points = HashMap::with_capacity(total);
+        for (i, (region, peer)) in task.regions.into_iter().enumerate() {
+            let region_id = region.get_id();
+            let last_safe_point = self.safe_points.get(&region_id).cloned().unwrap_or(0);
+            if last_safe_point >= safe_point {
+                safe_points.insert(region_id, last_safe_point);
+                continue;
+            }
+
+            metric_incr!("storage.gc.region");
+            match self.gc_region(&region, peer, safe_point) {
+                Ok(deleted) => {
+                    metric_incr!("storage.gc.region.success");
+                    metric_count!("storage.gc.deleted_versions", deleted as i64);
+                    info!("[region {}] {} versions are deleted by gc to safe point {}",
+                          region_id,
+                          deleted,
+                          safe_point);
+                    safe_points.insert(region_id, safe_point);
+                }
+                Err(e) => {
+                    error!("[region {}] failed to gc to safe point {}: {:?}",
+                           region_id,
+                           safe_point,
+                           e);
+                    safe_points.insert(region_id, last_safe_point);
+                }
+            }
+            metric_gauge!("storage.gc.progress", ((i + 1) * 100 / total) as u64);
+        }
+        // Regions no longer led by this store are forgotten.
+        self.safe_points = safe_points;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use kvproto::metapb;
+    use kvproto::pdpb;
+    use kvproto::kvrpcpb::Context;
+    use tempdir::TempDir;
+
+    use pd::{PdClient, Result as PdResult};
+    use raftstore::store::keys;
+    use raftstore::store::engine::Peekable;
+    use storage::{Engine, Dsn, Mutation, Options, MaxReadTs, make_key, new_engine, DEFAULT_CFS};
+    use storage::mvcc::{MvccReader, MvccTxn};
+    use util::rocksdb;
+    use util::worker::Runnable;
+    use super::{Runner, Task, GC_BATCH_SIZE};
+
+    struct MockPdClient {
+        safe_point: Mutex<u64>,
+    }
+
+    impl MockPdClient {
+        fn set_gc_safe_point(&self, safe_point: u64) {
+            *self.safe_point.lock().unwrap() = safe_point;
+        }
+    }
+
+    impl PdClient for MockPdClient {
+        fn bootstrap_cluster(&self, _: metapb::Store, _: metapb::Region) -> PdResult<()> {
+            unimplemented!()
+        }
+        fn is_cluster_bootstrapped(&self) -> PdResult<bool> {
+            unimplemented!()
+        }
+        fn alloc_id(&self) -> PdResult<u64> {
+            unimplemented!()
+        }
+        fn put_store(&self, _: metapb::Store) -> PdResult<()> {
+            unimplemented!()
+        }
+        fn get_store(&self, _: u64) -> PdResult<metapb::Store> {
+            unimplemented!()
+        }
+        fn get_cluster_config(&self) -> PdResult<metapb::Cluster> {
+            unimplemented!()
+        }
+        fn get_region(&self, _: &[u8]) -> PdResult<metapb::Region> {
+            unimplemented!()
+        }
+        fn region_heartbeat(&self,
+                            _: metapb::Region,
+                            _: metapb::Peer,
+                            _: Vec<pdpb::PeerStats>)
+                            -> PdResult<pdpb::RegionHeartbeatResponse> {
+            unimplemented!()
+        }
+        fn ask_split(&self, _: metapb::Region) -> PdResult<pdpb::AskSplitResponse> {
+            unimplemented!()
+        }
+        fn store_heartbeat(&self, _: pdpb::StoreStats) -> PdResult<()> {
+            unimplemented!()
+        }
+        fn report_split(&self, _: metapb::Region, _: metapb::Region) -> PdResult<()> {
+            unimplemented!()
+        }
+        fn get_gc_safe_point(&self) -> PdResult<u64> {
+            Ok(*self.safe_point.lock().unwrap())
+        }
+        fn get_ts(&self) -> PdResult<u64> {
+            unimplemented!()
+        }
+    }
+
+    struct GcTester {
+        pd_client: Arc<MockPdClient>,
+        engine: Box<Engine>,
+        runner: Runner<MockPdClient>,
+        _dir: TempDir,
+    }
+
+    impl GcTester {
+        fn new(compaction_filter: bool) -> GcTester {
+            let dir = TempDir::new("test-gc-worker").unwrap();
+            let db = Arc::new(rocksdb::new_engine(dir.path().to_str().unwrap(), DEFAULT_CFS)
+                .unwrap());
+            let pd_client = Arc::new(MockPdClient { safe_point: Mutex::new(0) });
+            let engine = new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
+            let runner = Runner::new(pd_client.clone(),
+                                     engine.clone(),
+                                     db,
+                                     MaxReadTs::new(),
+                                     0,
+                                     compaction_filter);
+            GcTester {
+                pd_client: pd_client,
+                engine: engine,
+                runner: runner,
+                _dir: dir,
+            }
+        }
+
+        fn must_put(&self, key: &[u8], start_ts: u64, commit_ts: u64) {
+            let snapshot = self.engine.snapshot(&Context::new()).unwrap();
+            let mut txn = MvccTxn::new(snapshot.as_ref(), start_ts);
+            txn.prewrite(Mutation::Put((make_key(key), key.to_vec())), key, &Options::default())
+                .unwrap();
+            self.engine.write(&Context::new(), txn.modifies()).unwrap();
+            let snapshot = self.engine.snapshot(&Context::new()).unwrap();
+            let mut txn = MvccTxn::new(snapshot.as_ref(), start_ts);
+            txn.commit(&make_key(key), commit_ts).unwrap();
+            self.engine.write(&Context::new(), txn.modifies()).unwrap();
+        }
+
+        fn versions(&self, key: &[u8]) -> usize {
+            let snapshot = self.engine.snapshot(&Context::new()).unwrap();
+            let mut reader = MvccReader::new(snapshot.as_ref());
+            reader.get_mvcc_info(&make_key(key)).unwrap().writes.len()
+        }
+
+        fn gc(&mut self, safe_point: u64, regions: Vec<metapb::Region>) {
+            self.pd_client.set_gc_safe_point(safe_point);
+            let regions = regions.into_iter().map(|r| (r, metapb::Peer::new())).collect();
+            self.runner.run(Task::new(regions));
+        }
+    }
+
+    fn new_region(id: u64, start_key: &[u8], end_key: &[u8]) -> metapb::Region {
+        let mut region = metapb::Region::new();
+        region.set_id(id);
+        if !start_key.is_empty() {
+            region.set_start_key(make_key(start_key).encoded().clone());
+        }
+        if !end_key.is_empty() {
+            region.set_end_key(make_key(end_key).encoded().clone());
+        }
+        region
+    }
+
+    #[test]
+    fn test_gc_region() {
+        let mut tester = GcTester::new(false);
+        for key in &[b"a", b"b", b"c", b"d"] {
+            tester.must_put(*key, 5, 10);
+            tester.must_put(*key, 15, 20);
+            tester.must_put(*key, 25, 30);
+        }
+
+        // Nothing is GCed before the safe point is set.
+        tester.gc(0, vec![new_region(1, b"b", b"d")]);
+        assert!(tester.runner.safe_points.is_empty());
+        for key in &[b"a", b"b", b"c", b"d"] {
+            assert_eq!(tester.versions(*key), 3);
+        }
+
+        // Only the keys in [b, d) are GCed.
+        tester.gc(22, vec![new_region(1, b"b", b"d")]);
+        assert_eq!(tester.runner.safe_points, map![1 => 22]);
+        assert_eq!(tester.versions(b"a"), 3);
+        assert_eq!(tester.versions(b"b"), 2);
+        assert_eq!(tester.versions(b"c"), 2);
+        assert_eq!(tester.versions(b"d"), 3);
+    }
+
+    #[test]
+    fn test_gc_skip_region() {
+        let mut tester = GcTester::new(false);
+        tester.must_put(b"k", 5, 10);
+        tester.must_put(b"k", 15, 20);
+        tester.must_put(b"k", 25, 30);
+
+        // The region has been GCed to a larger safe point, it's skipped.
+        tester.runner.safe_points.insert(1, 25);
+        tester.gc(22, vec![new_region(1, b"", b"")]);
+        assert_eq!(tester.runner.safe_points, map![1 => 25]);
+        assert_eq!(tester.versions(b"k"), 3);
+
+        tester.gc(32, vec![new_region(1, b"", b"")]);
+        assert_eq!(tester.runner.safe_points, map![1 => 32]);
+        assert_eq!(tester.versions(b"k"), 1);
+
+        // Regions no longer in the task are forgotten.
+        tester.gc(32, vec![new_region(2, b"", b"")]);
+        assert_eq!(tester.runner.safe_points, map![2 => 32]);
+    }
+
+    #[test]
+    fn test_gc_batches() {
+        let mut tester = GcTester::new(false);
+        let keys: Vec<_> = (0..GC_BATCH_SIZE * 2 + 10)
+            .map(|i| format!("k{:04}", i).into_bytes())
+            .collect();
+        for key in &keys {
+            tester.must_put(key, 5, 10);
+            tester.must_put(key, 15, 20);
+        }
+
+        tester.gc(22, vec![new_region(1, b"", b"")]);
+        for key in &keys {
+            assert_eq!(tester.versions(key), 1);
+        }
+    }
+
+    #[test]
+    fn test_gc_compaction_filter() {
+        let mut tester = GcTester::new(true);
+        tester.must_put(b"k", 5, 10);
+        tester.must_put(b"k", 15, 20);
+
+        // The safe point is persisted only, versions are left to compactions.
+        tester.gc(22, vec![new_region(1, b"", b"")]);
+        let db = tester.runner.db.clone();
+        assert_eq!(db.get_u64(keys::GC_SAFE_POINT_KEY).unwrap(), Some(22));
+        assert_eq!(tester.versions(b"k"), 2);
+
+        // The persisted safe point never goes backward.
+        tester.gc(12, vec![new_region(1, b"", b"")]);
+        assert_eq!(db.get_u64(keys::GC_SAFE_POINT_KEY).unwrap(), Some(22));
+        tester.gc(0, vec![new_region(1, b"", b"")]);
+        assert_eq!(db.get_u64(keys::GC_SAFE_POINT_KEY).unwrap(), Some(22));
+        tester.gc(32, vec![new_region(1, b"", b"")]);
+        assert_eq!(db.get_u64(keys::GC_SAFE_POINT_KEY).unwrap(), Some(32));
+    }
+}
@@ -15,8 +15,15 @@ mod snap;
 mod split_check;
 mod compact;
 mod pd;
+mod gc;
+mod cdc;
+mod backup;
 
 pub use self::snap::{Task as SnapTask, Runner as SnapRunner, MsgSender};
 pub use self::split_check::{Task as SplitCheckTask, Runner as SplitCheckRunner};
 pub use self::compact::{Task as CompactTask, Runner as CompactRunner};
 pub use self::pd::{Task as PdTask, Runner as PdRunner};
+pub use self::gc::{Task as GcTask, Runner as GcRunner};
+pub use self::backup::{Task as BackupTask, Runner as BackupRunner, BACKUP_META_NAME,
+                       file_checksum};
+pub use self::cdc::{Task as CdcTask, Runner as CdcRunner, Change as CdcChange, OnChangeData};
@@ -25,6 +25,7 @@ use util::worker::Runnable;
 use util::escape;
 use util::transport::SendCh;
 use pd::PdClient;
+use storage::MaxReadTs;
 use raftstore::store::Msg;
 use raftstore::Result;
 
@@ -47,6 +48,10 @@ pub enum Task {
         left: metapb::Region,
         right: metapb::Region,
     },
+    SeedMaxReadTs {
+        region_id: u64,
+        term: u64,
+    },
 }
 
 
@@ -69,6 +74,9 @@ impl Display for Task {
             Task::ReportSplit { ref left, ref right } => {
                 write!(f, "report split left {:?}, right {:?}", left, right)
             }
+            Task::SeedMaxReadTs { region_id, term } => {
+                write!(f, "seed max read ts of region {} in term {}", region_id, term)
+            }
         }
     }
 }
@@ -76,13 +84,15 @@ impl Display for Task {
 pub struct Runner<T: PdClient> {
     pd_client: Arc<T>,
     ch: SendCh<Msg>,
+    max_read_ts: MaxReadTs,
 }
 
 impl<T: PdClient> Runner<T> {
-    pub fn new(pd_client: Arc<T>, ch: SendCh<Msg>) -> Runner<T> {
+    pub fn new(pd_client: Arc<T>, ch: SendCh<Msg>, max_read_ts: MaxReadTs) -> Runner<T> {
         Runner {
             pd_client: pd_client,
             ch: ch,
+            max_read_ts: max_read_ts,
         }
     }
 
@@ -176,6 +186,23 @@ impl<T: PdClient> Runner<T> {
             error!("report split failed {:?}", e);
         }
     }
+
+    // The ts is fetched after the peer becomes leader, so it's larger than the
+    // ts of any read served by the former leaders.
+    fn handle_seed_max_read_ts(&self, region_id: u64, term: u64) {
+        if !self.max_read_ts.need_seed(region_id, term) {
+            return;
+        }
+        match self.pd_client.get_ts() {
+            Ok(ts) => {
+                if self.max_read_ts.seed(region_id, term, ts) {
+                    debug!("[region {}] max read ts is seeded with {}", region_id, ts);
+                }
+            }
+            // Retried on the next pd heartbeat tick.
+            Err(e) => error!("[region {}] failed to get ts: {:?}", region_id, e),
+        }
+    }
 }
 
 impl<T: PdClient> Runnable<Task> for Runner<T> {
@@ -191,6 +218,9 @@ impl<T: PdClient> Runnable<Task> for Runner<T> {
             }
             Task::StoreHeartbeat { stats } => self.handle_store_heartbeat(stats),
             Task::ReportSplit { left, right } => self.handle_report_split(left, right),
+            Task::SeedMaxReadTs { region_id, term } => {
+                self.handle_seed_max_read_ts(region_id, term)
+            }
         };
     }
 }
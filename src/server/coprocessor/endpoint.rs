@@ -29,12 +29,14 @@ use threadpool::ThreadPool;
 use storage::{Engine, SnapshotStore};
 use kvproto::msgpb::{MessageType, Message};
 use kvproto::coprocessor::{Request, Response, KeyRange};
-use storage::{engine, Snapshot, Key};
+use kvproto::errorpb;
+use storage::{engine, txn, Snapshot, Key, MaxReadTs};
 use util::codec::table::TableDecoder;
 use util::codec::number::NumberDecoder;
 use util::codec::datum::DatumDecoder;
 use util::codec::{Datum, table, datum, mysql};
 use util::xeval::Evaluator;
+use util::codec::convert::ConvertContext;
 use util::{escape, duration_to_ms, SlowTimer, Either};
 use util::worker::{BatchRunnable, Scheduler};
 use server::OnResponse;
@@ -46,6 +48,13 @@ pub const REQ_TYPE_SELECT: i64 = 101;
 pub const REQ_TYPE_INDEX: i64 = 102;
 
 const DEFAULT_ERROR_CODE: i32 = 1;
+// ER_TRUNCATED_WRONG_VALUE of MySQL.
+const WARN_TRUNCATED_CODE: i32 = 1292;
+
+// The flags of `SelectRequest`, a lossy conversion fails the request in strict
+// mode, which is used if neither of them is set.
+pub const FLAG_IGNORE_TRUNCATE: u64 = 1;
+pub const FLAG_TRUNCATE_AS_WARNING: u64 = 1 << 1;
 
 // TODO: make this number configurable.
 const DEFAULT_POOL_SIZE: usize = 8;
@@ -54,6 +63,7 @@ pub const SINGLE_GROUP: &'static [u8] = b"SingleGroup";
 
 pub struct Host {
     engine: Box<Engine>,
+    max_read_ts: MaxReadTs,
     sched: Scheduler<Task>,
     reqs: HashMap<u64, Vec<RequestTask>>,
     last_req_id: u64,
@@ -61,9 +71,10 @@ pub struct Host {
 }
 
 impl Host {
-    pub fn new(engine: Box<Engine>, scheduler: Scheduler<Task>) -> Host {
+    pub fn new(engine: Box<Engine>, max_read_ts: MaxReadTs, scheduler: Scheduler<Task>) -> Host {
         Host {
             engine: engine,
+            max_read_ts: max_read_ts,
             sched: scheduler,
             reqs: HashMap::new(),
             last_req_id: 0,
@@ -118,6 +129,10 @@ impl BatchRunnable<Task> for Host {
         for task in tasks.drain(..) {
             match task {
                 Task::Request(req) => {
+                    if let Err(e) = self.on_read(&req) {
+                        on_read_blocked(e, req);
+                        continue;
+                    }
                     let key = {
                         let ctx = req.req.get_context();
                         (ctx.get_region_id(),
@@ -160,6 +175,20 @@ impl BatchRunnable<Task> for Host {
     }
 }
 
+impl Host {
+    /// Record the read ts of `req` before its snapshot is taken, fail if it
+    /// should be retried later.
+    fn on_read(&self, req: &RequestTask) -> txn::Result<()> {
+        let mut sel = SelectRequest::new();
+        if sel.merge_from_bytes(req.req.get_data()).is_err() {
+            // The error is reported when the request is handled.
+            return Ok(());
+        }
+        let region_id = req.req.get_context().get_region_id();
+        self.max_read_ts.on_read(region_id, sel.get_start_ts())
+    }
+}
+
 type ResponseHandler = Box<FnBox(Response) -> ()>;
 
 fn on_error(e: Error, cb: ResponseHandler) {
@@ -172,6 +201,22 @@ fn on_error(e: Error, cb: ResponseHandler) {
     cb(resp)
 }
 
+// Unlike a busy server, the read can be retried as soon as the one-phase
+// commit is written.
+fn on_read_blocked(e: txn::Error, t: RequestTask) {
+    debug!("reject {}: {}", t, e);
+    let mut err = errorpb::Error::new();
+    err.set_message(format!("{}", e));
+    let on_resp = t.on_resp;
+    on_error(Error::Region(err),
+             box move |r| {
+        let mut resp_msg = Message::new();
+        resp_msg.set_msg_type(MessageType::CopResp);
+        resp_msg.set_cop_resp(r);
+        on_resp.call_box((resp_msg,));
+    });
+}
+
 fn on_snap_failed<E: Into<Error> + Debug>(e: E, reqs: Vec<RequestTask>) {
     error!("failed to get snapshot: {:?}", e);
     on_error(e.into(),
@@ -252,6 +297,13 @@ impl TiDbEndPoint {
         let resp_ts = Instant::now();
         let mut resp = Response::new();
         let mut sel_resp = SelectResponse::new();
+        // Warnings are only returned if the request asks for them.
+        let (warnings, warning_cnt) = ctx.core.eval.convert_ctx.take_warnings();
+        if ctx.core.sel.get_flags() & FLAG_TRUNCATE_AS_WARNING != 0 {
+            let warnings = warnings.into_iter().map(to_pb_warning).collect();
+            sel_resp.set_warnings(RepeatedField::from_vec(warnings));
+            sel_resp.set_warning_count(warning_cnt as i64);
+        }
         match res {
             Ok(rows) => sel_resp.set_rows(RepeatedField::from_vec(rows)),
             Err(e) => {
@@ -280,6 +332,13 @@ fn to_pb_error(err: &Error) -> select::Error {
     e
 }
 
+fn to_pb_warning(msg: String) -> select::Error {
+    let mut e = select::Error::new();
+    e.set_code(WARN_TRUNCATED_CODE);
+    e.set_msg(msg);
+    e
+}
+
 fn prefix_next(key: &[u8]) -> Vec<u8> {
     let mut nk = key.to_vec();
     if nk.is_empty() {
@@ -362,6 +421,10 @@ impl SelectContextCore {
     fn new(mut sel: SelectRequest) -> Result<SelectContextCore> {
         let cond_cols;
         let mut aggr_cols = vec![];
+        let mut eval = Evaluator::default();
+        let flags = sel.get_flags();
+        eval.convert_ctx =
+            ConvertContext::new(flags & (FLAG_IGNORE_TRUNCATE | FLAG_TRUNCATE_AS_WARNING) == 0);
 
         {
             let select_cols = if sel.has_table_info() {
@@ -369,6 +432,7 @@ impl SelectContextCore {
             } else {
                 sel.get_index_info().get_columns()
             };
+            eval.set_column_infos(select_cols);
             let mut cond_col_map = HashMap::new();
             try!(collect_col_in_expr(&mut cond_col_map, select_cols, sel.get_field_where()));
             let mut aggr_cols_map = HashMap::new();
@@ -406,7 +470,7 @@ impl SelectContextCore {
             aggr: !sel.get_aggregates().is_empty() || !sel.get_group_by().is_empty(),
             aggr_cols: aggr_cols,
             sel: sel,
-            eval: Default::default(),
+            eval: eval,
             cols: cols,
             cond_cols: cond_cols,
             gks: vec![],
@@ -57,10 +57,11 @@ impl From<engine::Error> for Error {
 impl From<txn::Error> for Error {
     fn from(e: txn::Error) -> Error {
         match e {
-            txn::Error::Mvcc(mvcc::Error::KeyIsLocked { primary, ts, key }) => {
+            txn::Error::Mvcc(mvcc::Error::KeyIsLocked { primary, ts, key, ttl }) => {
                 let mut info = LockInfo::new();
                 info.set_primary_lock(primary);
                 info.set_lock_version(ts);
+                info.set_lock_ttl(ttl);
                 info.set_key(key);
                 Error::Locked(info)
             }
@@ -70,4 +71,5 @@ impl From<txn::Error> for Error {
 }
 
 pub use self::endpoint::{Host as EndPointHost, RequestTask, SelectContext, SINGLE_GROUP,
-                         REQ_TYPE_SELECT, REQ_TYPE_INDEX, Task as EndPointTask};
+                         REQ_TYPE_SELECT, REQ_TYPE_INDEX, FLAG_IGNORE_TRUNCATE,
+                         FLAG_TRUNCATE_AS_WARNING, Task as EndPointTask};
@@ -15,17 +15,25 @@ use std::boxed::Box;
 
 use protobuf::RepeatedField;
 
-use kvproto::kvrpcpb::{CmdGetResponse, CmdScanResponse, CmdPrewriteResponse, CmdCommitResponse,
-                       CmdBatchRollbackResponse, CmdCleanupResponse, CmdRollbackThenGetResponse,
+use kvproto::kvrpcpb::{CmdGetResponse, CmdScanResponse, CmdReverseScanResponse,
+                       CmdPrewriteResponse, CmdCommitResponse, CmdBatchRollbackResponse,
+                       CmdCleanupResponse, CmdRollbackThenGetResponse,
                        CmdCommitThenGetResponse, CmdBatchGetResponse, CmdScanLockResponse,
-                       CmdResolveLockResponse, CmdGCResponse, Request, Response, MessageType,
-                       KvPair as RpcKvPair, KeyError, LockInfo, Op};
+                       CmdResolveLockResponse, CmdGCResponse, CmdCheckTxnStatusResponse,
+                       CmdRawGetResponse, CmdRawBatchGetResponse, CmdRawScanResponse,
+                       CmdRawPutResponse, CmdRawBatchPutResponse, CmdRawDeleteResponse,
+                       CmdRawBatchDeleteResponse, CmdAcquirePessimisticLockResponse,
+                       CmdPessimisticRollbackResponse, CmdMvccGetByKeyResponse,
+                       CmdMvccGetByStartTsResponse, Request, Response, MessageType,
+                       KvPair as RpcKvPair, KeyError, LockInfo, AlreadyExist, Op,
+                       MvccInfo as RpcMvccInfo, MvccLock, MvccWrite, MvccValue};
 use kvproto::msgpb;
-use kvproto::errorpb::Error as RegionError;
-use storage::{Engine, Storage, Key, Value, KvPair, Mutation, Callback, Result as StorageResult};
+use kvproto::errorpb::{Error as RegionError, ServerIsBusy};
+use storage::{Engine, Storage, Key, Value, KvPair, Mutation, Options, TxnStatus, MvccInfo,
+              MaxReadTs, Callback, Result as StorageResult};
 use storage::Error as StorageError;
 use storage::txn::Error as TxnError;
-use storage::mvcc::Error as MvccError;
+use storage::mvcc::{LockType, WriteType, Error as MvccError};
 use storage::engine::Error as EngineError;
 use util::escape;
 
@@ -58,17 +66,53 @@ impl StoreHandler {
         }
         let req = msg.take_cmd_scan_req();
         let start_key = req.get_start_key();
-        debug!("start_key [{}]", escape(&start_key));
+        let end_key = req.get_end_key();
+        debug!("start_key [{}], end_key [{}]",
+               escape(&start_key),
+               escape(&end_key));
+        let end_key = if end_key.is_empty() {
+            None
+        } else {
+            Some(Key::from_raw(end_key))
+        };
         let cb = self.make_cb(StoreHandler::cmd_scan_done, on_resp);
         self.store
             .async_scan(msg.take_context(),
                         Key::from_raw(start_key),
+                        end_key,
                         req.get_limit() as usize,
+                        req.get_key_only(),
                         req.get_version(),
                         cb)
             .map_err(Error::Storage)
     }
 
+    fn on_reverse_scan(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
+        if !msg.has_cmd_reverse_scan_req() {
+            return Err(box_err!("msg doesn't contain a CmdReverseScanRequest"));
+        }
+        let req = msg.take_cmd_reverse_scan_req();
+        let start_key = req.get_start_key();
+        let end_key = req.get_end_key();
+        debug!("start_key [{}], end_key [{}]",
+               escape(&start_key),
+               escape(&end_key));
+        let end_key = if end_key.is_empty() {
+            None
+        } else {
+            Some(Key::from_raw(end_key))
+        };
+        let cb = self.make_cb(StoreHandler::cmd_reverse_scan_done, on_resp);
+        self.store
+            .async_reverse_scan(msg.take_context(),
+                                Key::from_raw(start_key),
+                                end_key,
+                                req.get_limit() as usize,
+                                req.get_version(),
+                                cb)
+            .map_err(Error::Storage)
+    }
+
     fn on_prewrite(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
         if !msg.has_cmd_prewrite_req() {
             return Err(box_err!("msg doesn't contain a CmdPrewriteRequest"));
@@ -81,19 +125,65 @@ impl StoreHandler {
                     Op::Put => Mutation::Put((Key::from_raw(x.get_key()), x.take_value())),
                     Op::Del => Mutation::Delete(Key::from_raw(x.get_key())),
                     Op::Lock => Mutation::Lock(Key::from_raw(x.get_key())),
+                    Op::Insert => Mutation::Insert((Key::from_raw(x.get_key()), x.take_value())),
                 }
             })
             .collect();
+        let mut options = Options::new(req.get_lock_ttl());
+        if req.get_one_pc_commit_version() > 0 {
+            options.one_pc_commit_ts = Some(req.get_one_pc_commit_version());
+        }
         let cb = self.make_cb(StoreHandler::cmd_prewrite_done, on_resp);
         self.store
             .async_prewrite(msg.take_context(),
                             mutations,
                             req.get_primary_lock().to_vec(),
                             req.get_start_version(),
+                            options,
                             cb)
             .map_err(Error::Storage)
     }
 
+    fn on_acquire_pessimistic_lock(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
+        if !msg.has_cmd_acquire_pessimistic_lock_req() {
+            return Err(box_err!("msg doesn't contain a CmdAcquirePessimisticLockRequest"));
+        }
+        let req = msg.take_cmd_acquire_pessimistic_lock_req();
+        let keys = req.get_keys()
+            .iter()
+            .map(|x| Key::from_raw(x))
+            .collect();
+        let cb = self.make_cb(StoreHandler::cmd_acquire_pessimistic_lock_done, on_resp);
+        self.store
+            .async_acquire_pessimistic_lock(msg.take_context(),
+                                            keys,
+                                            req.get_primary_lock().to_vec(),
+                                            req.get_start_version(),
+                                            req.get_for_update_ts(),
+                                            Options::new(req.get_lock_ttl()),
+                                            cb)
+            .map_err(Error::Storage)
+    }
+
+    fn on_pessimistic_rollback(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
+        if !msg.has_cmd_pessimistic_rollback_req() {
+            return Err(box_err!("msg doesn't contain a CmdPessimisticRollbackRequest"));
+        }
+        let req = msg.take_cmd_pessimistic_rollback_req();
+        let keys = req.get_keys()
+            .iter()
+            .map(|x| Key::from_raw(x))
+            .collect();
+        let cb = self.make_cb(StoreHandler::cmd_pessimistic_rollback_done, on_resp);
+        self.store
+            .async_pessimistic_rollback(msg.take_context(),
+                                        keys,
+                                        req.get_start_version(),
+                                        req.get_for_update_ts(),
+                                        cb)
+            .map_err(Error::Storage)
+    }
+
     fn on_commit(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
         if !msg.has_cmd_commit_req() {
             return Err(box_err!("msg doesn't contain a CmdCommitRequest"));
@@ -194,6 +284,21 @@ impl StoreHandler {
             .map_err(Error::Storage)
     }
 
+    fn on_check_txn_status(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
+        if !msg.has_cmd_check_txn_status_req() {
+            return Err(box_err!("msg doesn't contain a CmdCheckTxnStatusRequest"));
+        }
+        let req = msg.take_cmd_check_txn_status_req();
+        let cb = self.make_cb(StoreHandler::cmd_check_txn_status_done, on_resp);
+        self.store
+            .async_check_txn_status(msg.take_context(),
+                                    Key::from_raw(req.get_primary_key()),
+                                    req.get_lock_version(),
+                                    req.get_current_version(),
+                                    cb)
+            .map_err(Error::Storage)
+    }
+
     fn on_resolve_lock(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
         if !msg.has_cmd_resolve_lock_req() {
             return Err(box_err!("msg doesn't contain a CmdResolveLockRequest"));
@@ -219,6 +324,112 @@ impl StoreHandler {
         self.store.async_gc(msg.take_context(), req.get_safe_point(), cb).map_err(Error::Storage)
     }
 
+    fn on_raw_get(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
+        if !msg.has_cmd_raw_get_req() {
+            return Err(box_err!("msg doesn't contain a CmdRawGetRequest"));
+        }
+        let mut req = msg.take_cmd_raw_get_req();
+        let cb = self.make_cb(StoreHandler::cmd_raw_get_done, on_resp);
+        self.store
+            .async_raw_get(msg.take_context(), req.take_key(), cb)
+            .map_err(Error::Storage)
+    }
+
+    fn on_raw_batch_get(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
+        if !msg.has_cmd_raw_batch_get_req() {
+            return Err(box_err!("msg doesn't contain a CmdRawBatchGetRequest"));
+        }
+        let mut req = msg.take_cmd_raw_batch_get_req();
+        let cb = self.make_cb(StoreHandler::cmd_raw_batch_get_done, on_resp);
+        self.store
+            .async_raw_batch_get(msg.take_context(), req.take_keys().into_vec(), cb)
+            .map_err(Error::Storage)
+    }
+
+    fn on_raw_scan(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
+        if !msg.has_cmd_raw_scan_req() {
+            return Err(box_err!("msg doesn't contain a CmdRawScanRequest"));
+        }
+        let mut req = msg.take_cmd_raw_scan_req();
+        let cb = self.make_cb(StoreHandler::cmd_raw_scan_done, on_resp);
+        self.store
+            .async_raw_scan(msg.take_context(),
+                            req.take_start_key(),
+                            req.get_limit() as usize,
+                            cb)
+            .map_err(Error::Storage)
+    }
+
+    fn on_raw_put(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
+        if !msg.has_cmd_raw_put_req() {
+            return Err(box_err!("msg doesn't contain a CmdRawPutRequest"));
+        }
+        let mut req = msg.take_cmd_raw_put_req();
+        let cb = self.make_cb(StoreHandler::cmd_raw_put_done, on_resp);
+        self.store
+            .async_raw_put(msg.take_context(), req.take_key(), req.take_value(), cb)
+            .map_err(Error::Storage)
+    }
+
+    fn on_raw_batch_put(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
+        if !msg.has_cmd_raw_batch_put_req() {
+            return Err(box_err!("msg doesn't contain a CmdRawBatchPutRequest"));
+        }
+        let mut req = msg.take_cmd_raw_batch_put_req();
+        let cb = self.make_cb(StoreHandler::cmd_raw_batch_put_done, on_resp);
+        let pairs = req.take_pairs()
+            .into_iter()
+            .map(|mut x| (x.take_key(), x.take_value()))
+            .collect();
+        self.store
+            .async_raw_batch_put(msg.take_context(), pairs, cb)
+            .map_err(Error::Storage)
+    }
+
+    fn on_raw_delete(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
+        if !msg.has_cmd_raw_delete_req() {
+            return Err(box_err!("msg doesn't contain a CmdRawDeleteRequest"));
+        }
+        let mut req = msg.take_cmd_raw_delete_req();
+        let cb = self.make_cb(StoreHandler::cmd_raw_delete_done, on_resp);
+        self.store
+            .async_raw_delete(msg.take_context(), req.take_key(), cb)
+            .map_err(Error::Storage)
+    }
+
+    fn on_raw_batch_delete(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
+        if !msg.has_cmd_raw_batch_delete_req() {
+            return Err(box_err!("msg doesn't contain a CmdRawBatchDeleteRequest"));
+        }
+        let mut req = msg.take_cmd_raw_batch_delete_req();
+        let cb = self.make_cb(StoreHandler::cmd_raw_batch_delete_done, on_resp);
+        self.store
+            .async_raw_batch_delete(msg.take_context(), req.take_keys().into_vec(), cb)
+            .map_err(Error::Storage)
+    }
+
+    fn on_mvcc_get_by_key(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
+        if !msg.has_cmd_mvcc_get_by_key_req() {
+            return Err(box_err!("msg doesn't contain a CmdMvccGetByKeyRequest"));
+        }
+        let req = msg.take_cmd_mvcc_get_by_key_req();
+        let cb = self.make_cb(StoreHandler::cmd_mvcc_get_by_key_done, on_resp);
+        self.store
+            .async_mvcc_by_key(msg.take_context(), Key::from_raw(req.get_key()), cb)
+            .map_err(Error::Storage)
+    }
+
+    fn on_mvcc_get_by_start_ts(&self, mut msg: Request, on_resp: OnResponse) -> Result<()> {
+        if !msg.has_cmd_mvcc_get_by_start_ts_req() {
+            return Err(box_err!("msg doesn't contain a CmdMvccGetByStartTsRequest"));
+        }
+        let req = msg.take_cmd_mvcc_get_by_start_ts_req();
+        let cb = self.make_cb(StoreHandler::cmd_mvcc_get_by_start_ts_done, on_resp);
+        self.store
+            .async_mvcc_by_start_ts(msg.take_context(), req.get_start_ts(), cb)
+            .map_err(Error::Storage)
+    }
+
     fn make_cb<T: 'static>(&self,
                            f: fn(StorageResult<T>, &mut Response),
                            on_resp: OnResponse)
@@ -254,6 +465,14 @@ impl StoreHandler {
         resp.set_cmd_scan_resp(scan_resp);
     }
 
+    fn cmd_reverse_scan_done(kvs: StorageResult<Vec<StorageResult<KvPair>>>,
+                             resp: &mut Response) {
+        resp.set_field_type(MessageType::CmdReverseScan);
+        let mut reverse_scan_resp = CmdReverseScanResponse::new();
+        reverse_scan_resp.set_pairs(RepeatedField::from_vec(extract_kv_pairs(kvs)));
+        resp.set_cmd_reverse_scan_resp(reverse_scan_resp);
+    }
+
     fn cmd_batch_get_done(kvs: StorageResult<Vec<StorageResult<KvPair>>>, resp: &mut Response) {
         resp.set_field_type(MessageType::CmdBatchGet);
         let mut batch_get_resp = CmdBatchGetResponse::new();
@@ -268,6 +487,23 @@ impl StoreHandler {
         resp.set_cmd_prewrite_resp(prewrite_resp);
     }
 
+    fn cmd_acquire_pessimistic_lock_done(results: StorageResult<Vec<StorageResult<()>>>,
+                                         resp: &mut Response) {
+        resp.set_field_type(MessageType::CmdAcquirePessimisticLock);
+        let mut lock_resp = CmdAcquirePessimisticLockResponse::new();
+        lock_resp.set_errors(RepeatedField::from_vec(extract_key_errors(results)));
+        resp.set_cmd_acquire_pessimistic_lock_resp(lock_resp);
+    }
+
+    fn cmd_pessimistic_rollback_done(r: StorageResult<()>, resp: &mut Response) {
+        resp.set_field_type(MessageType::CmdPessimisticRollback);
+        let mut rollback_resp = CmdPessimisticRollbackResponse::new();
+        if let Err(e) = r {
+            rollback_resp.set_error(extract_key_error(&e));
+        }
+        resp.set_cmd_pessimistic_rollback_resp(rollback_resp);
+    }
+
     fn cmd_commit_done(r: StorageResult<()>, resp: &mut Response) {
         resp.set_field_type(MessageType::CmdCommit);
         let mut cmd_commit_resp = CmdCommitResponse::new();
@@ -331,6 +567,20 @@ impl StoreHandler {
         resp.set_cmd_scan_lock_resp(scan_lock);
     }
 
+    fn cmd_check_txn_status_done(r: StorageResult<TxnStatus>, resp: &mut Response) {
+        resp.set_field_type(MessageType::CmdCheckTxnStatus);
+        let mut check_txn_status = CmdCheckTxnStatusResponse::new();
+        match r {
+            Ok(TxnStatus::Locked { ttl }) => check_txn_status.set_lock_ttl(ttl),
+            Ok(TxnStatus::Committed { commit_ts }) => {
+                check_txn_status.set_commit_version(commit_ts)
+            }
+            Ok(TxnStatus::RolledBack) => {}
+            Err(e) => check_txn_status.set_error(extract_key_error(&e)),
+        }
+        resp.set_cmd_check_txn_status_resp(check_txn_status);
+    }
+
     fn cmd_resolve_lock_done(r: StorageResult<()>, resp: &mut Response) {
         resp.set_field_type(MessageType::CmdResolveLock);
         let mut resolve_lock = CmdResolveLockResponse::new();
@@ -349,11 +599,108 @@ impl StoreHandler {
         resp.set_cmd_gc_resp(gc);
     }
 
+    fn cmd_raw_get_done(r: StorageResult<Option<Value>>, resp: &mut Response) {
+        resp.set_field_type(MessageType::CmdRawGet);
+        let mut raw_get = CmdRawGetResponse::new();
+        match r {
+            Ok(Some(val)) => raw_get.set_value(val),
+            Ok(None) => raw_get.set_value(vec![]),
+            Err(e) => raw_get.set_error(format!("{}", e)),
+        }
+        resp.set_cmd_raw_get_resp(raw_get);
+    }
+
+    fn cmd_raw_batch_get_done(kvs: StorageResult<Vec<StorageResult<KvPair>>>,
+                              resp: &mut Response) {
+        resp.set_field_type(MessageType::CmdRawBatchGet);
+        let mut raw_batch_get = CmdRawBatchGetResponse::new();
+        raw_batch_get.set_pairs(RepeatedField::from_vec(extract_kv_pairs(kvs)));
+        resp.set_cmd_raw_batch_get_resp(raw_batch_get);
+    }
+
+    fn cmd_raw_scan_done(kvs: StorageResult<Vec<StorageResult<KvPair>>>, resp: &mut Response) {
+        resp.set_field_type(MessageType::CmdRawScan);
+        let mut raw_scan = CmdRawScanResponse::new();
+        raw_scan.set_pairs(RepeatedField::from_vec(extract_kv_pairs(kvs)));
+        resp.set_cmd_raw_scan_resp(raw_scan);
+    }
+
+    fn cmd_raw_put_done(r: StorageResult<()>, resp: &mut Response) {
+        resp.set_field_type(MessageType::CmdRawPut);
+        let mut raw_put = CmdRawPutResponse::new();
+        if let Err(e) = r {
+            raw_put.set_error(format!("{}", e));
+        }
+        resp.set_cmd_raw_put_resp(raw_put);
+    }
+
+    fn cmd_raw_batch_put_done(r: StorageResult<()>, resp: &mut Response) {
+        resp.set_field_type(MessageType::CmdRawBatchPut);
+        let mut raw_batch_put = CmdRawBatchPutResponse::new();
+        if let Err(e) = r {
+            raw_batch_put.set_error(format!("{}", e));
+        }
+        resp.set_cmd_raw_batch_put_resp(raw_batch_put);
+    }
+
+    fn cmd_raw_delete_done(r: StorageResult<()>, resp: &mut Response) {
+        resp.set_field_type(MessageType::CmdRawDelete);
+        let mut raw_delete = CmdRawDeleteResponse::new();
+        if let Err(e) = r {
+            raw_delete.set_error(format!("{}", e));
+        }
+        resp.set_cmd_raw_delete_resp(raw_delete);
+    }
+
+    fn cmd_raw_batch_delete_done(r: StorageResult<()>, resp: &mut Response) {
+        resp.set_field_type(MessageType::CmdRawBatchDelete);
+        let mut raw_batch_delete = CmdRawBatchDeleteResponse::new();
+        if let Err(e) = r {
+            raw_batch_delete.set_error(format!("{}", e));
+        }
+        resp.set_cmd_raw_batch_delete_resp(raw_batch_delete);
+    }
+
+    fn cmd_mvcc_get_by_key_done(r: StorageResult<MvccInfo>, resp: &mut Response) {
+        resp.set_field_type(MessageType::CmdMvccGetByKey);
+        let mut mvcc_get = CmdMvccGetByKeyResponse::new();
+        match r {
+            Ok(mvcc) => mvcc_get.set_info(extract_mvcc_info(mvcc)),
+            Err(e) => mvcc_get.set_error(format!("{}", e)),
+        }
+        resp.set_cmd_mvcc_get_by_key_resp(mvcc_get);
+    }
+
+    fn cmd_mvcc_get_by_start_ts_done(r: StorageResult<Option<(Key, MvccInfo)>>,
+                                     resp: &mut Response) {
+        resp.set_field_type(MessageType::CmdMvccGetByStartTs);
+        let mut mvcc_get = CmdMvccGetByStartTsResponse::new();
+        match r {
+            Ok(Some((key, mvcc))) => {
+                match key.raw() {
+                    Ok(key) => {
+                        mvcc_get.set_key(key);
+                        mvcc_get.set_info(extract_mvcc_info(mvcc));
+                    }
+                    Err(e) => mvcc_get.set_error(format!("{}", e)),
+                }
+            }
+            Ok(None) => {}
+            Err(e) => mvcc_get.set_error(format!("{}", e)),
+        }
+        resp.set_cmd_mvcc_get_by_start_ts_resp(mvcc_get);
+    }
+
     pub fn on_request(&self, req: Request, on_resp: OnResponse) -> Result<()> {
         if let Err(e) = match req.get_field_type() {
             MessageType::CmdGet => self.on_get(req, on_resp),
             MessageType::CmdScan => self.on_scan(req, on_resp),
+            MessageType::CmdReverseScan => self.on_reverse_scan(req, on_resp),
             MessageType::CmdPrewrite => self.on_prewrite(req, on_resp),
+            MessageType::CmdAcquirePessimisticLock => {
+                self.on_acquire_pessimistic_lock(req, on_resp)
+            }
+            MessageType::CmdPessimisticRollback => self.on_pessimistic_rollback(req, on_resp),
             MessageType::CmdCommit => self.on_commit(req, on_resp),
             MessageType::CmdCleanup => self.on_cleanup(req, on_resp),
             MessageType::CmdCommitThenGet => self.on_commit_then_get(req, on_resp),
@@ -361,8 +708,18 @@ impl StoreHandler {
             MessageType::CmdBatchGet => self.on_batch_get(req, on_resp),
             MessageType::CmdBatchRollback => self.on_batch_rollback(req, on_resp),
             MessageType::CmdScanLock => self.on_scan_lock(req, on_resp),
+            MessageType::CmdCheckTxnStatus => self.on_check_txn_status(req, on_resp),
             MessageType::CmdResolveLock => self.on_resolve_lock(req, on_resp),
             MessageType::CmdGC => self.on_gc(req, on_resp),
+            MessageType::CmdRawGet => self.on_raw_get(req, on_resp),
+            MessageType::CmdRawBatchGet => self.on_raw_batch_get(req, on_resp),
+            MessageType::CmdRawScan => self.on_raw_scan(req, on_resp),
+            MessageType::CmdRawPut => self.on_raw_put(req, on_resp),
+            MessageType::CmdRawBatchPut => self.on_raw_batch_put(req, on_resp),
+            MessageType::CmdRawDelete => self.on_raw_delete(req, on_resp),
+            MessageType::CmdRawBatchDelete => self.on_raw_batch_delete(req, on_resp),
+            MessageType::CmdMvccGetByKey => self.on_mvcc_get_by_key(req, on_resp),
+            MessageType::CmdMvccGetByStartTs => self.on_mvcc_get_by_start_ts(req, on_resp),
         } {
             // TODO: should we return an error and tell the client later?
             error!("Some error occur err[{:?}]", e);
@@ -375,6 +732,10 @@ impl StoreHandler {
         self.store.get_engine()
     }
 
+    pub fn max_read_ts(&self) -> MaxReadTs {
+        self.store.get_max_read_ts()
+    }
+
     pub fn stop(&mut self) -> Result<()> {
         self.store.stop().map_err(From::from)
     }
@@ -388,6 +749,12 @@ fn extract_region_error<T>(res: &StorageResult<T>) -> Option<RegionError> {
         Err(StorageError::Txn(TxnError::Mvcc(MvccError::Engine(EngineError::Request(ref e))))) => {
             Some(e.to_owned())
         }
+        Err(StorageError::SchedTooBusy) => {
+            let mut err = RegionError::new();
+            err.set_message("scheduler is too busy".to_owned());
+            err.set_server_is_busy(ServerIsBusy::new());
+            Some(err)
+        }
         _ => None,
     }
 }
@@ -402,18 +769,35 @@ fn extract_committed(err: &StorageError) -> Option<u64> {
 fn extract_key_error(err: &StorageError) -> KeyError {
     let mut key_error = KeyError::new();
     match *err {
-        StorageError::Txn(TxnError::Mvcc(MvccError::KeyIsLocked { ref key, ref primary, ts })) => {
+        StorageError::Txn(TxnError::Mvcc(MvccError::KeyIsLocked { ref key,
+                                                                  ref primary,
+                                                                  ts,
+                                                                  ttl })) => {
             let mut lock_info = LockInfo::new();
             lock_info.set_key(key.to_owned());
             lock_info.set_primary_lock(primary.to_owned());
             lock_info.set_lock_version(ts);
+            lock_info.set_lock_ttl(ttl);
             key_error.set_locked(lock_info);
         }
+        StorageError::Txn(TxnError::Mvcc(MvccError::AlreadyExist { ref key })) => {
+            debug!("txn aborts: {}", err);
+            let mut already_exist = AlreadyExist::new();
+            already_exist.set_key(key.to_owned());
+            key_error.set_already_exist(already_exist);
+        }
         StorageError::Txn(TxnError::Mvcc(MvccError::WriteConflict)) |
+        StorageError::Txn(TxnError::Mvcc(MvccError::Deadlock { .. })) |
         StorageError::Txn(TxnError::Mvcc(MvccError::TxnLockNotFound)) => {
             debug!("txn conflicts: {}", err);
             key_error.set_retryable(format!("{:?}", err));
         }
+        // Retried after the one-phase commit is written or the region is seeded.
+        StorageError::Txn(TxnError::ReadBlocked { .. }) |
+        StorageError::Txn(TxnError::MaxReadTsNotReady { .. }) => {
+            debug!("retry later: {}", err);
+            key_error.set_retryable(format!("{:?}", err));
+        }
         _ => {
             error!("txn aborts: {}", err);
             key_error.set_abort(format!("{:?}", err));
@@ -449,6 +833,58 @@ fn extract_kv_pairs(res: StorageResult<Vec<StorageResult<KvPair>>>) -> Vec<RpcKv
     pairs
 }
 
+fn extract_mvcc_info(mvcc: MvccInfo) -> RpcMvccInfo {
+    let mut info = RpcMvccInfo::new();
+    if let Some(lock) = mvcc.lock {
+        let mut mvcc_lock = MvccLock::new();
+        let op = match lock.lock_type {
+            LockType::Put => Op::Put,
+            LockType::Delete => Op::Del,
+            LockType::Lock => Op::Lock,
+            LockType::Pessimistic => Op::PessimisticLock,
+            LockType::Insert => Op::Insert,
+        };
+        mvcc_lock.set_field_type(op);
+        mvcc_lock.set_start_ts(lock.ts);
+        mvcc_lock.set_primary(lock.primary);
+        if let Some(value) = lock.short_value {
+            mvcc_lock.set_short_value(value);
+        }
+        info.set_lock(mvcc_lock);
+    }
+    let writes = mvcc.writes
+        .into_iter()
+        .map(|(commit_ts, write)| {
+            let mut mvcc_write = MvccWrite::new();
+            let op = match write.write_type {
+                WriteType::Put => Op::Put,
+                WriteType::Delete => Op::Del,
+                WriteType::Lock => Op::Lock,
+                WriteType::Rollback => Op::Rollback,
+            };
+            mvcc_write.set_field_type(op);
+            mvcc_write.set_start_ts(write.start_ts);
+            mvcc_write.set_commit_ts(commit_ts);
+            if let Some(value) = write.short_value {
+                mvcc_write.set_short_value(value);
+            }
+            mvcc_write
+        })
+        .collect();
+    info.set_writes(RepeatedField::from_vec(writes));
+    let values = mvcc.values
+        .into_iter()
+        .map(|(start_ts, value)| {
+            let mut mvcc_value = MvccValue::new();
+            mvcc_value.set_start_ts(start_ts);
+            mvcc_value.set_value(value);
+            mvcc_value
+        })
+        .collect();
+    info.set_values(RepeatedField::from_vec(values));
+    info
+}
+
 fn extract_key_errors(res: StorageResult<Vec<StorageResult<()>>>) -> Vec<KeyError> {
     let mut errs = vec![];
     match res {
@@ -470,7 +906,7 @@ fn extract_key_errors(res: StorageResult<Vec<StorageResult<()>>>) -> Vec<KeyErro
 mod tests {
     use kvproto::kvrpcpb::*;
     use kvproto::errorpb::NotLeader;
-    use storage::{self, txn, mvcc, engine};
+    use storage::{self, txn, mvcc, engine, TxnStatus, MvccInfo};
     use storage::Result as StorageResult;
     use super::*;
 
@@ -557,8 +993,9 @@ mod tests {
         let k1 = vec![0x0, 0x1];
         let k1_primary = k0.clone();
         let k1_ts = 10000;
+        let k1_ttl = 3000;
         let kvs = vec![Ok((k0.clone(), v0.clone())),
-                       make_lock_error(k1.clone(), k1_primary.clone(), k1_ts)];
+                       make_lock_error(k1.clone(), k1_primary.clone(), k1_ts, k1_ttl)];
         let resp = build_resp(Ok(kvs), StoreHandler::cmd_scan_done);
         assert_eq!(MessageType::CmdScan, resp.get_field_type());
         let cmd = resp.get_cmd_scan_resp();
@@ -570,10 +1007,28 @@ mod tests {
         let mut lock_info1 = LockInfo::new();
         lock_info1.set_primary_lock(k1_primary.clone());
         lock_info1.set_lock_version(k1_ts);
+        lock_info1.set_lock_ttl(k1_ttl);
         lock_info1.set_key(k1.clone());
         assert_eq!(lock_info1, *pairs[1].get_error().get_locked());
     }
 
+    #[test]
+    fn test_reverse_scan_done_some() {
+        let k0 = vec![0x0, 0x1];
+        let v0 = vec![0xff, 0xfe];
+        let k1 = vec![0x0, 0x0];
+        let v1 = vec![0xff, 0xff];
+        let kvs = vec![Ok((k0.clone(), v0.clone())), Ok((k1.clone(), v1.clone()))];
+        let resp = build_resp(Ok(kvs), StoreHandler::cmd_reverse_scan_done);
+        assert_eq!(MessageType::CmdReverseScan, resp.get_field_type());
+        let pairs = resp.get_cmd_reverse_scan_resp().get_pairs();
+        assert_eq!(2, pairs.len());
+        assert_eq!(k0, pairs[0].get_key());
+        assert_eq!(v0, pairs[0].get_value());
+        assert_eq!(k1, pairs[1].get_key());
+        assert_eq!(v1, pairs[1].get_value());
+    }
+
     #[test]
     fn test_prewrite_done_ok() {
         let resp = build_resp(Ok(Vec::new()), StoreHandler::cmd_prewrite_done);
@@ -590,6 +1045,26 @@ mod tests {
         assert_eq!(cmd.get_errors().len(), 1);
     }
 
+    #[test]
+    fn test_prewrite_done_already_exist() {
+        // The scheduler returns a result for each mutation.
+        let err = mvcc::Error::AlreadyExist { key: b"k".to_vec() };
+        let res = vec![Ok(()), Err(storage::Error::from(txn::Error::from(err))), Ok(())];
+        let resp = build_resp(Ok(res), StoreHandler::cmd_prewrite_done);
+        let cmd = resp.get_cmd_prewrite_resp();
+        assert_eq!(cmd.get_errors().len(), 1);
+        assert_eq!(cmd.get_errors()[0].get_already_exist().get_key(), b"k");
+    }
+
+    #[test]
+    fn test_acquire_pessimistic_lock_done() {
+        let resp = build_resp(Ok(vec![Ok(()), Err(box_err!("error"))]),
+                              StoreHandler::cmd_acquire_pessimistic_lock_done);
+        assert_eq!(MessageType::CmdAcquirePessimisticLock, resp.get_field_type());
+        let cmd = resp.get_cmd_acquire_pessimistic_lock_resp();
+        assert_eq!(cmd.get_errors().len(), 1);
+    }
+
     #[test]
     fn test_commit_done_ok() {
         let resp = build_resp(Ok(()), StoreHandler::cmd_commit_done);
@@ -640,6 +1115,84 @@ mod tests {
         assert!(cmd.has_error());
     }
 
+    #[test]
+    fn test_check_txn_status_done() {
+        let resp = build_resp(Ok(TxnStatus::Locked { ttl: 100 }),
+                              StoreHandler::cmd_check_txn_status_done);
+        assert_eq!(MessageType::CmdCheckTxnStatus, resp.get_field_type());
+        let cmd = resp.get_cmd_check_txn_status_resp();
+        assert_eq!(cmd.get_lock_ttl(), 100);
+        assert_eq!(cmd.get_commit_version(), 0);
+
+        let resp = build_resp(Ok(TxnStatus::Committed { commit_ts: 10 }),
+                              StoreHandler::cmd_check_txn_status_done);
+        let cmd = resp.get_cmd_check_txn_status_resp();
+        assert_eq!(cmd.get_lock_ttl(), 0);
+        assert_eq!(cmd.get_commit_version(), 10);
+
+        let resp = build_resp(Ok(TxnStatus::RolledBack),
+                              StoreHandler::cmd_check_txn_status_done);
+        let cmd = resp.get_cmd_check_txn_status_resp();
+        assert_eq!(cmd.get_lock_ttl(), 0);
+        assert_eq!(cmd.get_commit_version(), 0);
+        assert!(!cmd.has_error());
+    }
+
+    #[test]
+    fn test_mvcc_get_by_key_done() {
+        let mvcc = MvccInfo {
+            lock: Some(mvcc::Lock::new(mvcc::LockType::Put, b"k".to_vec(), 20, 0, 0, None)),
+            writes: vec![(15, mvcc::Write::new(mvcc::WriteType::Put, 10, Some(b"v".to_vec()))),
+                         (8, mvcc::Write::new(mvcc::WriteType::Rollback, 8, None))],
+            values: vec![(20, b"v20".to_vec())],
+        };
+        let resp = build_resp(Ok(mvcc), StoreHandler::cmd_mvcc_get_by_key_done);
+        assert_eq!(MessageType::CmdMvccGetByKey, resp.get_field_type());
+        let cmd = resp.get_cmd_mvcc_get_by_key_resp();
+        assert!(cmd.get_error().is_empty());
+        let info = cmd.get_info();
+        assert_eq!(info.get_lock().get_field_type(), Op::Put);
+        assert_eq!(info.get_lock().get_start_ts(), 20);
+        assert_eq!(info.get_lock().get_primary(), b"k");
+        let writes = info.get_writes();
+        assert_eq!(writes.len(), 2);
+        assert_eq!((writes[0].get_commit_ts(), writes[0].get_start_ts()), (15, 10));
+        assert_eq!(writes[0].get_short_value(), b"v");
+        assert_eq!(writes[1].get_field_type(), Op::Rollback);
+        assert_eq!(info.get_values().len(), 1);
+        assert_eq!(info.get_values()[0].get_value(), b"v20");
+
+        let resp = build_resp(Ok(None), StoreHandler::cmd_mvcc_get_by_start_ts_done);
+        assert_eq!(MessageType::CmdMvccGetByStartTs, resp.get_field_type());
+        let cmd = resp.get_cmd_mvcc_get_by_start_ts_resp();
+        assert!(cmd.get_key().is_empty());
+        assert!(!cmd.has_info());
+    }
+
+    #[test]
+    fn test_raw_get_done() {
+        let resp = build_resp(Ok(Some(b"v".to_vec())), StoreHandler::cmd_raw_get_done);
+        assert_eq!(MessageType::CmdRawGet, resp.get_field_type());
+        let cmd = resp.get_cmd_raw_get_resp();
+        assert_eq!(cmd.get_value(), b"v");
+        assert!(cmd.get_error().is_empty());
+
+        let resp = build_resp(Err(box_err!("error")), StoreHandler::cmd_raw_get_done);
+        let cmd = resp.get_cmd_raw_get_resp();
+        assert!(cmd.get_value().is_empty());
+        assert!(!cmd.get_error().is_empty());
+    }
+
+    #[test]
+    fn test_raw_put_done() {
+        let resp = build_resp(Ok(()), StoreHandler::cmd_raw_put_done);
+        assert_eq!(MessageType::CmdRawPut, resp.get_field_type());
+        assert!(resp.get_cmd_raw_put_resp().get_error().is_empty());
+
+        let resp = build_resp(Err(box_err!("error")), StoreHandler::cmd_raw_put_done);
+        assert!(!resp.get_cmd_raw_put_resp().get_error().is_empty());
+    }
+
     #[test]
     fn test_get_not_leader() {
         let mut leader_info = NotLeader::new();
@@ -676,11 +1229,32 @@ mod tests {
         assert_eq!(region_err.get_not_leader(), &leader_info);
     }
 
-    fn make_lock_error<T>(key: Vec<u8>, primary: Vec<u8>, ts: u64) -> StorageResult<T> {
+    #[test]
+    fn test_prewrite_sched_too_busy() {
+        let storage_res = Err(storage::Error::SchedTooBusy);
+        let resp = build_resp(storage_res, StoreHandler::cmd_prewrite_done);
+        assert!(resp.has_region_error());
+        assert!(resp.get_region_error().has_server_is_busy());
+    }
+
+    #[test]
+    fn test_get_done_read_blocked() {
+        let err = txn::Error::ReadBlocked {
+            ts: 10,
+            commit_ts: 5,
+        };
+        let resp = build_resp(Err(storage::Error::from(err)), StoreHandler::cmd_get_done);
+        assert!(!resp.has_region_error());
+        let key_error = resp.get_cmd_get_resp().get_error();
+        assert!(!key_error.get_retryable().is_empty());
+    }
+
+    fn make_lock_error<T>(key: Vec<u8>, primary: Vec<u8>, ts: u64, ttl: u64) -> StorageResult<T> {
         Err(mvcc::Error::KeyIsLocked {
                 key: key,
                 primary: primary,
                 ts: ts,
+                ttl: ttl,
             })
             .map_err(txn::Error::from)
             .map_err(storage::Error::from)
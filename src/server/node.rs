@@ -21,18 +21,23 @@ use pd::{INVALID_ID, PdClient, Error as PdError};
 use kvproto::raft_serverpb::StoreIdent;
 use kvproto::metapb;
 use util::transport::SendCh;
+use util::worker::{Worker, Scheduler};
 use raftstore::store::{self, Msg, Store, Config as StoreConfig, keys, Peekable, Transport,
-                       SnapManager};
+                       SnapManager, CdcTask};
 use super::Result;
 use super::config::Config;
-use storage::{Storage, RaftKv};
-use super::transport::RaftStoreRouter;
-
-pub fn create_raft_storage<S>(router: S, db: Arc<DB>, cfg: &Config) -> Result<Storage>
+use storage::{Storage, RaftKv, MaxReadTs};
+use super::transport::{RaftStoreRouter, ServerRaftStoreRouter};
+
+pub fn create_raft_storage<S>(router: S,
+                              db: Arc<DB>,
+                              max_read_ts: MaxReadTs,
+                              cfg: &Config)
+                              -> Result<Storage>
     where S: RaftStoreRouter + 'static
 {
     let engine = box RaftKv::new(db, router);
-    let store = try!(Storage::from_engine(engine, &cfg.storage));
+    let store = try!(Storage::from_engine_with_max_read_ts(engine, &cfg.storage, max_read_ts));
     Ok(store)
 }
 
@@ -44,6 +49,11 @@ pub struct Node<C: PdClient + 'static> {
     store_cfg: StoreConfig,
     store_handle: Option<thread::JoinHandle<()>>,
     ch: SendCh<Msg>,
+    // The cdc worker is moved to the store once it's started.
+    cdc_worker: Option<Worker<CdcTask>>,
+    cdc_scheduler: Scheduler<CdcTask>,
+    // Shared by the store and the storage, the store seeds the regions it leads.
+    max_read_ts: MaxReadTs,
 
     pd_client: Arc<C>,
 }
@@ -66,6 +76,8 @@ impl<C> Node<C>
         }
 
         let ch = SendCh::new(event_loop.channel());
+        let cdc_worker = Worker::new("cdc worker");
+        let cdc_scheduler = cdc_worker.scheduler();
         Node {
             cluster_id: cfg.cluster_id,
             store: store,
@@ -73,9 +85,20 @@ impl<C> Node<C>
             store_handle: None,
             pd_client: pd_client,
             ch: ch,
+            cdc_worker: Some(cdc_worker),
+            cdc_scheduler: cdc_scheduler,
+            max_read_ts: MaxReadTs::with_seed(),
         }
     }
 
+    pub fn cdc_scheduler(&self) -> Scheduler<CdcTask> {
+        self.cdc_scheduler.clone()
+    }
+
+    pub fn max_read_ts(&self) -> MaxReadTs {
+        self.max_read_ts.clone()
+    }
+
     pub fn start<T>(&mut self,
                     event_loop: EventLoop<Store<T, C>>,
                     engine: Arc<DB>,
@@ -210,10 +233,24 @@ impl<C> Node<C>
         let pd_client = self.pd_client.clone();
         let store = self.store.clone();
         let ch = event_loop.channel();
+        let cdc_worker = self.cdc_worker.take().unwrap();
+        // The workers of the store write through raft like the storage does.
+        let kv_engine = box RaftKv::new(db.clone(), ServerRaftStoreRouter::new(self.ch.clone()));
+        let max_read_ts = self.max_read_ts.clone();
 
         let builder = thread::Builder::new().name(thd_name!(format!("raftstore-{}", store_id)));
         let h = try!(builder.spawn(move || {
-            let mut store = Store::new(ch, store, cfg, db, trans, pd_client, snap_mgr).unwrap();
+            let mut store = Store::new(ch,
+                                       store,
+                                       cfg,
+                                       db,
+                                       kv_engine,
+                                       trans,
+                                       pd_client,
+                                       snap_mgr,
+                                       cdc_worker,
+                                       max_read_ts)
+                .unwrap();
             if let Err(e) = store.run(&mut event_loop) {
                 error!("store {} run err {:?}", store_id, e);
             };
@@ -22,13 +22,16 @@ use mio::tcp::{TcpListener, TcpStream};
 
 use kvproto::raft_cmdpb::RaftCmdRequest;
 use kvproto::msgpb::{MessageType, Message};
+use kvproto::cdcpb::{ChangeDataRequest, ChangeDataEvent};
+use kvproto::backuppb::{BackupRequest, BackupResponse};
+use kvproto::importpb::{UploadSstRequest, UploadSstResponse};
 use super::{Msg, ConnData};
 use super::conn::Conn;
 use super::{Result, OnResponse, Config};
-use util::worker::{Stopped, Worker};
+use util::worker::{Stopped, Worker, Scheduler};
 use util::transport::SendCh;
 use storage::Storage;
-use raftstore::store::SnapManager;
+use raftstore::store::{SnapManager, CdcTask, OnChangeData};
 use super::kv::StoreHandler;
 use super::coprocessor::{RequestTask, EndPointHost, EndPointTask};
 use super::transport::RaftStoreRouter;
@@ -85,6 +88,9 @@ pub struct Server<T: RaftStoreRouter + 'static, S: StoreAddrResolver> {
     snap_mgr: SnapManager,
     snap_worker: Worker<SnapTask>,
 
+    // None if the server has no raft store to observe.
+    cdc_scheduler: Option<Scheduler<CdcTask>>,
+
     resolver: S,
 
     cfg: Config,
@@ -102,7 +108,8 @@ impl<T: RaftStoreRouter, S: StoreAddrResolver> Server<T, S> {
                storage: Storage,
                raft_router: T,
                resolver: S,
-               snap_mgr: SnapManager)
+               snap_mgr: SnapManager,
+               cdc_scheduler: Option<Scheduler<CdcTask>>)
                -> Result<Server<T, S>> {
         try!(event_loop.register(&listener,
                                  SERVER_TOKEN,
@@ -126,6 +133,7 @@ impl<T: RaftStoreRouter, S: StoreAddrResolver> Server<T, S> {
             end_point_worker: end_point_worker,
             snap_mgr: snap_mgr,
             snap_worker: snap_worker,
+            cdc_scheduler: cdc_scheduler,
             resolver: resolver,
             cfg: cfg.clone(),
         };
@@ -134,7 +142,9 @@ impl<T: RaftStoreRouter, S: StoreAddrResolver> Server<T, S> {
     }
 
     pub fn run(&mut self, event_loop: &mut EventLoop<Self>) -> Result<()> {
-        let end_point = EndPointHost::new(self.store.engine(), self.end_point_worker.scheduler());
+        let end_point = EndPointHost::new(self.store.engine(),
+                                          self.store.max_read_ts(),
+                                          self.end_point_worker.scheduler());
         box_try!(self.end_point_worker.start_batch(end_point, DEFAULT_COPROCESSOR_BATCH));
 
         let ch = self.get_sendch();
@@ -175,6 +185,13 @@ impl<T: RaftStoreRouter, S: StoreAddrResolver> Server<T, S> {
                 }
 
                 conn.close();
+
+                if let Some(ref scheduler) = self.cdc_scheduler {
+                    let task = CdcTask::Deregister { conn_id: token.as_usize() };
+                    if let Err(e) = scheduler.schedule(task) {
+                        error!("failed to deregister cdc for token {:?}: {:?}", token, e);
+                    }
+                }
             }
             None => {
                 debug!("missing connection for token {}", token.as_usize());
@@ -255,6 +272,11 @@ impl<T: RaftStoreRouter, S: StoreAddrResolver> Server<T, S> {
                 box_try!(self.end_point_worker.schedule(EndPointTask::Request(req)));
                 Ok(())
             }
+            MessageType::CdcReq => self.on_cdc_request(msg.take_cdc_req(), token, msg_id),
+            MessageType::BackupReq => self.on_backup(msg.take_backup_req(), token, msg_id),
+            MessageType::UploadSstReq => {
+                self.on_upload_sst(msg.take_upload_sst_req(), token, msg_id)
+            }
             _ => {
                 Err(box_err!("unsupported message {:?} for token {:?} with msg id {}",
                              msg_type,
@@ -281,6 +303,90 @@ impl<T: RaftStoreRouter, S: StoreAddrResolver> Server<T, S> {
         Ok(())
     }
 
+    fn on_backup(&mut self, mut req: BackupRequest, token: Token, msg_id: u64) -> Result<()> {
+        let on_resp = self.make_response_cb(token, msg_id);
+        let cb = box move |res| {
+            let mut resp = BackupResponse::new();
+            match res {
+                Ok(meta) => resp.set_meta(meta),
+                Err(e) => resp.set_error(format!("{:?}", e)),
+            }
+            let mut resp_msg = Message::new();
+            resp_msg.set_msg_type(MessageType::BackupResp);
+            resp_msg.set_backup_resp(resp);
+            on_resp.call_box((resp_msg,));
+        };
+        try!(self.raft_router.backup(req.get_backup_ts(), req.take_path(), cb));
+        Ok(())
+    }
+
+    fn on_upload_sst(&mut self,
+                     mut req: UploadSstRequest,
+                     token: Token,
+                     msg_id: u64)
+                     -> Result<()> {
+        let on_resp = self.make_response_cb(token, msg_id);
+        let cb = box move |res: Result<()>| {
+            let mut resp = UploadSstResponse::new();
+            if let Err(e) = res {
+                resp.set_error(format!("{:?}", e));
+            }
+            let mut resp_msg = Message::new();
+            resp_msg.set_msg_type(MessageType::UploadSstResp);
+            resp_msg.set_upload_sst_resp(resp);
+            on_resp.call_box((resp_msg,));
+        };
+        let task = SnapTask::UploadSst {
+            meta: req.take_meta(),
+            data: req.take_data(),
+            cb: cb,
+        };
+        if let Err(Stopped(SnapTask::UploadSst { cb, .. })) = self.snap_worker.schedule(task) {
+            cb(Err(box_err!("failed to upload sst: snap worker is stopped")));
+        }
+        Ok(())
+    }
+
+    fn on_cdc_request(&mut self,
+                      mut req: ChangeDataRequest,
+                      token: Token,
+                      msg_id: u64)
+                      -> Result<()> {
+        let ch = self.sendch.clone();
+        let on_change: OnChangeData = box move |event: ChangeDataEvent| {
+            let mut resp_msg = Message::new();
+            resp_msg.set_msg_type(MessageType::CdcResp);
+            resp_msg.set_cdc_resp(event);
+            if let Err(e) = ch.send(Msg::WriteData {
+                token: token,
+                data: ConnData::new(msg_id, resp_msg),
+            }) {
+                error!("send cdc event failed with token {:?}, msg id {}, err {:?}",
+                       token,
+                       msg_id,
+                       e);
+            }
+        };
+        let scheduler = match self.cdc_scheduler {
+            Some(ref scheduler) => scheduler,
+            None => {
+                let mut event = ChangeDataEvent::new();
+                event.set_region_id(req.get_context().get_region_id());
+                event.set_error("cdc is not supported".to_owned());
+                on_change(event);
+                return Ok(());
+            }
+        };
+        let task = CdcTask::Register {
+            checkpoint_ts: req.get_checkpoint_ts(),
+            ctx: req.take_context(),
+            conn_id: token.as_usize(),
+            on_change: on_change,
+        };
+        box_try!(scheduler.schedule(task));
+        Ok(())
+    }
+
     fn on_readable(&mut self, event_loop: &mut EventLoop<Self>, token: Token) {
         match token {
             SERVER_TOKEN => {
@@ -679,7 +785,8 @@ mod tests {
                                      storage,
                                      TestRaftStoreRouter { tx: tx },
                                      resolver,
-                                     store::new_snap_mgr("", None))
+                                     store::new_snap_mgr("", None),
+                                     None)
             .unwrap();
 
         let ch = server.get_sendch();
@@ -25,7 +25,7 @@ use mio::Token;
 
 use super::{Result, ConnData, Msg};
 use super::transport::RaftStoreRouter;
-use raftstore::store::{SnapFile, SnapManager, SnapKey, SnapEntry};
+use raftstore::store::{SnapFile, SnapManager, SnapKey, SnapEntry, SstImporter};
 use util::worker::Runnable;
 use util::codec::rpc;
 use util::buf::PipeBuffer;
@@ -33,6 +33,7 @@ use util::HandyRwLock;
 use util::transport::SendCh;
 
 use kvproto::raft_serverpb::RaftMessage;
+use kvproto::importpb::SstMeta;
 
 pub type Callback = Box<FnBox(Result<()>) + Send>;
 
@@ -46,7 +47,8 @@ const DEFAULT_WRITE_TIMEOUT: u64 = 30;
 /// `Write` write data to snapshot file;
 /// `Close` save the snapshot file;
 /// `Discard` discard all the unsaved changes made to snapshot file;
-/// `SendTo` send the snapshot file to specified address.
+/// `SendTo` send the snapshot file to specified address;
+/// `UploadSst` save an uploaded SST file to be ingested.
 pub enum Task {
     Register(Token, RaftMessage),
     Write(Token, PipeBuffer),
@@ -57,6 +59,11 @@ pub enum Task {
         data: ConnData,
         cb: Callback,
     },
+    UploadSst {
+        meta: SstMeta,
+        data: Vec<u8>,
+        cb: Callback,
+    },
 }
 
 impl Display for Task {
@@ -69,6 +76,7 @@ impl Display for Task {
             Task::SendTo { ref addr, ref data, .. } => {
                 write!(f, "SendTo Snap[to: {}, snap: {:?}]", addr, data.msg)
             }
+            Task::UploadSst { ref meta, .. } => write!(f, "UploadSst {:?}", meta),
         }
     }
 }
@@ -219,6 +227,14 @@ impl<R: RaftStoreRouter + 'static> Runnable<Task> for Runner<R> {
                     cb(res)
                 });
             }
+            Task::UploadSst { meta, data, cb } => {
+                let importer = SstImporter::new(self.snap_mgr.rl().import_dir());
+                let res = importer.upload(&meta, &data).map_err(From::from);
+                if let Err(ref e) = res {
+                    error!("failed to upload sst {:?}: {:?}", meta, e);
+                }
+                cb(res)
+            }
         }
     }
 }
@@ -14,7 +14,7 @@
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use raftstore::store::{Msg as StoreMsg, Transport, Callback};
+use raftstore::store::{Msg as StoreMsg, Transport, Callback, BackupCallback};
 use raftstore::Result as RaftStoreResult;
 use kvproto::raft_serverpb::RaftMessage;
 use kvproto::msgpb::{Message, MessageType};
@@ -59,6 +59,15 @@ pub trait RaftStoreRouter: Send + Clone {
             to_peer_id: to_peer_id,
         })
     }
+
+    // Backup the regions led by local store to `path`.
+    fn backup(&self, backup_ts: u64, path: String, cb: BackupCallback) -> RaftStoreResult<()> {
+        self.send(StoreMsg::Backup {
+            backup_ts: backup_ts,
+            path: path,
+            callback: cb,
+        })
+    }
 }
 
 #[derive(Clone)]
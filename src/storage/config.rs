@@ -16,6 +16,13 @@ const DEFAULT_SCHED_CAPACITY: usize = 10240;
 const DEFAULT_SCHED_MSG_PER_TICK: usize = 1024;
 const DEFAULT_SCHED_CONCURRENCY: usize = 1024;
 const DEFAULT_SCHED_WORKER_POOL_SIZE: usize = 4;
+// 0 means commands never wait for locks.
+const DEFAULT_SCHED_LOCK_WAIT_TIMEOUT: u64 = 0;
+const DEFAULT_READ_POOL_HIGH_CONCURRENCY: usize = 4;
+const DEFAULT_READ_POOL_LOW_CONCURRENCY: usize = 4;
+const DEFAULT_SCHED_TOO_BUSY_THRESHOLD: usize = 1000;
+// 100 MB
+const DEFAULT_SCHED_PENDING_WRITE_THRESHOLD: usize = 100 * 1024 * 1024;
 
 #[derive(Clone, Debug)]
 pub struct Config {
@@ -24,6 +31,15 @@ pub struct Config {
     pub sched_msg_per_tick: usize,
     pub sched_concurrency: usize,
     pub sched_worker_pool_size: usize,
+    // in milliseconds
+    pub sched_lock_wait_timeout: u64,
+    // new writes are rejected if the running write commands exceed the count
+    // or their total bytes exceed the pending write threshold.
+    pub sched_too_busy_threshold: usize,
+    pub sched_pending_write_threshold: usize,
+    // thread count of the read pool for point reads and scans.
+    pub read_pool_high_concurrency: usize,
+    pub read_pool_low_concurrency: usize,
 }
 
 impl Default for Config {
@@ -34,6 +50,11 @@ impl Default for Config {
             sched_msg_per_tick: DEFAULT_SCHED_MSG_PER_TICK,
             sched_concurrency: DEFAULT_SCHED_CONCURRENCY,
             sched_worker_pool_size: DEFAULT_SCHED_WORKER_POOL_SIZE,
+            sched_lock_wait_timeout: DEFAULT_SCHED_LOCK_WAIT_TIMEOUT,
+            sched_too_busy_threshold: DEFAULT_SCHED_TOO_BUSY_THRESHOLD,
+            sched_pending_write_threshold: DEFAULT_SCHED_PENDING_WRITE_THRESHOLD,
+            read_pool_high_concurrency: DEFAULT_READ_POOL_HIGH_CONCURRENCY,
+            read_pool_low_concurrency: DEFAULT_READ_POOL_LOW_CONCURRENCY,
         }
     }
 }
@@ -0,0 +1,329 @@
+// Copyright 2016 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap};
+use std::collections::Bound::{self, Included, Excluded, Unbounded};
+use std::fmt::{self, Formatter, Debug};
+use std::sync::{Arc, RwLock};
+use std::u64;
+use kvproto::kvrpcpb::Context;
+use storage::{Key, Value, CfName, CF_DEFAULT};
+use util::escape;
+use util::HandyRwLock;
+use super::{Engine, Snapshot, Modify, Cursor, Callback, Result};
+
+// The versions of a key written by each batch, the newest is the last one and
+// None means the key is deleted.
+type Versions = Vec<(u64, Option<Value>)>;
+type CfMap = BTreeMap<Vec<u8>, Versions>;
+
+struct Inner {
+    cfs: HashMap<CfName, CfMap>,
+    // sequence number of the last written batch.
+    seq: u64,
+    // sequence number -> count of the alive snapshots.
+    snapshots: BTreeMap<u64, usize>,
+}
+
+/// Get the value of the key seen by the snapshot at `seq`.
+fn visible(versions: &Versions, seq: u64) -> Option<&Value> {
+    versions.iter().rev().find(|v| v.0 <= seq).and_then(|v| v.1.as_ref())
+}
+
+impl Inner {
+    fn add_version(&mut self, cf: CfName, key: &[u8], value: Option<Value>) {
+        let (seq, oldest) = (self.seq, self.snapshots.keys().next().cloned());
+        let remove = {
+            let versions = self.cfs
+                .get_mut(cf)
+                .unwrap()
+                .entry(key.to_vec())
+                .or_insert_with(Vec::new);
+            versions.push((seq, value));
+            // Only the versions seen by the oldest snapshot and the later ones are kept.
+            let oldest = oldest.unwrap_or(u64::MAX);
+            if let Some(pos) = versions.iter().rposition(|v| v.0 <= oldest) {
+                versions.drain(..pos);
+            }
+            versions.len() == 1 && versions[0].1.is_none()
+        };
+        if remove {
+            self.cfs.get_mut(cf).unwrap().remove(key);
+        }
+    }
+}
+
+/// `EngineMemory` keeps every column family in an ordered map.
+///
+/// Every key keeps the versions written by different batches, a snapshot only
+/// sees the versions written before it's taken. The versions that no snapshot
+/// can see are dropped by the later writes of the same key. It's not persistent,
+/// and is mainly used by tests.
+pub struct EngineMemory {
+    inner: Arc<RwLock<Inner>>,
+}
+
+impl EngineMemory {
+    pub fn new(cfs: &[CfName]) -> EngineMemory {
+        let mut maps = HashMap::new();
+        maps.insert(CF_DEFAULT, CfMap::new());
+        for cf in cfs {
+            maps.insert(*cf, CfMap::new());
+        }
+        let inner = Inner {
+            cfs: maps,
+            seq: 0,
+            snapshots: BTreeMap::new(),
+        };
+        EngineMemory { inner: Arc::new(RwLock::new(inner)) }
+    }
+}
+
+impl Debug for EngineMemory {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Memory [cfs: {}]", self.inner.rl().cfs.len())
+    }
+}
+
+fn write_modifies(inner: &mut Inner, modifies: Vec<Modify>) -> Result<()> {
+    // Check all the column families first, so a batch is either applied
+    // entirely or not at all.
+    for m in &modifies {
+        match *m {
+            Modify::Delete(cf, _) |
+            Modify::Put(cf, _, _) => {
+                if !inner.cfs.contains_key(cf) {
+                    return Err(box_err!("cf {} not found", cf));
+                }
+            }
+            Modify::DeleteRange(..) => {}
+        }
+    }
+
+    inner.seq += 1;
+    for m in modifies {
+        match m {
+            Modify::Delete(cf, k) => {
+                trace!("EngineMemory: delete_cf {} {}", cf, k);
+                inner.add_version(cf, k.encoded(), None);
+            }
+            Modify::Put(cf, k, v) => {
+                trace!("EngineMemory: put_cf {}, {}, {}", cf, k, escape(&v));
+                inner.add_version(cf, k.encoded(), Some(v));
+            }
+            Modify::DeleteRange(start_key, end_key) => {
+                trace!("EngineMemory: delete_range {} {}", start_key, end_key);
+                let (start, end) = (start_key.encoded().as_slice(), end_key.encoded().as_slice());
+                let cfs: Vec<_> = inner.cfs.keys().cloned().collect();
+                for cf in cfs {
+                    let keys: Vec<Vec<u8>> = inner.cfs[cf]
+                        .range::<[u8], [u8]>(Included(start), Excluded(end))
+                        .filter(|&(_, versions)| visible(versions, u64::MAX).is_some())
+                        .map(|(k, _)| k.clone())
+                        .collect();
+                    for k in keys {
+                        inner.add_version(cf, &k, None);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Engine for EngineMemory {
+    fn async_write(&self, _: &Context, modifies: Vec<Modify>, cb: Callback<()>) -> Result<()> {
+        let res = write_modifies(&mut self.inner.wl(), modifies);
+        cb(res);
+        Ok(())
+    }
+
+    fn async_snapshot(&self, _: &Context, cb: Callback<Box<Snapshot>>) -> Result<()> {
+        let seq = {
+            let mut inner = self.inner.wl();
+            let seq = inner.seq;
+            *inner.snapshots.entry(seq).or_insert(0) += 1;
+            seq
+        };
+        let snap = MemorySnapshot {
+            inner: self.inner.clone(),
+            seq: seq,
+        };
+        cb(Ok(box snap));
+        Ok(())
+    }
+
+    fn clone(&self) -> Box<Engine> {
+        box EngineMemory { inner: self.inner.clone() }
+    }
+}
+
+pub struct MemorySnapshot {
+    inner: Arc<RwLock<Inner>>,
+    seq: u64,
+}
+
+impl MemorySnapshot {
+    /// Find the first key visible to the snapshot in the range, the range is
+    /// scanned backward if `reverse` is true.
+    fn find(&self,
+            cf: CfName,
+            lower: Bound<&[u8]>,
+            upper: Bound<&[u8]>,
+            reverse: bool)
+            -> Option<(Vec<u8>, Value)> {
+        let inner = self.inner.rl();
+        let map = &inner.cfs[cf];
+        let mut iter = map.range::<[u8], [u8]>(lower, upper);
+        let mut next = || if reverse { iter.next_back() } else { iter.next() };
+        while let Some((k, versions)) = next() {
+            if let Some(v) = visible(versions, self.seq) {
+                return Some((k.clone(), v.clone()));
+            }
+        }
+        None
+    }
+}
+
+impl Drop for MemorySnapshot {
+    fn drop(&mut self) {
+        let mut inner = self.inner.wl();
+        let remove = {
+            let count = inner.snapshots.get_mut(&self.seq).unwrap();
+            *count -= 1;
+            *count == 0
+        };
+        if remove {
+            inner.snapshots.remove(&self.seq);
+        }
+    }
+}
+
+impl Snapshot for MemorySnapshot {
+    fn get(&self, key: &Key) -> Result<Option<Value>> {
+        self.get_cf(CF_DEFAULT, key)
+    }
+
+    fn get_cf(&self, cf: CfName, key: &Key) -> Result<Option<Value>> {
+        trace!("MemorySnapshot: get_cf {} {}", cf, key);
+        let inner = self.inner.rl();
+        match inner.cfs.get(cf) {
+            Some(map) => Ok(map.get(key.encoded()).and_then(|v| visible(v, self.seq)).cloned()),
+            None => Err(box_err!("cf {} not found", cf)),
+        }
+    }
+
+    #[allow(needless_lifetimes)]
+    fn iter<'b>(&'b self) -> Result<Box<Cursor + 'b>> {
+        self.iter_cf(CF_DEFAULT)
+    }
+
+    #[allow(needless_lifetimes)]
+    fn iter_cf<'b>(&'b self, cf: CfName) -> Result<Box<Cursor + 'b>> {
+        trace!("MemorySnapshot: create cf iterator");
+        if !self.inner.rl().cfs.contains_key(cf) {
+            return Err(box_err!("cf {} not found", cf));
+        }
+        Ok(box MemoryCursor {
+            snap: self,
+            cf: cf,
+            cur: None,
+        })
+    }
+}
+
+/// `MemoryCursor` remembers the current entry, and every move is a lookup
+/// in the map starting from it.
+pub struct MemoryCursor<'a> {
+    snap: &'a MemorySnapshot,
+    cf: CfName,
+    cur: Option<(Vec<u8>, Value)>,
+}
+
+impl<'a> Cursor for MemoryCursor<'a> {
+    fn next(&mut self) -> bool {
+        self.cur = match self.cur.take() {
+            Some((k, _)) => self.snap.find(self.cf, Excluded(k.as_slice()), Unbounded, false),
+            None => None,
+        };
+        self.valid()
+    }
+
+    fn prev(&mut self) -> bool {
+        self.cur = match self.cur.take() {
+            Some((k, _)) => self.snap.find(self.cf, Unbounded, Excluded(k.as_slice()), true),
+            None => None,
+        };
+        self.valid()
+    }
+
+    fn seek(&mut self, key: &Key) -> Result<bool> {
+        self.cur = self.snap.find(self.cf, Included(key.encoded().as_slice()), Unbounded, false);
+        Ok(self.valid())
+    }
+
+    fn seek_to_first(&mut self) -> bool {
+        self.cur = self.snap.find(self.cf, Unbounded, Unbounded, false);
+        self.valid()
+    }
+
+    fn seek_to_last(&mut self) -> bool {
+        self.cur = self.snap.find(self.cf, Unbounded, Unbounded, true);
+        self.valid()
+    }
+
+    fn valid(&self) -> bool {
+        self.cur.is_some()
+    }
+
+    fn key(&self) -> &[u8] {
+        &self.cur.as_ref().unwrap().0
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.cur.as_ref().unwrap().1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kvproto::kvrpcpb::Context;
+    use storage::{make_key, CF_DEFAULT};
+    use storage::engine::Engine;
+    use util::HandyRwLock;
+    use super::EngineMemory;
+
+    fn versions(engine: &EngineMemory, key: &[u8]) -> usize {
+        engine.inner.rl().cfs[CF_DEFAULT].get(make_key(key).encoded()).map_or(0, |v| v.len())
+    }
+
+    #[test]
+    fn test_drop_versions() {
+        let engine = EngineMemory::new(&[]);
+        let ctx = Context::new();
+        engine.put(&ctx, make_key(b"k"), b"1".to_vec()).unwrap();
+        engine.put(&ctx, make_key(b"k"), b"2".to_vec()).unwrap();
+        assert_eq!(versions(&engine, b"k"), 1);
+
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        engine.put(&ctx, make_key(b"k"), b"3".to_vec()).unwrap();
+        engine.delete(&ctx, make_key(b"k")).unwrap();
+        assert_eq!(versions(&engine, b"k"), 3);
+        assert_eq!(snapshot.get(&make_key(b"k")).unwrap().unwrap(), b"2");
+
+        // The versions no snapshot can see are dropped by the next write.
+        drop(snapshot);
+        engine.delete(&ctx, make_key(b"k")).unwrap();
+        assert_eq!(versions(&engine, b"k"), 0);
+    }
+}
@@ -18,11 +18,13 @@ use std::boxed::FnBox;
 use std::time::Duration;
 
 use self::rocksdb::EngineRocksdb;
+use self::memory::EngineMemory;
 use storage::{Key, Value, CfName, CF_DEFAULT};
 use kvproto::kvrpcpb::Context;
 use kvproto::errorpb::Error as ErrorHeader;
 
 mod rocksdb;
+mod memory;
 pub mod raftkv;
 
 // only used for rocksdb without persistent.
@@ -37,6 +39,8 @@ pub type Callback<T> = Box<FnBox(Result<T>) + Send>;
 pub enum Modify {
     Delete(CfName, Key),
     Put(CfName, Key, Value),
+    // Delete all keys in [start_key, end_key) of every column family.
+    DeleteRange(Key, Key),
 }
 
 pub trait Engine: Send + Debug {
@@ -71,6 +75,10 @@ pub trait Engine: Send + Debug {
         self.write(ctx, vec![Modify::Delete(cf, key)])
     }
 
+    fn delete_range(&self, ctx: &Context, start_key: Key, end_key: Key) -> Result<()> {
+        self.write(ctx, vec![Modify::DeleteRange(start_key, end_key)])
+    }
+
     /// Create a share Engine pointer.
     fn clone(&self) -> Box<Engine + 'static>;
 }
@@ -95,11 +103,20 @@ pub trait Cursor {
     fn key(&self) -> &[u8];
     fn value(&self) -> &[u8];
 
+    /// Check whether the key can be sought by the cursor.
+    ///
+    /// A cursor that only sees part of the keys, like the cursor of a region,
+    /// should return an error for the keys out of its range.
+    fn check_key(&self, _: &Key) -> Result<()> {
+        Ok(())
+    }
+
     /// Seek the specified key.
     ///
     /// This method assume the current position of cursor is
     /// around `key`, otherwise you should use `seek` instead.
     fn near_seek(&mut self, key: &Key) -> Result<bool> {
+        try!(self.check_key(key));
         if !self.valid() {
             return self.seek(key);
         }
@@ -127,7 +144,6 @@ pub trait Cursor {
                 self.seek_to_first();
             }
         }
-        // TODO: check region range.
         Ok(self.valid())
     }
 
@@ -174,15 +190,17 @@ pub trait Cursor {
 pub enum Dsn<'a> {
     RocksDBPath(&'a str),
     RaftKv,
+    // Not persistent, mainly used by tests.
+    Memory,
 }
 
-// Now we only support RocksDB.
 pub fn new_engine(dsn: Dsn, cfs: &[CfName]) -> Result<Box<Engine>> {
     match dsn {
         Dsn::RocksDBPath(path) => {
             EngineRocksdb::new(path, cfs).map(|engine| -> Box<Engine> { Box::new(engine) })
         }
         Dsn::RaftKv => unimplemented!(),
+        Dsn::Memory => Ok(Box::new(EngineMemory::new(cfs))),
     }
 }
 
@@ -238,6 +256,23 @@ mod tests {
         test_near_seek(e.as_ref());
         test_cf(e.as_ref());
         test_empty_write(e.as_ref());
+        test_delete_range(e.as_ref());
+        test_snapshot_isolation(e.as_ref());
+    }
+
+    #[test]
+    fn memory() {
+        let e = new_engine(Dsn::Memory, TEST_ENGINE_CFS).unwrap();
+
+        test_get_put(e.as_ref());
+        test_batch(e.as_ref());
+        test_seek(e.as_ref());
+        test_near_seek(e.as_ref());
+        test_cf(e.as_ref());
+        test_empty_write(e.as_ref());
+        test_delete_range(e.as_ref());
+        test_snapshot_isolation(e.as_ref());
+        test_cursor(e.as_ref());
     }
 
     #[test]
@@ -380,4 +415,72 @@ mod tests {
     fn test_empty_write(engine: &Engine) {
         engine.write(&Context::new(), vec![]).unwrap();
     }
+
+    fn test_delete_range(engine: &Engine) {
+        for key in &[b"a", b"b", b"c", b"d"] {
+            must_put(engine, *key, b"v");
+            must_put_cf(engine, "cf", *key, b"v");
+        }
+        engine.delete_range(&Context::new(), make_key(b"b"), make_key(b"d")).unwrap();
+        for key in &[b"a", b"d"] {
+            assert_has(engine, *key, b"v");
+            assert_has_cf(engine, "cf", *key, b"v");
+        }
+        for key in &[b"b", b"c"] {
+            assert_none(engine, *key);
+            assert_none_cf(engine, "cf", *key);
+        }
+    }
+
+    fn test_snapshot_isolation(engine: &Engine) {
+        engine.delete_range(&Context::new(), make_key(b"a"), make_key(b"z")).unwrap();
+        must_put(engine, b"a", b"1");
+        must_put_cf(engine, "cf", b"a", b"1");
+        let snapshot = engine.snapshot(&Context::new()).unwrap();
+
+        must_put(engine, b"a", b"2");
+        must_put(engine, b"b", b"2");
+        muest_delete_cf(engine, "cf", b"a");
+        assert_eq!(snapshot.get(&make_key(b"a")).unwrap().unwrap(), b"1");
+        assert_eq!(snapshot.get(&make_key(b"b")).unwrap(), None);
+        assert_eq!(snapshot.get_cf("cf", &make_key(b"a")).unwrap().unwrap(),
+                   b"1");
+        let mut iter = snapshot.iter().unwrap();
+        assert!(iter.seek_to_first());
+        assert_eq!(iter.key(), &*bytes::encode_bytes(b"a"));
+        assert!(!iter.next());
+
+        engine.delete_range(&Context::new(), make_key(b"a"), make_key(b"c")).unwrap();
+        assert_eq!(snapshot.get(&make_key(b"a")).unwrap().unwrap(), b"1");
+        assert_none(engine, b"a");
+        assert_none(engine, b"b");
+    }
+
+    fn test_cursor(engine: &Engine) {
+        engine.delete_range(&Context::new(), make_key(b"a"), make_key(b"z")).unwrap();
+        for key in &[b"a", b"b", b"c"] {
+            must_put(engine, *key, *key);
+        }
+        let snapshot = engine.snapshot(&Context::new()).unwrap();
+        let mut iter = snapshot.iter().unwrap();
+        assert!(iter.seek_to_last());
+        assert_eq!(iter.value(), b"c");
+        assert!(iter.prev());
+        assert_eq!(iter.value(), b"b");
+        assert!(iter.prev());
+        assert_eq!(iter.value(), b"a");
+        assert!(!iter.prev());
+        assert!(iter.seek_to_first());
+        assert_eq!(iter.value(), b"a");
+        assert!(iter.next());
+        assert_eq!(iter.value(), b"b");
+        assert!(iter.seek(&make_key(b"b\x00")).unwrap());
+        assert_eq!(iter.value(), b"c");
+        assert!(!iter.next());
+
+        // An unknown cf is an error.
+        assert!(snapshot.iter_cf("unknown").is_err());
+        assert!(engine.put_cf(&Context::new(), "unknown", make_key(b"a"), b"v".to_vec())
+            .is_err());
+    }
 }
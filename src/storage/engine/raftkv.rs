@@ -17,7 +17,7 @@ use raftstore::errors::Error as RaftServerError;
 use raftstore::coprocessor::{RegionSnapshot, RegionIterator};
 use raftstore::store::engine::Peekable;
 use kvproto::raft_cmdpb::{RaftCmdRequest, RaftCmdResponse, RaftRequestHeader, Request, Response,
-                          CmdType, DeleteRequest, PutRequest};
+                          CmdType, DeleteRequest, PutRequest, DeleteRangeRequest};
 use kvproto::errorpb;
 use kvproto::kvrpcpb::Context;
 
@@ -201,6 +201,13 @@ impl<S: RaftStoreRouter> Engine for RaftKv<S> {
                     req.set_cmd_type(CmdType::Put);
                     req.set_put(put);
                 }
+                Modify::DeleteRange(start_key, end_key) => {
+                    let mut delete_range = DeleteRangeRequest::new();
+                    delete_range.set_start_key(start_key.encoded().to_owned());
+                    delete_range.set_end_key(end_key.encoded().to_owned());
+                    req.set_cmd_type(CmdType::DeleteRange);
+                    req.set_delete_range(delete_range);
+                }
             }
             reqs.push(req);
         }
@@ -297,4 +304,11 @@ impl<'a> Cursor for RegionIterator<'a> {
     fn value(&self) -> &[u8] {
         RegionIterator::value(self)
     }
+
+    fn check_key(&self, key: &Key) -> engine::Result<()> {
+        RegionIterator::should_seekable(self, key.encoded()).map_err(|e| {
+            let pb = e.into();
+            engine::Error::Request(pb)
+        })
+    }
 }
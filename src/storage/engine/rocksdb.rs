@@ -122,6 +122,19 @@ fn write_modifies(db: &DB, modifies: Vec<Modify>) -> Result<()> {
                     wb.put_cf(*handle, k.encoded(), &v)
                 }
             }
+            Modify::DeleteRange(start_key, end_key) => {
+                trace!("EngineRocksdb: delete_range {} {}", start_key, end_key);
+                // The range is deleted atomically with the other modifies.
+                let mut res = Ok(());
+                for cf in db.cf_names() {
+                    let handle = try!(rocksdb::get_cf_handle(db, cf));
+                    res = wb.delete_range_cf(*handle, start_key.encoded(), end_key.encoded());
+                    if res.is_err() {
+                        break;
+                    }
+                }
+                res
+            }
         };
         if let Err(msg) = res {
             return Err(Error::RocksDb(msg));
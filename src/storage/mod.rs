@@ -30,7 +30,8 @@ pub use self::config::Config;
 pub use self::engine::{Engine, Snapshot, Dsn, TEMP_DIR, new_engine, Modify, Cursor,
                        Error as EngineError};
 pub use self::engine::raftkv::RaftKv;
-pub use self::txn::{SnapshotStore, Scheduler, Msg};
+pub use self::txn::{SnapshotStore, Scheduler, Msg, ReadRunner, ReadTask, ReadPriority, MaxReadTs};
+pub use self::mvcc::{TxnStatus, MvccInfo};
 pub use self::types::{Key, Value, KvPair, make_key};
 pub type Callback<T> = Box<FnBox(Result<T>) + Send>;
 
@@ -45,6 +46,8 @@ pub enum Mutation {
     Put((Key, Value)),
     Delete(Key),
     Lock(Key),
+    // Put only if the key doesn't exist, otherwise prewrite fails with `AlreadyExist`.
+    Insert((Key, Value)),
 }
 
 #[allow(match_same_arms)]
@@ -54,6 +57,26 @@ impl Mutation {
             Mutation::Put((ref key, _)) => key,
             Mutation::Delete(ref key) => key,
             Mutation::Lock(ref key) => key,
+            Mutation::Insert((ref key, _)) => key,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    // ttl of the locks in milliseconds, 0 means the locks never expire.
+    pub lock_ttl: u64,
+    // If set, prewrite commits the mutations directly at this ts without
+    // leaving locks. It fails if the mutations are not in a single region or
+    // a read at a ts not less than the commit ts has been served.
+    pub one_pc_commit_ts: Option<u64>,
+}
+
+impl Options {
+    pub fn new(lock_ttl: u64) -> Options {
+        Options {
+            lock_ttl: lock_ttl,
+            one_pc_commit_ts: None,
         }
     }
 }
@@ -66,6 +89,9 @@ pub enum StorageCb {
     SingleValue(Callback<Option<Value>>),
     KvPairs(Callback<Vec<Result<KvPair>>>),
     Locks(Callback<Vec<LockInfo>>),
+    TxnStatus(Callback<TxnStatus>),
+    MvccInfoByKey(Callback<MvccInfo>),
+    MvccInfoByStartTs(Callback<Option<(Key, MvccInfo)>>),
 }
 
 #[allow(type_complexity)]
@@ -83,6 +109,15 @@ pub enum Command {
     Scan {
         ctx: Context,
         start_key: Key,
+        end_key: Option<Key>,
+        limit: usize,
+        key_only: bool,
+        start_ts: u64,
+    },
+    ReverseScan {
+        ctx: Context,
+        start_key: Key,
+        end_key: Option<Key>,
         limit: usize,
         start_ts: u64,
     },
@@ -91,6 +126,21 @@ pub enum Command {
         mutations: Vec<Mutation>,
         primary: Vec<u8>,
         start_ts: u64,
+        options: Options,
+    },
+    AcquirePessimisticLock {
+        ctx: Context,
+        keys: Vec<Key>,
+        primary: Vec<u8>,
+        start_ts: u64,
+        for_update_ts: u64,
+        options: Options,
+    },
+    PessimisticRollback {
+        ctx: Context,
+        keys: Vec<Key>,
+        start_ts: u64,
+        for_update_ts: u64,
     },
     Commit {
         ctx: Context,
@@ -124,10 +174,17 @@ pub enum Command {
         ctx: Context,
         max_ts: u64,
     },
+    CheckTxnStatus {
+        ctx: Context,
+        primary: Key,
+        lock_ts: u64,
+        current_ts: u64,
+    },
     ResolveLock {
         ctx: Context,
         start_ts: u64,
         commit_ts: Option<u64>,
+        keys: Vec<Key>,
     },
     Gc {
         ctx: Context,
@@ -135,6 +192,49 @@ pub enum Command {
         scan_key: Option<Key>,
         keys: Vec<Key>,
     },
+    RawGet {
+        ctx: Context,
+        key: Key,
+    },
+    RawBatchGet {
+        ctx: Context,
+        keys: Vec<Key>,
+    },
+    RawScan {
+        ctx: Context,
+        start_key: Key,
+        limit: usize,
+    },
+    RawPut {
+        ctx: Context,
+        key: Key,
+        value: Value,
+    },
+    RawBatchPut {
+        ctx: Context,
+        pairs: Vec<(Key, Value)>,
+    },
+    RawDelete {
+        ctx: Context,
+        key: Key,
+    },
+    RawBatchDelete {
+        ctx: Context,
+        keys: Vec<Key>,
+    },
+    DeleteRange {
+        ctx: Context,
+        start_key: Key,
+        end_key: Key,
+    },
+    MvccGetByKey {
+        ctx: Context,
+        key: Key,
+    },
+    MvccGetByStartTs {
+        ctx: Context,
+        start_ts: u64,
+    },
 }
 
 impl fmt::Display for Command {
@@ -146,10 +246,19 @@ impl fmt::Display for Command {
             Command::BatchGet { ref keys, start_ts, .. } => {
                 write!(f, "kv::command_batch_get {} @ {}", keys.len(), start_ts)
             }
-            Command::Scan { ref start_key, limit, start_ts, .. } => {
+            Command::Scan { ref start_key, ref end_key, limit, start_ts, .. } => {
+                write!(f,
+                       "kv::command::scan {}-{:?}({}) @ {}",
+                       start_key,
+                       end_key,
+                       limit,
+                       start_ts)
+            }
+            Command::ReverseScan { ref start_key, ref end_key, limit, start_ts, .. } => {
                 write!(f,
-                       "kv::command::scan {}({}) @ {}",
+                       "kv::command::reverse_scan {}-{:?}({}) @ {}",
                        start_key,
+                       end_key,
                        limit,
                        start_ts)
             }
@@ -159,6 +268,20 @@ impl fmt::Display for Command {
                        mutations.len(),
                        start_ts)
             }
+            Command::AcquirePessimisticLock { ref keys, start_ts, for_update_ts, .. } => {
+                write!(f,
+                       "kv::command::acquire_pessimistic_lock keys({}) @ {} {}",
+                       keys.len(),
+                       start_ts,
+                       for_update_ts)
+            }
+            Command::PessimisticRollback { ref keys, start_ts, for_update_ts, .. } => {
+                write!(f,
+                       "kv::command::pessimistic_rollback keys({}) @ {} {}",
+                       keys.len(),
+                       start_ts,
+                       for_update_ts)
+            }
             Command::Commit { ref keys, lock_ts, commit_ts, .. } => {
                 write!(f,
                        "kv::command::commit {} {} -> {}",
@@ -187,12 +310,43 @@ impl fmt::Display for Command {
                 write!(f, "kv::rollback_then_get {} @ {}", key, lock_ts)
             }
             Command::ScanLock { max_ts, .. } => write!(f, "kv::scan_lock {}", max_ts),
+            Command::CheckTxnStatus { ref primary, lock_ts, current_ts, .. } => {
+                write!(f,
+                       "kv::command::check_txn_status {} @ {} curr {}",
+                       primary,
+                       lock_ts,
+                       current_ts)
+            }
             Command::ResolveLock { start_ts, commit_ts, .. } => {
                 write!(f, "kv::resolve_txn {} -> {:?}", start_ts, commit_ts)
             }
             Command::Gc { safe_point, ref scan_key, .. } => {
                 write!(f, "kv::command::gc scan {:?} @{}", scan_key, safe_point)
             }
+            Command::RawGet { ref key, .. } => write!(f, "kv::command::rawget {}", key),
+            Command::RawBatchGet { ref keys, .. } => {
+                write!(f, "kv::command::raw_batch_get {}", keys.len())
+            }
+            Command::RawScan { ref start_key, limit, .. } => {
+                write!(f, "kv::command::rawscan {}({})", start_key, limit)
+            }
+            Command::RawPut { ref key, .. } => write!(f, "kv::command::rawput {}", key),
+            Command::RawBatchPut { ref pairs, .. } => {
+                write!(f, "kv::command::raw_batch_put {}", pairs.len())
+            }
+            Command::RawDelete { ref key, .. } => write!(f, "kv::command::rawdelete {}", key),
+            Command::RawBatchDelete { ref keys, .. } => {
+                write!(f, "kv::command::raw_batch_delete {}", keys.len())
+            }
+            Command::DeleteRange { ref start_key, ref end_key, .. } => {
+                write!(f, "kv::command::delete_range [{}, {})", start_key, end_key)
+            }
+            Command::MvccGetByKey { ref key, .. } => {
+                write!(f, "kv::command::mvcc_get_by_key {}", key)
+            }
+            Command::MvccGetByStartTs { start_ts, .. } => {
+                write!(f, "kv::command::mvcc_get_by_start_ts {}", start_ts)
+            }
         }
     }
 }
@@ -203,40 +357,124 @@ impl Command {
             Command::Get { .. } |
             Command::BatchGet { .. } |
             Command::Scan { .. } |
+            Command::ReverseScan { .. } |
             Command::ScanLock { .. } |
-            Command::ResolveLock { .. } => true,
+            Command::RawGet { .. } |
+            Command::RawBatchGet { .. } |
+            Command::RawScan { .. } |
+            Command::MvccGetByKey { .. } |
+            Command::MvccGetByStartTs { .. } => true,
+            Command::ResolveLock { ref keys, .. } |
             Command::Gc { ref keys, .. } => keys.is_empty(),
             _ => false,
         }
     }
+
+    /// The approximate size of the keys and values written by the command.
+    pub fn write_bytes(&self) -> usize {
+        let mut bytes = 0;
+        match *self {
+            Command::Prewrite { ref mutations, .. } => {
+                for m in mutations {
+                    match *m {
+                        Mutation::Put((ref key, ref value)) |
+                        Mutation::Insert((ref key, ref value)) => {
+                            bytes += key.encoded().len() + value.len()
+                        }
+                        Mutation::Delete(ref key) |
+                        Mutation::Lock(ref key) => bytes += key.encoded().len(),
+                    }
+                }
+            }
+            Command::AcquirePessimisticLock { ref keys, .. } |
+            Command::PessimisticRollback { ref keys, .. } |
+            Command::Commit { ref keys, .. } |
+            Command::Rollback { ref keys, .. } |
+            Command::ResolveLock { ref keys, .. } |
+            Command::Gc { ref keys, .. } |
+            Command::RawBatchDelete { ref keys, .. } => {
+                for key in keys {
+                    bytes += key.encoded().len();
+                }
+            }
+            Command::CommitThenGet { ref key, .. } |
+            Command::Cleanup { ref key, .. } |
+            Command::CheckTxnStatus { primary: ref key, .. } |
+            Command::RollbackThenGet { ref key, .. } |
+            Command::RawDelete { ref key, .. } => bytes += key.encoded().len(),
+            Command::RawPut { ref key, ref value, .. } => {
+                bytes += key.encoded().len() + value.len()
+            }
+            Command::RawBatchPut { ref pairs, .. } => {
+                for &(ref key, ref value) in pairs {
+                    bytes += key.encoded().len() + value.len();
+                }
+            }
+            Command::DeleteRange { ref start_key, ref end_key, .. } => {
+                bytes += start_key.encoded().len() + end_key.encoded().len()
+            }
+            _ => {}
+        }
+        bytes
+    }
+
+    /// Raw writes go to the engine directly without reading anything.
+    pub fn is_raw_write(&self) -> bool {
+        match *self {
+            Command::RawPut { .. } |
+            Command::RawBatchPut { .. } |
+            Command::RawDelete { .. } |
+            Command::RawBatchDelete { .. } |
+            Command::DeleteRange { .. } => true,
+            _ => false,
+        }
+    }
 }
 
 use util::transport::SendCh;
+use util::worker::{Worker, Scheduler as WorkerScheduler};
 
 struct StorageHandle {
     handle: Option<thread::JoinHandle<()>>,
     event_loop: Option<EventLoop<Scheduler>>,
+    read_worker: Worker<ReadTask>,
 }
 
 pub struct Storage {
     engine: Box<Engine>,
     sendch: SendCh<Msg>,
+    // reads without latches bypass the scheduler
+    read_sched: WorkerScheduler<ReadTask>,
+    max_read_ts: MaxReadTs,
     handle: Arc<Mutex<StorageHandle>>,
 }
 
 impl Storage {
     pub fn from_engine(engine: Box<Engine>, config: &Config) -> Result<Storage> {
+        Storage::from_engine_with_max_read_ts(engine, config, MaxReadTs::new())
+    }
+
+    /// Create a storage sharing `max_read_ts` with the component that knows
+    /// which regions are led by the local peers.
+    pub fn from_engine_with_max_read_ts(engine: Box<Engine>,
+                                        config: &Config,
+                                        max_read_ts: MaxReadTs)
+                                        -> Result<Storage> {
         let event_loop = try!(create_event_loop(config.sched_notify_capacity,
                                                 config.sched_msg_per_tick));
         let sendch = SendCh::new(event_loop.channel());
+        let read_worker = Worker::new("storage-read-pool");
 
         info!("storage {:?} started.", engine);
         Ok(Storage {
             engine: engine,
             sendch: sendch,
+            read_sched: read_worker.scheduler(),
+            max_read_ts: max_read_ts,
             handle: Arc::new(Mutex::new(StorageHandle {
                 handle: None,
                 event_loop: Some(event_loop),
+                read_worker: read_worker,
             })),
         })
     }
@@ -257,9 +495,20 @@ impl Storage {
         let mut el = handle.event_loop.take().unwrap();
         let sched_concurrency = config.sched_concurrency;
         let sched_worker_pool_size = config.sched_worker_pool_size;
+        let sched_lock_wait_timeout = config.sched_lock_wait_timeout;
+        let max_read_ts = self.max_read_ts.clone();
+        let sched_too_busy_threshold = config.sched_too_busy_threshold;
+        let sched_pending_write_threshold = config.sched_pending_write_threshold;
         let ch = self.sendch.clone();
         let h = try!(builder.spawn(move || {
-            let mut sched = Scheduler::new(engine, ch, sched_concurrency, sched_worker_pool_size);
+            let mut sched = Scheduler::new(engine,
+                                           ch,
+                                           sched_concurrency,
+                                           sched_worker_pool_size,
+                                           sched_lock_wait_timeout,
+                                           max_read_ts,
+                                           sched_too_busy_threshold,
+                                           sched_pending_write_threshold);
             if let Err(e) = el.run(&mut sched) {
                 panic!("scheduler run err:{:?}", e);
             }
@@ -267,6 +516,13 @@ impl Storage {
         }));
         handle.handle = Some(h);
 
+        let read_runner = ReadRunner::new(self.engine.clone(),
+                                          self.read_sched.clone(),
+                                          self.max_read_ts.clone(),
+                                          config.read_pool_high_concurrency,
+                                          config.read_pool_low_concurrency);
+        try!(handle.read_worker.start(read_runner));
+
         Ok(())
     }
 
@@ -286,6 +542,12 @@ impl Storage {
             return Err(box_err!("failed to join sched_handle, err:{:?}", e));
         }
 
+        if let Some(h) = handle.read_worker.stop() {
+            if let Err(e) = h.join() {
+                return Err(box_err!("failed to join read pool, err:{:?}", e));
+            }
+        }
+
         info!("storage {:?} closed.", self.engine);
         Ok(())
     }
@@ -294,11 +556,23 @@ impl Storage {
         self.engine.clone()
     }
 
+    /// The reads served outside of the storage, like coprocessor requests,
+    /// must be recorded here before taking snapshots.
+    pub fn get_max_read_ts(&self) -> MaxReadTs {
+        self.max_read_ts.clone()
+    }
+
     fn send(&self, cmd: Command, cb: StorageCb) -> Result<()> {
         box_try!(self.sendch.send(Msg::RawCmd { cmd: cmd, cb: cb }));
         Ok(())
     }
 
+    /// Send a read command that takes no latch to the read pool.
+    fn send_read(&self, cmd: Command, cb: StorageCb) -> Result<()> {
+        box_try!(self.read_sched.schedule(ReadTask::Read { cmd: cmd, cb: cb }));
+        Ok(())
+    }
+
     pub fn async_get(&self,
                      ctx: Context,
                      key: Key,
@@ -310,7 +584,7 @@ impl Storage {
             key: key,
             start_ts: start_ts,
         };
-        try!(self.send(cmd, StorageCb::SingleValue(callback)));
+        try!(self.send_read(cmd, StorageCb::SingleValue(callback)));
         Ok(())
     }
 
@@ -325,24 +599,50 @@ impl Storage {
             keys: keys,
             start_ts: start_ts,
         };
-        try!(self.send(cmd, StorageCb::KvPairs(callback)));
+        try!(self.send_read(cmd, StorageCb::KvPairs(callback)));
         Ok(())
     }
 
+    /// Scan forward from `start_key` (inclusive) to `end_key` (exclusive).
+    /// If `key_only` is true, the values in the result are left empty.
     pub fn async_scan(&self,
                       ctx: Context,
                       start_key: Key,
+                      end_key: Option<Key>,
                       limit: usize,
+                      key_only: bool,
                       start_ts: u64,
                       callback: Callback<Vec<Result<KvPair>>>)
                       -> Result<()> {
         let cmd = Command::Scan {
             ctx: ctx,
             start_key: start_key,
+            end_key: end_key,
             limit: limit,
+            key_only: key_only,
             start_ts: start_ts,
         };
-        try!(self.send(cmd, StorageCb::KvPairs(callback)));
+        try!(self.send_read(cmd, StorageCb::KvPairs(callback)));
+        Ok(())
+    }
+
+    /// Scan backward from `start_key` (exclusive) to `end_key` (inclusive).
+    pub fn async_reverse_scan(&self,
+                              ctx: Context,
+                              start_key: Key,
+                              end_key: Option<Key>,
+                              limit: usize,
+                              start_ts: u64,
+                              callback: Callback<Vec<Result<KvPair>>>)
+                              -> Result<()> {
+        let cmd = Command::ReverseScan {
+            ctx: ctx,
+            start_key: start_key,
+            end_key: end_key,
+            limit: limit,
+            start_ts: start_ts,
+        };
+        try!(self.send_read(cmd, StorageCb::KvPairs(callback)));
         Ok(())
     }
 
@@ -351,6 +651,7 @@ impl Storage {
                           mutations: Vec<Mutation>,
                           primary: Vec<u8>,
                           start_ts: u64,
+                          options: Options,
                           callback: Callback<Vec<Result<()>>>)
                           -> Result<()> {
         let cmd = Command::Prewrite {
@@ -358,11 +659,52 @@ impl Storage {
             mutations: mutations,
             primary: primary,
             start_ts: start_ts,
+            options: options,
         };
         try!(self.send(cmd, StorageCb::Booleans(callback)));
         Ok(())
     }
 
+    /// Lock `keys` for a pessimistic transaction. Conflicts are checked
+    /// against `for_update_ts` instead of `start_ts`.
+    pub fn async_acquire_pessimistic_lock(&self,
+                                          ctx: Context,
+                                          keys: Vec<Key>,
+                                          primary: Vec<u8>,
+                                          start_ts: u64,
+                                          for_update_ts: u64,
+                                          options: Options,
+                                          callback: Callback<Vec<Result<()>>>)
+                                          -> Result<()> {
+        let cmd = Command::AcquirePessimisticLock {
+            ctx: ctx,
+            keys: keys,
+            primary: primary,
+            start_ts: start_ts,
+            for_update_ts: for_update_ts,
+            options: options,
+        };
+        try!(self.send(cmd, StorageCb::Booleans(callback)));
+        Ok(())
+    }
+
+    pub fn async_pessimistic_rollback(&self,
+                                      ctx: Context,
+                                      keys: Vec<Key>,
+                                      start_ts: u64,
+                                      for_update_ts: u64,
+                                      callback: Callback<()>)
+                                      -> Result<()> {
+        let cmd = Command::PessimisticRollback {
+            ctx: ctx,
+            keys: keys,
+            start_ts: start_ts,
+            for_update_ts: for_update_ts,
+        };
+        try!(self.send(cmd, StorageCb::Boolean(callback)));
+        Ok(())
+    }
+
     pub fn async_commit(&self,
                         ctx: Context,
                         keys: Vec<Key>,
@@ -457,6 +799,23 @@ impl Storage {
         Ok(())
     }
 
+    pub fn async_check_txn_status(&self,
+                                  ctx: Context,
+                                  primary: Key,
+                                  lock_ts: u64,
+                                  current_ts: u64,
+                                  callback: Callback<TxnStatus>)
+                                  -> Result<()> {
+        let cmd = Command::CheckTxnStatus {
+            ctx: ctx,
+            primary: primary,
+            lock_ts: lock_ts,
+            current_ts: current_ts,
+        };
+        try!(self.send(cmd, StorageCb::TxnStatus(callback)));
+        Ok(())
+    }
+
     pub fn async_resolve_lock(&self,
                               ctx: Context,
                               start_ts: u64,
@@ -467,6 +826,7 @@ impl Storage {
             ctx: ctx,
             start_ts: start_ts,
             commit_ts: commit_ts,
+            keys: vec![],
         };
         try!(self.send(cmd, StorageCb::Boolean(callback)));
         Ok(())
@@ -482,6 +842,146 @@ impl Storage {
         try!(self.send(cmd, StorageCb::Boolean(callback)));
         Ok(())
     }
+
+    pub fn async_raw_get(&self,
+                         ctx: Context,
+                         key: Vec<u8>,
+                         callback: Callback<Option<Value>>)
+                         -> Result<()> {
+        let cmd = Command::RawGet {
+            ctx: ctx,
+            key: Key::from_encoded(key),
+        };
+        try!(self.send_read(cmd, StorageCb::SingleValue(callback)));
+        Ok(())
+    }
+
+    pub fn async_raw_batch_get(&self,
+                               ctx: Context,
+                               keys: Vec<Vec<u8>>,
+                               callback: Callback<Vec<Result<KvPair>>>)
+                               -> Result<()> {
+        let cmd = Command::RawBatchGet {
+            ctx: ctx,
+            keys: keys.into_iter().map(Key::from_encoded).collect(),
+        };
+        try!(self.send_read(cmd, StorageCb::KvPairs(callback)));
+        Ok(())
+    }
+
+    pub fn async_raw_scan(&self,
+                          ctx: Context,
+                          start_key: Vec<u8>,
+                          limit: usize,
+                          callback: Callback<Vec<Result<KvPair>>>)
+                          -> Result<()> {
+        let cmd = Command::RawScan {
+            ctx: ctx,
+            start_key: Key::from_encoded(start_key),
+            limit: limit,
+        };
+        try!(self.send_read(cmd, StorageCb::KvPairs(callback)));
+        Ok(())
+    }
+
+    pub fn async_raw_put(&self,
+                         ctx: Context,
+                         key: Vec<u8>,
+                         value: Vec<u8>,
+                         callback: Callback<()>)
+                         -> Result<()> {
+        let cmd = Command::RawPut {
+            ctx: ctx,
+            key: Key::from_encoded(key),
+            value: value,
+        };
+        try!(self.send(cmd, StorageCb::Boolean(callback)));
+        Ok(())
+    }
+
+    pub fn async_raw_batch_put(&self,
+                               ctx: Context,
+                               pairs: Vec<KvPair>,
+                               callback: Callback<()>)
+                               -> Result<()> {
+        let cmd = Command::RawBatchPut {
+            ctx: ctx,
+            pairs: pairs.into_iter().map(|(k, v)| (Key::from_encoded(k), v)).collect(),
+        };
+        try!(self.send(cmd, StorageCb::Boolean(callback)));
+        Ok(())
+    }
+
+    pub fn async_raw_delete(&self,
+                            ctx: Context,
+                            key: Vec<u8>,
+                            callback: Callback<()>)
+                            -> Result<()> {
+        let cmd = Command::RawDelete {
+            ctx: ctx,
+            key: Key::from_encoded(key),
+        };
+        try!(self.send(cmd, StorageCb::Boolean(callback)));
+        Ok(())
+    }
+
+    pub fn async_raw_batch_delete(&self,
+                                  ctx: Context,
+                                  keys: Vec<Vec<u8>>,
+                                  callback: Callback<()>)
+                                  -> Result<()> {
+        let cmd = Command::RawBatchDelete {
+            ctx: ctx,
+            keys: keys.into_iter().map(Key::from_encoded).collect(),
+        };
+        try!(self.send(cmd, StorageCb::Boolean(callback)));
+        Ok(())
+    }
+
+    /// Delete all keys in [`start_key`, `end_key`) of every column family, both raw keys and
+    /// MVCC data are dropped without leaving any tombstone versions.
+    pub fn async_delete_range(&self,
+                              ctx: Context,
+                              start_key: Vec<u8>,
+                              end_key: Vec<u8>,
+                              callback: Callback<()>)
+                              -> Result<()> {
+        let cmd = Command::DeleteRange {
+            ctx: ctx,
+            start_key: Key::from_encoded(start_key),
+            end_key: Key::from_encoded(end_key),
+        };
+        try!(self.send(cmd, StorageCb::Boolean(callback)));
+        Ok(())
+    }
+
+    /// Get the lock, write records and values of `key`, it's used for debugging.
+    pub fn async_mvcc_by_key(&self,
+                             ctx: Context,
+                             key: Key,
+                             callback: Callback<MvccInfo>)
+                             -> Result<()> {
+        let cmd = Command::MvccGetByKey {
+            ctx: ctx,
+            key: key,
+        };
+        try!(self.send(cmd, StorageCb::MvccInfoByKey(callback)));
+        Ok(())
+    }
+
+    /// Like `async_mvcc_by_key`, for a key of the transaction `start_ts`.
+    pub fn async_mvcc_by_start_ts(&self,
+                                  ctx: Context,
+                                  start_ts: u64,
+                                  callback: Callback<Option<(Key, MvccInfo)>>)
+                                  -> Result<()> {
+        let cmd = Command::MvccGetByStartTs {
+            ctx: ctx,
+            start_ts: start_ts,
+        };
+        try!(self.send(cmd, StorageCb::MvccInfoByStartTs(callback)));
+        Ok(())
+    }
 }
 
 impl Clone for Storage {
@@ -489,6 +989,7 @@ impl Clone for Storage {
         Storage {
             engine: self.engine.clone(),
             sendch: self.sendch.clone(),
+            read_sched: self.read_sched.clone(),
             handle: self.handle.clone(),
         }
     }
@@ -510,6 +1011,9 @@ quick_error! {
         Closed {
             description("storage is closed.")
         }
+        SchedTooBusy {
+            description("scheduler is too busy")
+        }
         Other(err: Box<error::Error + Send + Sync>) {
             from()
             cause(err.as_ref())
@@ -541,6 +1045,11 @@ mod tests {
     use std::sync::mpsc::{channel, Sender};
     use kvproto::kvrpcpb::Context;
 
+    fn new_storage(config: &Config) -> Storage {
+        let engine = new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
+        Storage::from_engine(engine, config).unwrap()
+    }
+
     fn expect_get_none(done: Sender<i32>) -> Callback<Option<Value>> {
         Box::new(move |x: Result<Option<Value>>| {
             assert_eq!(x.unwrap(), None);
@@ -569,6 +1078,16 @@ mod tests {
         })
     }
 
+    fn expect_too_busy<T>(done: Sender<i32>) -> Callback<T> {
+        Box::new(move |x: Result<T>| {
+            match x {
+                Err(Error::SchedTooBusy) => {}
+                _ => panic!("expect SchedTooBusy"),
+            }
+            done.send(1).unwrap();
+        })
+    }
+
     fn expect_scan(done: Sender<i32>, pairs: Vec<Option<KvPair>>) -> Callback<Vec<Result<KvPair>>> {
         Box::new(move |rlt: Result<Vec<Result<KvPair>>>| {
             let rlt: Vec<Option<KvPair>> = rlt.unwrap()
@@ -583,7 +1102,7 @@ mod tests {
     #[test]
     fn test_get_put() {
         let config = Config::new();
-        let mut storage = Storage::new(&config).unwrap();
+        let mut storage = new_storage(&config);
         storage.start(&config).unwrap();
         let (tx, rx) = channel();
         storage.async_get(Context::new(),
@@ -596,6 +1115,7 @@ mod tests {
                             vec![Mutation::Put((make_key(b"x"), b"100".to_vec()))],
                             b"x".to_vec(),
                             100,
+                            Options::default(),
                             expect_ok(tx.clone()))
             .unwrap();
         rx.recv().unwrap();
@@ -623,7 +1143,7 @@ mod tests {
     #[test]
     fn test_scan() {
         let config = Config::new();
-        let mut storage = Storage::new(&config).unwrap();
+        let mut storage = new_storage(&config);
         storage.start(&config).unwrap();
         let (tx, rx) = channel();
         storage.async_prewrite(Context::new(),
@@ -634,6 +1154,7 @@ mod tests {
             ],
                             b"a".to_vec(),
                             1,
+                            Options::default(),
                             expect_ok(tx.clone()))
             .unwrap();
         rx.recv().unwrap();
@@ -646,7 +1167,9 @@ mod tests {
         rx.recv().unwrap();
         storage.async_scan(Context::new(),
                         make_key(b"\x00"),
+                        None,
                         1000,
+                        false,
                         5,
                         expect_scan(tx.clone(),
                                     vec![
@@ -662,19 +1185,21 @@ mod tests {
     #[test]
     fn test_txn() {
         let config = Config::new();
-        let mut storage = Storage::new(&config).unwrap();
+        let mut storage = new_storage(&config);
         storage.start(&config).unwrap();
         let (tx, rx) = channel();
         storage.async_prewrite(Context::new(),
                             vec![Mutation::Put((make_key(b"x"), b"100".to_vec()))],
                             b"x".to_vec(),
                             100,
+                            Options::default(),
                             expect_ok(tx.clone()))
             .unwrap();
         storage.async_prewrite(Context::new(),
                             vec![Mutation::Put((make_key(b"y"), b"101".to_vec()))],
                             b"y".to_vec(),
                             101,
+                            Options::default(),
                             expect_ok(tx.clone()))
             .unwrap();
         rx.recv().unwrap();
@@ -709,9 +1234,173 @@ mod tests {
                             vec![Mutation::Put((make_key(b"x"), b"105".to_vec()))],
                             b"x".to_vec(),
                             105,
+                            Options::default(),
+                            expect_fail(tx.clone()))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.stop().unwrap();
+    }
+
+    #[test]
+    fn test_one_pc_after_read() {
+        let config = Config::new();
+        let mut storage = new_storage(&config);
+        storage.start(&config).unwrap();
+        let (tx, rx) = channel();
+        storage.async_get(Context::new(),
+                       make_key(b"x"),
+                       110,
+                       expect_get_none(tx.clone()))
+            .unwrap();
+        rx.recv().unwrap();
+        // The read at 110 has missed the commit at 105.
+        let mut options = Options::default();
+        options.one_pc_commit_ts = Some(105);
+        storage.async_prewrite(Context::new(),
+                            vec![Mutation::Put((make_key(b"x"), b"100".to_vec()))],
+                            b"x".to_vec(),
+                            100,
+                            options,
                             expect_fail(tx.clone()))
             .unwrap();
         rx.recv().unwrap();
+        let mut options = Options::default();
+        options.one_pc_commit_ts = Some(111);
+        storage.async_prewrite(Context::new(),
+                            vec![Mutation::Put((make_key(b"x"), b"100".to_vec()))],
+                            b"x".to_vec(),
+                            100,
+                            options,
+                            expect_ok(tx.clone()))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_get(Context::new(),
+                       make_key(b"x"),
+                       110,
+                       expect_get_none(tx.clone()))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_get(Context::new(),
+                       make_key(b"x"),
+                       111,
+                       expect_get_val(tx.clone(), b"100".to_vec()))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.stop().unwrap();
+    }
+
+    #[test]
+    fn test_raw() {
+        let config = Config::new();
+        let mut storage = new_storage(&config);
+        storage.start(&config).unwrap();
+        let (tx, rx) = channel();
+        storage.async_raw_get(Context::new(), b"a".to_vec(), expect_get_none(tx.clone()))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_raw_batch_put(Context::new(),
+                                 vec![(b"a".to_vec(), b"aa".to_vec()),
+                                      (b"b".to_vec(), b"bb".to_vec())],
+                                 expect_ok(tx.clone()))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_raw_put(Context::new(),
+                           b"c".to_vec(),
+                           b"cc".to_vec(),
+                           expect_ok(tx.clone()))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_raw_get(Context::new(),
+                           b"a".to_vec(),
+                           expect_get_val(tx.clone(), b"aa".to_vec()))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_raw_scan(Context::new(),
+                            b"".to_vec(),
+                            2,
+                            expect_scan(tx.clone(),
+                                        vec![
+            Some((b"a".to_vec(), b"aa".to_vec())),
+            Some((b"b".to_vec(), b"bb".to_vec())),
+            ]))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_raw_delete(Context::new(), b"a".to_vec(), expect_ok(tx.clone()))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_raw_batch_delete(Context::new(),
+                                    vec![b"b".to_vec(), b"x".to_vec()],
+                                    expect_ok(tx.clone()))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_raw_batch_get(Context::new(),
+                                 vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()],
+                                 expect_scan(tx.clone(),
+                                             vec![Some((b"c".to_vec(), b"cc".to_vec()))]))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.stop().unwrap();
+    }
+
+    #[test]
+    fn test_delete_range() {
+        let config = Config::new();
+        let mut storage = new_storage(&config);
+        storage.start(&config).unwrap();
+        let (tx, rx) = channel();
+        storage.async_raw_batch_put(Context::new(),
+                                 vec![(b"a".to_vec(), b"aa".to_vec()),
+                                      (b"b".to_vec(), b"bb".to_vec()),
+                                      (b"c".to_vec(), b"cc".to_vec())],
+                                 expect_ok(tx.clone()))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_delete_range(Context::new(),
+                                b"a".to_vec(),
+                                b"c".to_vec(),
+                                expect_ok(tx.clone()))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_raw_scan(Context::new(),
+                            b"".to_vec(),
+                            3,
+                            expect_scan(tx.clone(), vec![Some((b"c".to_vec(), b"cc".to_vec()))]))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.stop().unwrap();
+    }
+
+    #[test]
+    fn test_sched_too_busy() {
+        let mut config = Config::new();
+        config.sched_too_busy_threshold = 0;
+        let mut storage = new_storage(&config);
+        storage.start(&config).unwrap();
+        let (tx, rx) = channel();
+        // Reads are never rejected.
+        storage.async_get(Context::new(), make_key(b"x"), 100, expect_get_none(tx.clone()))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_prewrite(Context::new(),
+                            vec![Mutation::Put((make_key(b"x"), b"100".to_vec()))],
+                            b"x".to_vec(),
+                            100,
+                            Options::default(),
+                            expect_too_busy(tx.clone()))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.stop().unwrap();
+
+        let mut config = Config::new();
+        config.sched_pending_write_threshold = 0;
+        let mut storage = new_storage(&config);
+        storage.start(&config).unwrap();
+        storage.async_raw_put(Context::new(),
+                           b"a".to_vec(),
+                           b"aa".to_vec(),
+                           expect_too_busy(tx.clone()))
+            .unwrap();
+        rx.recv().unwrap();
         storage.stop().unwrap();
     }
 }
@@ -0,0 +1,313 @@
+// Copyright 2016 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::sync::{Arc, Weak, Mutex, RwLock};
+
+use rocksdb::{DB, Writable, WriteBatch, CompactionFilter, CompactionFilterFactory,
+              CompactionFilterContext};
+
+use raftstore::store::keys;
+use raftstore::store::engine::Peekable;
+use storage::{Key, CF_DEFAULT};
+use util::{rocksdb, escape};
+use util::worker::{Worker, Runnable};
+use super::write::{Write, WriteType};
+
+/// The values in `CF_DEFAULT` of the Puts dropped by a compaction.
+pub struct DefaultDeletes {
+    keys: Vec<Vec<u8>>,
+}
+
+impl Display for DefaultDeletes {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "delete {} default values", self.keys.len())
+    }
+}
+
+struct DefaultDeleteRunner {
+    db: Weak<DB>,
+}
+
+impl Runnable<DefaultDeletes> for DefaultDeleteRunner {
+    fn run(&mut self, task: DefaultDeletes) {
+        let db = match self.db.upgrade() {
+            Some(db) => db,
+            None => return,
+        };
+        let wb = WriteBatch::new();
+        let handle = rocksdb::get_cf_handle(&db, CF_DEFAULT).unwrap();
+        for key in &task.keys {
+            if let Err(e) = wb.delete_cf(*handle, key) {
+                error!("failed to delete default value {}: {:?}", escape(key), e);
+                return;
+            }
+        }
+        if let Err(e) = db.write(wb) {
+            error!("failed to {}: {:?}", task, e);
+        }
+    }
+}
+
+/// The engine shared by the gc compaction filters. Filters are created before
+/// the engine is opened, so the engine must be set once it's ready, until then
+/// nothing is filtered.
+///
+/// The values dropped by the filters are deleted by a worker, since writing in
+/// the compaction thread may wait for the compaction itself when writes are
+/// stalled.
+pub struct GcContext {
+    db: RwLock<Option<Weak<DB>>>,
+    worker: Mutex<Worker<DefaultDeletes>>,
+}
+
+impl GcContext {
+    pub fn new() -> GcContext {
+        GcContext {
+            db: RwLock::new(None),
+            worker: Mutex::new(Worker::new("gc-default-cf")),
+        }
+    }
+
+    pub fn set_db(&self, db: &Arc<DB>) -> Result<(), io::Error> {
+        let runner = DefaultDeleteRunner { db: Arc::downgrade(db) };
+        try!(self.worker.lock().unwrap().start(runner));
+        *self.db.write().unwrap() = Some(Arc::downgrade(db));
+        Ok(())
+    }
+
+    fn db(&self) -> Option<Arc<DB>> {
+        self.db.read().unwrap().as_ref().and_then(|db| db.upgrade())
+    }
+
+    fn delete_default_values(&self, keys: Vec<Vec<u8>>) {
+        let task = DefaultDeletes { keys: keys };
+        if let Err(e) = self.worker.lock().unwrap().schedule(task) {
+            error!("failed to schedule {}: {:?}", e.0, e);
+        }
+    }
+}
+
+impl Drop for GcContext {
+    fn drop(&mut self) {
+        if let Some(h) = self.worker.lock().unwrap().stop() {
+            if let Err(e) = h.join() {
+                error!("failed to join gc-default-cf worker: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Get the persisted gc safe point, 0 means nothing can be filtered.
+fn safe_point(db: &DB) -> u64 {
+    match db.get_u64(keys::GC_SAFE_POINT_KEY) {
+        Ok(safe_point) => safe_point.unwrap_or(0),
+        Err(e) => {
+            error!("failed to get gc safe point: {:?}", e);
+            0
+        }
+    }
+}
+
+/// Split a data key in `CF_WRITE` into the user key and ts.
+fn split_ts(key: &[u8]) -> Option<(Key, u64)> {
+    if !key.starts_with(keys::DATA_PREFIX_KEY) {
+        return None;
+    }
+    let key = Key::from_encoded(keys::origin_key(key).to_vec());
+    match (key.truncate_ts(), key.decode_ts()) {
+        (Ok(k), Ok(ts)) => Some((k, ts)),
+        _ => None,
+    }
+}
+
+/// Creates a `WriteCompactionFilter` for each compaction of `CF_WRITE`, the
+/// safe point is read once when the compaction starts.
+pub struct WriteCompactionFilterFactory {
+    ctx: Arc<GcContext>,
+}
+
+impl WriteCompactionFilterFactory {
+    pub fn new(ctx: Arc<GcContext>) -> WriteCompactionFilterFactory {
+        WriteCompactionFilterFactory { ctx: ctx }
+    }
+}
+
+impl CompactionFilterFactory for WriteCompactionFilterFactory {
+    fn create_compaction_filter(&self, _: &CompactionFilterContext) -> Box<CompactionFilter> {
+        let safe_point = self.ctx.db().map_or(0, |db| safe_point(&db));
+        box WriteCompactionFilter::new(self.ctx.clone(), safe_point)
+    }
+}
+
+/// Drops records in `CF_WRITE` following the rules of `MvccTxn::gc`, except
+/// that the latest Delete is kept, and so is the latest Rollback unless a
+/// newer Put, Delete or Rollback is seen: it still makes a delayed prewrite
+/// of the rolled back transaction fail. Compaction doesn't cover all levels,
+/// the versions it hides may still exist in other levels.
+///
+/// Records of a key come in the order of descending commit ts, so the drops
+/// are decided by the records seen before without reading the engine. The
+/// values of the dropped Puts are deleted from `CF_DEFAULT` when the
+/// compaction finishes.
+pub struct WriteCompactionFilter {
+    ctx: Arc<GcContext>,
+    safe_point: u64,
+    // the user key of the last record
+    last_key: Vec<u8>,
+    // whether the latest Put/Delete of `last_key` before the safe point has
+    // been seen, all the older records are invisible
+    remove_older: bool,
+    // whether a Put/Delete/Rollback of `last_key` has been seen, its commit ts
+    // is larger than the start ts of the older transactions
+    has_newer: bool,
+    // keys in `CF_DEFAULT` of the values to be deleted
+    default_deletes: Vec<Vec<u8>>,
+}
+
+impl WriteCompactionFilter {
+    pub fn new(ctx: Arc<GcContext>, safe_point: u64) -> WriteCompactionFilter {
+        WriteCompactionFilter {
+            ctx: ctx,
+            safe_point: safe_point,
+            last_key: vec![],
+            remove_older: false,
+            has_newer: false,
+            default_deletes: vec![],
+        }
+    }
+}
+
+impl CompactionFilter for WriteCompactionFilter {
+    fn filter(&mut self, _: usize, key: &[u8], value: &[u8]) -> bool {
+        if self.safe_point == 0 {
+            return false;
+        }
+        let (key, commit_ts) = match split_ts(key) {
+            Some(res) => res,
+            None => return false,
+        };
+        if *key.encoded() != self.last_key {
+            self.last_key = key.encoded().clone();
+            self.remove_older = false;
+            self.has_newer = false;
+        }
+        let write = match Write::parse(value) {
+            Ok(write) => write,
+            Err(_) => return false,
+        };
+        let has_newer = self.has_newer;
+        match write.write_type {
+            WriteType::Lock => {}
+            _ => self.has_newer = true,
+        }
+        if commit_ts > self.safe_point {
+            return false;
+        }
+        if self.remove_older {
+            if let (WriteType::Put, None) = (write.write_type, write.short_value) {
+                let default_key = keys::data_key(key.append_ts(write.start_ts).encoded());
+                self.default_deletes.push(default_key);
+            }
+            return true;
+        }
+        match write.write_type {
+            WriteType::Rollback => has_newer,
+            WriteType::Lock => true,
+            WriteType::Put | WriteType::Delete => {
+                self.remove_older = true;
+                false
+            }
+        }
+    }
+}
+
+impl Drop for WriteCompactionFilter {
+    fn drop(&mut self) {
+        if self.default_deletes.is_empty() || self.ctx.db().is_none() {
+            return;
+        }
+        let keys = self.default_deletes.split_off(0);
+        self.ctx.delete_default_values(keys);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rocksdb::CompactionFilter;
+    use tempdir::TempDir;
+
+    use raftstore::store::keys;
+    use raftstore::store::engine::Mutable;
+    use storage::{make_key, DEFAULT_CFS};
+    use util::rocksdb;
+    use super::super::write::{Write, WriteType};
+    use super::{GcContext, WriteCompactionFilter, safe_point};
+
+    fn write_key(key: &[u8], ts: u64) -> Vec<u8> {
+        keys::data_key(make_key(key).append_ts(ts).encoded())
+    }
+
+    #[test]
+    fn test_compaction_filter() {
+        let path = TempDir::new("test-gc-compaction-filter").unwrap();
+        let db = Arc::new(rocksdb::new_engine(path.path().to_str().unwrap(), DEFAULT_CFS)
+            .unwrap());
+        // Records in the order of a compaction.
+        let records = vec![(b"k1", 40, Write::new(WriteType::Put, 35, None)),
+                           (b"k1", 30, Write::new(WriteType::Delete, 28, None)),
+                           (b"k1", 25, Write::new(WriteType::Rollback, 25, None)),
+                           (b"k1", 20, Write::new(WriteType::Put, 15, None)),
+                           (b"k1", 10, Write::new(WriteType::Put, 5, Some(b"v".to_vec()))),
+                           (b"k2", 10, Write::new(WriteType::Put, 5, None)),
+                           (b"k3", 15, Write::new(WriteType::Lock, 14, None)),
+                           (b"k3", 12, Write::new(WriteType::Rollback, 12, None)),
+                           (b"k3", 8, Write::new(WriteType::Rollback, 8, None))];
+
+        let ctx = Arc::new(GcContext::new());
+        let check = |safe_point: u64, expect: &[bool], expect_deletes: &[u64]| {
+            let mut filter = WriteCompactionFilter::new(ctx.clone(), safe_point);
+            for (&(key, commit_ts, ref write), &e) in records.iter().zip(expect) {
+                assert_eq!(filter.filter(0, &write_key(key, commit_ts), &write.to_bytes()),
+                           e);
+            }
+            let deletes: Vec<_> = expect_deletes.iter().map(|&ts| write_key(b"k1", ts)).collect();
+            assert_eq!(filter.default_deletes, deletes);
+            filter.default_deletes.clear();
+        };
+
+        // Nothing is filtered before the safe point is set.
+        ctx.set_db(&db).unwrap();
+        assert_eq!(safe_point(&db), 0);
+        check(0, &[false; 9], &[]);
+
+        db.put_u64(keys::GC_SAFE_POINT_KEY, 22).unwrap();
+        assert_eq!(safe_point(&db), 22);
+        // The latest Rollback is kept.
+        check(22,
+              &[false, false, false, false, true, false, true, false, true],
+              &[]);
+
+        // The latest Delete is kept.
+        check(32,
+              &[false, false, true, true, true, false, true, false, true],
+              &[15]);
+
+        check(45,
+              &[false, true, true, true, true, false, true, false, true],
+              &[15]);
+    }
+}
@@ -12,21 +12,28 @@
 // limitations under the License.
 
 use byteorder::ReadBytesExt;
-use storage::Mutation;
+use storage::{Mutation, Value};
 use util::codec::number::{NumberEncoder, NumberDecoder, MAX_VAR_U64_LEN};
 use util::codec::bytes::{BytesEncoder, CompactBytesDecoder};
-use super::{Error, Result};
+use super::{Error, Result, extract_physical};
+use super::write::{SHORT_VALUE_MAX_LEN, encode_short_value, decode_short_value};
 
-#[derive(Debug,Clone,Copy)]
+#[derive(Debug,Clone,Copy,PartialEq)]
 pub enum LockType {
     Put,
     Delete,
     Lock,
+    // Acquired by a pessimistic transaction before prewrite, it holds no data.
+    Pessimistic,
+    // A Put prewritten after checking that the key doesn't exist.
+    Insert,
 }
 
 const FLAG_PUT: u8 = b'P';
 const FLAG_DELETE: u8 = b'D';
 const FLAG_LOCK: u8 = b'L';
+const FLAG_PESSIMISTIC: u8 = b'S';
+const FLAG_INSERT: u8 = b'I';
 
 impl LockType {
     pub fn from_mutation(mutation: &Mutation) -> LockType {
@@ -34,6 +41,7 @@ impl LockType {
             Mutation::Put(_) => LockType::Put,
             Mutation::Delete(_) => LockType::Delete,
             Mutation::Lock(_) => LockType::Lock,
+            Mutation::Insert(_) => LockType::Insert,
         }
     }
 
@@ -42,6 +50,8 @@ impl LockType {
             FLAG_PUT => Some(LockType::Put),
             FLAG_DELETE => Some(LockType::Delete),
             FLAG_LOCK => Some(LockType::Lock),
+            FLAG_PESSIMISTIC => Some(LockType::Pessimistic),
+            FLAG_INSERT => Some(LockType::Insert),
             _ => None,
         }
     }
@@ -51,6 +61,8 @@ impl LockType {
             LockType::Put => FLAG_PUT,
             LockType::Delete => FLAG_DELETE,
             LockType::Lock => FLAG_LOCK,
+            LockType::Pessimistic => FLAG_PESSIMISTIC,
+            LockType::Insert => FLAG_INSERT,
         }
     }
 }
@@ -59,22 +71,48 @@ pub struct Lock {
     pub lock_type: LockType,
     pub primary: Vec<u8>,
     pub ts: u64,
+    // ttl in milliseconds, 0 means the lock never expires.
+    pub ttl: u64,
+    // for_update_ts of a pessimistic lock, 0 for other locks.
+    pub for_update_ts: u64,
+    // the value of a Put, if it's short enough to be inlined.
+    pub short_value: Option<Value>,
 }
 
 impl Lock {
-    pub fn new(lock_type: LockType, primary: Vec<u8>, ts: u64) -> Lock {
+    pub fn new(lock_type: LockType,
+               primary: Vec<u8>,
+               ts: u64,
+               ttl: u64,
+               for_update_ts: u64,
+               short_value: Option<Value>)
+               -> Lock {
         Lock {
             lock_type: lock_type,
             primary: primary,
             ts: ts,
+            ttl: ttl,
+            for_update_ts: for_update_ts,
+            short_value: short_value,
         }
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut b = Vec::with_capacity(1 + MAX_VAR_U64_LEN + self.primary.len() + MAX_VAR_U64_LEN);
+        let mut b = Vec::with_capacity(1 + MAX_VAR_U64_LEN + self.primary.len() +
+                                       MAX_VAR_U64_LEN * 3 +
+                                       2 + SHORT_VALUE_MAX_LEN);
         b.push(self.lock_type.to_u8());
         b.encode_compact_bytes(&self.primary).unwrap();
         b.encode_var_u64(self.ts).unwrap();
+        b.encode_var_u64(self.ttl).unwrap();
+        // Optional fields are positional, for_update_ts must be written if
+        // the short value is present.
+        if self.for_update_ts > 0 || self.short_value.is_some() {
+            b.encode_var_u64(self.for_update_ts).unwrap();
+        }
+        if let Some(ref v) = self.short_value {
+            encode_short_value(&mut b, v);
+        }
         b
     }
 
@@ -85,6 +123,77 @@ impl Lock {
         let lock_type = try!(LockType::from_u8(try!(b.read_u8())).ok_or(Error::BadFormatLock));
         let primary = try!(b.decode_compact_bytes());
         let ts = try!(b.decode_var_u64());
-        Ok(Lock::new(lock_type, primary, ts))
+        // Locks written before ttl was introduced don't carry it.
+        let ttl = if b.is_empty() {
+            0
+        } else {
+            try!(b.decode_var_u64())
+        };
+        let for_update_ts = if b.is_empty() {
+            0
+        } else {
+            try!(b.decode_var_u64())
+        };
+        let short_value = try!(decode_short_value(b).ok_or(Error::BadFormatLock));
+        Ok(Lock::new(lock_type, primary, ts, ttl, for_update_ts, short_value))
+    }
+
+    /// Check whether the lock has expired at `current_ts`.
+    pub fn is_expired(&self, current_ts: u64) -> bool {
+        self.ttl > 0 && extract_physical(self.ts) + self.ttl <= extract_physical(current_ts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::codec::number::NumberEncoder;
+    use util::codec::bytes::BytesEncoder;
+    use storage::mvcc::compose_ts;
+
+    #[test]
+    fn test_lock() {
+        let lock = Lock::new(LockType::Put, b"pk".to_vec(), 1, 100, 0, None);
+        let b = lock.to_bytes();
+        let lock = Lock::parse(&b).unwrap();
+        assert_eq!(lock.primary, b"pk");
+        assert_eq!(lock.ts, 1);
+        assert_eq!(lock.ttl, 100);
+        assert_eq!(lock.for_update_ts, 0);
+        assert_eq!(lock.short_value, None);
+
+        let lock = Lock::new(LockType::Put, b"pk".to_vec(), 1, 100, 0, Some(b"v".to_vec()));
+        let b = lock.to_bytes();
+        let lock = Lock::parse(&b).unwrap();
+        assert_eq!(lock.ttl, 100);
+        assert_eq!(lock.for_update_ts, 0);
+        assert_eq!(lock.short_value, Some(b"v".to_vec()));
+
+        let lock = Lock::new(LockType::Pessimistic, b"pk".to_vec(), 1, 100, 5, None);
+        let b = lock.to_bytes();
+        let lock = Lock::parse(&b).unwrap();
+        assert_eq!(lock.lock_type, LockType::Pessimistic);
+        assert_eq!(lock.ttl, 100);
+        assert_eq!(lock.for_update_ts, 5);
+
+        // Lock without ttl.
+        let mut b = vec![FLAG_LOCK];
+        b.encode_compact_bytes(b"pk").unwrap();
+        b.encode_var_u64(1).unwrap();
+        let lock = Lock::parse(&b).unwrap();
+        assert_eq!(lock.ts, 1);
+        assert_eq!(lock.ttl, 0);
+        assert!(!lock.is_expired(u64::max_value()));
+
+        assert!(Lock::parse(b"").is_err());
+    }
+
+    #[test]
+    fn test_lock_expired() {
+        let lock = Lock::new(LockType::Put, b"pk".to_vec(), compose_ts(10, 1), 100, 0, None);
+        assert!(!lock.is_expired(compose_ts(10, 2)));
+        assert!(!lock.is_expired(compose_ts(109, 0)));
+        assert!(lock.is_expired(compose_ts(110, 0)));
+        assert!(lock.is_expired(compose_ts(200, 0)));
     }
 }
@@ -15,10 +15,15 @@ mod reader;
 mod txn;
 mod lock;
 mod write;
+mod compaction_filter;
 
 use std::io;
 pub use self::txn::MvccTxn;
-pub use self::reader::MvccReader;
+pub use self::reader::{MvccReader, MvccInfo};
+pub use self::lock::{Lock, LockType};
+pub use self::write::{Write, WriteType, SHORT_VALUE_MAX_LEN};
+pub use self::txn::TxnStatus;
+pub use self::compaction_filter::{GcContext, WriteCompactionFilter, WriteCompactionFilterFactory};
 use util::escape;
 
 quick_error! {
@@ -39,9 +44,13 @@ quick_error! {
             cause(err)
             description(err.description())
         }
-        KeyIsLocked {key: Vec<u8>, primary: Vec<u8>, ts: u64} {
+        KeyIsLocked {key: Vec<u8>, primary: Vec<u8>, ts: u64, ttl: u64} {
             description("key is locked (backoff or cleanup)")
-            display("key is locked (backoff or cleanup) {}-{}@{}", escape(key), escape(primary), ts)
+            display("key is locked (backoff or cleanup) {}-{}@{} ttl {}",
+                    escape(key),
+                    escape(primary),
+                    ts,
+                    ttl)
         }
         BadFormatLock {description("bad format lock data")}
         BadFormatWrite {description("bad format write data")}
@@ -50,12 +59,49 @@ quick_error! {
             display("txn already committed @{}", commit_ts)
         }
         TxnLockNotFound {description("txn lock not found")}
+        LockTypeNotMatch {key: Vec<u8>, start_ts: u64} {
+            description("lock type not match")
+            display("lock type not match, key:{} start_ts:{}", escape(key), start_ts)
+        }
+        PessimisticLockRolledBack {key: Vec<u8>, start_ts: u64} {
+            description("pessimistic lock already rolled back")
+            display("pessimistic lock already rolled back, key:{} start_ts:{}",
+                    escape(key),
+                    start_ts)
+        }
         WriteConflict {description("write conflict")}
+        InvalidTxnTso {start_ts: u64, commit_ts: u64} {
+            description("invalid txn tso")
+            display("invalid txn tso with start_ts:{}, commit_ts:{}", start_ts, commit_ts)
+        }
+        Deadlock {key: Vec<u8>, start_ts: u64, lock_ts: u64} {
+            description("deadlock")
+            display("deadlock, txn {} waits for lock of txn {} on key {}",
+                    start_ts,
+                    lock_ts,
+                    escape(key))
+        }
+        AlreadyExist {key: Vec<u8>} {
+            description("already exists")
+            display("key {} already exists", escape(key))
+        }
         KeyVersion {description("bad format key(version)")}
     }
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
+// A timestamp allocated by PD consists of a physical part in milliseconds
+// and a logical part of `TS_LOGICAL_BITS` bits.
+const TS_LOGICAL_BITS: u64 = 18;
+
+pub fn extract_physical(ts: u64) -> u64 {
+    ts >> TS_LOGICAL_BITS
+}
+
+pub fn compose_ts(physical: u64, logical: u64) -> u64 {
+    (physical << TS_LOGICAL_BITS) + logical
+}
+
 // Make sure meta version in tests could never catch up with key version(timestamp).
 pub const TEST_TS_BASE: u64 = 1000000;
@@ -11,12 +11,23 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::u64;
+
 use storage::engine::{Snapshot, Cursor};
 use storage::{Key, Value, CF_LOCK, CF_WRITE};
 use super::{Error, Result};
-use super::lock::Lock;
+use super::lock::{Lock, LockType};
 use super::write::{Write, WriteType};
 
+/// All the MVCC data of a key, it's used for debugging.
+pub struct MvccInfo {
+    pub lock: Option<Lock>,
+    // (commit_ts, write), the latest one comes first.
+    pub writes: Vec<(u64, Write)>,
+    // (start_ts, value) in `CF_DEFAULT`, short values are kept in the lock and writes.
+    pub values: Vec<(u64, Value)>,
+}
+
 pub struct MvccReader<'a> {
     snapshot: &'a Snapshot,
     // cursors are used for speeding up scans.
@@ -93,23 +104,48 @@ impl<'a> MvccReader<'a> {
         Ok(Some((commit_ts, write)))
     }
 
-    pub fn get(&mut self, key: &Key, mut ts: u64) -> Result<Option<Value>> {
+    pub fn get(&mut self, key: &Key, ts: u64) -> Result<Option<Value>> {
+        self.get_impl(key, ts, false)
+    }
+
+    /// Get the value of `key` visible at `ts`. If `key_only` is true, the
+    /// value is not loaded from `CF_DEFAULT` and an empty value is returned
+    /// for an existing key instead.
+    fn get_impl(&mut self, key: &Key, ts: u64, key_only: bool) -> Result<Option<Value>> {
         // Check for locks that signal concurrent writes.
         if let Some(lock) = try!(self.load_lock(key)) {
-            if lock.ts <= ts {
+            // Pessimistic locks hold no data, so they never block readers.
+            if lock.ts <= ts && lock.lock_type != LockType::Pessimistic {
                 // There is a pending lock. Client should wait or clean it.
                 return Err(Error::KeyIsLocked {
                     key: try!(key.raw()),
                     primary: lock.primary,
                     ts: lock.ts,
+                    ttl: lock.ttl,
                 });
             }
         }
+        match try!(self.get_write(key, ts)) {
+            Some(write) => {
+                if key_only {
+                    return Ok(Some(vec![]));
+                }
+                if write.short_value.is_some() {
+                    return Ok(write.short_value);
+                }
+                self.load_data(key, write.start_ts)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Get the Put record of `key` visible at `ts`, locks are ignored.
+    fn get_write(&mut self, key: &Key, mut ts: u64) -> Result<Option<Write>> {
         loop {
             match try!(self.seek_write(key, ts)) {
                 Some((commit_ts, write)) => {
                     match write.write_type {
-                        WriteType::Put => return self.load_data(key, write.start_ts),
+                        WriteType::Put => return Ok(Some(write)),
                         WriteType::Delete => return Ok(None),
                         WriteType::Lock | WriteType::Rollback => ts = commit_ts - 1,
                     }
@@ -119,6 +155,11 @@ impl<'a> MvccReader<'a> {
         }
     }
 
+    /// Check whether `key` has a committed value visible at `ts`, locks are ignored.
+    pub fn key_exist(&mut self, key: &Key, ts: u64) -> Result<bool> {
+        Ok(try!(self.get_write(key, ts)).is_some())
+    }
+
     pub fn get_txn_commit_ts(&mut self, key: &Key, start_ts: u64) -> Result<Option<u64>> {
         if let Some((commit_ts, write)) = try!(self.reverse_seek_write(key, start_ts)) {
             if write.start_ts == start_ts {
@@ -140,18 +181,76 @@ impl<'a> MvccReader<'a> {
         Ok(())
     }
 
-    pub fn seek(&mut self, mut key: Key, ts: u64) -> Result<Option<(Key, Value)>> {
+    /// Find the first user key >= `key` (or the last one < `key` if
+    /// `reverse`) that has a write record or a lock. Short values don't
+    /// exist in `CF_DEFAULT`, so it can't be used to find keys.
+    fn near_seek_key(&mut self, key: &Key, reverse: bool) -> Result<Option<Key>> {
+        if self.write_cursor.is_none() {
+            self.write_cursor = Some(try!(self.snapshot.iter_cf(CF_WRITE)));
+        }
+        if self.lock_cursor.is_none() {
+            self.lock_cursor = Some(try!(self.snapshot.iter_cf(CF_LOCK)));
+        }
+        let write_key = {
+            let mut cursor = self.write_cursor.as_mut().unwrap();
+            let ok = if reverse {
+                try!(cursor.near_reverse_seek(key))
+            } else {
+                try!(cursor.near_seek(key))
+            };
+            if ok {
+                Some(try!(Key::from_encoded(cursor.key().to_vec()).truncate_ts()))
+            } else {
+                None
+            }
+        };
+        let lock_key = {
+            let mut cursor = self.lock_cursor.as_mut().unwrap();
+            let ok = if reverse {
+                try!(cursor.near_reverse_seek(key))
+            } else {
+                try!(cursor.near_seek(key))
+            };
+            if ok {
+                Some(Key::from_encoded(cursor.key().to_vec()))
+            } else {
+                None
+            }
+        };
+        Ok(match (write_key, lock_key) {
+            (Some(w), Some(l)) => {
+                if (w.encoded() < l.encoded()) != reverse {
+                    Some(w)
+                } else {
+                    Some(l)
+                }
+            }
+            (w, None) => w,
+            (None, l) => l,
+        })
+    }
+
+    /// Seek the first key >= `key` that is visible at `ts`. Keys >= `end_key`
+    /// are treated as not found.
+    pub fn seek(&mut self,
+                mut key: Key,
+                end_key: Option<&Key>,
+                ts: u64,
+                key_only: bool)
+                -> Result<Option<(Key, Value)>> {
         try!(self.create_data_cursor());
 
         loop {
-            key = {
-                let mut cursor = self.data_cursor.as_mut().unwrap();
-                if !try!(cursor.near_seek(&key)) {
+            key = match try!(self.near_seek_key(&key, false)) {
+                Some(k) => k,
+                None => return Ok(None),
+            };
+            if let Some(end_key) = end_key {
+                if key.encoded() >= end_key.encoded() {
                     return Ok(None);
                 }
-                try!(Key::from_encoded(cursor.key().to_vec()).truncate_ts())
-            };
-            if let Some(v) = try!(self.get(&key, ts)) {
+            }
+            if let Some(v) = try!(self.get_impl(&key, ts, key_only)) {
                 return Ok(Some((key, v)));
             }
             key = key.append_ts(0);
@@ -162,12 +261,9 @@ impl<'a> MvccReader<'a> {
         try!(self.create_data_cursor());
 
         loop {
-            key = {
-                let mut cursor = self.data_cursor.as_mut().unwrap();
-                if !try!(cursor.near_reverse_seek(&key)) {
-                    return Ok(None);
-                }
-                try!(Key::from_encoded(cursor.key().to_vec()).truncate_ts())
+            key = match try!(self.near_seek_key(&key, true)) {
+                Some(k) => k,
+                None => return Ok(None),
             };
             if let Some(v) = try!(self.get(&key, ts)) {
                 return Ok(Some((key, v)));
@@ -220,4 +316,60 @@ impl<'a> MvccReader<'a> {
             keys.push(key);
         }
     }
+
+    /// Get the lock, all the write records and the values of `key`.
+    pub fn get_mvcc_info(&mut self, key: &Key) -> Result<MvccInfo> {
+        let lock = try!(self.load_lock(key));
+        let mut writes = vec![];
+        let mut ts = u64::MAX;
+        while let Some((commit_ts, write)) = try!(self.seek_write(key, ts)) {
+            writes.push((commit_ts, write));
+            if commit_ts == 0 {
+                break;
+            }
+            ts = commit_ts - 1;
+        }
+
+        let mut start_ts: Vec<u64> = lock.iter().map(|l| l.ts).collect();
+        for &(_, ref write) in &writes {
+            if let WriteType::Put = write.write_type {
+                start_ts.push(write.start_ts);
+            }
+        }
+        let mut values = vec![];
+        for ts in start_ts {
+            if let Some(v) = try!(self.load_data(key, ts)) {
+                values.push((ts, v));
+            }
+        }
+
+        Ok(MvccInfo {
+            lock: lock,
+            writes: writes,
+            values: values,
+        })
+    }
+
+    /// Find a key locked or written by the transaction `start_ts`. Locks are
+    /// checked first, then all the write records are scanned.
+    pub fn seek_ts(&mut self, start_ts: u64) -> Result<Option<Key>> {
+        let mut locks = try!(self.scan_lock(|lock| lock.ts == start_ts));
+        if !locks.is_empty() {
+            return Ok(Some(locks.swap_remove(0).0));
+        }
+
+        if self.write_cursor.is_none() {
+            self.write_cursor = Some(try!(self.snapshot.iter_cf(CF_WRITE)));
+        }
+        let mut cursor = self.write_cursor.as_mut().unwrap();
+        let mut ok = cursor.seek_to_first();
+        while ok {
+            if try!(Write::parse(cursor.value())).start_ts == start_ts {
+                let key = Key::from_encoded(cursor.key().to_vec());
+                return Ok(Some(try!(key.truncate_ts())));
+            }
+            ok = cursor.next();
+        }
+        Ok(None)
+    }
 }
@@ -12,13 +12,21 @@
 // limitations under the License.
 
 use std::fmt;
-use storage::{Key, Value, Mutation, CF_DEFAULT, CF_LOCK, CF_WRITE};
+use storage::{Key, Value, Mutation, Options, CF_DEFAULT, CF_LOCK, CF_WRITE};
 use storage::engine::{Snapshot, Modify};
 use super::reader::MvccReader;
 use super::lock::{LockType, Lock};
-use super::write::{WriteType, Write};
+use super::write::{WriteType, Write, SHORT_VALUE_MAX_LEN};
 use super::{Error, Result};
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TxnStatus {
+    /// The primary lock is still alive, `ttl` is the lock's ttl in milliseconds.
+    Locked { ttl: u64 },
+    Committed { commit_ts: u64 },
+    RolledBack,
+}
+
 pub struct MvccTxn<'a> {
     reader: MvccReader<'a>,
     start_ts: u64,
@@ -44,8 +52,14 @@ impl<'a> MvccTxn<'a> {
         self.writes.drain(..).collect()
     }
 
-    fn lock_key(&mut self, key: Key, lock_type: LockType, primary: Vec<u8>) {
-        let lock = Lock::new(lock_type, primary, self.start_ts);
+    fn lock_key(&mut self,
+                key: Key,
+                lock_type: LockType,
+                primary: Vec<u8>,
+                ttl: u64,
+                for_update_ts: u64,
+                short_value: Option<Value>) {
+        let lock = Lock::new(lock_type, primary, self.start_ts, ttl, for_update_ts, short_value);
         self.writes.push(Modify::Put(CF_LOCK, key, lock.to_bytes()));
     }
 
@@ -57,38 +71,172 @@ impl<'a> MvccTxn<'a> {
         self.reader.get(key, self.start_ts)
     }
 
-    pub fn prewrite(&mut self, mutation: Mutation, primary: &[u8]) -> Result<()> {
-        let key = mutation.key();
-        if let Some((commit, _)) = try!(self.reader.seek_write(&key, u64::max_value())) {
-            // Abort on writes after our start timestamp ...
-            if commit >= self.start_ts {
+    /// Acquire a pessimistic lock on `key`. The lock is taken on the latest
+    /// version as of `for_update_ts`, so only writes committed after
+    /// `for_update_ts` are treated as conflicts.
+    pub fn acquire_pessimistic_lock(&mut self,
+                                    key: &Key,
+                                    primary: &[u8],
+                                    for_update_ts: u64,
+                                    options: &Options)
+                                    -> Result<()> {
+        if let Some(lock) = try!(self.reader.load_lock(key)) {
+            if lock.ts != self.start_ts {
+                return Err(Error::KeyIsLocked {
+                    key: try!(key.raw()),
+                    primary: lock.primary,
+                    ts: lock.ts,
+                    ttl: lock.ttl,
+                });
+            }
+            // Already locked by this transaction, only a pessimistic lock with a
+            // smaller for_update_ts needs to be updated.
+            if lock.lock_type == LockType::Pessimistic && lock.for_update_ts < for_update_ts {
+                self.lock_key(key.clone(),
+                              LockType::Pessimistic,
+                              primary.to_vec(),
+                              options.lock_ttl,
+                              for_update_ts,
+                              None);
+            }
+            return Ok(());
+        }
+        if let Some((commit, _)) = try!(self.reader.seek_write(key, u64::max_value())) {
+            if commit > for_update_ts {
                 return Err(Error::WriteConflict);
             }
         }
-        // ... or locks at any timestamp.
+        if let Some((_, write)) = try!(self.reader.reverse_seek_write(key, self.start_ts)) {
+            if write.start_ts == self.start_ts {
+                if let WriteType::Rollback = write.write_type {
+                    return Err(Error::PessimisticLockRolledBack {
+                        key: try!(key.raw()),
+                        start_ts: self.start_ts,
+                    });
+                }
+            }
+        }
+        self.lock_key(key.clone(),
+                      LockType::Pessimistic,
+                      primary.to_vec(),
+                      options.lock_ttl,
+                      for_update_ts,
+                      None);
+        Ok(())
+    }
+
+    /// Release the pessimistic lock on `key` if it was acquired with a
+    /// for_update_ts not greater than `for_update_ts`. No rollback record is
+    /// written, so the transaction can lock the key again later.
+    pub fn pessimistic_rollback(&mut self, key: &Key, for_update_ts: u64) -> Result<()> {
+        if let Some(lock) = try!(self.reader.load_lock(key)) {
+            if lock.lock_type == LockType::Pessimistic && lock.ts == self.start_ts &&
+               lock.for_update_ts <= for_update_ts {
+                self.unlock_key(key.clone());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn prewrite(&mut self,
+                    mutation: Mutation,
+                    primary: &[u8],
+                    options: &Options)
+                    -> Result<()> {
+        let key = mutation.key();
+        let mut pessimistic_locked = false;
+        // The ts the existence of the key is checked at.
+        let mut read_ts = self.start_ts;
         if let Some(lock) = try!(self.reader.load_lock(&key)) {
+            // Abort on locks at any timestamp ...
             if lock.ts != self.start_ts {
                 return Err(Error::KeyIsLocked {
                     key: try!(key.raw()),
                     primary: lock.primary,
                     ts: lock.ts,
+                    ttl: lock.ttl,
                 });
             }
+            // Conflicts have been checked at for_update_ts when the pessimistic
+            // lock was acquired, the lock is converted in place.
+            if lock.lock_type == LockType::Pessimistic {
+                pessimistic_locked = true;
+                read_ts = lock.for_update_ts;
+            }
         }
-        self.lock_key(key.clone(),
-                      LockType::from_mutation(&mutation),
-                      primary.to_vec());
-
-        if let Mutation::Put((_, ref value)) = mutation {
-            let value_key = key.append_ts(self.start_ts);
-            self.writes.push(Modify::Put(CF_DEFAULT, value_key, value.clone()));
+        if !pessimistic_locked {
+            // ... or writes after our start timestamp.
+            if let Some((commit, _)) = try!(self.reader.seek_write(&key, u64::max_value())) {
+                if commit >= self.start_ts {
+                    return Err(Error::WriteConflict);
+                }
+            }
+        }
+        if let Mutation::Insert(_) = mutation {
+            if try!(self.reader.key_exist(&key, read_ts)) {
+                return Err(Error::AlreadyExist { key: try!(key.raw()) });
+            }
+        }
+        let lock_type = LockType::from_mutation(&mutation);
+        // Short values are inlined into the lock and the write record.
+        let mut short_value = None;
+        match mutation {
+            Mutation::Put((_, ref value)) |
+            Mutation::Insert((_, ref value)) => {
+                if value.len() <= SHORT_VALUE_MAX_LEN {
+                    short_value = Some(value.clone());
+                } else {
+                    let value_key = key.append_ts(self.start_ts);
+                    self.writes.push(Modify::Put(CF_DEFAULT, value_key, value.clone()));
+                }
+            }
+            Mutation::Delete(_) | Mutation::Lock(_) => {}
+        }
+        match options.one_pc_commit_ts {
+            // One-phase commit, write the commit record directly.
+            Some(commit_ts) => {
+                if commit_ts <= self.start_ts {
+                    return Err(Error::InvalidTxnTso {
+                        start_ts: self.start_ts,
+                        commit_ts: commit_ts,
+                    });
+                }
+                if pessimistic_locked {
+                    self.unlock_key(key.clone());
+                }
+                let write = Write::new(WriteType::from_lock_type(lock_type).unwrap(),
+                                       self.start_ts,
+                                       short_value);
+                self.writes.push(Modify::Put(CF_WRITE, key.append_ts(commit_ts), write.to_bytes()));
+            }
+            None => {
+                self.lock_key(key.clone(),
+                              lock_type,
+                              primary.to_vec(),
+                              options.lock_ttl,
+                              0,
+                              short_value)
+            }
         }
         Ok(())
     }
 
     pub fn commit(&mut self, key: &Key, commit_ts: u64) -> Result<()> {
-        let lock_type = match try!(self.reader.load_lock(key)) {
-            Some(ref lock) if lock.ts == self.start_ts => lock.lock_type,
+        let (write_type, short_value) = match try!(self.reader.load_lock(key)) {
+            Some(lock) if lock.ts == self.start_ts => {
+                match WriteType::from_lock_type(lock.lock_type) {
+                    Some(write_type) => (write_type, lock.short_value),
+                    None => {
+                        warn!("commit a pessimistic lock, key:{}, start_ts:{}",
+                              key,
+                              self.start_ts);
+                        return Err(Error::LockTypeNotMatch {
+                            key: try!(key.raw()),
+                            start_ts: self.start_ts,
+                        });
+                    }
+                }
+            }
             _ => {
                 return match try!(self.reader.get_txn_commit_ts(key, self.start_ts)) {
                     // Committed by concurrent transaction.
@@ -104,17 +252,37 @@ impl<'a> MvccTxn<'a> {
                 };
             }
         };
-        let write = Write::new(WriteType::from_lock_type(lock_type), self.start_ts);
+        let write = Write::new(write_type, self.start_ts, short_value);
         self.writes.push(Modify::Put(CF_WRITE, key.append_ts(commit_ts), write.to_bytes()));
         self.unlock_key(key.clone());
         Ok(())
     }
 
+    /// Resolve the lock on `key` left by the transaction, commit it at
+    /// `commit_ts` or roll it back if `commit_ts` is `None`. The key of a
+    /// pessimistic lock is not written by a committed transaction, so the lock
+    /// is released without a write record.
+    pub fn resolve_lock(&mut self, key: &Key, commit_ts: Option<u64>) -> Result<()> {
+        let commit_ts = match commit_ts {
+            Some(ts) => ts,
+            None => return self.rollback(key),
+        };
+        if let Some(lock) = try!(self.reader.load_lock(key)) {
+            if lock.ts == self.start_ts && lock.lock_type == LockType::Pessimistic {
+                self.unlock_key(key.clone());
+                return Ok(());
+            }
+        }
+        self.commit(key, commit_ts)
+    }
+
     pub fn rollback(&mut self, key: &Key) -> Result<()> {
         match try!(self.reader.load_lock(key)) {
             Some(ref lock) if lock.ts == self.start_ts => {
-                let data_key = key.append_ts(lock.ts);
-                self.writes.push(Modify::Delete(CF_DEFAULT, data_key));
+                if lock.short_value.is_none() {
+                    let data_key = key.append_ts(lock.ts);
+                    self.writes.push(Modify::Delete(CF_DEFAULT, data_key));
+                }
             }
             _ => {
                 return match try!(self.reader.get_txn_commit_ts(key, self.start_ts)) {
@@ -131,51 +299,98 @@ impl<'a> MvccTxn<'a> {
                 };
             }
         }
-        self.writes.push(Modify::Put(CF_WRITE,
-                                     key.append_ts(self.start_ts),
-                                     Write::new(WriteType::Rollback, self.start_ts).to_bytes()));
+        let write = Write::new(WriteType::Rollback, self.start_ts, None);
+        self.writes.push(Modify::Put(CF_WRITE, key.append_ts(self.start_ts), write.to_bytes()));
         self.unlock_key(key.clone());
         Ok(())
     }
 
-    pub fn gc(&mut self, key: &Key, safe_point: u64) -> Result<()> {
+    /// Check the status of the transaction whose primary lock is on `primary`.
+    ///
+    /// If the primary lock has expired at `current_ts`, the transaction is
+    /// rolled back so that readers blocked by it can make progress.
+    pub fn check_txn_status(&mut self, primary: &Key, current_ts: u64) -> Result<TxnStatus> {
+        match try!(self.reader.load_lock(primary)) {
+            Some(ref lock) if lock.ts == self.start_ts => {
+                if !lock.is_expired(current_ts) {
+                    return Ok(TxnStatus::Locked { ttl: lock.ttl });
+                }
+                info!("lock ttl expired, rollback txn, key:{}, start_ts:{}, ttl:{}",
+                      primary,
+                      self.start_ts,
+                      lock.ttl);
+            }
+            _ => {
+                if let Some(ts) = try!(self.reader.get_txn_commit_ts(primary, self.start_ts)) {
+                    return Ok(TxnStatus::Committed { commit_ts: ts });
+                }
+                // The prewrite of the primary may be delayed, write a rollback
+                // record so it fails with a write conflict when it arrives.
+                let write = Write::new(WriteType::Rollback, self.start_ts, None);
+                let write_key = primary.append_ts(self.start_ts);
+                self.writes.push(Modify::Put(CF_WRITE, write_key, write.to_bytes()));
+                return Ok(TxnStatus::RolledBack);
+            }
+        }
+        try!(self.rollback(primary));
+        Ok(TxnStatus::RolledBack)
+    }
+
+    /// Delete the versions of `key` that are invisible to any reader after
+    /// `safe_point`, and return how many of them are deleted.
+    pub fn gc(&mut self, key: &Key, safe_point: u64) -> Result<usize> {
         let mut after_safe_point = false;
+        let mut deleted = 0;
         let mut ts: u64 = u64::max_value();
         while let Some((commit, write)) = try!(self.reader.seek_write(key, ts)) {
             if !after_safe_point {
                 if commit <= safe_point {
-                    // Set `after_safe_point` after the latest write after `safe_point`.
-                    after_safe_point = true;
-                    // Latest write can be deleted if its type is Delete/Rollback.
-                    match write.write_type {
-                        WriteType::Delete | WriteType::Rollback => {
-                            self.writes.push(Modify::Delete(CF_WRITE, key.append_ts(commit)))
+                    // Set `after_safe_point` after the latest Put/Delete before `safe_point`,
+                    // all versions older than it are invisible. Rollback and Lock are skipped
+                    // by readers, so they can be deleted without exposing older versions.
+                    let keep = match write.write_type {
+                        WriteType::Put => {
+                            after_safe_point = true;
+                            true
                         }
-                        WriteType::Put | WriteType::Lock => {}
+                        WriteType::Delete => {
+                            after_safe_point = true;
+                            false
+                        }
+                        WriteType::Rollback | WriteType::Lock => false,
+                    };
+                    if !keep {
+                        self.writes.push(Modify::Delete(CF_WRITE, key.append_ts(commit)));
+                        deleted += 1;
                     }
                 }
             } else {
                 // Delete all data after safe point.
                 self.writes.push(Modify::Delete(CF_WRITE, key.append_ts(commit)));
-                self.writes.push(Modify::Delete(CF_DEFAULT, key.append_ts(write.start_ts)));
+                if write.short_value.is_none() {
+                    self.writes.push(Modify::Delete(CF_DEFAULT, key.append_ts(write.start_ts)));
+                }
+                deleted += 1;
             }
             ts = commit - 1;
         }
-        Ok(())
+        Ok(deleted)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use kvproto::kvrpcpb::Context;
-    use super::MvccTxn;
-    use storage::{make_key, Mutation, DEFAULT_CFS};
-    use storage::engine::{self, Engine, Dsn, TEMP_DIR};
-    use storage::mvcc::TEST_TS_BASE;
+    use super::{MvccTxn, TxnStatus};
+    use super::super::MvccReader;
+    use storage::{make_key, Mutation, Options, DEFAULT_CFS};
+    use storage::engine::{self, Engine, Dsn};
+    use storage::mvcc::{Error, TEST_TS_BASE, compose_ts};
+    use storage::mvcc::write::SHORT_VALUE_MAX_LEN;
 
     #[test]
     fn test_mvcc_txn_read() {
-        let engine = engine::new_engine(Dsn::RocksDBPath(TEMP_DIR), DEFAULT_CFS).unwrap();
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
 
         must_get_none(engine.as_ref(), b"x", 1);
 
@@ -197,9 +412,30 @@ mod tests {
         must_get_none(engine.as_ref(), b"x", 23);
     }
 
+    #[test]
+    fn test_mvcc_txn_long_value() {
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
+        let long_value = vec![b'v'; SHORT_VALUE_MAX_LEN + 1];
+        let short_value = vec![b'v'; SHORT_VALUE_MAX_LEN];
+
+        must_prewrite_put(engine.as_ref(), b"x", &long_value, b"x", 5);
+        must_commit(engine.as_ref(), b"x", 5, 10);
+        must_prewrite_put(engine.as_ref(), b"x", &short_value, b"x", 15);
+        must_commit(engine.as_ref(), b"x", 15, 20);
+        must_prewrite_put(engine.as_ref(), b"x", &long_value, b"x", 25);
+        must_rollback(engine.as_ref(), b"x", 25);
+        must_get(engine.as_ref(), b"x", 12, &long_value);
+        must_get(engine.as_ref(), b"x", 22, &short_value);
+        must_get(engine.as_ref(), b"x", 30, &short_value);
+
+        must_gc(engine.as_ref(), b"x", 22);
+        must_get_none(engine.as_ref(), b"x", 12);
+        must_get(engine.as_ref(), b"x", 22, &short_value);
+    }
+
     #[test]
     fn test_mvcc_txn_prewrite() {
-        let engine = engine::new_engine(Dsn::RocksDBPath(TEMP_DIR), DEFAULT_CFS).unwrap();
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
 
         must_prewrite_put(engine.as_ref(), b"x", b"x5", b"x", 5);
         // Key is locked.
@@ -219,7 +455,7 @@ mod tests {
 
     #[test]
     fn test_mvcc_txn_commit_ok() {
-        let engine = engine::new_engine(Dsn::RocksDBPath(TEMP_DIR), DEFAULT_CFS).unwrap();
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
         must_prewrite_put(engine.as_ref(), b"x", b"x10", b"x", 10);
         must_prewrite_lock(engine.as_ref(), b"y", b"x", 10);
         must_commit(engine.as_ref(), b"x", 10, 15);
@@ -231,7 +467,7 @@ mod tests {
 
     #[test]
     fn test_mvcc_txn_commit_err() {
-        let engine = engine::new_engine(Dsn::RocksDBPath(TEMP_DIR), DEFAULT_CFS).unwrap();
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
 
         // Not prewrite yet
         must_commit_err(engine.as_ref(), b"x", 1, 2);
@@ -245,7 +481,7 @@ mod tests {
 
     #[test]
     fn test_mvcc_txn_rollback() {
-        let engine = engine::new_engine(Dsn::RocksDBPath(TEMP_DIR), DEFAULT_CFS).unwrap();
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
 
         must_prewrite_put(engine.as_ref(), b"x", b"x5", b"x", 5);
         must_rollback(engine.as_ref(), b"x", 5);
@@ -260,7 +496,7 @@ mod tests {
 
     #[test]
     fn test_mvcc_txn_rollback_err() {
-        let engine = engine::new_engine(Dsn::RocksDBPath(TEMP_DIR), DEFAULT_CFS).unwrap();
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
 
         must_prewrite_put(engine.as_ref(), b"x", b"x5", b"x", 5);
         must_commit(engine.as_ref(), b"x", 5, 10);
@@ -269,7 +505,7 @@ mod tests {
 
     #[test]
     fn test_gc() {
-        let engine = engine::new_engine(Dsn::RocksDBPath(TEMP_DIR), DEFAULT_CFS).unwrap();
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
 
         must_prewrite_put(engine.as_ref(), b"x", b"x5", b"x", 5);
         must_commit(engine.as_ref(), b"x", 5, 10);
@@ -278,17 +514,236 @@ mod tests {
         must_prewrite_delete(engine.as_ref(), b"x", b"x", 25);
         must_commit(engine.as_ref(), b"x", 25, 30);
 
-        must_gc(engine.as_ref(), b"x", 12);
+        assert_eq!(must_gc(engine.as_ref(), b"x", 12), 0);
         must_get(engine.as_ref(), b"x", 12, b"x5");
 
-        must_gc(engine.as_ref(), b"x", 22);
+        assert_eq!(must_gc(engine.as_ref(), b"x", 22), 1);
         must_get_none(engine.as_ref(), b"x", 12);
 
-        must_gc(engine.as_ref(), b"x", 32);
+        assert_eq!(must_gc(engine.as_ref(), b"x", 32), 2);
         must_get_none(engine.as_ref(), b"x", 22);
         must_get_none(engine.as_ref(), b"x", 40);
     }
 
+    #[test]
+    fn test_gc_with_rollback_and_lock() {
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
+
+        must_prewrite_put(engine.as_ref(), b"x", b"x5", b"x", 5);
+        must_commit(engine.as_ref(), b"x", 5, 10);
+        must_prewrite_put(engine.as_ref(), b"x", b"x15", b"x", 15);
+        must_rollback(engine.as_ref(), b"x", 15);
+        must_prewrite_lock(engine.as_ref(), b"x", b"x", 25);
+        must_commit(engine.as_ref(), b"x", 25, 30);
+
+        // Rollback and Lock records don't hide the Put before them.
+        assert_eq!(must_gc(engine.as_ref(), b"x", 40), 2);
+        must_get(engine.as_ref(), b"x", 40, b"x5");
+        assert_eq!(must_gc(engine.as_ref(), b"x", 40), 0);
+    }
+
+    #[test]
+    fn test_check_txn_status() {
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
+        let (ts1, ts2, ts3) = (compose_ts(100, 0), compose_ts(200, 0), compose_ts(300, 0));
+
+        // Lock is alive.
+        must_prewrite_put_with_ttl(engine.as_ref(), b"k", b"v", b"k", ts1, 150);
+        must_check_txn_status(engine.as_ref(),
+                              b"k",
+                              ts1,
+                              compose_ts(249, 0),
+                              TxnStatus::Locked { ttl: 150 });
+        must_get_err(engine.as_ref(), b"k", ts2);
+        // Lock is expired, txn should be rolled back.
+        must_check_txn_status(engine.as_ref(),
+                              b"k",
+                              ts1,
+                              compose_ts(250, 0),
+                              TxnStatus::RolledBack);
+        must_get_none(engine.as_ref(), b"k", ts2);
+        must_commit_err(engine.as_ref(), b"k", ts1, ts2);
+        // Check again after rollback.
+        must_check_txn_status(engine.as_ref(), b"k", ts1, ts2, TxnStatus::RolledBack);
+
+        // Committed txn.
+        must_prewrite_put_with_ttl(engine.as_ref(), b"k", b"v", b"k", ts2, 10);
+        must_commit(engine.as_ref(), b"k", ts2, ts2 + 1);
+        must_check_txn_status(engine.as_ref(),
+                              b"k",
+                              ts2,
+                              ts3,
+                              TxnStatus::Committed { commit_ts: to_fake_ts(ts2 + 1) });
+
+        // Lock without ttl never expires.
+        must_prewrite_put_with_ttl(engine.as_ref(), b"k", b"v", b"k", ts3, 0);
+        must_check_txn_status(engine.as_ref(),
+                              b"k",
+                              ts3,
+                              compose_ts(1000000, 0),
+                              TxnStatus::Locked { ttl: 0 });
+    }
+
+    #[test]
+    fn test_check_txn_status_before_prewrite() {
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
+        let (ts1, ts2) = (compose_ts(100, 0), compose_ts(200, 0));
+
+        // The primary is not prewritten yet, the txn is rolled back.
+        must_check_txn_status(engine.as_ref(), b"k", ts1, ts2, TxnStatus::RolledBack);
+
+        // The delayed prewrite of the primary must not succeed.
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(ts1));
+        match txn.prewrite(Mutation::Put((make_key(b"k"), b"v".to_vec())),
+                           b"k",
+                           &Options::default()) {
+            Err(Error::WriteConflict) => {}
+            res => panic!("expect WriteConflict, got {:?}", res),
+        }
+        must_get_none(engine.as_ref(), b"k", ts2);
+        must_check_txn_status(engine.as_ref(), b"k", ts1, ts2, TxnStatus::RolledBack);
+    }
+
+    #[test]
+    fn test_pessimistic_lock() {
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
+
+        must_prewrite_put(engine.as_ref(), b"x", b"x5", b"x", 5);
+        must_commit(engine.as_ref(), b"x", 5, 10);
+        // Write conflict with for_update_ts.
+        must_acquire_pessimistic_lock_err(engine.as_ref(), b"x", b"x", 8, 8);
+        must_acquire_pessimistic_lock(engine.as_ref(), b"x", b"x", 8, 12);
+        // Pessimistic lock doesn't block readers.
+        must_get(engine.as_ref(), b"x", 15, b"x5");
+        // But blocks other writers.
+        must_prewrite_lock_err(engine.as_ref(), b"x", b"x", 13);
+        must_acquire_pessimistic_lock_err(engine.as_ref(), b"x", b"x", 13, 13);
+        // Acquire again should be idempotent.
+        must_acquire_pessimistic_lock(engine.as_ref(), b"x", b"x", 8, 14);
+        // Prewrite converts the pessimistic lock without checking conflicts.
+        must_prewrite_put(engine.as_ref(), b"x", b"x8", b"x", 8);
+        must_get_err(engine.as_ref(), b"x", 15);
+        must_commit(engine.as_ref(), b"x", 8, 15);
+        must_get(engine.as_ref(), b"x", 16, b"x8");
+
+        // Pessimistic lock can't be committed directly.
+        must_acquire_pessimistic_lock(engine.as_ref(), b"y", b"y", 20, 20);
+        must_commit_err(engine.as_ref(), b"y", 20, 21);
+        // Pessimistic rollback with a smaller for_update_ts is a no-op.
+        must_pessimistic_rollback(engine.as_ref(), b"y", 20, 19);
+        must_acquire_pessimistic_lock_err(engine.as_ref(), b"y", b"y", 22, 22);
+        must_pessimistic_rollback(engine.as_ref(), b"y", 20, 20);
+        must_acquire_pessimistic_lock(engine.as_ref(), b"y", b"y", 22, 22);
+        must_rollback(engine.as_ref(), b"y", 22);
+        // Can't lock again after the txn is rolled back.
+        must_acquire_pessimistic_lock_err(engine.as_ref(), b"y", b"y", 22, 23);
+        must_get_none(engine.as_ref(), b"y", 30);
+    }
+
+    #[test]
+    fn test_resolve_pessimistic_lock() {
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
+
+        // The pessimistic lock of a committed txn is released without a write.
+        must_acquire_pessimistic_lock(engine.as_ref(), b"x", b"x", 5, 5);
+        must_acquire_pessimistic_lock(engine.as_ref(), b"y", b"x", 5, 5);
+        must_prewrite_put(engine.as_ref(), b"x", b"x5", b"x", 5);
+        must_resolve_lock(engine.as_ref(), b"x", 5, Some(10));
+        must_resolve_lock(engine.as_ref(), b"y", 5, Some(10));
+        must_get(engine.as_ref(), b"x", 15, b"x5");
+        must_get_none(engine.as_ref(), b"y", 15);
+        must_prewrite_lock(engine.as_ref(), b"y", b"y", 15);
+        must_rollback(engine.as_ref(), b"y", 15);
+
+        // A rolled back txn can't lock the key again.
+        must_acquire_pessimistic_lock(engine.as_ref(), b"y", b"y", 20, 20);
+        must_resolve_lock(engine.as_ref(), b"y", 20, None);
+        must_acquire_pessimistic_lock_err(engine.as_ref(), b"y", b"y", 20, 25);
+        must_get_none(engine.as_ref(), b"y", 30);
+    }
+
+    #[test]
+    fn test_one_pc() {
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
+
+        must_prewrite_put_one_pc(engine.as_ref(), b"x", b"x5", 5, 10);
+        // No lock is left.
+        must_get_none(engine.as_ref(), b"x", 7);
+        must_get(engine.as_ref(), b"x", 12, b"x5");
+        must_commit_err(engine.as_ref(), b"x", 5, 10);
+        // Conflicts are still checked.
+        must_prewrite_lock_err(engine.as_ref(), b"x", b"x", 8);
+        // commit_ts must be greater than start_ts.
+        must_prewrite_put_one_pc_err(engine.as_ref(), b"x", b"x15", 15, 15);
+
+        // Pessimistic lock is removed by one-phase commit.
+        must_acquire_pessimistic_lock(engine.as_ref(), b"x", b"x", 20, 20);
+        must_prewrite_put_one_pc(engine.as_ref(), b"x", b"x20", 20, 25);
+        must_get(engine.as_ref(), b"x", 30, b"x20");
+        must_prewrite_lock(engine.as_ref(), b"x", b"x", 30);
+    }
+
+    #[test]
+    fn test_insert() {
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
+
+        must_prewrite_insert(engine.as_ref(), b"x", b"x5", b"x", 5);
+        must_commit(engine.as_ref(), b"x", 5, 10);
+        must_get(engine.as_ref(), b"x", 12, b"x5");
+        must_prewrite_insert_err(engine.as_ref(), b"x", b"x15", b"x", 15);
+
+        // Insert after the key is deleted, Lock and Rollback records are skipped.
+        must_prewrite_delete(engine.as_ref(), b"x", b"x", 20);
+        must_commit(engine.as_ref(), b"x", 20, 25);
+        must_prewrite_lock(engine.as_ref(), b"x", b"x", 30);
+        must_commit(engine.as_ref(), b"x", 30, 35);
+        must_prewrite_put(engine.as_ref(), b"x", b"x40", b"x", 40);
+        must_rollback(engine.as_ref(), b"x", 40);
+        let long_value = vec![b'v'; SHORT_VALUE_MAX_LEN + 1];
+        must_prewrite_insert(engine.as_ref(), b"x", &long_value, b"x", 45);
+        must_commit(engine.as_ref(), b"x", 45, 50);
+        must_get(engine.as_ref(), b"x", 55, &long_value);
+        must_prewrite_insert_err(engine.as_ref(), b"x", b"x60", b"x", 60);
+
+        // With a pessimistic lock, the key is checked at for_update_ts.
+        must_prewrite_insert(engine.as_ref(), b"y", b"y65", b"y", 65);
+        must_commit(engine.as_ref(), b"y", 65, 70);
+        must_acquire_pessimistic_lock(engine.as_ref(), b"y", b"y", 68, 72);
+        must_prewrite_insert_err(engine.as_ref(), b"y", b"y68", b"y", 68);
+    }
+
+    #[test]
+    fn test_mvcc_info() {
+        let engine = engine::new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
+
+        let long_value = vec![b'v'; SHORT_VALUE_MAX_LEN + 1];
+        must_prewrite_put(engine.as_ref(), b"x", &long_value, b"x", 5);
+        must_commit(engine.as_ref(), b"x", 5, 10);
+        must_prewrite_put(engine.as_ref(), b"x", b"x15", b"x", 15);
+        must_rollback(engine.as_ref(), b"x", 15);
+        must_prewrite_delete(engine.as_ref(), b"x", b"x", 20);
+        must_commit(engine.as_ref(), b"x", 20, 25);
+        must_prewrite_put(engine.as_ref(), b"x", &long_value, b"x", 30);
+
+        let snapshot = engine.snapshot(&Context::new()).unwrap();
+        let mut reader = MvccReader::new(snapshot.as_ref());
+        let info = reader.get_mvcc_info(&make_key(b"x")).unwrap();
+        assert_eq!(info.lock.unwrap().ts, to_fake_ts(30));
+        let writes: Vec<_> = info.writes.iter().map(|&(ts, ref w)| (ts, w.start_ts)).collect();
+        assert_eq!(writes,
+                   vec![(to_fake_ts(25), to_fake_ts(20)),
+                        (to_fake_ts(15), to_fake_ts(15)),
+                        (to_fake_ts(10), to_fake_ts(5))]);
+        assert_eq!(info.values,
+                   vec![(to_fake_ts(30), long_value.clone()), (to_fake_ts(5), long_value)]);
+
+        assert_eq!(reader.seek_ts(to_fake_ts(20)).unwrap(), Some(make_key(b"x")));
+        assert_eq!(reader.seek_ts(to_fake_ts(30)).unwrap(), Some(make_key(b"x")));
+        assert_eq!(reader.seek_ts(to_fake_ts(10)).unwrap(), None);
+    }
+
     fn to_fake_ts(ts: u64) -> u64 {
         TEST_TS_BASE + ts
     }
@@ -318,15 +773,92 @@ mod tests {
         let ctx = Context::new();
         let snapshot = engine.snapshot(&ctx).unwrap();
         let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(ts));
-        txn.prewrite(Mutation::Put((make_key(key), value.to_vec())), pk).unwrap();
+        txn.prewrite(Mutation::Put((make_key(key), value.to_vec())),
+                      pk,
+                      &Options::default())
+            .unwrap();
+        engine.write(&ctx, txn.modifies()).unwrap();
+    }
+
+    fn must_prewrite_put_with_ttl(engine: &Engine,
+                                  key: &[u8],
+                                  value: &[u8],
+                                  pk: &[u8],
+                                  ts: u64,
+                                  ttl: u64) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(ts));
+        txn.prewrite(Mutation::Put((make_key(key), value.to_vec())),
+                      pk,
+                      &Options::new(ttl))
+            .unwrap();
         engine.write(&ctx, txn.modifies()).unwrap();
     }
 
+    fn one_pc_options(commit_ts: u64) -> Options {
+        let mut options = Options::default();
+        options.one_pc_commit_ts = Some(to_fake_ts(commit_ts));
+        options
+    }
+
+    fn must_prewrite_put_one_pc(engine: &Engine,
+                                key: &[u8],
+                                value: &[u8],
+                                start_ts: u64,
+                                commit_ts: u64) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(start_ts));
+        txn.prewrite(Mutation::Put((make_key(key), value.to_vec())),
+                      key,
+                      &one_pc_options(commit_ts))
+            .unwrap();
+        engine.write(&ctx, txn.modifies()).unwrap();
+    }
+
+    fn must_prewrite_put_one_pc_err(engine: &Engine,
+                                    key: &[u8],
+                                    value: &[u8],
+                                    start_ts: u64,
+                                    commit_ts: u64) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(start_ts));
+        assert!(txn.prewrite(Mutation::Put((make_key(key), value.to_vec())),
+                             key,
+                             &one_pc_options(commit_ts))
+            .is_err());
+    }
+
+    fn must_prewrite_insert(engine: &Engine, key: &[u8], value: &[u8], pk: &[u8], ts: u64) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(ts));
+        txn.prewrite(Mutation::Insert((make_key(key), value.to_vec())),
+                      pk,
+                      &Options::default())
+            .unwrap();
+        engine.write(&ctx, txn.modifies()).unwrap();
+    }
+
+    fn must_prewrite_insert_err(engine: &Engine, key: &[u8], value: &[u8], pk: &[u8], ts: u64) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(ts));
+        match txn.prewrite(Mutation::Insert((make_key(key), value.to_vec())),
+                           pk,
+                           &Options::default()) {
+            Err(Error::AlreadyExist { .. }) => {}
+            res => panic!("expect AlreadyExist, got {:?}", res),
+        }
+    }
+
     fn must_prewrite_delete(engine: &Engine, key: &[u8], pk: &[u8], ts: u64) {
         let ctx = Context::new();
         let snapshot = engine.snapshot(&ctx).unwrap();
         let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(ts));
-        txn.prewrite(Mutation::Delete(make_key(key)), pk).unwrap();
+        txn.prewrite(Mutation::Delete(make_key(key)), pk, &Options::default()).unwrap();
         engine.write(&ctx, txn.modifies()).unwrap();
     }
 
@@ -334,7 +866,7 @@ mod tests {
         let ctx = Context::new();
         let snapshot = engine.snapshot(&ctx).unwrap();
         let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(ts));
-        txn.prewrite(Mutation::Lock(make_key(key)), pk).unwrap();
+        txn.prewrite(Mutation::Lock(make_key(key)), pk, &Options::default()).unwrap();
         engine.write(&ctx, txn.modifies()).unwrap();
     }
 
@@ -342,7 +874,46 @@ mod tests {
         let ctx = Context::new();
         let snapshot = engine.snapshot(&ctx).unwrap();
         let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(ts));
-        assert!(txn.prewrite(Mutation::Lock(make_key(key)), pk).is_err());
+        assert!(txn.prewrite(Mutation::Lock(make_key(key)), pk, &Options::default()).is_err());
+    }
+
+    fn must_acquire_pessimistic_lock(engine: &Engine,
+                                     key: &[u8],
+                                     pk: &[u8],
+                                     start_ts: u64,
+                                     for_update_ts: u64) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(start_ts));
+        txn.acquire_pessimistic_lock(&make_key(key),
+                                      pk,
+                                      to_fake_ts(for_update_ts),
+                                      &Options::default())
+            .unwrap();
+        engine.write(&ctx, txn.modifies()).unwrap();
+    }
+
+    fn must_acquire_pessimistic_lock_err(engine: &Engine,
+                                         key: &[u8],
+                                         pk: &[u8],
+                                         start_ts: u64,
+                                         for_update_ts: u64) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(start_ts));
+        assert!(txn.acquire_pessimistic_lock(&make_key(key),
+                                             pk,
+                                             to_fake_ts(for_update_ts),
+                                             &Options::default())
+            .is_err());
+    }
+
+    fn must_pessimistic_rollback(engine: &Engine, key: &[u8], start_ts: u64, for_update_ts: u64) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(start_ts));
+        txn.pessimistic_rollback(&make_key(key), to_fake_ts(for_update_ts)).unwrap();
+        engine.write(&ctx, txn.modifies()).unwrap();
     }
 
     fn must_commit(engine: &Engine, key: &[u8], start_ts: u64, commit_ts: u64) {
@@ -360,6 +931,14 @@ mod tests {
         assert!(txn.commit(&make_key(key), to_fake_ts(commit_ts)).is_err());
     }
 
+    fn must_resolve_lock(engine: &Engine, key: &[u8], start_ts: u64, commit_ts: Option<u64>) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(start_ts));
+        txn.resolve_lock(&make_key(key), commit_ts.map(to_fake_ts)).unwrap();
+        engine.write(&ctx, txn.modifies()).unwrap();
+    }
+
     fn must_rollback(engine: &Engine, key: &[u8], start_ts: u64) {
         let ctx = Context::new();
         let snapshot = engine.snapshot(&ctx).unwrap();
@@ -375,11 +954,25 @@ mod tests {
         assert!(txn.rollback(&make_key(key)).is_err());
     }
 
-    fn must_gc(engine: &Engine, key: &[u8], safe_point: u64) {
+    fn must_check_txn_status(engine: &Engine,
+                             key: &[u8],
+                             lock_ts: u64,
+                             current_ts: u64,
+                             expect: TxnStatus) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut txn = MvccTxn::new(snapshot.as_ref(), to_fake_ts(lock_ts));
+        let status = txn.check_txn_status(&make_key(key), to_fake_ts(current_ts)).unwrap();
+        assert_eq!(status, expect);
+        engine.write(&ctx, txn.modifies()).unwrap();
+    }
+
+    fn must_gc(engine: &Engine, key: &[u8], safe_point: u64) -> usize {
         let ctx = Context::new();
         let snapshot = engine.snapshot(&ctx).unwrap();
         let mut txn = MvccTxn::new(snapshot.as_ref(), 0);
-        txn.gc(&make_key(key), to_fake_ts(safe_point)).unwrap();
+        let deleted = txn.gc(&make_key(key), to_fake_ts(safe_point)).unwrap();
         engine.write(&ctx, txn.modifies()).unwrap();
+        deleted
     }
 }
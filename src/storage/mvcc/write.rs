@@ -12,10 +12,41 @@
 // limitations under the License.
 
 use byteorder::ReadBytesExt;
+use storage::Value;
 use util::codec::number::{NumberEncoder, NumberDecoder, MAX_VAR_U64_LEN};
 use super::lock::LockType;
 use super::{Error, Result};
 
+// Values not longer than this are stored in the lock and the write record
+// instead of `CF_DEFAULT`.
+pub const SHORT_VALUE_MAX_LEN: usize = 64;
+pub const SHORT_VALUE_PREFIX: u8 = b'v';
+
+/// Encode `value` as `SHORT_VALUE_PREFIX`, len(1 byte), value.
+pub fn encode_short_value(b: &mut Vec<u8>, value: &[u8]) {
+    assert!(value.len() <= SHORT_VALUE_MAX_LEN);
+    b.push(SHORT_VALUE_PREFIX);
+    b.push(value.len() as u8);
+    b.extend_from_slice(value);
+}
+
+/// Decode the short value encoded by `encode_short_value`, an empty input
+/// means there is no short value.
+pub fn decode_short_value(mut b: &[u8]) -> Option<Option<Value>> {
+    if b.is_empty() {
+        return Some(None);
+    }
+    if b[0] != SHORT_VALUE_PREFIX || b.len() < 2 {
+        return None;
+    }
+    let len = b[1] as usize;
+    b = &b[2..];
+    if b.len() != len {
+        return None;
+    }
+    Some(Some(b.to_vec()))
+}
+
 #[derive(Debug,Clone,Copy)]
 pub enum WriteType {
     Put,
@@ -30,11 +61,14 @@ const FLAG_LOCK: u8 = b'L';
 const FLAG_ROLLBACK: u8 = b'R';
 
 impl WriteType {
-    pub fn from_lock_type(tp: LockType) -> WriteType {
+    /// Pessimistic locks must be prewritten before commit, so they have no
+    /// corresponding write type.
+    pub fn from_lock_type(tp: LockType) -> Option<WriteType> {
         match tp {
-            LockType::Put => WriteType::Put,
-            LockType::Delete => WriteType::Delete,
-            LockType::Lock => WriteType::Lock,
+            LockType::Put | LockType::Insert => Some(WriteType::Put),
+            LockType::Delete => Some(WriteType::Delete),
+            LockType::Lock => Some(WriteType::Lock),
+            LockType::Pessimistic => None,
         }
     }
 
@@ -61,20 +95,26 @@ impl WriteType {
 pub struct Write {
     pub write_type: WriteType,
     pub start_ts: u64,
+    // the value of a Put, if it's short enough to be inlined.
+    pub short_value: Option<Value>,
 }
 
 impl Write {
-    pub fn new(write_type: WriteType, start_ts: u64) -> Write {
+    pub fn new(write_type: WriteType, start_ts: u64, short_value: Option<Value>) -> Write {
         Write {
             write_type: write_type,
             start_ts: start_ts,
+            short_value: short_value,
         }
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut b = Vec::with_capacity(1 + MAX_VAR_U64_LEN);
+        let mut b = Vec::with_capacity(1 + MAX_VAR_U64_LEN + 2 + SHORT_VALUE_MAX_LEN);
         b.push(self.write_type.to_u8());
         b.encode_var_u64(self.start_ts).unwrap();
+        if let Some(ref v) = self.short_value {
+            encode_short_value(&mut b, v);
+        }
         b
     }
 
@@ -84,6 +124,36 @@ impl Write {
         }
         let write_type = try!(WriteType::from_u8(try!(b.read_u8())).ok_or(Error::BadFormatWrite));
         let start_ts = try!(b.decode_var_u64());
-        Ok(Write::new(write_type, start_ts))
+        let short_value = try!(decode_short_value(b).ok_or(Error::BadFormatWrite));
+        Ok(Write::new(write_type, start_ts, short_value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::codec::number::NumberEncoder;
+
+    #[test]
+    fn test_write() {
+        let write = Write::new(WriteType::Put, 1, Some(b"v".to_vec()));
+        let write = Write::parse(&write.to_bytes()).unwrap();
+        assert_eq!(write.start_ts, 1);
+        assert_eq!(write.short_value, Some(b"v".to_vec()));
+
+        let write = Write::new(WriteType::Put, 1, None);
+        let write = Write::parse(&write.to_bytes()).unwrap();
+        assert_eq!(write.short_value, None);
+
+        // Write without short value.
+        let mut b = vec![FLAG_PUT];
+        b.encode_var_u64(1).unwrap();
+        let write = Write::parse(&b).unwrap();
+        assert_eq!(write.start_ts, 1);
+        assert_eq!(write.short_value, None);
+
+        b.push(SHORT_VALUE_PREFIX);
+        assert!(Write::parse(&b).is_err());
+        assert!(Write::parse(b"").is_err());
     }
 }
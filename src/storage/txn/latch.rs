@@ -71,6 +71,12 @@ impl Latches {
         Lock::new(slots)
     }
 
+    /// Generate a lock of all the slots, it's used by the commands whose keys
+    /// can't be enumerated, like deleting a range.
+    pub fn gen_lock_all(&self) -> Lock {
+        Lock::new((0..self.size).collect())
+    }
+
     pub fn acquire(&mut self, lock: &mut Lock, who: u64) -> bool {
         let mut acquired_count: usize = 0;
         for i in &lock.required_slots[lock.owned_count..] {
@@ -196,4 +202,29 @@ mod tests {
         assert_eq!(acquired_c, true);
 
     }
+
+    #[test]
+    fn test_lock_all() {
+        let mut latches = Latches::new(256);
+
+        let mut lock_a = Lock::new(vec![3]);
+        let mut lock_b = latches.gen_lock_all();
+        let mut lock_c = Lock::new(vec![1]);
+        let cid_a: u64 = 1;
+        let cid_b: u64 = 2;
+        let cid_c: u64 = 3;
+
+        // b waits for a on slot 3, and c waits for b on slot 1.
+        assert!(latches.acquire(&mut lock_a, cid_a));
+        assert!(!latches.acquire(&mut lock_b, cid_b));
+        assert!(!latches.acquire(&mut lock_c, cid_c));
+
+        let wakeup = latches.release(&lock_a, cid_a);
+        assert_eq!(wakeup, vec![cid_b]);
+        assert!(latches.acquire(&mut lock_b, cid_b));
+
+        let wakeup = latches.release(&lock_b, cid_b);
+        assert_eq!(wakeup, vec![cid_c]);
+        assert!(latches.acquire(&mut lock_c, cid_c));
+    }
 }
@@ -0,0 +1,230 @@
+// Copyright 2016 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use storage::Key;
+
+/// A command waiting for the lock of transaction `lock_ts` to be released.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waiter {
+    pub cid: u64,
+    // distinguishes the waits of the same command, a command may wait again
+    // after being woken up while the timer of its previous wait is pending
+    pub wait_seq: u64,
+    pub start_ts: u64,
+    pub lock_ts: u64,
+}
+
+/// Wait-for graph between transactions, an edge `a -> b` means transaction
+/// `a` is waiting for a lock held by transaction `b`.
+pub struct DeadlockDetector {
+    // waiter ts -> (lock ts -> count of waiting commands)
+    wait_for_map: HashMap<u64, HashMap<u64, usize>>,
+}
+
+impl DeadlockDetector {
+    pub fn new() -> DeadlockDetector {
+        DeadlockDetector { wait_for_map: HashMap::new() }
+    }
+
+    /// Add an edge `waiter_ts -> lock_ts` if it doesn't form a cycle, return
+    /// false if it does.
+    pub fn detect(&mut self, waiter_ts: u64, lock_ts: u64) -> bool {
+        if self.reachable(lock_ts, waiter_ts) {
+            return false;
+        }
+        let locks = self.wait_for_map.entry(waiter_ts).or_insert_with(HashMap::new);
+        *locks.entry(lock_ts).or_insert(0) += 1;
+        true
+    }
+
+    pub fn clean_up_wait_for(&mut self, waiter_ts: u64, lock_ts: u64) {
+        let remove_waiter = match self.wait_for_map.get_mut(&waiter_ts) {
+            Some(locks) => {
+                let remove_lock = match locks.get_mut(&lock_ts) {
+                    Some(cnt) => {
+                        *cnt -= 1;
+                        *cnt == 0
+                    }
+                    None => false,
+                };
+                if remove_lock {
+                    locks.remove(&lock_ts);
+                }
+                locks.is_empty()
+            }
+            None => false,
+        };
+        if remove_waiter {
+            self.wait_for_map.remove(&waiter_ts);
+        }
+    }
+
+    fn reachable(&self, from: u64, to: u64) -> bool {
+        let mut visited = vec![from];
+        let mut stack = vec![from];
+        while let Some(ts) = stack.pop() {
+            if ts == to {
+                return true;
+            }
+            if let Some(locks) = self.wait_for_map.get(&ts) {
+                for next in locks.keys() {
+                    if !visited.contains(next) {
+                        visited.push(*next);
+                        stack.push(*next);
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Commands waiting for locks, grouped by the locked key.
+pub struct WaiterManager {
+    waiters: HashMap<Key, Vec<Waiter>>,
+    // cid -> the key it is waiting for
+    wait_keys: HashMap<u64, Key>,
+    detector: DeadlockDetector,
+}
+
+impl WaiterManager {
+    pub fn new() -> WaiterManager {
+        WaiterManager {
+            waiters: HashMap::new(),
+            wait_keys: HashMap::new(),
+            detector: DeadlockDetector::new(),
+        }
+    }
+
+    /// Park `waiter` until the lock on `key` is released. Return false if
+    /// waiting would cause a deadlock, in which case the waiter is not added.
+    pub fn wait_for(&mut self, key: Key, waiter: Waiter) -> bool {
+        if !self.detector.detect(waiter.start_ts, waiter.lock_ts) {
+            return false;
+        }
+        self.wait_keys.insert(waiter.cid, key.clone());
+        self.waiters.entry(key).or_insert_with(Vec::new).push(waiter);
+        true
+    }
+
+    /// Remove the waiter of command `cid` if it's still in the wait
+    /// `wait_seq`, it's used when the wait times out.
+    pub fn remove_waiter(&mut self, cid: u64, wait_seq: u64) -> Option<Waiter> {
+        let (waiter, is_empty) = {
+            let key = match self.wait_keys.get(&cid) {
+                Some(key) => key,
+                None => return None,
+            };
+            let waiters = self.waiters.get_mut(key).unwrap();
+            let pos = waiters.iter().position(|w| w.cid == cid).unwrap();
+            if waiters[pos].wait_seq != wait_seq {
+                return None;
+            }
+            (waiters.remove(pos), waiters.is_empty())
+        };
+        let key = self.wait_keys.remove(&cid).unwrap();
+        if is_empty {
+            self.waiters.remove(&key);
+        }
+        self.detector.clean_up_wait_for(waiter.start_ts, waiter.lock_ts);
+        Some(waiter)
+    }
+
+    /// Take all waiters of `key` in arrival order, it's used when the lock on
+    /// `key` is released.
+    pub fn wake_up(&mut self, key: &Key) -> Vec<Waiter> {
+        let waiters = self.waiters.remove(key).unwrap_or_else(Vec::new);
+        for w in &waiters {
+            self.wait_keys.remove(&w.cid);
+            self.detector.clean_up_wait_for(w.start_ts, w.lock_ts);
+        }
+        waiters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeadlockDetector, WaiterManager, Waiter};
+    use storage::make_key;
+
+    fn waiter(cid: u64, start_ts: u64, lock_ts: u64) -> Waiter {
+        Waiter {
+            cid: cid,
+            wait_seq: cid,
+            start_ts: start_ts,
+            lock_ts: lock_ts,
+        }
+    }
+
+    #[test]
+    fn test_deadlock_detector() {
+        let mut detector = DeadlockDetector::new();
+        // 1 -> 2 -> 3
+        assert!(detector.detect(1, 2));
+        assert!(detector.detect(2, 3));
+        // 3 -> 1 forms a cycle.
+        assert!(!detector.detect(3, 1));
+        assert!(!detector.detect(3, 2));
+        assert!(detector.detect(1, 3));
+        // 1 -> 2, 1 -> 3
+        detector.clean_up_wait_for(2, 3);
+        assert!(detector.detect(3, 2));
+        // 1 -> 2, 1 -> 3, 3 -> 2
+        assert!(!detector.detect(2, 1));
+        detector.clean_up_wait_for(1, 2);
+        assert!(!detector.detect(2, 1));
+        detector.clean_up_wait_for(1, 3);
+        assert!(detector.detect(2, 1));
+    }
+
+    #[test]
+    fn test_waiter_manager() {
+        let mut mgr = WaiterManager::new();
+        let (k1, k2) = (make_key(b"k1"), make_key(b"k2"));
+
+        assert!(mgr.wait_for(k1.clone(), waiter(1, 10, 5)));
+        assert!(mgr.wait_for(k1.clone(), waiter(2, 20, 5)));
+        assert!(mgr.wait_for(k2.clone(), waiter(3, 5, 30)));
+        // 30 -> 5 -> 30 is a deadlock.
+        assert!(!mgr.wait_for(k2.clone(), waiter(4, 30, 5)));
+
+        assert_eq!(mgr.remove_waiter(3, 3), Some(waiter(3, 5, 30)));
+        assert_eq!(mgr.remove_waiter(3, 3), None);
+        assert!(mgr.wake_up(&k2).is_empty());
+        // No deadlock after cid 3 is removed.
+        assert!(mgr.wait_for(k2.clone(), waiter(4, 30, 5)));
+
+        assert_eq!(mgr.wake_up(&k1), vec![waiter(1, 10, 5), waiter(2, 20, 5)]);
+        assert!(mgr.wake_up(&k1).is_empty());
+        assert_eq!(mgr.remove_waiter(1, 1), None);
+        assert_eq!(mgr.wake_up(&k2), vec![waiter(4, 30, 5)]);
+    }
+
+    #[test]
+    fn test_stale_wait_timeout() {
+        let mut mgr = WaiterManager::new();
+        let k1 = make_key(b"k1");
+
+        assert!(mgr.wait_for(k1.clone(), waiter(1, 10, 5)));
+        assert_eq!(mgr.wake_up(&k1), vec![waiter(1, 10, 5)]);
+        // The command waits again with a new sequence.
+        let mut w = waiter(1, 10, 5);
+        w.wait_seq = 2;
+        assert!(mgr.wait_for(k1.clone(), w.clone()));
+        // The timer of the first wait must not remove the second one.
+        assert_eq!(mgr.remove_waiter(1, 1), None);
+        assert_eq!(mgr.remove_waiter(1, 2), Some(w));
+        assert!(mgr.wake_up(&k1).is_empty());
+    }
+}
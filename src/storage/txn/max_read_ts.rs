@@ -0,0 +1,239 @@
+// Copyright 2016 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::{Error, Result};
+
+struct RegionReadTs {
+    max_read_ts: u64,
+    // commit ts of the one-phase commits being written
+    one_pc_commit_ts: Vec<u64>,
+    // the term in which the local peer became leader, 0 if it's not the leader
+    term: u64,
+    // whether `max_read_ts` covers the reads served by the former leaders
+    seeded: bool,
+}
+
+impl RegionReadTs {
+    fn new(seeded: bool) -> RegionReadTs {
+        RegionReadTs {
+            max_read_ts: 0,
+            one_pc_commit_ts: vec![],
+            term: 0,
+            seeded: seeded,
+        }
+    }
+}
+
+/// `MaxReadTs` tracks the max ts of the reads served in each region and the
+/// one-phase commits being written.
+///
+/// A one-phase commit writes its commit records without leaving any lock, so
+/// a read must never be served at a ts not less than the commit ts before the
+/// records are written: the commit ts must be larger than the max read ts of
+/// the region, and reads at a larger ts are rejected until the write finishes.
+/// Reads must be recorded before their snapshots are taken.
+///
+/// With raft, the reads served by the former leaders of a region are unknown
+/// to the local peer. A region must be seeded with a ts from pd after the peer
+/// becomes leader, which is larger than the ts of any read served before, and
+/// one-phase commits are rejected until then.
+#[derive(Clone)]
+pub struct MaxReadTs {
+    regions: Arc<Mutex<HashMap<u64, RegionReadTs>>>,
+    need_seed: bool,
+}
+
+impl MaxReadTs {
+    /// Create a tracker for a local engine, all the reads are served by it.
+    pub fn new() -> MaxReadTs {
+        MaxReadTs {
+            regions: Arc::new(Mutex::new(HashMap::new())),
+            need_seed: false,
+        }
+    }
+
+    /// Create a tracker for the regions led by raft peers.
+    pub fn with_seed() -> MaxReadTs {
+        MaxReadTs { need_seed: true, ..MaxReadTs::new() }
+    }
+
+    /// Record a read at `ts` in region `region_id`. Fail if the read should be
+    /// retried later since a one-phase commit that it must see is being
+    /// written.
+    pub fn on_read(&self, region_id: u64, ts: u64) -> Result<()> {
+        let mut regions = self.regions.lock().unwrap();
+        let region = regions.entry(region_id).or_insert_with(|| RegionReadTs::new(!self.need_seed));
+        if let Some(&commit_ts) = region.one_pc_commit_ts.iter().find(|&&c| c <= ts) {
+            return Err(Error::ReadBlocked {
+                ts: ts,
+                commit_ts: commit_ts,
+            });
+        }
+        if ts > region.max_read_ts {
+            region.max_read_ts = ts;
+        }
+        Ok(())
+    }
+
+    /// Start writing a one-phase commit at `commit_ts` in region `region_id`.
+    /// Fail if the commit must not be written, since `commit_ts` is not larger
+    /// than the max read ts of the region or the region is not seeded yet.
+    pub fn begin_one_pc(&self, region_id: u64, commit_ts: u64) -> Result<()> {
+        let mut regions = self.regions.lock().unwrap();
+        let region = regions.entry(region_id).or_insert_with(|| RegionReadTs::new(!self.need_seed));
+        if !region.seeded {
+            return Err(Error::MaxReadTsNotReady { region_id: region_id });
+        }
+        if region.max_read_ts >= commit_ts {
+            return Err(Error::OnePcCommitTsExpired {
+                commit_ts: commit_ts,
+                max_read_ts: region.max_read_ts,
+            });
+        }
+        region.one_pc_commit_ts.push(commit_ts);
+        Ok(())
+    }
+
+    /// Finish writing the one-phase commit started by `begin_one_pc`, whether
+    /// the write succeeds or not.
+    pub fn finish_one_pc(&self, region_id: u64, commit_ts: u64) {
+        let mut regions = self.regions.lock().unwrap();
+        // The region may be removed while the commit is being written.
+        if let Some(region) = regions.get_mut(&region_id) {
+            if let Some(pos) = region.one_pc_commit_ts.iter().position(|&ts| ts == commit_ts) {
+                region.one_pc_commit_ts.swap_remove(pos);
+            }
+        }
+    }
+
+    /// The local peer of region `region_id` becomes leader in `term`, the
+    /// region must be seeded again before one-phase commits are allowed.
+    pub fn on_leader(&self, region_id: u64, term: u64) {
+        let mut regions = self.regions.lock().unwrap();
+        let region = regions.entry(region_id).or_insert_with(|| RegionReadTs::new(false));
+        region.term = term;
+        region.seeded = !self.need_seed;
+    }
+
+    /// The local peer of region `region_id` is not the leader any more.
+    pub fn on_follower(&self, region_id: u64) {
+        if let Some(region) = self.regions.lock().unwrap().get_mut(&region_id) {
+            region.term = 0;
+            region.seeded = !self.need_seed;
+        }
+    }
+
+    /// Return whether the region led in `term` still needs to be seeded.
+    pub fn need_seed(&self, region_id: u64, term: u64) -> bool {
+        match self.regions.lock().unwrap().get(&region_id) {
+            Some(region) => region.term == term && !region.seeded,
+            None => false,
+        }
+    }
+
+    /// Seed the region led in `term` with `ts`, which is fetched from pd after
+    /// the peer becomes leader. It's ignored if the leadership has changed.
+    pub fn seed(&self, region_id: u64, term: u64, ts: u64) -> bool {
+        let mut regions = self.regions.lock().unwrap();
+        let region = match regions.get_mut(&region_id) {
+            Some(region) => region,
+            None => return false,
+        };
+        if region.term != term {
+            return false;
+        }
+        if ts > region.max_read_ts {
+            region.max_read_ts = ts;
+        }
+        region.seeded = true;
+        true
+    }
+
+    /// Forget the region, which is destroyed on this store.
+    pub fn remove(&self, region_id: u64) {
+        self.regions.lock().unwrap().remove(&region_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MaxReadTs;
+    use super::super::Error;
+
+    #[test]
+    fn test_max_read_ts() {
+        let tracker = MaxReadTs::new();
+        tracker.on_read(1, 10).unwrap();
+        tracker.on_read(1, 5).unwrap();
+        tracker.on_read(2, 20).unwrap();
+
+        // The commit ts must be larger than the reads of the same region.
+        match tracker.begin_one_pc(1, 10) {
+            Err(Error::OnePcCommitTsExpired { max_read_ts: 10, .. }) => {}
+            res => panic!("expect OnePcCommitTsExpired, got {:?}", res),
+        }
+        assert!(tracker.begin_one_pc(2, 15).is_err());
+        tracker.begin_one_pc(1, 11).unwrap();
+        tracker.begin_one_pc(3, 1).unwrap();
+
+        // Reads that must see the commit being written are rejected.
+        match tracker.on_read(1, 11) {
+            Err(Error::ReadBlocked { ts: 11, commit_ts: 11 }) => {}
+            res => panic!("expect ReadBlocked, got {:?}", res),
+        }
+        assert!(tracker.on_read(1, 30).is_err());
+        tracker.on_read(1, 10).unwrap();
+        tracker.on_read(2, 30).unwrap();
+        tracker.finish_one_pc(1, 11);
+        tracker.on_read(1, 30).unwrap();
+        assert!(tracker.begin_one_pc(1, 25).is_err());
+
+        tracker.finish_one_pc(3, 1);
+        tracker.on_read(3, 1).unwrap();
+    }
+
+    #[test]
+    fn test_seed_max_read_ts() {
+        let tracker = MaxReadTs::with_seed();
+        tracker.on_read(1, 10).unwrap();
+        // Not led by the local peer.
+        assert!(!tracker.need_seed(1, 5));
+        match tracker.begin_one_pc(1, 20) {
+            Err(Error::MaxReadTsNotReady { region_id: 1 }) => {}
+            res => panic!("expect MaxReadTsNotReady, got {:?}", res),
+        }
+
+        tracker.on_leader(1, 5);
+        assert!(tracker.need_seed(1, 5));
+        assert!(tracker.begin_one_pc(1, 20).is_err());
+        // The seed of an old leadership is ignored.
+        assert!(!tracker.seed(1, 4, 30));
+        assert!(tracker.seed(1, 5, 30));
+        assert!(!tracker.need_seed(1, 5));
+        assert!(tracker.begin_one_pc(1, 30).is_err());
+        tracker.begin_one_pc(1, 31).unwrap();
+
+        // The region is destroyed while the commit is being written.
+        tracker.remove(1);
+        tracker.finish_one_pc(1, 31);
+        assert!(tracker.begin_one_pc(1, 40).is_err());
+
+        tracker.on_leader(1, 6);
+        tracker.on_follower(1);
+        assert!(!tracker.seed(1, 6, 50));
+        assert!(tracker.begin_one_pc(1, 60).is_err());
+    }
+}
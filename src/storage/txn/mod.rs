@@ -14,12 +14,17 @@
 mod store;
 mod scheduler;
 mod latch;
+mod lock_wait;
+mod read_pool;
+mod max_read_ts;
 
 use std::error;
 use std::io::Error as IoError;
 
 pub use self::scheduler::{Scheduler, Msg};
 pub use self::store::SnapshotStore;
+pub use self::read_pool::{Runner as ReadRunner, Task as ReadTask, Priority as ReadPriority};
+pub use self::max_read_ts::MaxReadTs;
 
 quick_error! {
     #[derive(Debug)]
@@ -55,6 +60,20 @@ quick_error! {
             cause(err)
             description(err.description())
         }
+        OnePcCommitTsExpired {commit_ts: u64, max_read_ts: u64} {
+            description("one-phase commit ts expired")
+            display("one-phase commit ts:{} is not larger than max read ts:{}",
+                    commit_ts,
+                    max_read_ts)
+        }
+        MaxReadTsNotReady {region_id: u64} {
+            description("max read ts is not ready")
+            display("max read ts of region {} is not seeded yet", region_id)
+        }
+        ReadBlocked {ts: u64, commit_ts: u64} {
+            description("read is blocked by a one-phase commit")
+            display("read at ts:{} waits for the one-phase commit at ts:{}", ts, commit_ts)
+        }
     }
 }
 
@@ -0,0 +1,266 @@
+// Copyright 2016 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::fmt::{self, Formatter, Display};
+use std::sync::{Arc, Mutex};
+use threadpool::ThreadPool;
+use storage::{Engine, Snapshot, Command, StorageCb, Error as StorageError};
+use storage::engine::Result as EngineResult;
+use util::worker::{Runnable, Scheduler};
+use super::scheduler::{ProcessResult, execute_read, execute_callback, extract_ctx};
+use super::max_read_ts::MaxReadTs;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Priority {
+    High,
+    Low,
+}
+
+impl Priority {
+    /// Point reads are served with high priority, scans may take long and are
+    /// served with low priority so they can't stall point reads.
+    pub fn of(cmd: &Command) -> Priority {
+        match *cmd {
+            Command::Scan { .. } |
+            Command::ReverseScan { .. } |
+            Command::RawScan { .. } => Priority::Low,
+            _ => Priority::High,
+        }
+    }
+}
+
+/// The ts of the transactional reads, which must be recorded in `MaxReadTs`.
+fn read_ts(cmd: &Command) -> Option<u64> {
+    match *cmd {
+        Command::Get { start_ts, .. } |
+        Command::BatchGet { start_ts, .. } |
+        Command::Scan { start_ts, .. } |
+        Command::ReverseScan { start_ts, .. } => Some(start_ts),
+        _ => None,
+    }
+}
+
+pub enum Task {
+    Read { cmd: Command, cb: StorageCb },
+    SnapRes {
+        id: u64,
+        snapshot: EngineResult<Box<Snapshot>>,
+    },
+}
+
+impl Display for Task {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Task::Read { ref cmd, .. } => write!(f, "read {}", cmd),
+            Task::SnapRes { id, .. } => write!(f, "snapres [{}]", id),
+        }
+    }
+}
+
+/// `Runner` serves the read commands that don't need any latch.
+///
+/// The snapshot of a read is taken from the engine directly, and the read is
+/// executed in the pool of its priority, bypassing the transaction scheduler.
+pub struct Runner {
+    engine: Box<Engine>,
+    sched: Scheduler<Task>,
+    max_read_ts: MaxReadTs,
+    // reads waiting for snapshots, shared with the snapshot callbacks.
+    reads: Arc<Mutex<HashMap<u64, (Command, StorageCb)>>>,
+    last_id: u64,
+    high_pool: ThreadPool,
+    low_pool: ThreadPool,
+}
+
+impl Runner {
+    pub fn new(engine: Box<Engine>,
+               sched: Scheduler<Task>,
+               max_read_ts: MaxReadTs,
+               high_concurrency: usize,
+               low_concurrency: usize)
+               -> Runner {
+        Runner {
+            engine: engine,
+            sched: sched,
+            max_read_ts: max_read_ts,
+            reads: Arc::new(Mutex::new(HashMap::new())),
+            last_id: 0,
+            high_pool: ThreadPool::new_with_name(thd_name!("read-pool-high"), high_concurrency),
+            low_pool: ThreadPool::new_with_name(thd_name!("read-pool-low"), low_concurrency),
+        }
+    }
+
+    fn on_read(&mut self, cmd: Command, cb: StorageCb) {
+        if let Some(ts) = read_ts(&cmd) {
+            let region_id = extract_ctx(&cmd).get_region_id();
+            if let Err(e) = self.max_read_ts.on_read(region_id, ts) {
+                debug!("reject read {}: {}", cmd, e);
+                execute_callback(cb, ProcessResult::Failed { err: StorageError::from(e) });
+                return;
+            }
+        }
+        self.last_id += 1;
+        let id = self.last_id;
+        let ctx = extract_ctx(&cmd).clone();
+        // The read is inserted before taking the snapshot, so whoever fails it
+        // can take its callback.
+        self.reads.lock().unwrap().insert(id, (cmd, cb));
+        let sched = self.sched.clone();
+        let reads = self.reads.clone();
+        let res = self.engine.async_snapshot(&ctx,
+                                             box move |snapshot| {
+            if let Err(e) = sched.schedule(Task::SnapRes {
+                id: id,
+                snapshot: snapshot,
+            }) {
+                error!("failed to schedule snapshot of read {}: {:?}", id, e);
+                let read = reads.lock().unwrap().remove(&id);
+                if let Some((_, cb)) = read {
+                    let pr = ProcessResult::Failed { err: box_err!("read pool is stopped") };
+                    execute_callback(cb, pr);
+                }
+            }
+        });
+        if let Err(e) = res {
+            let read = self.reads.lock().unwrap().remove(&id);
+            if let Some((_, cb)) = read {
+                execute_callback(cb, ProcessResult::Failed { err: StorageError::from(e) });
+            }
+        }
+    }
+
+    fn on_snapshot(&mut self, id: u64, snapshot: EngineResult<Box<Snapshot>>) {
+        let (mut cmd, cb) = self.reads.lock().unwrap().remove(&id).unwrap();
+        let snapshot = match snapshot {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                execute_callback(cb, ProcessResult::Failed { err: StorageError::from(e) });
+                return;
+            }
+        };
+        let pool = match Priority::of(&cmd) {
+            Priority::High => &self.high_pool,
+            Priority::Low => &self.low_pool,
+        };
+        pool.execute(move || {
+            let pr = execute_read(&mut cmd, snapshot.as_ref());
+            execute_callback(cb, pr);
+        });
+    }
+}
+
+impl Runnable<Task> for Runner {
+    fn run(&mut self, task: Task) {
+        match task {
+            Task::Read { cmd, cb } => self.on_read(cmd, cb),
+            Task::SnapRes { id, snapshot } => self.on_snapshot(id, snapshot),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use kvproto::kvrpcpb::Context;
+    use storage::{Command, StorageCb, Mutation, Options, Result, Value, KvPair, make_key,
+                  new_engine, Dsn, DEFAULT_CFS};
+    use storage::mvcc::MvccTxn;
+    use util::worker::{Worker, Runnable, dummy_scheduler};
+    use storage::txn::MaxReadTs;
+    use super::*;
+
+    #[test]
+    fn test_read_pool() {
+        let engine = new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
+        {
+            let snapshot = engine.snapshot(&Context::new()).unwrap();
+            let mut txn = MvccTxn::new(snapshot.as_ref(), 10);
+            txn.prewrite(Mutation::Put((make_key(b"k"), b"v".to_vec())),
+                          b"k",
+                          &Options::default())
+                .unwrap();
+            engine.write(&Context::new(), txn.modifies()).unwrap();
+            let snapshot = engine.snapshot(&Context::new()).unwrap();
+            let mut txn = MvccTxn::new(snapshot.as_ref(), 10);
+            txn.commit(&make_key(b"k"), 20).unwrap();
+            engine.write(&Context::new(), txn.modifies()).unwrap();
+        }
+
+        let mut worker = Worker::new("test-read-pool");
+        let runner = Runner::new(engine.clone(), worker.scheduler(), MaxReadTs::new(), 1, 1);
+        worker.start(runner).unwrap();
+
+        let (tx, rx) = channel();
+        let get = Command::Get {
+            ctx: Context::new(),
+            key: make_key(b"k"),
+            start_ts: 30,
+        };
+        let tx1 = tx.clone();
+        let cb = StorageCb::SingleValue(box move |v: Result<Option<Value>>| {
+            tx1.send(v.unwrap()).unwrap()
+        });
+        worker.schedule(Task::Read { cmd: get, cb: cb }).unwrap();
+        assert_eq!(rx.recv().unwrap(), Some(b"v".to_vec()));
+
+        // The version committed at 20 is invisible at 15.
+        let get = Command::Get {
+            ctx: Context::new(),
+            key: make_key(b"k"),
+            start_ts: 15,
+        };
+        let cb = StorageCb::SingleValue(box move |v: Result<Option<Value>>| {
+            tx.send(v.unwrap()).unwrap()
+        });
+        worker.schedule(Task::Read { cmd: get, cb: cb }).unwrap();
+        assert_eq!(rx.recv().unwrap(), None);
+
+        let (tx, rx) = channel();
+        let scan = Command::Scan {
+            ctx: Context::new(),
+            start_key: make_key(b""),
+            end_key: None,
+            limit: 10,
+            key_only: false,
+            start_ts: 30,
+        };
+        let cb = StorageCb::KvPairs(box move |pairs: Result<Vec<Result<KvPair>>>| {
+            tx.send(pairs.unwrap().len()).unwrap()
+        });
+        worker.schedule(Task::Read { cmd: scan, cb: cb }).unwrap();
+        assert_eq!(rx.recv().unwrap(), 1);
+
+        worker.stop().unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_schedule_snapshot_failed() {
+        let engine = new_engine(Dsn::Memory, DEFAULT_CFS).unwrap();
+        let mut runner = Runner::new(engine, dummy_scheduler(), MaxReadTs::new(), 1, 1);
+
+        // The snapshot can't be scheduled back, the read must still be finished.
+        let (tx, rx) = channel();
+        let get = Command::Get {
+            ctx: Context::new(),
+            key: make_key(b"k"),
+            start_ts: 10,
+        };
+        let cb = StorageCb::SingleValue(box move |v: Result<Option<Value>>| {
+            tx.send(v.is_err()).unwrap()
+        });
+        runner.run(Task::Read { cmd: get, cb: cb });
+        assert!(rx.recv().unwrap());
+        assert!(runner.reads.lock().unwrap().is_empty());
+    }
+}
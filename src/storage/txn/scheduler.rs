@@ -13,19 +13,24 @@
 
 use std::time::Duration;
 use std::boxed::Box;
+use std::mem;
 use threadpool::ThreadPool;
-use storage::{Engine, Command, Snapshot, StorageCb, Result as StorageResult, Error as StorageError};
+use storage::{Engine, Command, Snapshot, Cursor, StorageCb, Result as StorageResult,
+              Error as StorageError, CF_DEFAULT, CF_WRITE};
 use kvproto::kvrpcpb::{Context, LockInfo};
-use storage::mvcc::{MvccTxn, MvccReader, Error as MvccError};
+use storage::mvcc::{MvccTxn, MvccReader, MvccInfo, TxnStatus, Error as MvccError};
 use storage::{Key, Value, KvPair};
 use std::collections::HashMap;
 use mio::{self, EventLoop};
 use util::transport::SendCh;
+use util::escape;
 use storage::engine::{Result as EngineResult, Callback as EngineCallback, Modify};
 use super::Result;
 use super::Error;
 use super::store::SnapshotStore;
 use super::latch::{Latches, Lock};
+use super::lock_wait::{WaiterManager, Waiter};
+use super::max_read_ts::MaxReadTs;
 
 const REPORT_STATISTIC_INTERVAL: u64 = 60000; // 60 seconds
 
@@ -34,6 +39,8 @@ const GC_BATCH_SIZE: usize = 512;
 
 pub enum Tick {
     ReportStatistic,
+    // (cid, wait_seq)
+    LockWaitTimeout(u64, u64),
 }
 
 pub enum ProcessResult {
@@ -50,6 +57,15 @@ pub enum ProcessResult {
     Locks {
         locks: Vec<LockInfo>,
     },
+    TxnStatus {
+        status: TxnStatus,
+    },
+    MvccKey {
+        mvcc: MvccInfo,
+    },
+    MvccStartTs {
+        mvcc: Option<(Key, MvccInfo)>,
+    },
     NextCommand {
         cmd: Command,
     },
@@ -82,6 +98,12 @@ pub enum Msg {
         cid: u64,
         err: Error,
     },
+    WaitForLock {
+        cid: u64,
+        cmd: Command,
+        key: Vec<u8>,
+        lock_ts: u64,
+    },
     WriteFinished {
         cid: u64,
         pr: ProcessResult,
@@ -89,7 +111,7 @@ pub enum Msg {
     },
 }
 
-fn execute_callback(callback: StorageCb, pr: ProcessResult) {
+pub fn execute_callback(callback: StorageCb, pr: ProcessResult) {
     match callback {
         StorageCb::Boolean(cb) => {
             match pr {
@@ -126,6 +148,27 @@ fn execute_callback(callback: StorageCb, pr: ProcessResult) {
                 _ => panic!("process result mismatch"),
             }
         }
+        StorageCb::TxnStatus(cb) => {
+            match pr {
+                ProcessResult::TxnStatus { status } => cb(Ok(status)),
+                ProcessResult::Failed { err } => cb(Err(err)),
+                _ => panic!("process result mismatch"),
+            }
+        }
+        StorageCb::MvccInfoByKey(cb) => {
+            match pr {
+                ProcessResult::MvccKey { mvcc } => cb(Ok(mvcc)),
+                ProcessResult::Failed { err } => cb(Err(err)),
+                _ => panic!("process result mismatch"),
+            }
+        }
+        StorageCb::MvccInfoByStartTs(cb) => {
+            match pr {
+                ProcessResult::MvccStartTs { mvcc } => cb(Ok(mvcc)),
+                ProcessResult::Failed { err } => cb(Err(err)),
+                _ => panic!("process result mismatch"),
+            }
+        }
     }
 }
 
@@ -134,15 +177,33 @@ pub struct RunningCtx {
     cmd: Option<Command>,
     lock: Lock,
     callback: Option<StorageCb>,
+    // whether the command can wait for locks of other transactions
+    wait_lock: bool,
+    // keys whose locks are released by the command, waiters on them are
+    // woken up after the command is written
+    released_keys: Vec<Key>,
+    // bytes written by the command, None for read commands
+    write_bytes: Option<usize>,
+    // (region id, commit ts) of the one-phase commit being written
+    one_pc: Option<(u64, u64)>,
 }
 
 impl RunningCtx {
     pub fn new(cid: u64, cmd: Command, lock: Lock, cb: StorageCb) -> RunningCtx {
+        let write_bytes = if cmd.readonly() {
+            None
+        } else {
+            Some(cmd.write_bytes())
+        };
         RunningCtx {
             cid: cid,
             cmd: Some(cmd),
             lock: lock,
             callback: Some(cb),
+            wait_lock: false,
+            released_keys: vec![],
+            write_bytes: write_bytes,
+            one_pc: None,
         }
     }
 }
@@ -177,13 +238,36 @@ pub struct Scheduler {
 
     // worker pool
     worker_pool: ThreadPool,
+
+    // commands waiting for locks of other transactions
+    waiter_mgr: WaiterManager,
+    // lock wait sequence generator
+    wait_seq_alloc: u64,
+
+    // in milliseconds, 0 means commands never wait for locks
+    lock_wait_timeout: u64,
+
+    // max read ts of each region, shared with the read paths
+    max_read_ts: MaxReadTs,
+
+    // the running write commands and their total bytes, new writes are
+    // rejected when either reaches its threshold. Commands waiting for locks
+    // are not counted, or the commands releasing the locks could be rejected.
+    running_write_count: usize,
+    running_write_bytes: usize,
+    too_busy_threshold: usize,
+    pending_write_threshold: usize,
 }
 
 impl Scheduler {
     pub fn new(engine: Box<Engine>,
                schedch: SendCh<Msg>,
                concurrency: usize,
-               worker_pool_size: usize)
+               worker_pool_size: usize,
+               lock_wait_timeout: u64,
+               max_read_ts: MaxReadTs,
+               too_busy_threshold: usize,
+               pending_write_threshold: usize)
                -> Scheduler {
         Scheduler {
             engine: engine,
@@ -193,15 +277,32 @@ impl Scheduler {
             latches: Latches::new(concurrency),
             worker_pool: ThreadPool::new_with_name(thd_name!("sched-worker-pool"),
                                                    worker_pool_size),
+            waiter_mgr: WaiterManager::new(),
+            wait_seq_alloc: 0,
+            lock_wait_timeout: lock_wait_timeout,
+            max_read_ts: max_read_ts,
+            running_write_count: 0,
+            running_write_bytes: 0,
+            too_busy_threshold: too_busy_threshold,
+            pending_write_threshold: pending_write_threshold,
         }
     }
 }
 
 fn process_read(cid: u64, mut cmd: Command, ch: SendCh<Msg>, snapshot: Box<Snapshot>) {
     debug!("process read cmd(cid={}) in worker pool.", cid);
-    let pr = match cmd {
+    let pr = execute_read(&mut cmd, snapshot.as_ref());
+    if let Err(e) = ch.send(Msg::ReadFinished { cid: cid, pr: pr }) {
+        // Todo: if this happens we need to clean up command's context
+        error!("send read finished failed, cid={}, err={:?}", cid, e);
+    }
+}
+
+/// Execute a read command on the snapshot, it's shared by the scheduler and the read pool.
+pub fn execute_read(cmd: &mut Command, snapshot: &Snapshot) -> ProcessResult {
+    match *cmd {
         Command::Get { ref key, start_ts, .. } => {
-            let snap_store = SnapshotStore::new(snapshot.as_ref(), start_ts);
+            let snap_store = SnapshotStore::new(snapshot, start_ts);
             let res = snap_store.get(key);
             match res {
                 Ok(val) => ProcessResult::Value { value: val },
@@ -209,7 +310,7 @@ fn process_read(cid: u64, mut cmd: Command, ch: SendCh<Msg>, snapshot: Box<Snaps
             }
         }
         Command::BatchGet { ref keys, start_ts, .. } => {
-            let snap_store = SnapshotStore::new(snapshot.as_ref(), start_ts);
+            let snap_store = SnapshotStore::new(snapshot, start_ts);
             match snap_store.batch_get(keys) {
                 Ok(results) => {
                     let mut res = vec![];
@@ -225,10 +326,26 @@ fn process_read(cid: u64, mut cmd: Command, ch: SendCh<Msg>, snapshot: Box<Snaps
                 Err(e) => ProcessResult::Failed { err: StorageError::from(e) },
             }
         }
-        Command::Scan { ref start_key, limit, start_ts, .. } => {
-            let snap_store = SnapshotStore::new(snapshot.as_ref(), start_ts);
+        Command::Scan { ref start_key, ref end_key, limit, key_only, start_ts, .. } => {
+            let snap_store = SnapshotStore::new(snapshot, start_ts);
             let res = snap_store.scanner()
-                .and_then(|mut scanner| scanner.scan(start_key.clone(), limit))
+                .and_then(|mut scanner| {
+                    scanner.scan(start_key.clone(), end_key.as_ref(), limit, key_only)
+                })
+                .and_then(|mut results| {
+                    Ok(results.drain(..).map(|x| x.map_err(StorageError::from)).collect())
+                });
+            match res {
+                Ok(pairs) => ProcessResult::MultiKvpairs { pairs: pairs },
+                Err(e) => ProcessResult::Failed { err: e.into() },
+            }
+        }
+        Command::ReverseScan { ref start_key, ref end_key, limit, start_ts, .. } => {
+            let snap_store = SnapshotStore::new(snapshot, start_ts);
+            let res = snap_store.scanner()
+                .and_then(|mut scanner| {
+                    scanner.reverse_scan(start_key.clone(), end_key.as_ref(), limit)
+                })
                 .and_then(|mut results| {
                     Ok(results.drain(..).map(|x| x.map_err(StorageError::from)).collect())
                 });
@@ -238,7 +355,7 @@ fn process_read(cid: u64, mut cmd: Command, ch: SendCh<Msg>, snapshot: Box<Snaps
             }
         }
         Command::ScanLock { max_ts, .. } => {
-            let mut reader = MvccReader::new(snapshot.as_ref());
+            let mut reader = MvccReader::new(snapshot);
             let res = reader.scan_lock(|lock| lock.ts <= max_ts)
                 .map_err(Error::from)
                 .and_then(|v| {
@@ -247,6 +364,7 @@ fn process_read(cid: u64, mut cmd: Command, ch: SendCh<Msg>, snapshot: Box<Snaps
                         let mut lock_info = LockInfo::new();
                         lock_info.set_primary_lock(lock.primary);
                         lock_info.set_lock_version(lock.ts);
+                        lock_info.set_lock_ttl(lock.ttl);
                         lock_info.set_key(try!(key.raw()));
                         locks.push(lock_info);
                     }
@@ -257,38 +375,28 @@ fn process_read(cid: u64, mut cmd: Command, ch: SendCh<Msg>, snapshot: Box<Snaps
                 Err(e) => ProcessResult::Failed { err: e.into() },
             }
         }
-        Command::ResolveLock { ref ctx, start_ts, commit_ts } => {
-            let mut reader = MvccReader::new(snapshot.as_ref());
+        Command::ResolveLock { ref ctx, start_ts, commit_ts, .. } => {
+            let mut reader = MvccReader::new(snapshot);
             let res = reader.scan_lock(|lock| lock.ts == start_ts)
                 .map_err(Error::from)
-                .and_then(|v| {
-                    let keys = v.into_iter().map(|x| x.0).collect();
-                    let next_cmd = match commit_ts {
-                        Some(ts) => {
-                            Command::Commit {
-                                ctx: ctx.clone(),
-                                keys: keys,
-                                lock_ts: start_ts,
-                                commit_ts: ts,
-                            }
-                        }
-                        None => {
-                            Command::Rollback {
-                                ctx: ctx.clone(),
-                                keys: keys,
-                                start_ts: start_ts,
-                            }
-                        }
-                    };
-                    Ok(next_cmd)
-                });
+                .map(|v| v.into_iter().map(|x| x.0).collect::<Vec<_>>());
             match res {
-                Ok(cmd) => ProcessResult::NextCommand { cmd: cmd },
+                // The command is read only without keys, so finish it here.
+                Ok(ref keys) if keys.is_empty() => ProcessResult::Res,
+                Ok(keys) => {
+                    let cmd = Command::ResolveLock {
+                        ctx: ctx.clone(),
+                        start_ts: start_ts,
+                        commit_ts: commit_ts,
+                        keys: keys,
+                    };
+                    ProcessResult::NextCommand { cmd: cmd }
+                }
                 Err(e) => ProcessResult::Failed { err: e.into() },
             }
         }
         Command::Gc { ref ctx, safe_point, ref mut scan_key, .. } => {
-            let mut reader = MvccReader::new(snapshot.as_ref());
+            let mut reader = MvccReader::new(snapshot);
             let res = reader.scan_keys(scan_key.take(), GC_BATCH_SIZE)
                 .map_err(Error::from)
                 .and_then(|(keys, next_start)| {
@@ -304,17 +412,93 @@ fn process_read(cid: u64, mut cmd: Command, ch: SendCh<Msg>, snapshot: Box<Snaps
                 Err(e) => ProcessResult::Failed { err: e.into() },
             }
         }
+        Command::RawGet { ref key, .. } => {
+            match snapshot.get(key) {
+                Ok(val) => ProcessResult::Value { value: val },
+                Err(e) => ProcessResult::Failed { err: StorageError::from(e) },
+            }
+        }
+        Command::RawBatchGet { ref keys, .. } => {
+            let mut pairs = vec![];
+            for k in keys {
+                match snapshot.get(k) {
+                    Ok(Some(v)) => pairs.push(Ok((k.encoded().to_owned(), v))),
+                    Ok(None) => {}
+                    Err(e) => pairs.push(Err(StorageError::from(e))),
+                }
+            }
+            ProcessResult::MultiKvpairs { pairs: pairs }
+        }
+        Command::RawScan { ref start_key, limit, .. } => {
+            let res = snapshot.iter()
+                .map_err(Error::from)
+                .and_then(|mut cursor| raw_scan(cursor.as_mut(), start_key, limit));
+            match res {
+                Ok(pairs) => ProcessResult::MultiKvpairs { pairs: pairs },
+                Err(e) => ProcessResult::Failed { err: e.into() },
+            }
+        }
+        Command::MvccGetByKey { ref key, .. } => {
+            let mut reader = MvccReader::new(snapshot);
+            match reader.get_mvcc_info(key) {
+                Ok(mvcc) => ProcessResult::MvccKey { mvcc: mvcc },
+                Err(e) => ProcessResult::Failed { err: Error::from(e).into() },
+            }
+        }
+        Command::MvccGetByStartTs { start_ts, .. } => {
+            let mut reader = MvccReader::new(snapshot);
+            let res = match reader.seek_ts(start_ts) {
+                Ok(Some(key)) => reader.get_mvcc_info(&key).map(|mvcc| Some((key, mvcc))),
+                Ok(None) => Ok(None),
+                Err(e) => Err(e),
+            };
+            match res {
+                Ok(mvcc) => ProcessResult::MvccStartTs { mvcc: mvcc },
+                Err(e) => ProcessResult::Failed { err: Error::from(e).into() },
+            }
+        }
         _ => panic!("unsupported read command"),
-    };
+    }
+}
 
-    if let Err(e) = ch.send(Msg::ReadFinished { cid: cid, pr: pr }) {
-        // Todo: if this happens we need to clean up command's context
-        error!("send read finished failed, cid={}, err={:?}", cid, e);
+fn raw_scan(cursor: &mut Cursor,
+            start_key: &Key,
+            limit: usize)
+            -> Result<Vec<StorageResult<KvPair>>> {
+    let mut pairs = vec![];
+    let mut valid = try!(cursor.seek(start_key));
+    while valid && pairs.len() < limit {
+        pairs.push(Ok((cursor.key().to_vec(), cursor.value().to_vec())));
+        valid = cursor.next();
     }
+    Ok(pairs)
 }
 
-fn process_write(cid: u64, cmd: Command, ch: SendCh<Msg>, snapshot: Box<Snapshot>) {
-    if let Err(e) = process_write_impl(cid, cmd, ch.clone(), snapshot.as_ref()) {
+fn raw_modifies(cmd: &mut Command) -> Vec<Modify> {
+    match *cmd {
+        Command::RawPut { ref key, ref mut value, .. } => {
+            vec![Modify::Put(CF_DEFAULT, key.clone(), mem::replace(value, vec![]))]
+        }
+        Command::RawBatchPut { ref mut pairs, .. } => {
+            pairs.drain(..).map(|(k, v)| Modify::Put(CF_DEFAULT, k, v)).collect()
+        }
+        Command::RawDelete { ref key, .. } => vec![Modify::Delete(CF_DEFAULT, key.clone())],
+        Command::RawBatchDelete { ref keys, .. } => {
+            keys.iter().map(|k| Modify::Delete(CF_DEFAULT, k.clone())).collect()
+        }
+        Command::DeleteRange { ref start_key, ref end_key, .. } => {
+            vec![Modify::DeleteRange(start_key.clone(), end_key.clone())]
+        }
+        _ => panic!("unsupported raw write command"),
+    }
+}
+
+fn process_write(cid: u64,
+                 cmd: Command,
+                 ch: SendCh<Msg>,
+                 snapshot: Box<Snapshot>,
+                 wait_lock: bool) {
+    if let Err(e) = process_write_impl(cid, cmd, ch.clone(), snapshot.as_ref(), wait_lock) {
         if let Err(err) = ch.send(Msg::WritePrepareFailed { cid: cid, err: e }) {
             // Todo: if this happens, lock will hold for ever
             panic!("send WritePrepareFailed message to channel failed. cid={}, err={:?}",
@@ -324,26 +508,88 @@ fn process_write(cid: u64, cmd: Command, ch: SendCh<Msg>, snapshot: Box<Snapshot
     }
 }
 
+/// Find the first key locked by another transaction, return its raw key and
+/// the lock's ts.
+fn find_locked_key(results: &[Result<()>]) -> Option<(Vec<u8>, u64)> {
+    for r in results {
+        if let Err(Error::Mvcc(MvccError::KeyIsLocked { ref key, ts, .. })) = *r {
+            return Some((key.clone(), ts));
+        }
+    }
+    None
+}
+
 fn process_write_impl(cid: u64,
                       mut cmd: Command,
                       ch: SendCh<Msg>,
-                      snapshot: &Snapshot)
+                      snapshot: &Snapshot,
+                      wait_lock: bool)
                       -> Result<()> {
+    let mut wait_for = None;
     let (pr, modifies) = match cmd {
-        Command::Prewrite { ref mutations, ref primary, start_ts, .. } => {
+        Command::Prewrite { ref mutations, ref primary, start_ts, ref options, .. } => {
+            if options.one_pc_commit_ts.is_some() {
+                // A one-phase commit is only atomic in a single region.
+                let cursor = try!(snapshot.iter_cf(CF_WRITE));
+                for m in mutations {
+                    try!(cursor.check_key(m.key()));
+                }
+            }
             let mut txn = MvccTxn::new(snapshot, start_ts);
             let mut results = vec![];
             for m in mutations {
-                match txn.prewrite(m.clone(), primary) {
+                match txn.prewrite(m.clone(), primary, options) {
+                    Ok(_) => results.push(Ok(())),
+                    e @ Err(MvccError::KeyIsLocked { .. }) |
+                    e @ Err(MvccError::AlreadyExist { .. }) => {
+                        results.push(e.map_err(Error::from))
+                    }
+                    Err(e) => return Err(Error::from(e)),
+                }
+            }
+            if wait_lock {
+                wait_for = find_locked_key(&results);
+            }
+            let mut modifies = txn.modifies();
+            // A one-phase commit must write all mutations or nothing.
+            if options.one_pc_commit_ts.is_some() && results.iter().any(|r| r.is_err()) {
+                modifies.clear();
+            }
+            let res = results.drain(..).map(|x| x.map_err(StorageError::from)).collect();
+            let pr = ProcessResult::MultiRes { results: res };
+            (pr, modifies)
+        }
+        Command::AcquirePessimisticLock { ref keys,
+                                          ref primary,
+                                          start_ts,
+                                          for_update_ts,
+                                          ref options,
+                                          .. } => {
+            let mut txn = MvccTxn::new(snapshot, start_ts);
+            let mut results = vec![];
+            for k in keys {
+                match txn.acquire_pessimistic_lock(k, primary, for_update_ts, options) {
                     Ok(_) => results.push(Ok(())),
                     e @ Err(MvccError::KeyIsLocked { .. }) => results.push(e.map_err(Error::from)),
                     Err(e) => return Err(Error::from(e)),
                 }
             }
+            if wait_lock {
+                wait_for = find_locked_key(&results);
+            }
             let res = results.drain(..).map(|x| x.map_err(StorageError::from)).collect();
             let pr = ProcessResult::MultiRes { results: res };
             (pr, txn.modifies())
         }
+        Command::PessimisticRollback { ref keys, start_ts, for_update_ts, .. } => {
+            let mut txn = MvccTxn::new(snapshot, start_ts);
+            for k in keys {
+                try!(txn.pessimistic_rollback(k, for_update_ts));
+            }
+
+            let pr = ProcessResult::Res;
+            (pr, txn.modifies())
+        }
         Command::Commit { ref keys, lock_ts, commit_ts, .. } => {
             let mut txn = MvccTxn::new(snapshot, lock_ts);
             for k in keys {
@@ -369,6 +615,22 @@ fn process_write_impl(cid: u64,
             let pr = ProcessResult::Res;
             (pr, txn.modifies())
         }
+        Command::ResolveLock { ref keys, start_ts, commit_ts, .. } => {
+            let mut txn = MvccTxn::new(snapshot, start_ts);
+            for k in keys {
+                try!(txn.resolve_lock(k, commit_ts));
+            }
+
+            let pr = ProcessResult::Res;
+            (pr, txn.modifies())
+        }
+        Command::CheckTxnStatus { ref primary, lock_ts, current_ts, .. } => {
+            let mut txn = MvccTxn::new(snapshot, lock_ts);
+            let status = try!(txn.check_txn_status(primary, current_ts));
+
+            let pr = ProcessResult::TxnStatus { status: status };
+            (pr, txn.modifies())
+        }
         Command::Gc { ref ctx, safe_point, ref mut scan_key, ref keys } => {
             let mut txn = MvccTxn::new(snapshot, 0);
             for k in keys {
@@ -391,6 +653,17 @@ fn process_write_impl(cid: u64,
         _ => panic!("unsupported write command"),
     };
 
+    // Nothing is written, the command is retried after the lock is released.
+    if let Some((key, lock_ts)) = wait_for {
+        box_try!(ch.send(Msg::WaitForLock {
+            cid: cid,
+            cmd: cmd,
+            key: key,
+            lock_ts: lock_ts,
+        }));
+        return Ok(());
+    }
+
     box_try!(ch.send(Msg::WritePrepareFinished {
         cid: cid,
         cmd: cmd,
@@ -401,20 +674,49 @@ fn process_write_impl(cid: u64,
     Ok(())
 }
 
-fn extract_ctx(cmd: &Command) -> &Context {
+/// Keys whose locks may be released after `cmd` is written.
+fn lock_released_keys(cmd: &Command) -> Vec<Key> {
+    match *cmd {
+        Command::Commit { ref keys, .. } |
+        Command::Rollback { ref keys, .. } |
+        Command::PessimisticRollback { ref keys, .. } |
+        Command::ResolveLock { ref keys, .. } => keys.clone(),
+        Command::CommitThenGet { ref key, .. } |
+        Command::Cleanup { ref key, .. } |
+        Command::RollbackThenGet { ref key, .. } |
+        Command::CheckTxnStatus { primary: ref key, .. } => vec![key.clone()],
+        _ => vec![],
+    }
+}
+
+pub fn extract_ctx(cmd: &Command) -> &Context {
     match *cmd {
         Command::Get { ref ctx, .. } |
         Command::BatchGet { ref ctx, .. } |
         Command::Scan { ref ctx, .. } |
+        Command::ReverseScan { ref ctx, .. } |
         Command::Prewrite { ref ctx, .. } |
+        Command::AcquirePessimisticLock { ref ctx, .. } |
+        Command::PessimisticRollback { ref ctx, .. } |
         Command::Commit { ref ctx, .. } |
         Command::CommitThenGet { ref ctx, .. } |
         Command::Cleanup { ref ctx, .. } |
         Command::Rollback { ref ctx, .. } |
         Command::RollbackThenGet { ref ctx, .. } |
         Command::ScanLock { ref ctx, .. } |
+        Command::CheckTxnStatus { ref ctx, .. } |
         Command::ResolveLock { ref ctx, .. } |
-        Command::Gc { ref ctx, .. } => ctx,
+        Command::Gc { ref ctx, .. } |
+        Command::RawGet { ref ctx, .. } |
+        Command::RawBatchGet { ref ctx, .. } |
+        Command::RawScan { ref ctx, .. } |
+        Command::RawPut { ref ctx, .. } |
+        Command::RawBatchPut { ref ctx, .. } |
+        Command::RawDelete { ref ctx, .. } |
+        Command::RawBatchDelete { ref ctx, .. } |
+        Command::DeleteRange { ref ctx, .. } |
+        Command::MvccGetByKey { ref ctx, .. } |
+        Command::MvccGetByStartTs { ref ctx, .. } => ctx,
     }
 }
 
@@ -430,11 +732,24 @@ impl Scheduler {
                 let keys: Vec<&Key> = mutations.iter().map(|x| x.key()).collect();
                 self.latches.gen_lock(&keys)
             }
+            Command::RawBatchPut { ref pairs, .. } => {
+                let keys: Vec<&Key> = pairs.iter().map(|x| &x.0).collect();
+                self.latches.gen_lock(&keys)
+            }
+            // Writes in the range must not be reordered with the deletion.
+            Command::DeleteRange { .. } => self.latches.gen_lock_all(),
+            Command::AcquirePessimisticLock { ref keys, .. } |
+            Command::PessimisticRollback { ref keys, .. } |
             Command::Commit { ref keys, .. } |
-            Command::Rollback { ref keys, .. } => self.latches.gen_lock(keys),
+            Command::Rollback { ref keys, .. } |
+            Command::ResolveLock { ref keys, .. } |
+            Command::RawBatchDelete { ref keys, .. } => self.latches.gen_lock(keys),
             Command::CommitThenGet { ref key, .. } |
             Command::Cleanup { ref key, .. } |
-            Command::RollbackThenGet { ref key, .. } => self.latches.gen_lock(&[key]),
+            Command::CheckTxnStatus { primary: ref key, .. } |
+            Command::RollbackThenGet { ref key, .. } |
+            Command::RawPut { ref key, .. } |
+            Command::RawDelete { ref key, .. } => self.latches.gen_lock(&[key]),
             _ => Lock::new(vec![]),
         }
     }
@@ -451,15 +766,15 @@ impl Scheduler {
         if readcmd {
             self.worker_pool.execute(move || process_read(cid, cmd, ch, snapshot));
         } else {
-            self.worker_pool.execute(move || process_write(cid, cmd, ch, snapshot));
+            let wait_lock = self.cmd_ctxs[&cid].wait_lock;
+            self.worker_pool.execute(move || process_write(cid, cmd, ch, snapshot, wait_lock));
         }
     }
 
     fn finish_with_err(&mut self, cid: u64, err: Error) {
         debug!("command cid={}, finished with error", cid);
 
-        let mut ctx = self.cmd_ctxs.remove(&cid).unwrap();
-        assert_eq!(ctx.cid, cid);
+        let mut ctx = self.remove_ctx(cid);
         let cb = ctx.callback.take().unwrap();
         let pr = ProcessResult::Failed { err: StorageError::from(err) };
         execute_callback(cb, pr);
@@ -467,6 +782,38 @@ impl Scheduler {
         self.release_lock(&ctx.lock, cid);
     }
 
+    fn remove_ctx(&mut self, cid: u64) -> RunningCtx {
+        let ctx = self.cmd_ctxs.remove(&cid).unwrap();
+        assert_eq!(ctx.cid, cid);
+        if let Some(bytes) = ctx.write_bytes {
+            self.running_write_count -= 1;
+            self.running_write_bytes -= bytes;
+        }
+        if let Some((region_id, commit_ts)) = ctx.one_pc {
+            self.max_read_ts.finish_one_pc(region_id, commit_ts);
+        }
+        ctx
+    }
+
+    fn park_write(&mut self, cid: u64) {
+        if let Some(bytes) = self.cmd_ctxs[&cid].write_bytes {
+            self.running_write_count -= 1;
+            self.running_write_bytes -= bytes;
+        }
+    }
+
+    fn unpark_write(&mut self, cid: u64) {
+        if let Some(bytes) = self.cmd_ctxs[&cid].write_bytes {
+            self.running_write_count += 1;
+            self.running_write_bytes += bytes;
+        }
+    }
+
+    fn too_busy(&self) -> bool {
+        self.running_write_count >= self.too_busy_threshold ||
+        self.running_write_bytes >= self.pending_write_threshold
+    }
+
     fn extract_context(&self, cid: u64) -> &Context {
         let ctx = &self.cmd_ctxs.get(&cid).unwrap();
         assert_eq!(ctx.cid, cid);
@@ -482,22 +829,42 @@ impl Scheduler {
     }
 
     fn on_report_staticstic_tick(&self, event_loop: &mut EventLoop<Self>) {
-        info!("all running cmd count = {}", self.cmd_ctxs.len());
+        info!("all running cmd count = {}, write count = {}, write bytes = {}",
+              self.cmd_ctxs.len(),
+              self.running_write_count,
+              self.running_write_bytes);
 
         self.register_report_tick(event_loop);
     }
 
+    /// Flow control only applies to the commands from clients, the commands
+    /// following a finished one are always accepted.
+    fn on_receive_raw_cmd(&mut self, cmd: Command, callback: StorageCb) {
+        if !cmd.readonly() && self.too_busy() {
+            debug!("scheduler is too busy, reject command {}", cmd);
+            execute_callback(callback,
+                             ProcessResult::Failed { err: StorageError::SchedTooBusy });
+            return;
+        }
+        self.on_receive_new_cmd(cmd, callback);
+    }
+
     fn on_receive_new_cmd(&mut self, cmd: Command, callback: StorageCb) {
         let cid = self.gen_id();
         debug!("received new command, cid={}, cmd={}", cid, cmd);
         let lock = self.gen_lock(&cmd);
-        let ctx = RunningCtx::new(cid, cmd, lock, callback);
+        let mut ctx = RunningCtx::new(cid, cmd, lock, callback);
+        ctx.wait_lock = self.lock_wait_timeout > 0;
+        if let Some(bytes) = ctx.write_bytes {
+            self.running_write_count += 1;
+            self.running_write_bytes += bytes;
+        }
         if self.cmd_ctxs.insert(cid, ctx).is_some() {
             panic!("command cid={} shouldn't exist", cid);
         }
 
         if self.acquire_lock(cid) {
-            self.get_snapshot(cid);
+            self.start_cmd(cid);
         }
     }
 
@@ -507,7 +874,44 @@ impl Scheduler {
         self.latches.acquire(&mut ctx.lock, cid)
     }
 
+    fn start_cmd(&mut self, cid: u64) {
+        let is_raw_write = self.cmd_ctxs[&cid].cmd.as_ref().unwrap().is_raw_write();
+        if is_raw_write {
+            self.process_raw_write(cid);
+        } else {
+            self.get_snapshot(cid);
+        }
+    }
+
+    /// Raw writes don't depend on any existing data, so they are sent to
+    /// the engine directly without taking a snapshot.
+    fn process_raw_write(&mut self, cid: u64) {
+        debug!("process raw write cmd, cid={}", cid);
+        let mut cmd = {
+            let ctx = &mut self.cmd_ctxs.get_mut(&cid).unwrap();
+            assert_eq!(ctx.cid, cid);
+            ctx.cmd.take().unwrap()
+        };
+        let modifies = raw_modifies(&mut cmd);
+        self.on_write_prepare_finished(cid, cmd, ProcessResult::Res, modifies);
+    }
+
     fn get_snapshot(&mut self, cid: u64) {
+        // GC reads the versions at the safe point, no one-phase commit may be
+        // written at or before it later.
+        let gc_read = match self.cmd_ctxs[&cid].cmd {
+            Some(Command::Gc { ref ctx, safe_point, .. }) => {
+                Some((ctx.get_region_id(), safe_point))
+            }
+            _ => None,
+        };
+        if let Some((region_id, safe_point)) = gc_read {
+            if let Err(e) = self.max_read_ts.on_read(region_id, safe_point) {
+                self.finish_with_err(cid, e);
+                return;
+            }
+        }
+
         let ch = self.schedch.clone();
         let cb = box move |snapshot: EngineResult<Box<Snapshot>>| {
             if let Err(e) = ch.send(Msg::SnapshotFinished {
@@ -534,8 +938,7 @@ impl Scheduler {
     fn on_read_finished(&mut self, cid: u64, pr: ProcessResult) {
         debug!("read command(cid={}) finished", cid);
 
-        let mut ctx = self.cmd_ctxs.remove(&cid).unwrap();
-        assert_eq!(ctx.cid, cid);
+        let mut ctx = self.remove_ctx(cid);
         let cb = ctx.callback.take().unwrap();
         if let ProcessResult::NextCommand { cmd } = pr {
             self.on_receive_new_cmd(cmd, cb);
@@ -547,8 +950,7 @@ impl Scheduler {
     fn on_write_prepare_failed(&mut self, cid: u64, e: Error) {
         debug!("write command(cid={}) failed at prewrite.", cid);
 
-        let mut ctx = self.cmd_ctxs.remove(&cid).unwrap();
-        assert_eq!(ctx.cid, cid);
+        let mut ctx = self.remove_ctx(cid);
         let cb = ctx.callback.take().unwrap();
         let pr = ProcessResult::Failed { err: StorageError::from(e) };
         execute_callback(cb, pr);
@@ -561,12 +963,27 @@ impl Scheduler {
                                  cmd: Command,
                                  pr: ProcessResult,
                                  to_be_write: Vec<Modify>) {
+        if self.lock_wait_timeout > 0 {
+            let ctx = &mut self.cmd_ctxs.get_mut(&cid).unwrap();
+            ctx.released_keys = lock_released_keys(&cmd);
+        }
+        if let Command::Prewrite { ref ctx, ref options, .. } = cmd {
+            if let Some(commit_ts) = options.one_pc_commit_ts {
+                if !to_be_write.is_empty() {
+                    let region_id = ctx.get_region_id();
+                    if let Err(e) = self.max_read_ts.begin_one_pc(region_id, commit_ts) {
+                        self.on_write_prepare_failed(cid, e);
+                        return;
+                    }
+                    self.cmd_ctxs.get_mut(&cid).unwrap().one_pc = Some((region_id, commit_ts));
+                }
+            }
+        }
         if let Err(e) = {
             let engine_cb = make_engine_cb(cid, pr, self.schedch.clone());
             self.engine.async_write(extract_ctx(&cmd), to_be_write, engine_cb)
         } {
-            let mut ctx = self.cmd_ctxs.remove(&cid).unwrap();
-            assert_eq!(ctx.cid, cid);
+            let mut ctx = self.remove_ctx(cid);
             let cb = ctx.callback.take().unwrap();
             execute_callback(cb, ProcessResult::Failed { err: StorageError::from(e) });
 
@@ -576,8 +993,7 @@ impl Scheduler {
 
     fn on_write_finished(&mut self, cid: u64, pr: ProcessResult, result: EngineResult<()>) {
         debug!("write finished for command, cid={}", cid);
-        let mut ctx = self.cmd_ctxs.remove(&cid).unwrap();
-        assert_eq!(ctx.cid, cid);
+        let mut ctx = self.remove_ctx(cid);
         let cb = ctx.callback.take().unwrap();
         let pr = match result {
             Ok(()) => pr,
@@ -590,6 +1006,87 @@ impl Scheduler {
         }
 
         self.release_lock(&ctx.lock, cid);
+        for key in &ctx.released_keys {
+            self.wake_up_waiters(key);
+        }
+    }
+
+    fn on_wait_for_lock(&mut self,
+                        event_loop: &mut EventLoop<Self>,
+                        cid: u64,
+                        cmd: Command,
+                        key: Vec<u8>,
+                        lock_ts: u64) {
+        let start_ts = match cmd {
+            Command::Prewrite { start_ts, .. } |
+            Command::AcquirePessimisticLock { start_ts, .. } => start_ts,
+            _ => panic!("unsupported command to wait for lock"),
+        };
+        debug!("command cid={} waits for lock {} on key {}",
+               cid,
+               lock_ts,
+               escape(&key));
+
+        // Release latches so that the lock owner can commit or rollback.
+        let lock = {
+            let ctx = &mut self.cmd_ctxs.get_mut(&cid).unwrap();
+            assert_eq!(ctx.cid, cid);
+            ctx.cmd = Some(cmd);
+            let lock = ctx.lock.clone();
+            ctx.lock.owned_count = 0;
+            lock
+        };
+        self.release_lock(&lock, cid);
+
+        self.wait_seq_alloc += 1;
+        let wait_seq = self.wait_seq_alloc;
+        let waiter = Waiter {
+            cid: cid,
+            wait_seq: wait_seq,
+            start_ts: start_ts,
+            lock_ts: lock_ts,
+        };
+        if !self.waiter_mgr.wait_for(Key::from_raw(&key), waiter) {
+            info!("deadlock detected, txn {} waits for txn {} on key {}",
+                  start_ts,
+                  lock_ts,
+                  escape(&key));
+            let err = MvccError::Deadlock {
+                key: key,
+                start_ts: start_ts,
+                lock_ts: lock_ts,
+            };
+            self.finish_with_err(cid, Error::from(err));
+            return;
+        }
+        self.park_write(cid);
+        if let Err(e) = register_timer(event_loop,
+                                       Tick::LockWaitTimeout(cid, wait_seq),
+                                       self.lock_wait_timeout) {
+            error!("register lock wait timeout err: {:?}", e);
+            self.on_lock_wait_timeout(cid, wait_seq);
+        }
+    }
+
+    fn on_lock_wait_timeout(&mut self, cid: u64, wait_seq: u64) {
+        // The command has been woken up before timeout, it may be waiting
+        // again in a later wait which has its own timer.
+        if self.waiter_mgr.remove_waiter(cid, wait_seq).is_none() {
+            return;
+        }
+        debug!("command cid={} lock wait timeout", cid);
+        // Run it again and return the lock to client this time.
+        self.cmd_ctxs.get_mut(&cid).unwrap().wait_lock = false;
+        self.unpark_write(cid);
+        self.wakeup_cmd(cid);
+    }
+
+    fn wake_up_waiters(&mut self, key: &Key) {
+        for w in self.waiter_mgr.wake_up(key) {
+            debug!("wake up command cid={} waiting for key {}", w.cid, key);
+            self.unpark_write(w.cid);
+            self.wakeup_cmd(w.cid);
+        }
     }
 
     fn release_lock(&mut self, lock: &Lock, cid: u64) {
@@ -601,7 +1098,7 @@ impl Scheduler {
 
     fn wakeup_cmd(&mut self, cid: u64) {
         if self.acquire_lock(cid) {
-            self.get_snapshot(cid);
+            self.start_cmd(cid);
         }
     }
 
@@ -626,19 +1123,23 @@ impl mio::Handler for Scheduler {
     fn timeout(&mut self, event_loop: &mut EventLoop<Self>, timeout: Tick) {
         match timeout {
             Tick::ReportStatistic => self.on_report_staticstic_tick(event_loop),
+            Tick::LockWaitTimeout(cid, wait_seq) => self.on_lock_wait_timeout(cid, wait_seq),
         }
     }
 
     fn notify(&mut self, event_loop: &mut EventLoop<Self>, msg: Msg) {
         match msg {
             Msg::Quit => self.shutdown(event_loop),
-            Msg::RawCmd { cmd, cb } => self.on_receive_new_cmd(cmd, cb),
+            Msg::RawCmd { cmd, cb } => self.on_receive_raw_cmd(cmd, cb),
             Msg::SnapshotFinished { cid, snapshot } => self.on_snapshot_finished(cid, snapshot),
             Msg::ReadFinished { cid, pr } => self.on_read_finished(cid, pr),
             Msg::WritePrepareFinished { cid, cmd, pr, to_be_write } => {
                 self.on_write_prepare_finished(cid, cmd, pr, to_be_write)
             }
             Msg::WritePrepareFailed { cid, err } => self.on_write_prepare_failed(cid, err),
+            Msg::WaitForLock { cid, cmd, key, lock_ts } => {
+                self.on_wait_for_lock(event_loop, cid, cmd, key, lock_ts)
+            }
             Msg::WriteFinished { cid, pr, result } => self.on_write_finished(cid, pr, result),
         }
     }
@@ -59,21 +59,27 @@ pub struct StoreScanner<'a> {
 
 impl<'a> StoreScanner<'a> {
     pub fn seek(&mut self, key: Key) -> Result<Option<(Key, Value)>> {
-        Ok(try!(self.reader.seek(key, self.start_ts)))
+        Ok(try!(self.reader.seek(key, None, self.start_ts, false)))
     }
 
     pub fn reverse_seek(&mut self, key: Key) -> Result<Option<(Key, Value)>> {
         Ok(try!(self.reader.reverse_seek(key, self.start_ts)))
     }
 
+    /// Get the key of a `KeyIsLocked` error, such errors are returned as a
+    /// part of the scan result instead of failing the whole scan.
     #[inline]
-    fn handle_mvcc_err(e: MvccError, result: &mut Vec<Result<KvPair>>) -> Result<Key> {
-        let key = if let MvccError::KeyIsLocked { key: ref k, .. } = e {
-            Some(Key::from_raw(k))
+    fn locked_key(e: &MvccError) -> Option<Key> {
+        if let MvccError::KeyIsLocked { ref key, .. } = *e {
+            Some(Key::from_raw(key))
         } else {
             None
-        };
-        match key {
+        }
+    }
+
+    #[inline]
+    fn handle_mvcc_err(e: MvccError, result: &mut Vec<Result<KvPair>>) -> Result<Key> {
+        match StoreScanner::locked_key(&e) {
             Some(k) => {
                 result.push(Err(e.into()));
                 Ok(k)
@@ -82,10 +88,17 @@ impl<'a> StoreScanner<'a> {
         }
     }
 
-    pub fn scan(&mut self, mut key: Key, limit: usize) -> Result<Vec<Result<KvPair>>> {
+    /// Scan forward from `key` (inclusive) and stop before `end_key` (exclusive)
+    /// if it is specified. Values are left empty if `key_only` is true.
+    pub fn scan(&mut self,
+                mut key: Key,
+                end_key: Option<&Key>,
+                limit: usize,
+                key_only: bool)
+                -> Result<Vec<Result<KvPair>>> {
         let mut results = vec![];
         while results.len() < limit {
-            match self.seek(key) {
+            match self.reader.seek(key, end_key, self.start_ts, key_only).map_err(Error::from) {
                 Ok(Some((k, v))) => {
                     results.push(Ok((try!(k.raw()), v)));
                     key = k;
@@ -99,18 +112,36 @@ impl<'a> StoreScanner<'a> {
         Ok(results)
     }
 
-    pub fn reverse_scan(&mut self, mut key: Key, limit: usize) -> Result<Vec<Result<KvPair>>> {
+    /// Scan backward from `key` (exclusive) and stop at `end_key` (inclusive)
+    /// if it is specified.
+    pub fn reverse_scan(&mut self,
+                        mut key: Key,
+                        end_key: Option<&Key>,
+                        limit: usize)
+                        -> Result<Vec<Result<KvPair>>> {
         let mut results = vec![];
         while results.len() < limit {
-            match self.reverse_seek(key) {
+            let (k, res) = match self.reverse_seek(key) {
                 Ok(Some((k, v))) => {
-                    results.push(Ok((try!(k.raw()), v)));
-                    key = k;
+                    let raw = try!(k.raw());
+                    (k, Ok((raw, v)))
                 }
                 Ok(None) => break,
-                Err(Error::Mvcc(e)) => key = try!(StoreScanner::handle_mvcc_err(e, &mut results)),
+                Err(Error::Mvcc(e)) => {
+                    match StoreScanner::locked_key(&e) {
+                        Some(k) => (k, Err(e.into())),
+                        None => return Err(e.into()),
+                    }
+                }
                 Err(e) => return Err(e),
+            };
+            if let Some(end_key) = end_key {
+                if k.encoded() < end_key.encoded() {
+                    break;
+                }
             }
+            results.push(res);
+            key = k;
         }
         Ok(results)
     }
@@ -85,6 +85,8 @@ impl PartialEq for Key {
     }
 }
 
+impl Eq for Key {}
+
 pub fn make_key(k: &[u8]) -> Key {
     Key::from_raw(k)
 }
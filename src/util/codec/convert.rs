@@ -13,8 +13,490 @@
 
 
 use std;
+use std::{i64, u64, f64, mem, str};
+use std::time::Duration as StdDuration;
 
-use super::Result;
+use util::escape;
+use super::{Result, Error, Datum};
+use super::mysql::{self, Decimal, Duration, Time};
+
+/// The max number of warnings kept by `ConvertContext`, the same as the default
+/// `max_error_count` of MySQL.
+pub const MAX_WARNING_CNT: usize = 64;
+
+/// `ConvertContext` decides how a lossy conversion is handled.
+///
+/// In strict mode the conversion fails, otherwise the value is truncated and
+/// a warning is recorded like MySQL does.
+#[derive(Debug, Default)]
+pub struct ConvertContext {
+    pub strict: bool,
+    /// The first `MAX_WARNING_CNT` warnings.
+    pub warnings: Vec<String>,
+    pub warning_cnt: usize,
+}
+
+impl ConvertContext {
+    pub fn new(strict: bool) -> ConvertContext {
+        ConvertContext { strict: strict, ..Default::default() }
+    }
+
+    /// Handle a lossy conversion described by `msg`.
+    pub fn truncate(&mut self, msg: String) -> Result<()> {
+        if self.strict {
+            return Err(Error::Truncated(msg));
+        }
+        self.warning_cnt += 1;
+        if self.warnings.len() < MAX_WARNING_CNT {
+            self.warnings.push(msg);
+        }
+        Ok(())
+    }
+
+    /// Take the warnings recorded so far and their count, and reset them.
+    pub fn take_warnings(&mut self) -> (Vec<String>, usize) {
+        let cnt = mem::replace(&mut self.warning_cnt, 0);
+        (mem::replace(&mut self.warnings, vec![]), cnt)
+    }
+}
+
+fn incorrect_value(tp: &str, s: &[u8]) -> String {
+    format!("Truncated incorrect {} value: '{}'", tp, escape(s))
+}
+
+fn is_space(c: u8) -> bool {
+    c == b' ' || c == b'\t' || c == b'\n' || c == b'\r'
+}
+
+fn trim(s: &[u8]) -> &[u8] {
+    let start = s.iter().position(|&c| !is_space(c)).unwrap_or(s.len());
+    let end = s.iter().rposition(|&c| !is_space(c)).map_or(start, |i| i + 1);
+    &s[start..end]
+}
+
+fn digits_len(s: &[u8]) -> usize {
+    s.iter().position(|&c| c < b'0' || c > b'9').unwrap_or(s.len())
+}
+
+fn sign_len(s: &[u8]) -> usize {
+    match s.first() {
+        Some(&b'-') | Some(&b'+') => 1,
+        _ => 0,
+    }
+}
+
+/// Get the longest prefix of `s` which is a valid float number.
+fn float_prefix(s: &[u8]) -> &[u8] {
+    let mut i = sign_len(s);
+    let int_len = digits_len(&s[i..]);
+    i += int_len;
+    let mut frac_len = 0;
+    if s.get(i) == Some(&b'.') {
+        frac_len = digits_len(&s[i + 1..]);
+        i += frac_len + 1;
+    }
+    if int_len == 0 && frac_len == 0 {
+        return &s[..0];
+    }
+    if i < s.len() && (s[i] == b'e' || s[i] == b'E') {
+        let exp_sign = sign_len(&s[i + 1..]);
+        let exp_len = digits_len(&s[i + 1 + exp_sign..]);
+        if exp_len > 0 {
+            i += 1 + exp_sign + exp_len;
+        }
+    }
+    &s[..i]
+}
+
+/// Parse the integer prefix of `s`, return whether it's negative and its
+/// absolute value, which is None if it overflows u64.
+fn parse_int_prefix(ctx: &mut ConvertContext, s: &[u8]) -> Result<(bool, Option<u64>)> {
+    let trimmed = trim(s);
+    let sign = sign_len(trimmed);
+    let len = digits_len(&trimmed[sign..]);
+    if len == 0 || sign + len < trimmed.len() {
+        try!(ctx.truncate(incorrect_value("INTEGER", s)));
+    }
+    let abs = trimmed[sign..sign + len]
+        .iter()
+        .fold(Some(0u64), |r, &c| {
+            r.and_then(|r| r.checked_mul(10)).and_then(|r| r.checked_add((c - b'0') as u64))
+        });
+    Ok((trimmed.first() == Some(&b'-'), abs))
+}
+
+/// `str_to_i64` converts the integer prefix of `s` to an i64, a value out of
+/// range is clamped.
+pub fn str_to_i64(ctx: &mut ConvertContext, s: &[u8]) -> Result<i64> {
+    let (neg, abs) = try!(parse_int_prefix(ctx, s));
+    match abs {
+        Some(abs) if !neg && abs <= i64::MAX as u64 => Ok(abs as i64),
+        Some(abs) if neg && abs <= i64::MIN as u64 => Ok((abs as i64).wrapping_neg()),
+        _ => {
+            try!(ctx.truncate(incorrect_value("INTEGER", s)));
+            Ok(if neg { i64::MIN } else { i64::MAX })
+        }
+    }
+}
+
+/// `str_to_u64` converts the integer prefix of `s` to an u64, a negative value
+/// is converted to its two's complement like MySQL does.
+pub fn str_to_u64(ctx: &mut ConvertContext, s: &[u8]) -> Result<u64> {
+    let (neg, abs) = try!(parse_int_prefix(ctx, s));
+    match abs {
+        Some(abs) if !neg => Ok(abs),
+        Some(abs) if abs <= i64::MIN as u64 => Ok((abs as i64).wrapping_neg() as u64),
+        _ => {
+            try!(ctx.truncate(incorrect_value("INTEGER", s)));
+            Ok(if neg { i64::MIN as u64 } else { u64::MAX })
+        }
+    }
+}
+
+/// `str_to_f64` converts the float prefix of `s` to a f64.
+pub fn str_to_f64(ctx: &mut ConvertContext, s: &[u8]) -> Result<f64> {
+    let trimmed = trim(s);
+    let prefix = float_prefix(trimmed);
+    if prefix.is_empty() || prefix.len() < trimmed.len() {
+        try!(ctx.truncate(incorrect_value("DOUBLE", s)));
+    }
+    if prefix.is_empty() {
+        return Ok(0f64);
+    }
+    let prefix = unsafe { str::from_utf8_unchecked(prefix) };
+    let f: f64 = box_try!(prefix.parse());
+    if f.is_infinite() {
+        try!(ctx.truncate(incorrect_value("DOUBLE", s)));
+        return Ok(if f > 0f64 { f64::MAX } else { f64::MIN });
+    }
+    Ok(f)
+}
+
+/// `str_to_dec` converts the float prefix of `s` to a decimal.
+pub fn str_to_dec(ctx: &mut ConvertContext, s: &[u8]) -> Result<Decimal> {
+    let trimmed = trim(s);
+    let prefix = float_prefix(trimmed);
+    if prefix.is_empty() || prefix.len() < trimmed.len() {
+        try!(ctx.truncate(incorrect_value("DECIMAL", s)));
+    }
+    if prefix.is_empty() {
+        return Ok(0u64.into());
+    }
+    let s = unsafe { str::from_utf8_unchecked(prefix) };
+    s.parse()
+}
+
+/// `f64_to_i64` rounds `f` to an i64, a value out of range is clamped.
+pub fn f64_to_i64(ctx: &mut ConvertContext, f: f64) -> Result<i64> {
+    let r = f.round();
+    if r < i64::MIN as f64 {
+        try!(ctx.truncate(format!("{} is out of range for BIGINT", f)));
+        return Ok(i64::MIN);
+    }
+    // `i64::MAX as f64` is 2^63, which is out of range.
+    if r >= i64::MAX as f64 {
+        try!(ctx.truncate(format!("{} is out of range for BIGINT", f)));
+        return Ok(i64::MAX);
+    }
+    Ok(r as i64)
+}
+
+/// `f64_to_u64` rounds `f` to an u64, a value out of range is clamped.
+pub fn f64_to_u64(ctx: &mut ConvertContext, f: f64) -> Result<u64> {
+    let r = f.round();
+    if r < 0f64 {
+        try!(ctx.truncate(format!("{} is out of range for BIGINT UNSIGNED", f)));
+        return Ok(0);
+    }
+    if r >= u64::MAX as f64 {
+        try!(ctx.truncate(format!("{} is out of range for BIGINT UNSIGNED", f)));
+        return Ok(u64::MAX);
+    }
+    Ok(r as u64)
+}
+
+/// `dec_to_i64` rounds `dec` to an i64, a value out of range is clamped.
+pub fn dec_to_i64(ctx: &mut ConvertContext, dec: &Decimal) -> Result<i64> {
+    if let Some(i) = dec.round(0).i64() {
+        return Ok(i);
+    }
+    try!(ctx.truncate(format!("{} is out of range for BIGINT", dec)));
+    Ok(if dec.is_negative() { i64::MIN } else { i64::MAX })
+}
+
+/// `dec_to_u64` rounds `dec` to an u64, a value out of range is clamped.
+pub fn dec_to_u64(ctx: &mut ConvertContext, dec: &Decimal) -> Result<u64> {
+    let r = dec.round(0);
+    if r.is_negative() {
+        try!(ctx.truncate(format!("{} is out of range for BIGINT UNSIGNED", dec)));
+        return Ok(0);
+    }
+    if let Some(u) = r.u64() {
+        return Ok(u);
+    }
+    try!(ctx.truncate(format!("{} is out of range for BIGINT UNSIGNED", dec)));
+    Ok(u64::MAX)
+}
+
+/// `produce_dec_with_spec` rounds `dec` to `frac` fraction digits, and clamps it
+/// to the max value of DECIMAL(`flen`, `frac`) if it's out of range. A negative
+/// `flen` or `frac` means it's unspecified.
+pub fn produce_dec_with_spec(ctx: &mut ConvertContext,
+                             dec: Decimal,
+                             flen: i32,
+                             frac: i32)
+                             -> Result<Decimal> {
+    if flen >= 0 && frac > flen {
+        return Err(invalid_type!("invalid DECIMAL({}, {})", flen, frac));
+    }
+    let dec = if frac >= 0 {
+        dec.round(frac as u8)
+    } else {
+        dec
+    };
+    if flen < 0 {
+        return Ok(dec);
+    }
+    let frac = if frac < 0 { 0 } else { frac as usize };
+    let mut max_str = String::with_capacity(flen as usize + 2);
+    if flen as usize == frac {
+        max_str.push('0');
+    }
+    for i in 0..flen as usize {
+        if i == flen as usize - frac {
+            max_str.push('.');
+        }
+        max_str.push('9');
+    }
+    let max: Decimal = try!(max_str.parse());
+    let min = Decimal::from(0u64) - max.clone();
+    if dec > max || dec < min {
+        try!(ctx.truncate(format!("{} is out of range for DECIMAL({}, {})", dec, flen, frac)));
+        return Ok(if dec.is_negative() { min } else { max });
+    }
+    Ok(dec)
+}
+
+/// `truncate_str` truncates `s` to `flen` characters, or `flen` bytes if it's
+/// not a valid utf8 string. A negative `flen` means it's unspecified.
+pub fn truncate_str(ctx: &mut ConvertContext, mut s: Vec<u8>, flen: i32) -> Result<Vec<u8>> {
+    if flen < 0 {
+        return Ok(s);
+    }
+    let flen = flen as usize;
+    let end = match str::from_utf8(&s) {
+        Ok(u) => u.char_indices().nth(flen).map(|(i, _)| i),
+        Err(_) if s.len() > flen => Some(flen),
+        Err(_) => None,
+    };
+    if let Some(end) = end {
+        try!(ctx.truncate(incorrect_value(&format!("CHAR({})", flen), &s)));
+        s.truncate(end);
+    }
+    Ok(s)
+}
+
+/// `str_to_time` parses `s` as a time of type `tp` with `fsp` fraction digits,
+/// return None if it's invalid.
+pub fn str_to_time(ctx: &mut ConvertContext, s: &[u8], tp: u8, fsp: u8) -> Result<Option<Time>> {
+    let res = match str::from_utf8(trim(s)) {
+        // The zero number is the zero time.
+        Ok("0") => Time::parse_datetime("00000000", mysql::MAX_FSP),
+        Ok(u) => Time::parse_datetime(u, mysql::MAX_FSP),
+        Err(e) => Err(Error::from(e)),
+    };
+    match res.and_then(|t| t.convert(tp, fsp)) {
+        Ok(t) => Ok(Some(t)),
+        Err(_) => {
+            try!(ctx.truncate(format!("Incorrect datetime value: '{}'", escape(s))));
+            Ok(None)
+        }
+    }
+}
+
+/// `round_dur` rounds the fraction part of `dur` to `fsp` digits.
+pub fn round_dur(dur: &Duration, fsp: u8) -> Result<Duration> {
+    if fsp > mysql::MAX_FSP {
+        return Err(invalid_type!("Invalid fsp {}", fsp));
+    }
+    let nanos = dur.to_nanos();
+    let unit = 10i64.pow(9 - fsp as u32);
+    let rounded = (nanos.abs() + unit / 2) / unit * unit;
+    Duration::from_nanos(if nanos < 0 { -rounded } else { rounded }, fsp)
+}
+
+/// `str_to_dur` parses `s` as a duration with `fsp` fraction digits, return
+/// None if it's invalid.
+pub fn str_to_dur(ctx: &mut ConvertContext, s: &[u8], fsp: u8) -> Result<Option<Duration>> {
+    match Duration::parse(trim(s), mysql::MAX_FSP).and_then(|d| round_dur(&d, fsp)) {
+        Ok(d) => Ok(Some(d)),
+        Err(_) => {
+            try!(ctx.truncate(format!("Incorrect time value: '{}'", escape(s))));
+            Ok(None)
+        }
+    }
+}
+
+/// `dec_to_dur` converts a number in the format of `[-]HHMMSS[.fraction]` to a
+/// duration with `fsp` fraction digits, return None if it's invalid.
+pub fn dec_to_dur(ctx: &mut ConvertContext, dec: &Decimal, fsp: u8) -> Result<Option<Duration>> {
+    let s = format!("{}", dec.round(fsp));
+    let (neg, s) = match s.find('-') {
+        Some(0) => (true, &s[1..]),
+        _ => (false, s.as_str()),
+    };
+    let mut parts = s.splitn(2, '.');
+    let int_part = parts.next().unwrap();
+    let frac_part = parts.next().unwrap_or("");
+    let n: u64 = match int_part.parse() {
+        Ok(n) if n % 10000 / 100 < 60 && n % 100 < 60 => n,
+        _ => {
+            try!(ctx.truncate(format!("Incorrect time value: '{}'", dec)));
+            return Ok(None);
+        }
+    };
+    let secs = n / 10000 * 3600 + n % 10000 / 100 * 60 + n % 100;
+    let nanos = frac_part.bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(9)
+        .fold(0, |r, c| r * 10 + (c - b'0') as u32);
+    match Duration::new(StdDuration::new(secs, nanos), neg, fsp) {
+        Ok(d) => Ok(Some(d)),
+        Err(_) => {
+            try!(ctx.truncate(format!("Incorrect time value: '{}'", dec)));
+            Ok(None)
+        }
+    }
+}
+
+/// `cast_as_int` converts `d` to an integer, an unsigned one if `unsigned`
+/// is true. Integers are converted between signed and unsigned in two's
+/// complement.
+pub fn cast_as_int(ctx: &mut ConvertContext, d: Datum, unsigned: bool) -> Result<Datum> {
+    let res = match d {
+        Datum::Null => Datum::Null,
+        Datum::I64(i) if unsigned => Datum::U64(i as u64),
+        Datum::U64(u) if !unsigned => Datum::I64(u as i64),
+        d @ Datum::I64(_) |
+        d @ Datum::U64(_) => d,
+        Datum::F64(f) if unsigned => Datum::U64(try!(f64_to_u64(ctx, f))),
+        Datum::F64(f) => Datum::I64(try!(f64_to_i64(ctx, f))),
+        Datum::Bytes(bs) => {
+            if unsigned {
+                Datum::U64(try!(str_to_u64(ctx, &bs)))
+            } else {
+                Datum::I64(try!(str_to_i64(ctx, &bs)))
+            }
+        }
+        d @ Datum::Dec(_) |
+        d @ Datum::Time(_) |
+        d @ Datum::Dur(_) => {
+            let dec = try!(d.into_dec());
+            if unsigned {
+                Datum::U64(try!(dec_to_u64(ctx, &dec)))
+            } else {
+                Datum::I64(try!(dec_to_i64(ctx, &dec)))
+            }
+        }
+        d => return Err(invalid_type!("can't cast {:?} to int", d)),
+    };
+    Ok(res)
+}
+
+/// `cast_as_real` converts `d` to a float.
+pub fn cast_as_real(ctx: &mut ConvertContext, d: Datum) -> Result<Datum> {
+    let res = match d {
+        Datum::Null => Datum::Null,
+        Datum::I64(i) => Datum::F64(i as f64),
+        Datum::U64(u) => Datum::F64(u as f64),
+        d @ Datum::F64(_) => d,
+        Datum::Bytes(bs) => Datum::F64(try!(str_to_f64(ctx, &bs))),
+        d @ Datum::Dec(_) |
+        d @ Datum::Time(_) |
+        d @ Datum::Dur(_) => Datum::F64(try!(try!(d.into_dec()).to_f64())),
+        d => return Err(invalid_type!("can't cast {:?} to real", d)),
+    };
+    Ok(res)
+}
+
+/// `cast_as_decimal` converts `d` to a decimal of DECIMAL(`flen`, `frac`), see
+/// `produce_dec_with_spec`.
+pub fn cast_as_decimal(ctx: &mut ConvertContext, d: Datum, flen: i32, frac: i32) -> Result<Datum> {
+    let dec = match d {
+        Datum::Null => return Ok(Datum::Null),
+        Datum::Bytes(bs) => try!(str_to_dec(ctx, &bs)),
+        d @ Datum::I64(_) |
+        d @ Datum::U64(_) |
+        d @ Datum::F64(_) |
+        d @ Datum::Dec(_) |
+        d @ Datum::Time(_) |
+        d @ Datum::Dur(_) => try!(d.into_dec()),
+        d => return Err(invalid_type!("can't cast {:?} to decimal", d)),
+    };
+    produce_dec_with_spec(ctx, dec, flen, frac).map(Datum::Dec)
+}
+
+/// `cast_as_string` converts `d` to a string of at most `flen` characters, see
+/// `truncate_str`.
+pub fn cast_as_string(ctx: &mut ConvertContext, d: Datum, flen: i32) -> Result<Datum> {
+    let bs = match d {
+        Datum::Null => return Ok(Datum::Null),
+        Datum::Bytes(bs) => bs,
+        d => try!(d.into_string()).into_bytes(),
+    };
+    truncate_str(ctx, bs, flen).map(Datum::Bytes)
+}
+
+/// `cast_as_time` converts `d` to a time of type `tp` with `fsp` fraction digits,
+/// the result is NULL if it's an invalid time. Numbers are parsed in the format
+/// of `YYYYMMDD[HHMMSS[.fraction]]`.
+pub fn cast_as_time(ctx: &mut ConvertContext, d: Datum, tp: u8, fsp: u8) -> Result<Datum> {
+    let res = match d {
+        Datum::Null => None,
+        Datum::Bytes(bs) => try!(str_to_time(ctx, &bs, tp, fsp)),
+        Datum::Time(t) => {
+            match t.convert(tp, fsp) {
+                Ok(t) => Some(t),
+                Err(_) => {
+                    try!(ctx.truncate(format!("Incorrect datetime value: '{}'", t)));
+                    None
+                }
+            }
+        }
+        d @ Datum::I64(_) |
+        d @ Datum::U64(_) |
+        d @ Datum::F64(_) |
+        d @ Datum::Dec(_) => {
+            let s = try!(d.into_string());
+            try!(str_to_time(ctx, s.as_bytes(), tp, fsp))
+        }
+        // A duration is converted with the current date, which depends on the
+        // time zone, so it's left to TiDB.
+        d => return Err(invalid_type!("can't cast {:?} to time", d)),
+    };
+    Ok(res.into())
+}
+
+/// `cast_as_duration` converts `d` to a duration with `fsp` fraction digits, the
+/// result is NULL if it's an invalid duration. Numbers are parsed in the format
+/// of `[-]HHMMSS[.fraction]`.
+pub fn cast_as_duration(ctx: &mut ConvertContext, d: Datum, fsp: u8) -> Result<Datum> {
+    let res = match d {
+        Datum::Null => None,
+        Datum::Bytes(bs) => try!(str_to_dur(ctx, &bs, fsp)),
+        Datum::Time(t) => Some(try!(t.to_dur(fsp))),
+        Datum::Dur(d) => Some(try!(round_dur(&d, fsp))),
+        d @ Datum::I64(_) |
+        d @ Datum::U64(_) |
+        d @ Datum::F64(_) |
+        d @ Datum::Dec(_) => {
+            let dec = try!(d.into_dec());
+            try!(dec_to_dur(ctx, &dec, fsp))
+        }
+        d => return Err(invalid_type!("can't cast {:?} to duration", d)),
+    };
+    Ok(res.into())
+}
 
 /// `bytes_to_int` converts a byte arrays to an i64 in best effort.
 /// TODO: handle overflow.
@@ -65,8 +547,12 @@ pub fn bytes_to_f64(bytes: &[u8]) -> Result<f64> {
 mod test {
     use super::*;
 
+    use std::{i64, u64, f64};
     use std::f64::EPSILON;
 
+    use util::codec::Datum;
+    use util::codec::mysql::{Decimal, types};
+
     #[test]
     fn test_bytes_to_i64() {
         let tests: Vec<(&'static [u8], i64)> = vec![
@@ -112,4 +598,162 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_str_to_int() {
+        // (input, i64, warned, u64, warned)
+        let tests: Vec<(&'static [u8], i64, bool, u64, bool)> = vec![
+            (b"0", 0, false, 0, false),
+            (b" 23 ", 23, false, 23, false),
+            (b"+1024", 1024, false, 1024, false),
+            (b"-231", -231, false, (-231i64) as u64, false),
+            (b"2.9", 2, true, 2, true),
+            (b"23e10", 23, true, 23, true),
+            (b"ab", 0, true, 0, true),
+            (b"", 0, true, 0, true),
+            (b"-", 0, true, 0, true),
+            (b"9223372036854775808", i64::MAX, true, 9223372036854775808, false),
+            (b"-9223372036854775808", i64::MIN, false, 9223372036854775808, false),
+            (b"18446744073709551616", i64::MAX, true, u64::MAX, true),
+        ];
+        for (bs, i, i_warned, u, u_warned) in tests {
+            let mut ctx = ConvertContext::default();
+            assert_eq!(str_to_i64(&mut ctx, bs).unwrap(), i);
+            assert_eq!(ctx.warning_cnt > 0, i_warned);
+            let mut ctx = ConvertContext::default();
+            assert_eq!(str_to_u64(&mut ctx, bs).unwrap(), u);
+            assert_eq!(ctx.warning_cnt > 0, u_warned);
+
+            let mut ctx = ConvertContext::new(true);
+            assert_eq!(str_to_i64(&mut ctx, bs).is_err(), i_warned);
+        }
+    }
+
+    #[test]
+    fn test_str_to_f64() {
+        let tests: Vec<(&'static [u8], f64, bool)> = vec![
+            (b" 1.5 ", 1.5, false),
+            (b"-.5", -0.5, false),
+            (b"1.", 1.0, false),
+            (b"1e2", 100.0, false),
+            (b"1e", 1.0, true),
+            (b"1.1.1", 1.1, true),
+            (b"12abc", 12.0, true),
+            (b"abc", 0.0, true),
+            (b"1e400", f64::MAX, true),
+        ];
+        for (bs, f, warn) in tests {
+            let mut ctx = ConvertContext::default();
+            let ff = str_to_f64(&mut ctx, bs).unwrap();
+            if (ff - f).abs() > EPSILON {
+                panic!("{:?} should be converted to {}, but got {}", bs, f, ff);
+            }
+            assert_eq!(ctx.warnings.is_empty(), !warn);
+        }
+    }
+
+    #[test]
+    fn test_produce_dec_with_spec() {
+        let tests = vec![
+            ("1.555", 5, 2, "1.56", false),
+            ("-1.555", -1, 2, "-1.56", false),
+            ("1.5", 5, -1, "1.5", false),
+            ("1000", 4, 1, "999.9", true),
+            ("-1000", 4, 1, "-999.9", true),
+            ("0.5", 2, 2, "0.50", false),
+            ("12.5", 2, 2, "0.99", true),
+        ];
+        for (s, flen, frac, exp, warn) in tests {
+            let mut ctx = ConvertContext::default();
+            let dec: Decimal = s.parse().unwrap();
+            let res = produce_dec_with_spec(&mut ctx, dec, flen, frac).unwrap();
+            assert_eq!(format!("{}", res), exp);
+            assert_eq!(ctx.warnings.is_empty(), !warn);
+        }
+
+        let mut ctx = ConvertContext::default();
+        assert!(produce_dec_with_spec(&mut ctx, 1u64.into(), 1, 2).is_err());
+    }
+
+    #[test]
+    fn test_truncate_str() {
+        let mut ctx = ConvertContext::default();
+        let res = truncate_str(&mut ctx, "中文abc".as_bytes().to_vec(), 3).unwrap();
+        assert_eq!(res, "中文a".as_bytes().to_vec());
+        let res = truncate_str(&mut ctx, vec![0xff, 0xfe, 0xfd], 2).unwrap();
+        assert_eq!(res, vec![0xff, 0xfe]);
+        assert_eq!(ctx.warning_cnt, 2);
+        let res = truncate_str(&mut ctx, b"abc".to_vec(), 3).unwrap();
+        assert_eq!(res, b"abc".to_vec());
+        let res = truncate_str(&mut ctx, b"abc".to_vec(), -1).unwrap();
+        assert_eq!(res, b"abc".to_vec());
+        assert_eq!(ctx.warning_cnt, 2);
+        let (warnings, cnt) = ctx.take_warnings();
+        assert_eq!((warnings.len(), cnt), (2, 2));
+        assert_eq!(ctx.take_warnings(), (vec![], 0));
+
+        let mut ctx = ConvertContext::new(true);
+        assert!(truncate_str(&mut ctx, b"abc".to_vec(), 2).is_err());
+    }
+
+    #[test]
+    fn test_cast() {
+        let s = |s: &str| Datum::Bytes(s.as_bytes().to_vec());
+        let dec = |s: &str| Datum::Dec(s.parse().unwrap());
+        let mut ctx = ConvertContext::default();
+
+        let tests = vec![
+            (Datum::I64(-1), true, Datum::U64(u64::MAX)),
+            (Datum::U64(u64::MAX), false, Datum::I64(-1)),
+            (Datum::F64(-1.5), false, Datum::I64(-2)),
+            (Datum::F64(-1.5), true, Datum::U64(0)),
+            (dec("2.5"), false, Datum::I64(3)),
+            (s("-1"), true, Datum::U64(u64::MAX)),
+            (Datum::Null, false, Datum::Null),
+        ];
+        for (d, unsigned, exp) in tests {
+            assert_eq!(cast_as_int(&mut ctx, d, unsigned).unwrap(), exp);
+        }
+
+        assert_eq!(cast_as_real(&mut ctx, dec("1.25")).unwrap(), Datum::F64(1.25));
+        assert_eq!(cast_as_real(&mut ctx, s("1.25x")).unwrap(), Datum::F64(1.25));
+        assert_eq!(cast_as_decimal(&mut ctx, Datum::F64(1.25), 10, 1).unwrap(),
+                   dec("1.3"));
+        assert_eq!(cast_as_string(&mut ctx, Datum::I64(-123), 2).unwrap(), s("-1"));
+
+        let tests = vec![
+            (s("2010-01-02 12:30:59.5"), types::DATE, 0, "2010-01-02"),
+            (s("2010-01-02 12:30:59.5"), types::DATETIME, 0, "2010-01-02 12:31:00"),
+            (Datum::I64(20100102), types::DATE, 0, "2010-01-02"),
+            (Datum::I64(0), types::DATE, 0, "0000-00-00"),
+            (dec("20100102123059.5"), types::DATETIME, 1, "2010-01-02 12:30:59.5"),
+        ];
+        for (d, tp, fsp, exp) in tests {
+            let res = cast_as_time(&mut ctx, d, tp, fsp).unwrap();
+            assert_eq!(res.into_string().unwrap(), exp);
+        }
+
+        let tests = vec![
+            (s("12:30:59.5"), 0, "12:31:00"),
+            (Datum::I64(123), 0, "00:01:23"),
+            (Datum::I64(-1000000), 0, "-100:00:00"),
+            (dec("123.45"), 1, "00:01:23.5"),
+            (Datum::F64(123.5), 0, "00:01:24"),
+        ];
+        for (d, fsp, exp) in tests {
+            let res = cast_as_duration(&mut ctx, d, fsp).unwrap();
+            assert_eq!(res.into_string().unwrap(), exp);
+        }
+
+        let warning_cnt = ctx.warning_cnt;
+        assert_eq!(cast_as_time(&mut ctx, s("2010-13-01"), types::DATE, 0).unwrap(),
+                   Datum::Null);
+        assert_eq!(cast_as_duration(&mut ctx, Datum::I64(160), 0).unwrap(),
+                   Datum::Null);
+        assert_eq!(ctx.warning_cnt, warning_cnt + 2);
+
+        let mut ctx = ConvertContext::new(true);
+        assert!(cast_as_time(&mut ctx, s("2010-13-01"), types::DATE, 0).is_err());
+        assert!(cast_as_int(&mut ctx, s("1a"), false).is_err());
+    }
 }
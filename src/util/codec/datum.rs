@@ -25,6 +25,10 @@ use super::{number, Result, bytes, convert};
 use super::number::NumberDecoder;
 use super::mysql::{self, Duration, MAX_FSP, Decimal, DecimalEncoder, DecimalDecoder, Time};
 
+/// The default `div_precision_increment` of MySQL, the number of fraction digits
+/// by which the result of `/` is increased.
+const DIV_FRAC_INCR: u8 = 4;
+
 pub const NIL_FLAG: u8 = 0;
 const BYTES_FLAG: u8 = 1;
 const COMPACT_BYTES_FLAG: u8 = 2;
@@ -111,6 +115,36 @@ fn checked_add_i64(l: u64, r: i64) -> Option<u64> {
     }
 }
 
+#[inline]
+fn checked_sub_i64(l: u64, r: i64) -> Option<u64> {
+    if r >= 0 {
+        l.checked_sub(r as u64)
+    } else {
+        l.checked_add(opp_neg!(r))
+    }
+}
+
+#[inline]
+fn checked_mul_i64(l: u64, r: i64) -> Option<u64> {
+    if r >= 0 {
+        l.checked_mul(r as u64)
+    } else if l == 0 {
+        Some(0)
+    } else {
+        None
+    }
+}
+
+/// Convert the result of a float arithmetic, Null means overflow.
+#[inline]
+fn f64_res(f: f64) -> Datum {
+    if f.is_finite() {
+        Datum::F64(f)
+    } else {
+        Datum::Null
+    }
+}
+
 #[allow(should_implement_trait)]
 impl Datum {
     pub fn cmp(&self, datum: &Datum) -> Result<Ordering> {
@@ -282,6 +316,31 @@ impl Datum {
         Ok(b)
     }
 
+    /// `into_i64` converts self to an i64, the fraction part is rounded.
+    /// source function name is `ToInt64`.
+    pub fn into_i64(self) -> Result<i64> {
+        match self {
+            Datum::I64(i) => Ok(i),
+            Datum::U64(u) => Ok(u as i64),
+            Datum::F64(f) => {
+                let f = f.round();
+                if f >= i64::MIN as f64 && f < i64::MAX as f64 {
+                    Ok(f as i64)
+                } else {
+                    Err(box_err!("{} to int will overflow", f))
+                }
+            }
+            Datum::Bytes(bs) => convert::bytes_to_int(&bs),
+            d @ Datum::Time(_) |
+            d @ Datum::Dur(_) |
+            d @ Datum::Dec(_) => {
+                let dec = try!(d.into_dec());
+                dec.i64().ok_or_else(|| box_err!("{} to int will overflow", dec))
+            }
+            d => Err(invalid_type!("can't convert {:?} to int", d)),
+        }
+    }
+
     /// into_string convert self into a string.
     /// source function name is `ToString`.
     pub fn into_string(self) -> Result<String> {
@@ -409,22 +468,170 @@ impl Datum {
             (Datum::I64(l), Datum::U64(r)) |
             (Datum::U64(r), Datum::I64(l)) => checked_add_i64(r, l).into(),
             (Datum::U64(l), Datum::U64(r)) => l.checked_add(r).into(),
-            (Datum::F64(l), Datum::F64(r)) => {
-                let res = l + r;
-                if !res.is_finite() {
+            (Datum::F64(l), Datum::F64(r)) => f64_res(l + r),
+            (Datum::Dec(l), Datum::Dec(r)) => Datum::Dec(l + r),
+            (l, r) => return Err(invalid_type!("{:?} and {:?} can't be add together.", l, r)),
+        };
+        if let Datum::Null = res {
+            return Err(box_err!("overflow"));
+        }
+        Ok(res)
+    }
+
+    /// Keep compatible with TiDB's `ComputeMinus` function.
+    pub fn checked_sub(self, d: Datum) -> Result<Datum> {
+        let res: Datum = match (self, d) {
+            (Datum::I64(l), Datum::I64(r)) => l.checked_sub(r).into(),
+            (Datum::I64(l), Datum::U64(r)) => {
+                if l < 0 {
                     Datum::Null
                 } else {
-                    Datum::F64(res)
+                    (l as u64).checked_sub(r).into()
+                }
+            }
+            (Datum::U64(l), Datum::I64(r)) => checked_sub_i64(l, r).into(),
+            (Datum::U64(l), Datum::U64(r)) => l.checked_sub(r).into(),
+            (Datum::F64(l), Datum::F64(r)) => f64_res(l - r),
+            (Datum::Dec(l), Datum::Dec(r)) => Datum::Dec(l - r),
+            (l, r) => return Err(invalid_type!("{:?} can't minus {:?}.", l, r)),
+        };
+        if let Datum::Null = res {
+            return Err(box_err!("overflow"));
+        }
+        Ok(res)
+    }
+
+    /// Keep compatible with TiDB's `ComputeMul` function.
+    pub fn checked_mul(self, d: Datum) -> Result<Datum> {
+        let res: Datum = match (self, d) {
+            (Datum::I64(l), Datum::I64(r)) => l.checked_mul(r).into(),
+            (Datum::I64(l), Datum::U64(r)) |
+            (Datum::U64(r), Datum::I64(l)) => checked_mul_i64(r, l).into(),
+            (Datum::U64(l), Datum::U64(r)) => l.checked_mul(r).into(),
+            (Datum::F64(l), Datum::F64(r)) => f64_res(l * r),
+            (Datum::Dec(l), Datum::Dec(r)) => Datum::Dec(l * r),
+            (l, r) => return Err(invalid_type!("{:?} and {:?} can't be multiplied.", l, r)),
+        };
+        if let Datum::Null = res {
+            return Err(box_err!("overflow"));
+        }
+        Ok(res)
+    }
+
+    /// Test if the datum is a zero number, which can't be a divisor.
+    fn is_zero_num(&self) -> bool {
+        match *self {
+            Datum::I64(0) | Datum::U64(0) => true,
+            Datum::F64(f) => f == 0f64,
+            Datum::Dec(ref d) => d.is_zero(),
+            _ => false,
+        }
+    }
+
+    /// Keep compatible with TiDB's `ComputeDiv` function.
+    ///
+    /// Integers are divided as decimals, dividing by zero returns Null.
+    pub fn checked_div(self, d: Datum) -> Result<Datum> {
+        if d.is_zero_num() {
+            return Ok(Datum::Null);
+        }
+        let res: Datum = match (self, d) {
+            (Datum::F64(l), Datum::F64(r)) => f64_res(l / r),
+            (l, r) => {
+                match (l.coerce_to_dec(), r.coerce_to_dec()) {
+                    (Datum::Dec(l), Datum::Dec(r)) => {
+                        Datum::Dec(l.checked_div(&r, DIV_FRAC_INCR).unwrap())
+                    }
+                    (l, r) => return Err(invalid_type!("{:?} can't be divided by {:?}.", l, r)),
                 }
             }
-            (Datum::Dec(l), Datum::Dec(r)) => Datum::Dec(l + r),
-            (l, r) => return Err(invalid_type!("{:?} and {:?} can't be add together.", l, r)),
         };
         if let Datum::Null = res {
             return Err(box_err!("overflow"));
         }
         Ok(res)
     }
+
+    /// Keep compatible with TiDB's `ComputeIntDiv` function.
+    ///
+    /// The quotient is truncated to an integer, dividing by zero returns Null.
+    pub fn checked_int_div(self, d: Datum) -> Result<Datum> {
+        if d.is_zero_num() {
+            return Ok(Datum::Null);
+        }
+        let res: Datum = match (self, d) {
+            (Datum::I64(l), Datum::I64(r)) => l.checked_div(r).into(),
+            (Datum::I64(l), Datum::U64(r)) => {
+                if l >= 0 {
+                    Datum::U64(l as u64 / r)
+                } else if opp_neg!(l) < r {
+                    // The result is unsigned, only a zero quotient is valid.
+                    Datum::U64(0)
+                } else {
+                    Datum::Null
+                }
+            }
+            (Datum::U64(l), Datum::I64(r)) => {
+                if r >= 0 {
+                    Datum::U64(l / r as u64)
+                } else if l < opp_neg!(r) {
+                    Datum::U64(0)
+                } else {
+                    Datum::Null
+                }
+            }
+            (Datum::U64(l), Datum::U64(r)) => Datum::U64(l / r),
+            (Datum::F64(l), Datum::F64(r)) => {
+                let q = (l / r).trunc();
+                if q >= i64::MIN as f64 && q < i64::MAX as f64 {
+                    Datum::I64(q as i64)
+                } else {
+                    Datum::Null
+                }
+            }
+            (Datum::Dec(l), Datum::Dec(r)) => l.checked_int_div(&r).and_then(|q| q.i64()).into(),
+            (l, r) => return Err(invalid_type!("{:?} can't be divided by {:?}.", l, r)),
+        };
+        if let Datum::Null = res {
+            return Err(box_err!("overflow"));
+        }
+        Ok(res)
+    }
+
+    /// Keep compatible with TiDB's `ComputeMod` function.
+    ///
+    /// The sign of the result is the same as the dividend's, dividing by zero
+    /// returns Null.
+    pub fn checked_rem(self, d: Datum) -> Result<Datum> {
+        if d.is_zero_num() {
+            return Ok(Datum::Null);
+        }
+        let res = match (self, d) {
+            // `i64::MIN % -1` overflows, but the remainder is 0 anyway.
+            (Datum::I64(l), Datum::I64(r)) => Datum::I64(l.wrapping_rem(r)),
+            (Datum::I64(l), Datum::U64(r)) => {
+                if l >= 0 {
+                    Datum::U64(l as u64 % r)
+                } else {
+                    // The remainder may be 2^63 if `l` is `i64::MIN`, the wrapping
+                    // negation is still right.
+                    Datum::I64(((opp_neg!(l) % r) as i64).wrapping_neg())
+                }
+            }
+            (Datum::U64(l), Datum::I64(r)) => {
+                if r >= 0 {
+                    Datum::U64(l % r as u64)
+                } else {
+                    Datum::U64(l % opp_neg!(r))
+                }
+            }
+            (Datum::U64(l), Datum::U64(r)) => Datum::U64(l % r),
+            (Datum::F64(l), Datum::F64(r)) => Datum::F64(l % r),
+            (Datum::Dec(l), Datum::Dec(r)) => Datum::Dec(l.checked_rem(&r).unwrap()),
+            (l, r) => return Err(invalid_type!("{:?} can't be divided by {:?}.", l, r)),
+        };
+        Ok(res)
+    }
 }
 
 impl From<bool> for Datum {
@@ -1000,6 +1207,29 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_datum_to_i64() {
+        let tests = vec![
+            (Datum::I64(-1), Some(-1)),
+            (Datum::U64(1), Some(1)),
+            (Datum::F64(0.4), Some(0)),
+            (Datum::F64(-1.5), Some(-2)),
+            (Datum::F64(1e20), None),
+            (b"12abc".as_ref().into(), Some(12)),
+            (b"abc".as_ref().into(), Some(0)),
+            (Time::parse_datetime("2011-11-10 11:11:11", 0).unwrap().into(),
+             Some(20111110111111)),
+            (Duration::parse(b"11:11:11.5", 1).unwrap().into(), Some(111112)),
+            (Datum::Dec("3.5".parse().unwrap()), Some(4)),
+            (Datum::Null, None),
+        ];
+        for (d, i) in tests {
+            if d.clone().into_i64().ok() != i {
+                panic!("expect {:?} to be {:?}", d, i);
+            }
+        }
+    }
+
     #[test]
     fn test_split_datum() {
         let table = vec![
@@ -64,6 +64,10 @@ quick_error! {
             description("invalid data type")
             display("{}", reason)
         }
+        Truncated(reason: String) {
+            description("data truncated")
+            display("{}", reason)
+        }
         Encoding(err: Utf8Error) {
             from()
             cause(err)
@@ -16,7 +16,7 @@ use num::bigint::{Sign, BigUint, BigInt};
 use num::integer::Integer;
 use std::cmp::{self, Ordering};
 use std::io::Write;
-use std::ops::Add;
+use std::ops::{Add, Sub, Mul};
 use std::fmt::{self, Display, Formatter};
 use std::str::{self, FromStr};
 use std::{i32, u64};
@@ -218,6 +218,37 @@ impl Decimal {
         }
     }
 
+    /// Get the int part of this decimal as an unsigned integer.
+    ///
+    /// Return None if the decimal is negative or overflow.
+    pub fn u64(&self) -> Option<u64> {
+        match self.rescale(0) {
+            Some(d) => d.u64(),
+            None => self.coeff.to_u64(),
+        }
+    }
+
+    /// Round the decimal to `frac` fraction digits, half away from zero.
+    pub fn round(&self, frac: u8) -> Decimal {
+        let frac = cmp::min(frac, MAX_FSP);
+        let exp = -(frac as i32);
+        if self.exp >= exp {
+            let mut d = self.clone();
+            d.fsp = frac;
+            return d;
+        }
+        let divisor = pow10((exp - self.exp) as usize);
+        let (mut coeff, rem) = self.coeff.div_rem(&divisor);
+        if rem.abs() * BigInt::from(2) >= divisor {
+            if self.is_negative() {
+                coeff = coeff - BigInt::one();
+            } else {
+                coeff = coeff + BigInt::one();
+            }
+        }
+        Decimal::new(coeff, exp, frac)
+    }
+
     /// Truncate truncates off digits from the number, without rounding.
     /// Note: this function will round the last digit, which is not compatible with
     /// TiDB. But since we are about to use `MyDecimal` very soon, so let's keep it now.
@@ -230,6 +261,70 @@ impl Decimal {
         d.fsp = precision as u8;
         d
     }
+
+    /// Get the coefficients of both decimals under the smaller exponent.
+    fn align(&self, rhs: &Decimal) -> (BigInt, BigInt, i32) {
+        let exp = cmp::min(self.exp, rhs.exp);
+        let l = self.rescale(exp).map_or_else(|| self.coeff.clone(), |d| d.coeff);
+        let r = rhs.rescale(exp).map_or_else(|| rhs.coeff.clone(), |d| d.coeff);
+        (l, r, exp)
+    }
+
+    /// Divide the decimal by `rhs`.
+    ///
+    /// The quotient keeps `frac_incr` more fraction digits than the dividend, and
+    /// the rest digits are rounded half away from zero. Return None if `rhs` is zero.
+    pub fn checked_div(&self, rhs: &Decimal, frac_incr: u8) -> Option<Decimal> {
+        if rhs.is_zero() {
+            return None;
+        }
+        let fsp = cmp::min(self.fsp.saturating_add(frac_incr), MAX_FSP);
+        // quotient * 10^-fsp = (l * 10^le) / (r * 10^re), so
+        // quotient = l * 10^(le - re + fsp) / r.
+        let shift = self.exp - rhs.exp + fsp as i32;
+        let (dividend, divisor) = if shift >= 0 {
+            (self.coeff.clone() * pow10(shift as usize), rhs.coeff.clone())
+        } else {
+            (self.coeff.clone(), rhs.coeff.clone() * pow10(-shift as usize))
+        };
+        let (mut coeff, rem) = dividend.div_rem(&divisor);
+        if rem.abs() * BigInt::from(2) >= divisor.abs() {
+            if dividend.is_negative() == divisor.is_negative() {
+                coeff = coeff + BigInt::one();
+            } else {
+                coeff = coeff - BigInt::one();
+            }
+        }
+        Some(Decimal::new(coeff, -(fsp as i32), fsp).compact())
+    }
+
+    /// Divide the decimal by `rhs`, and truncate the quotient to an integer.
+    ///
+    /// Return None if `rhs` is zero.
+    pub fn checked_int_div(&self, rhs: &Decimal) -> Option<Decimal> {
+        if rhs.is_zero() {
+            return None;
+        }
+        let (l, r, _) = self.align(rhs);
+        Some(Decimal::new(l / r, 0, 0))
+    }
+
+    /// Get the remainder of dividing the decimal by `rhs`, its sign is the same
+    /// as the dividend's.
+    ///
+    /// Return None if `rhs` is zero.
+    pub fn checked_rem(&self, rhs: &Decimal) -> Option<Decimal> {
+        if rhs.is_zero() {
+            return None;
+        }
+        let fsp = cmp::max(self.fsp, rhs.fsp);
+        let (l, r, exp) = self.align(rhs);
+        Some(Decimal::new(l % r, exp, fsp).compact())
+    }
+}
+
+fn pow10(n: usize) -> BigInt {
+    BigInt::from(num::pow(BigUint::from(10u8), n))
 }
 
 impl FromStr for Decimal {
@@ -415,6 +510,26 @@ impl Add<Decimal> for Decimal {
     }
 }
 
+impl Sub<Decimal> for Decimal {
+    type Output = Decimal;
+
+    fn sub(self, rhs: Decimal) -> Decimal {
+        let fsp = cmp::max(self.fsp, rhs.fsp);
+        let (l, r, exp) = self.align(&rhs);
+        Decimal::new(l - r, exp, fsp).compact()
+    }
+}
+
+impl Mul<Decimal> for Decimal {
+    type Output = Decimal;
+
+    fn mul(self, rhs: Decimal) -> Decimal {
+        // Fraction digits beyond `MAX_FSP` are rounded by `Decimal::new`.
+        let fsp = self.fsp.saturating_add(rhs.fsp);
+        Decimal::new(self.coeff * rhs.coeff, self.exp + rhs.exp, fsp).compact()
+    }
+}
+
 pub trait DecimalEncoder: BytesEncoder {
     /// Encode decimal to compareable bytes.
     ///
@@ -526,6 +641,7 @@ impl<T: BytesDecoder> DecimalDecoder for T {}
 mod test {
     use std::f64;
     use std::f32;
+    use std::u64;
     use std::str::FromStr;
 
     use super::*;
@@ -781,4 +897,114 @@ mod test {
             assert_eq!(res_str, exp.to_owned());
         }
     }
+
+    #[test]
+    fn test_decimal_sub() {
+        let cases = vec![
+            ("5", "3", "2"),
+            ("3", "5", "-2"),
+            ("24545.2954604593", ".3451204593", "24544.9503400000"),
+            (".1", ".1", "0.0"),
+            ("-1.5", "1.25", "-2.75"),
+            ("0", "1.001", "-1.001"),
+        ];
+        for (a, b, exp) in cases {
+            let lhs: Decimal = a.parse().unwrap();
+            let rhs: Decimal = b.parse().unwrap();
+            let res = lhs - rhs;
+            assert_eq!(format!("{}", res), exp.to_owned());
+        }
+    }
+
+    #[test]
+    fn test_decimal_mul() {
+        let cases = vec![
+            ("2", "3", "6"),
+            ("1.5", "-2", "-3.0"),
+            ("1.25", "1.25", "1.5625"),
+            ("-.1", "-.1", "0.01"),
+            ("0", "1.001", "0.000"),
+            ("12345678901234567890", "10", "123456789012345678900"),
+        ];
+        for (a, b, exp) in cases {
+            let lhs: Decimal = a.parse().unwrap();
+            let rhs: Decimal = b.parse().unwrap();
+            let res = lhs * rhs;
+            assert_eq!(format!("{}", res), exp.to_owned());
+        }
+    }
+
+    #[test]
+    fn test_decimal_div() {
+        let cases = vec![
+            ("1", "3", Some("0.3333")),
+            ("2", "3", Some("0.6667")),
+            ("-2", "3", Some("-0.6667")),
+            ("2", "-3", Some("-0.6667")),
+            ("6", "2", Some("3.0000")),
+            ("1.5", "0.5", Some("3.00000")),
+            ("1", "0.0", None),
+        ];
+        for (a, b, exp) in cases {
+            let lhs: Decimal = a.parse().unwrap();
+            let rhs: Decimal = b.parse().unwrap();
+            let res = lhs.checked_div(&rhs, 4).map(|d| format!("{}", d));
+            assert_eq!(res, exp.map(|s| s.to_owned()));
+        }
+
+        let cases = vec![
+            ("7", "2", Some("3")),
+            ("-7", "2", Some("-3")),
+            ("7.5", "-2.5", Some("-3")),
+            ("0.5", "3", Some("0")),
+            ("1", "0", None),
+        ];
+        for (a, b, exp) in cases {
+            let lhs: Decimal = a.parse().unwrap();
+            let rhs: Decimal = b.parse().unwrap();
+            let res = lhs.checked_int_div(&rhs).map(|d| format!("{}", d));
+            assert_eq!(res, exp.map(|s| s.to_owned()));
+        }
+    }
+
+    #[test]
+    fn test_decimal_rem() {
+        let cases = vec![
+            ("7", "3", Some("1")),
+            ("-7", "3", Some("-1")),
+            ("7", "-3", Some("1")),
+            ("7.5", "2", Some("1.5")),
+            ("6", "1.5", Some("0.0")),
+            ("1", "0", None),
+        ];
+        for (a, b, exp) in cases {
+            let lhs: Decimal = a.parse().unwrap();
+            let rhs: Decimal = b.parse().unwrap();
+            let res = lhs.checked_rem(&rhs).map(|d| format!("{}", d));
+            assert_eq!(res, exp.map(|s| s.to_owned()));
+        }
+    }
+
+    #[test]
+    fn test_decimal_round() {
+        let cases = vec![
+            ("1.5", 0, "2"),
+            ("-1.5", 0, "-2"),
+            ("1.49", 0, "1"),
+            ("1.555", 2, "1.56"),
+            ("-1.555", 2, "-1.56"),
+            ("1.5", 3, "1.500"),
+            ("123", 1, "123.0"),
+            ("0.004", 2, "0.00"),
+        ];
+        for (a, frac, exp) in cases {
+            let d: Decimal = a.parse().unwrap();
+            assert_eq!(format!("{}", d.round(frac)), exp);
+        }
+
+        let d: Decimal = "18446744073709551615.4".parse().unwrap();
+        assert_eq!(d.u64(), Some(u64::MAX));
+        let d: Decimal = "-1".parse().unwrap();
+        assert_eq!(d.u64(), None);
+    }
 }
@@ -59,7 +59,7 @@ pub use self::duration::Duration;
 pub use self::decimal::{Decimal, DecimalEncoder, DecimalDecoder, encoded_len};
 pub use self::types::{has_unsigned_flag, has_not_null_flag};
 pub use self::mydecimal::dec_encoded_len;
-pub use self::time::Time;
+pub use self::time::{Time, Interval};
 
 #[cfg(test)]
 mod test {
@@ -12,9 +12,10 @@
 // limitations under the License.
 
 
-use std::cmp::Ordering;
+use std::cmp::{self, Ordering};
 use std::str::{self, FromStr};
 use std::fmt::{self, Formatter, Display};
+use std::ops::Neg;
 
 use chrono::{NaiveDate, NaiveDateTime, Timelike, Datelike, Duration};
 
@@ -63,6 +64,128 @@ fn ymd_hms_nanos(year: i32,
         })
 }
 
+const MONTH_NAMES: [&'static str; 12] = ["January",
+                                          "February",
+                                          "March",
+                                          "April",
+                                          "May",
+                                          "June",
+                                          "July",
+                                          "August",
+                                          "September",
+                                          "October",
+                                          "November",
+                                          "December"];
+
+const WEEKDAY_NAMES: [&'static str; 7] = ["Sunday",
+                                          "Monday",
+                                          "Tuesday",
+                                          "Wednesday",
+                                          "Thursday",
+                                          "Friday",
+                                          "Saturday"];
+
+// The flags of the week mode, see MySQL's `WEEK` function.
+const WEEK_MONDAY_FIRST: u8 = 1;
+const WEEK_YEAR: u8 = 2;
+const WEEK_FIRST_WEEKDAY: u8 = 4;
+
+/// Any interval longer than this overflows the supported time range.
+const MAX_INTERVAL_DAYS: i64 = 10000 * 366;
+const SECS_PER_DAY: i64 = 86400;
+const MICROS_PER_SEC: i64 = 1_000_000;
+
+fn days_in_year(year: i32) -> i64 {
+    if NaiveDate::from_ymd_opt(year, 2, 29).is_some() {
+        366
+    } else {
+        365
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (y, m) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    NaiveDate::from_ymd(y, m, 1).pred().day()
+}
+
+/// Get the week number of the date and the year the week belongs to.
+///
+/// It's a port of MySQL's `calc_week`.
+fn calc_week(date: &NaiveDate, week_mode: u8) -> (i32, u32) {
+    let monday_first = week_mode & WEEK_MONDAY_FIRST != 0;
+    let mut week_year = week_mode & WEEK_YEAR != 0;
+    let first_weekday = week_mode & WEEK_FIRST_WEEKDAY != 0;
+
+    let mut year = date.year();
+    let first_day = NaiveDate::from_ymd(year, 1, 1);
+    // The days since the first day of the year.
+    let mut offset = (*date - first_day).num_days();
+    let mut weekday = if monday_first {
+        first_day.weekday().num_days_from_monday() as i64
+    } else {
+        first_day.weekday().num_days_from_sunday() as i64
+    };
+
+    if date.month() == 1 && date.day() as i64 <= 7 - weekday {
+        if !week_year && ((first_weekday && weekday != 0) || (!first_weekday && weekday >= 4)) {
+            return (year, 0);
+        }
+        // The date belongs to the last week of the previous year.
+        week_year = true;
+        year -= 1;
+        let days = days_in_year(year);
+        offset += days;
+        weekday = (weekday + 53 * 7 - days) % 7;
+    }
+
+    let days = if (first_weekday && weekday != 0) || (!first_weekday && weekday >= 4) {
+        offset - (7 - weekday)
+    } else {
+        offset + weekday
+    };
+
+    if week_year && days >= 52 * 7 {
+        weekday = (weekday + days_in_year(year)) % 7;
+        if (!first_weekday && weekday < 4) || (first_weekday && weekday == 0) {
+            // The date belongs to the first week of the next year.
+            return (year + 1, 1);
+        }
+    }
+    (year, (days / 7 + 1) as u32)
+}
+
+/// Add some days, seconds and microseconds to the time, return None if the
+/// result is out of [0000-01-01, 9999-12-31].
+fn add_micros(time: NaiveDateTime, days: i64, secs: i64, micros: i64) -> Option<NaiveDateTime> {
+    // `chrono` panics if a duration is too long.
+    if days.abs() > MAX_INTERVAL_DAYS || secs.abs() > MAX_INTERVAL_DAYS * SECS_PER_DAY ||
+       micros.abs() > MAX_INTERVAL_DAYS * SECS_PER_DAY * MICROS_PER_SEC {
+        return None;
+    }
+    time.checked_add(Duration::days(days))
+        .and_then(|t| t.checked_add(Duration::seconds(secs)))
+        .and_then(|t| t.checked_add(Duration::microseconds(micros)))
+        .and_then(|t| if t.year() < 0 || t.year() > 9999 {
+            None
+        } else {
+            Some(t)
+        })
+}
+
+fn day_suffix(day: u32) -> &'static str {
+    match day {
+        11 | 12 | 13 => "th",
+        d if d % 10 == 1 => "st",
+        d if d % 10 == 2 => "nd",
+        d if d % 10 == 3 => "rd",
+        _ => "th",
+    }
+}
+
 #[inline]
 fn from_bytes(bs: &[u8]) -> &str {
     unsafe { str::from_utf8_unchecked(bs) }
@@ -268,6 +391,381 @@ impl Time {
         let micro = t.nanosecond() as u64 / 1000;
         (((ymd << 17) | hms) << 24) | micro
     }
+
+    /// Get the year, all the date and time parts of the zero time are 0.
+    pub fn year(&self) -> i32 {
+        if self.is_zero() {
+            return 0;
+        }
+        self.time.year()
+    }
+
+    pub fn month(&self) -> u32 {
+        if self.is_zero() {
+            return 0;
+        }
+        self.time.month()
+    }
+
+    pub fn day(&self) -> u32 {
+        if self.is_zero() {
+            return 0;
+        }
+        self.time.day()
+    }
+
+    pub fn hour(&self) -> u32 {
+        if self.is_zero() {
+            return 0;
+        }
+        self.time.hour()
+    }
+
+    pub fn minute(&self) -> u32 {
+        if self.is_zero() {
+            return 0;
+        }
+        self.time.minute()
+    }
+
+    pub fn second(&self) -> u32 {
+        if self.is_zero() {
+            return 0;
+        }
+        self.time.second()
+    }
+
+    /// Get the day of week, 1 for Sunday and 7 for Saturday like MySQL's
+    /// `DAYOFWEEK`. Return None if the time is zero.
+    pub fn day_of_week(&self) -> Option<u32> {
+        if self.is_zero() {
+            return None;
+        }
+        Some(self.time.weekday().num_days_from_sunday() + 1)
+    }
+
+    /// Format the time with the specifiers of MySQL's `DATE_FORMAT`.
+    ///
+    /// Return None if the time is zero.
+    pub fn date_format(&self, layout: &str) -> Option<String> {
+        if self.is_zero() {
+            return None;
+        }
+        let t = &self.time;
+        let date = t.date();
+        let hour12 = (t.hour() + 11) % 12 + 1;
+        let am_pm = if t.hour() < 12 { "AM" } else { "PM" };
+        let mut res = String::with_capacity(layout.len() * 2);
+        let mut chars = layout.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                res.push(c);
+                continue;
+            }
+            let c = match chars.next() {
+                Some(c) => c,
+                None => {
+                    res.push('%');
+                    break;
+                }
+            };
+            let s = match c {
+                'a' => WEEKDAY_NAMES[t.weekday().num_days_from_sunday() as usize][..3].to_owned(),
+                'b' => MONTH_NAMES[t.month0() as usize][..3].to_owned(),
+                'c' => format!("{}", t.month()),
+                'D' => format!("{}{}", t.day(), day_suffix(t.day())),
+                'd' => format!("{:02}", t.day()),
+                'e' => format!("{}", t.day()),
+                'f' => format!("{:06}", t.nanosecond() / 1000),
+                'H' => format!("{:02}", t.hour()),
+                'h' | 'I' => format!("{:02}", hour12),
+                'i' => format!("{:02}", t.minute()),
+                'j' => format!("{:03}", t.ordinal()),
+                'k' => format!("{}", t.hour()),
+                'l' => format!("{}", hour12),
+                'M' => MONTH_NAMES[t.month0() as usize].to_owned(),
+                'm' => format!("{:02}", t.month()),
+                'p' => am_pm.to_owned(),
+                'r' => format!("{:02}:{:02}:{:02} {}", hour12, t.minute(), t.second(), am_pm),
+                'S' | 's' => format!("{:02}", t.second()),
+                'T' => format!("{:02}:{:02}:{:02}", t.hour(), t.minute(), t.second()),
+                'U' => format!("{:02}", calc_week(&date, WEEK_FIRST_WEEKDAY).1),
+                'u' => format!("{:02}", calc_week(&date, WEEK_MONDAY_FIRST).1),
+                'V' => format!("{:02}", calc_week(&date, WEEK_YEAR | WEEK_FIRST_WEEKDAY).1),
+                'v' => format!("{:02}", calc_week(&date, WEEK_YEAR | WEEK_MONDAY_FIRST).1),
+                'W' => WEEKDAY_NAMES[t.weekday().num_days_from_sunday() as usize].to_owned(),
+                'w' => format!("{}", t.weekday().num_days_from_sunday()),
+                'X' => format!("{:04}", calc_week(&date, WEEK_YEAR | WEEK_FIRST_WEEKDAY).0),
+                'x' => format!("{:04}", calc_week(&date, WEEK_YEAR | WEEK_MONDAY_FIRST).0),
+                'Y' => format!("{:04}", t.year()),
+                'y' => format!("{:02}", t.year() % 100),
+                // `%%` and any unknown specifier output the character itself.
+                c => c.to_string(),
+            };
+            res.push_str(&s);
+        }
+        Some(res)
+    }
+
+    /// Add a duration to the time, the result is a datetime.
+    ///
+    /// Return None if the time is zero or the result is out of range.
+    pub fn checked_add_dur(&self, d: &mysql::Duration) -> Option<Time> {
+        if self.is_zero() {
+            return None;
+        }
+        let nanos = d.to_nanos();
+        let secs = nanos / 1_000_000_000;
+        let micros = nanos % 1_000_000_000 / 1000;
+        add_micros(self.time, 0, secs, micros).map(|t| {
+            Time {
+                time: t,
+                tp: types::DATETIME,
+                fsp: cmp::max(self.fsp, d.get_fsp()),
+            }
+        })
+    }
+
+    /// Subtract a duration from the time, the result is a datetime.
+    ///
+    /// Return None if the time is zero or the result is out of range.
+    pub fn checked_sub_dur(&self, d: &mysql::Duration) -> Option<Time> {
+        match mysql::Duration::from_nanos(-d.to_nanos(), d.get_fsp()) {
+            Ok(neg) => self.checked_add_dur(&neg),
+            Err(_) => None,
+        }
+    }
+
+    /// Add an interval to the time like MySQL's `DATE_ADD`.
+    ///
+    /// Months are added first, and the day is clamped to the last day of the
+    /// month. A date stays a date if the interval has no time part. Return None
+    /// if the time is zero or the result is out of range.
+    pub fn add_interval(&self, interval: &Interval) -> Option<Time> {
+        if self.is_zero() {
+            return None;
+        }
+        let date = self.time.date();
+        let months = match (date.year() as i64 * 12 + date.month0() as i64)
+            .checked_add(interval.months) {
+            Some(months) if months >= 0 && months < 10000 * 12 => months,
+            _ => return None,
+        };
+        let (year, month) = ((months / 12) as i32, (months % 12) as u32 + 1);
+        let day = cmp::min(date.day(), days_in_month(year, month));
+        let time = NaiveDate::from_ymd(year, month, day).and_time(self.time.time());
+        let time = match add_micros(time, interval.days, interval.secs, interval.micros) {
+            Some(time) => time,
+            None => return None,
+        };
+        let tp = if self.tp == types::DATE && !interval.has_time {
+            types::DATE
+        } else {
+            types::DATETIME
+        };
+        Some(Time {
+            time: time,
+            tp: tp,
+            fsp: cmp::max(self.fsp, interval.fsp),
+        })
+    }
+
+    /// Convert the time to type `tp` with `fsp` fraction digits, the time part of
+    /// a DATE is cleared and the extra fraction digits are rounded.
+    pub fn convert(&self, tp: u8, fsp: u8) -> Result<Time> {
+        try!(check_fsp(fsp));
+        if self.is_zero() {
+            return Time::new(zero_time(), tp, fsp);
+        }
+        if tp == types::DATE {
+            return Time::new(self.time.date().and_hms(0, 0, 0), tp, fsp);
+        }
+        let unit = TEN_POW[9 - fsp as usize] as i64;
+        let nanos = self.time.nanosecond() as i64;
+        let rounded = (nanos + unit / 2) / unit * unit;
+        match self.time.checked_add(Duration::nanoseconds(rounded - nanos)) {
+            Some(t) if t.year() <= 9999 => Time::new(t, tp, fsp),
+            _ => Err(box_err!("{} is out of range", self)),
+        }
+    }
+
+    /// Get the time part as a duration with `fsp` fraction digits.
+    pub fn to_dur(&self, fsp: u8) -> Result<mysql::Duration> {
+        let t = try!(self.convert(types::DATETIME, fsp));
+        let secs = t.hour() as i64 * 3600 + t.minute() as i64 * 60 + t.second() as i64;
+        let nanos = if t.is_zero() {
+            0
+        } else {
+            t.time.nanosecond() as i64
+        };
+        mysql::Duration::from_nanos(secs * 1_000_000_000 + nanos, fsp)
+    }
+}
+
+/// The parts of an interval unit, from the longest to the shortest.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum IntervalPart {
+    Year,
+    Quarter,
+    Month,
+    Week,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Microsecond,
+}
+
+fn interval_parts(unit: &str) -> Option<Vec<IntervalPart>> {
+    use self::IntervalPart::*;
+    let parts = match unit.to_uppercase().as_str() {
+        "MICROSECOND" => vec![Microsecond],
+        "SECOND" => vec![Second],
+        "MINUTE" => vec![Minute],
+        "HOUR" => vec![Hour],
+        "DAY" => vec![Day],
+        "WEEK" => vec![Week],
+        "MONTH" => vec![Month],
+        "QUARTER" => vec![Quarter],
+        "YEAR" => vec![Year],
+        "SECOND_MICROSECOND" => vec![Second, Microsecond],
+        "MINUTE_MICROSECOND" => vec![Minute, Second, Microsecond],
+        "MINUTE_SECOND" => vec![Minute, Second],
+        "HOUR_MICROSECOND" => vec![Hour, Minute, Second, Microsecond],
+        "HOUR_SECOND" => vec![Hour, Minute, Second],
+        "HOUR_MINUTE" => vec![Hour, Minute],
+        "DAY_MICROSECOND" => vec![Day, Hour, Minute, Second, Microsecond],
+        "DAY_SECOND" => vec![Day, Hour, Minute, Second],
+        "DAY_MINUTE" => vec![Day, Hour, Minute],
+        "DAY_HOUR" => vec![Day, Hour],
+        "YEAR_MONTH" => vec![Year, Month],
+        _ => return None,
+    };
+    Some(parts)
+}
+
+fn leading_digits(s: &str) -> &str {
+    let end = s.find(|c: char| !c.is_digit(10)).unwrap_or_else(|| s.len());
+    &s[..end]
+}
+
+fn checked_mul_add(acc: i64, n: i64, factor: i64) -> Result<i64> {
+    n.checked_mul(factor)
+        .and_then(|n| acc.checked_add(n))
+        .ok_or_else(|| box_err!("interval overflows"))
+}
+
+/// `Interval` is the `INTERVAL expr unit` expression used by `DATE_ADD`
+/// and `DATE_SUB`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interval {
+    months: i64,
+    days: i64,
+    secs: i64,
+    micros: i64,
+    // Whether the unit has a part shorter than a day.
+    has_time: bool,
+    fsp: u8,
+}
+
+impl Interval {
+    /// Parse the value of an interval in `unit`.
+    ///
+    /// The value of a compound unit like `DAY_SECOND` consists of numbers
+    /// separated by any non-digit characters, and the missing leading parts are
+    /// zeros. A fraction of `SECOND` and the microsecond part of a compound unit
+    /// are scaled to microseconds, so `'1.5' SECOND` is 1 second 500000
+    /// microseconds. A simple unit other than `SECOND` only takes the first number.
+    pub fn parse(value: &str, unit: &str) -> Result<Interval> {
+        use self::IntervalPart::*;
+        let unit_parts = match interval_parts(unit) {
+            Some(parts) => parts,
+            None => return Err(box_err!("unknown interval unit {}", unit)),
+        };
+        let value = value.trim();
+        let (neg, value) = if value.starts_with('-') {
+            (true, &value[1..])
+        } else {
+            (false, value)
+        };
+        let (parts, nums): (Vec<IntervalPart>, Vec<&str>) = if unit_parts.len() > 1 {
+            let nums: Vec<&str> = value.split(|c: char| !c.is_digit(10))
+                .filter(|s| !s.is_empty())
+                .collect();
+            if nums.len() > unit_parts.len() {
+                return Err(box_err!("invalid interval '{}' {}", value, unit));
+            }
+            (unit_parts[unit_parts.len() - nums.len()..].to_vec(), nums)
+        } else if unit_parts[0] == Second {
+            let mut it = value.splitn(2, '.');
+            let secs = leading_digits(it.next().unwrap());
+            let frac = it.next().map_or("", leading_digits);
+            (vec![Second, Microsecond], vec![secs, frac])
+        } else {
+            (unit_parts.clone(), vec![leading_digits(value)])
+        };
+        let scale_micros = parts.len() > 1;
+
+        let mut interval = Interval {
+            months: 0,
+            days: 0,
+            secs: 0,
+            micros: 0,
+            has_time: unit_parts.iter().any(|p| *p >= Hour),
+            fsp: if unit_parts.contains(&Microsecond) {
+                mysql::MAX_FSP
+            } else {
+                0
+            },
+        };
+        for (part, num) in parts.into_iter().zip(nums) {
+            if num.is_empty() {
+                continue;
+            }
+            let n: i64 = if part == Microsecond && scale_micros {
+                // Only the first 6 digits are kept, and the missing digits are zeros.
+                let digits = &num[..cmp::min(num.len(), 6)];
+                let n: i64 = box_try!(digits.parse());
+                n * 10i64.pow(6 - digits.len() as u32)
+            } else {
+                box_try!(num.parse())
+            };
+            match part {
+                Year => interval.months = try!(checked_mul_add(interval.months, n, 12)),
+                Quarter => interval.months = try!(checked_mul_add(interval.months, n, 3)),
+                Month => interval.months = try!(checked_mul_add(interval.months, n, 1)),
+                Week => interval.days = try!(checked_mul_add(interval.days, n, 7)),
+                Day => interval.days = try!(checked_mul_add(interval.days, n, 1)),
+                Hour => interval.secs = try!(checked_mul_add(interval.secs, n, 3600)),
+                Minute => interval.secs = try!(checked_mul_add(interval.secs, n, 60)),
+                Second => interval.secs = try!(checked_mul_add(interval.secs, n, 1)),
+                Microsecond => {
+                    interval.micros = n;
+                    if n != 0 {
+                        interval.fsp = mysql::MAX_FSP;
+                    }
+                }
+            }
+        }
+        if neg {
+            interval = -interval;
+        }
+        Ok(interval)
+    }
+}
+
+impl Neg for Interval {
+    type Output = Interval;
+
+    fn neg(self) -> Interval {
+        Interval {
+            months: -self.months,
+            days: -self.days,
+            secs: -self.secs,
+            micros: -self.micros,
+            ..self
+        }
+    }
 }
 
 impl PartialOrd for Time {
@@ -337,7 +835,7 @@ mod test {
 
     use std::cmp::Ordering;
 
-    use util::codec::mysql::{MAX_FSP, types};
+    use util::codec::mysql::{MAX_FSP, Duration, types};
 
     #[test]
     fn test_parse_datetime() {
@@ -473,4 +971,144 @@ mod test {
             assert_eq!(res, exp);
         }
     }
+
+    #[test]
+    fn test_date_parts() {
+        let t = Time::parse_datetime("2010-01-02 03:04:05", 0).unwrap();
+        assert_eq!((t.year(), t.month(), t.day()), (2010, 1, 2));
+        assert_eq!((t.hour(), t.minute(), t.second()), (3, 4, 5));
+        assert_eq!(t.day_of_week(), Some(7));
+        let t = Time::parse_datetime("2010-01-03 00:00:00", 0).unwrap();
+        assert_eq!(t.day_of_week(), Some(1));
+
+        let t = Time::parse_datetime("0000-00-00 00:00:00", 0).unwrap();
+        assert_eq!((t.year(), t.month(), t.day()), (0, 0, 0));
+        assert_eq!((t.hour(), t.minute(), t.second()), (0, 0, 0));
+        assert_eq!(t.day_of_week(), None);
+    }
+
+    #[test]
+    fn test_date_format() {
+        let cases = vec![
+            ("2009-10-04 22:23:00", "%W %M %Y", "Sunday October 2009"),
+            ("2007-10-04 22:23:00", "%H:%i:%s", "22:23:00"),
+            ("1900-10-04 22:23:00", "%D %y %a %d %m %b %j", "4th 00 Thu 04 10 Oct 277"),
+            ("1997-10-04 22:23:00",
+             "%H %k %I %r %T %S %w",
+             "22 22 10 10:23:00 PM 22:23:00 00 6"),
+            ("1999-01-01 00:00:00", "%X %V", "1998 52"),
+            ("2010-01-01 00:00:00.123456",
+             "%f %U %u %v %x %p %h %l %c %e",
+             "123456 00 00 53 2009 AM 12 12 1 1"),
+            ("2010-01-02 00:00:00", "%D %D", "2nd 2nd"),
+            ("2010-01-23 00:00:00", "%D", "23rd"),
+            ("2010-01-11 00:00:00", "%D", "11th"),
+            ("2010-01-01 00:00:00", "%% %Q %Y%", "% Q 2010%"),
+            ("2010-01-01 00:00:00", "年%Y", "年2010"),
+        ];
+        for (t, layout, exp) in cases {
+            let t = Time::parse_datetime(t, MAX_FSP).unwrap();
+            assert_eq!(t.date_format(layout), Some(exp.to_owned()));
+        }
+
+        let t = Time::parse_datetime("0000-00-00 00:00:00", 0).unwrap();
+        assert_eq!(t.date_format("%Y"), None);
+    }
+
+    #[test]
+    fn test_add_interval() {
+        let cases = vec![
+            ("2010-01-31 00:00:00", "1", "MONTH", Some("2010-02-28 00:00:00")),
+            ("2012-03-31 00:00:00", "-1", "month", Some("2012-02-29 00:00:00")),
+            ("2010-12-31 23:59:59", "1", "SECOND", Some("2011-01-01 00:00:00")),
+            ("2010-12-31 23:59:59", "1:1", "MINUTE_SECOND", Some("2011-01-01 00:01:00")),
+            ("2010-01-01 00:00:00", "-1 10", "DAY_HOUR", Some("2009-12-30 14:00:00")),
+            ("2010-01-01 00:00:00", "10", "DAY_HOUR", Some("2010-01-01 10:00:00")),
+            ("2010-01-01 00:00:00", "1.5", "SECOND", Some("2010-01-01 00:00:01.5")),
+            ("2010-01-01 00:00:00",
+             "1.000002",
+             "SECOND_MICROSECOND",
+             Some("2010-01-01 00:00:01.000002")),
+            ("2010-01-01 00:00:00", "2", "MICROSECOND", Some("2010-01-01 00:00:00.000002")),
+            ("2010-01-01 00:00:00", "1-2", "YEAR_MONTH", Some("2011-03-01 00:00:00")),
+            ("2010-01-01 00:00:00", "2", "QUARTER", Some("2010-07-01 00:00:00")),
+            ("2010-01-01 00:00:00", "2", "WEEK", Some("2010-01-15 00:00:00")),
+            ("2010-01-01 00:00:00", "-3", "YEAR", Some("2007-01-01 00:00:00")),
+            ("2010-01-01 00:00:00", "2.9", "DAY", Some("2010-01-03 00:00:00")),
+            ("9999-12-31 00:00:00", "1", "DAY", None),
+            ("0001-01-01 00:00:00", "-2", "YEAR", None),
+            ("2010-01-01 00:00:00", "100000000", "DAY", None),
+            ("0000-00-00 00:00:00", "1", "DAY", None),
+        ];
+        for (t, value, unit, exp) in cases {
+            let t = Time::parse_datetime(t, 0).unwrap();
+            let interval = Interval::parse(value, unit).unwrap();
+            let exp = exp.map(|s| Time::parse_datetime(s, MAX_FSP).unwrap());
+            assert_eq!(t.add_interval(&interval), exp);
+        }
+
+        let mut t = Time::parse_datetime("2010-01-01", 0).unwrap();
+        t.tp = types::DATE;
+        let interval = Interval::parse("1", "DAY").unwrap();
+        assert_eq!(t.add_interval(&interval).unwrap().tp, types::DATE);
+        let interval = Interval::parse("1", "HOUR").unwrap();
+        assert_eq!(t.add_interval(&interval).unwrap().tp, types::DATETIME);
+        let interval = -Interval::parse("1", "DAY").unwrap();
+        assert_eq!(format!("{}", t.add_interval(&interval).unwrap()), "2009-12-31");
+
+        assert!(Interval::parse("1:2:3", "DAY_HOUR").is_err());
+        assert!(Interval::parse("1", "FORTNIGHT").is_err());
+        assert!(Interval::parse("99999999999999999999", "DAY").is_err());
+    }
+
+    #[test]
+    fn test_add_dur() {
+        let t = Time::parse_datetime("2010-01-01 00:00:00", 0).unwrap();
+        let d = Duration::parse(b"-01:00:00", 0).unwrap();
+        let exp = Time::parse_datetime("2009-12-31 23:00:00", 0).unwrap();
+        assert_eq!(t.checked_add_dur(&d), Some(exp));
+
+        let d = Duration::parse(b"11:30:00.5", 1).unwrap();
+        let res = t.checked_sub_dur(&d).unwrap();
+        assert_eq!(format!("{}", res), "2009-12-31 12:29:59.5");
+        let res = t.checked_add_dur(&d).unwrap();
+        assert_eq!(format!("{}", res), "2010-01-01 11:30:00.5");
+
+        let t = Time::parse_datetime("9999-12-31 23:00:00", 0).unwrap();
+        let d = Duration::parse(b"01:00:00", 0).unwrap();
+        assert_eq!(t.checked_add_dur(&d), None);
+        let t = Time::parse_datetime("0000-00-00 00:00:00", 0).unwrap();
+        assert_eq!(t.checked_add_dur(&d), None);
+    }
+
+    #[test]
+    fn test_convert() {
+        let cases = vec![
+            ("2010-01-02 12:30:59.5", types::DATE, 0, "2010-01-02"),
+            ("2010-01-02 12:30:59.5", types::DATETIME, 0, "2010-01-02 12:31:00"),
+            ("2010-01-02 12:30:59.1234", types::DATETIME, 2, "2010-01-02 12:30:59.12"),
+            ("2010-01-02 12:30:59.5", types::DATETIME, 3, "2010-01-02 12:30:59.500"),
+            ("2010-12-31 23:59:59.9", types::TIMESTAMP, 0, "2011-01-01 00:00:00"),
+            ("0000-00-00 00:00:00", types::DATE, 0, "0000-00-00"),
+        ];
+        for (s, tp, fsp, exp) in cases {
+            let t = Time::parse_datetime(s, MAX_FSP).unwrap();
+            assert_eq!(format!("{}", t.convert(tp, fsp).unwrap()), exp);
+        }
+
+        let t = Time::parse_datetime("9999-12-31 23:59:59.9", 1).unwrap();
+        assert!(t.convert(types::DATETIME, 0).is_err());
+        assert!(t.convert(types::DATETIME, 7).is_err());
+
+        let cases = vec![
+            ("2010-01-02 12:30:59.5", 0, "12:31:00"),
+            ("2010-01-02 12:30:59.5", 1, "12:30:59.5"),
+            ("2010-01-02", 0, "00:00:00"),
+            ("0000-00-00 00:00:00", 0, "00:00:00"),
+        ];
+        for (s, fsp, exp) in cases {
+            let t = Time::parse_datetime(s, MAX_FSP).unwrap();
+            assert_eq!(format!("{}", t.to_dur(fsp).unwrap()), exp);
+        }
+    }
 }
@@ -11,7 +11,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use rocksdb::{DB, Options};
+use rocksdb::{DB, Options, CompactionFilterFactory};
 use rocksdb::rocksdb_ffi::DBCFHandle;
 
 pub fn get_cf_handle<'a>(db: &'a DB, cf: &str) -> Result<&'a DBCFHandle, String> {
@@ -44,14 +44,27 @@ pub fn new_engine(path: &str, cfs: &[&str]) -> Result<DB, String> {
     for _ in 0..cfs.len() {
         cfs_opts.push(Options::new());
     }
-    new_engine_opt(opts, path, cfs, cfs_opts)
+    new_engine_opt(opts, path, cfs, cfs_opts, vec![])
 }
 
+/// Open the engine, `filters` are installed as the compaction filter factories
+/// of the corresponding column families.
 pub fn new_engine_opt(mut opts: Options,
                       path: &str,
                       cfs: &[&str],
-                      cfs_opts: Vec<Options>)
+                      mut cfs_opts: Vec<Options>,
+                      filters: Vec<(&str, Box<CompactionFilterFactory>)>)
                       -> Result<DB, String> {
+    for (cf, factory) in filters {
+        let pos = match cfs.iter().position(|&c| c == cf) {
+            Some(pos) => pos,
+            None => return Err(format!("cf {} not found.", cf)),
+        };
+        // Snapshots are not respected, filters must only drop data invisible to any reader.
+        let name = format!("{}_compaction_filter_factory", cf);
+        try!(cfs_opts[pos].set_compaction_filter_factory(name, factory));
+    }
+
     // Currently we support 1) Create new db. 2) Open a db with CFs we want. 3) Open db with no
     // CF.
     // TODO: Support open db with incomplete CFs.
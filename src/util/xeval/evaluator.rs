@@ -15,15 +15,19 @@
 use util::codec::number::NumberDecoder;
 use util::codec::datum::{Datum, DatumDecoder};
 use util::codec::mysql::DecimalDecoder;
-use util::codec::mysql::{MAX_FSP, Duration};
+use util::codec::mysql::{self, types, MAX_FSP, Duration, Time, Interval};
+use util::codec::convert::{self, ConvertContext};
 use util::TryInsertWith;
 use super::{Result, Error};
 use util::codec;
 
 use std::collections::HashMap;
 use std::cmp::Ordering;
+use std::{i64, usize};
 use std::ascii::AsciiExt;
 use tipb::expression::{Expr, ExprType};
+use tipb::schema::ColumnInfo;
+use protobuf;
 
 /// `Evaluator` evaluates `tipb::Expr`.
 #[derive(Default)]
@@ -32,9 +36,23 @@ pub struct Evaluator {
     pub row: HashMap<i64, Datum>,
     // expr pointer -> value list
     cached_value_list: HashMap<isize, Vec<Datum>>,
+    // expr pointer -> the type to cast to
+    cached_cast_type: HashMap<isize, ColumnInfo>,
+    // decides how a lossy cast is handled and keeps its warnings.
+    pub convert_ctx: ConvertContext,
+    // column_id -> a datum of the column type, to infer the result types.
+    col_samples: HashMap<i64, Datum>,
 }
 
 impl Evaluator {
+    /// Set the columns of the request, their types are used to infer the
+    /// result types of the column references.
+    pub fn set_column_infos(&mut self, cols: &[ColumnInfo]) {
+        self.col_samples = cols.iter()
+            .filter_map(|c| column_sample(c).map(|d| (c.get_column_id(), d)))
+            .collect();
+    }
+
     pub fn batch_eval(&mut self, exprs: &[Expr]) -> Result<Vec<Datum>> {
         let mut res = Vec::with_capacity(exprs.len());
         for expr in exprs {
@@ -69,7 +87,41 @@ impl Evaluator {
             ExprType::MysqlDecimal => self.eval_decimal(expr),
             ExprType::In => self.eval_in(expr),
             ExprType::Plus => self.eval_arith(expr, Datum::checked_add),
-            _ => Ok(Datum::Null),
+            ExprType::Minus => self.eval_arith(expr, Datum::checked_sub),
+            ExprType::Mul => self.eval_arith(expr, Datum::checked_mul),
+            ExprType::Div => self.eval_arith(expr, Datum::checked_div),
+            ExprType::IntDiv => self.eval_arith(expr, Datum::checked_int_div),
+            ExprType::Mod => self.eval_arith(expr, Datum::checked_rem),
+            ExprType::Concat => self.eval_func(expr, 1, usize::MAX, concat),
+            ExprType::Length => self.eval_func(expr, 1, 1, length),
+            ExprType::CharLength => self.eval_func(expr, 1, 1, char_length),
+            ExprType::Substring => self.eval_func(expr, 2, 3, substring),
+            ExprType::Lower => self.eval_func(expr, 1, 1, lower),
+            ExprType::Upper => self.eval_func(expr, 1, 1, upper),
+            ExprType::Trim => self.eval_func(expr, 1, 3, trim),
+            ExprType::Left => self.eval_func(expr, 2, 2, left),
+            ExprType::Right => self.eval_func(expr, 2, 2, right),
+            ExprType::Replace => self.eval_func(expr, 3, 3, replace),
+            ExprType::Locate => self.eval_func(expr, 2, 3, locate),
+            ExprType::Year => self.eval_func(expr, 1, 1, year),
+            ExprType::Month => self.eval_func(expr, 1, 1, month),
+            ExprType::Day => self.eval_func(expr, 1, 1, day),
+            ExprType::Hour => self.eval_func(expr, 1, 1, hour),
+            ExprType::Minute => self.eval_func(expr, 1, 1, minute),
+            ExprType::Second => self.eval_func(expr, 1, 1, second),
+            ExprType::DayOfWeek => self.eval_func(expr, 1, 1, day_of_week),
+            ExprType::DateFormat => self.eval_func(expr, 2, 2, date_format),
+            ExprType::DateAdd => self.eval_func(expr, 3, 3, date_add),
+            ExprType::DateSub => self.eval_func(expr, 3, 3, date_sub),
+            ExprType::If => self.eval_if(expr),
+            ExprType::IfNull => self.eval_if_null(expr),
+            ExprType::Coalesce => self.eval_coalesce(expr),
+            ExprType::Case => self.eval_case(expr),
+            ExprType::IsNull => self.eval_is_null(expr),
+            ExprType::IsTruth => self.eval_is_truth(expr),
+            ExprType::Cast => self.eval_cast(expr),
+            ExprType::Null => Ok(Datum::Null),
+            tp => Err(Error::Expr(format!("unsupported expression type {:?}", tp))),
         }
     }
 
@@ -264,6 +316,242 @@ impl Evaluator {
         let (left, right) = try!(self.eval_two_children(expr));
         eval_arith(left, right, f)
     }
+
+    /// Evaluate a function whose arguments are the children, the result is NULL
+    /// if any of the arguments is NULL.
+    fn eval_func<F>(&mut self, expr: &Expr, min_args: usize, max_args: usize, f: F) -> Result<Datum>
+        where F: FnOnce(Vec<Datum>) -> Result<Datum>
+    {
+        try!(check_children_cnt(expr, min_args, max_args));
+        let args = try!(self.batch_eval(expr.get_children()));
+        if args.iter().any(|d| *d == Datum::Null) {
+            return Ok(Datum::Null);
+        }
+        f(args)
+    }
+
+    // The control flow functions only evaluate the branch taken, so an error in
+    // another branch doesn't fail them.
+
+    /// `IF(cond, res1, res2)`.
+    fn eval_if(&mut self, expr: &Expr) -> Result<Datum> {
+        try!(check_children_cnt(expr, 3, 3));
+        let children = expr.get_children();
+        let cond = try!(self.eval(&children[0]));
+        let res = if try!(cond.into_bool()) == Some(true) {
+            try!(self.eval(&children[1]))
+        } else {
+            try!(self.eval(&children[2]))
+        };
+        self.coerce_branches(res, &children[1..])
+    }
+
+    /// `IFNULL(res1, res2)`.
+    fn eval_if_null(&mut self, expr: &Expr) -> Result<Datum> {
+        try!(check_children_cnt(expr, 2, 2));
+        let children = expr.get_children();
+        let mut res = try!(self.eval(&children[0]));
+        if res == Datum::Null {
+            res = try!(self.eval(&children[1]));
+        }
+        self.coerce_branches(res, children)
+    }
+
+    /// `COALESCE(res1, res2, ...)` returns the first non-NULL argument.
+    fn eval_coalesce(&mut self, expr: &Expr) -> Result<Datum> {
+        try!(check_children_cnt(expr, 1, usize::MAX));
+        let children = expr.get_children();
+        let mut res = Datum::Null;
+        for child in children {
+            res = try!(self.eval(child));
+            if res != Datum::Null {
+                break;
+            }
+        }
+        self.coerce_branches(res, children)
+    }
+
+    /// `CASE WHEN cond1 THEN res1 [WHEN cond2 THEN res2 ...] [ELSE res] END`,
+    /// the children are the conditions and the results in pairs, followed by
+    /// the optional else result.
+    fn eval_case(&mut self, expr: &Expr) -> Result<Datum> {
+        try!(check_children_cnt(expr, 1, usize::MAX));
+        let children = expr.get_children();
+        let mut res = Datum::Null;
+        for pair in children.chunks(2) {
+            if pair.len() == 1 {
+                res = try!(self.eval(&pair[0]));
+                break;
+            }
+            let cond = try!(self.eval(&pair[0]));
+            if try!(cond.into_bool()) == Some(true) {
+                res = try!(self.eval(&pair[1]));
+                break;
+            }
+        }
+        let last = children.len() - 1;
+        let branches = children.iter()
+            .enumerate()
+            .filter(|&(i, _)| i % 2 == 1 || i == last)
+            .map(|(_, e)| e);
+        self.coerce_branches(res, branches)
+    }
+
+    fn eval_is_null(&mut self, expr: &Expr) -> Result<Datum> {
+        try!(check_children_cnt(expr, 1, 1));
+        let d = try!(self.eval(&expr.get_children()[0]));
+        Ok((d == Datum::Null).into())
+    }
+
+    /// `IS TRUE` is false for NULL.
+    fn eval_is_truth(&mut self, expr: &Expr) -> Result<Datum> {
+        try!(check_children_cnt(expr, 1, 1));
+        let d = try!(self.eval(&expr.get_children()[0]));
+        Ok((try!(d.into_bool()) == Some(true)).into())
+    }
+
+    /// `CAST(expr AS type)`, the target type is encoded in the value as a
+    /// `ColumnInfo`, whose length and decimal are -1 if unspecified.
+    fn eval_cast(&mut self, expr: &Expr) -> Result<Datum> {
+        try!(check_children_cnt(expr, 1, 1));
+        let (tp, flag, flen, decimal) = {
+            let col = try!(self.decode_cast_type(expr));
+            (col.get_tp() as u8, col.get_flag(), col.get_columnLen(), col.get_decimal())
+        };
+        let d = try!(self.eval(&expr.get_children()[0]));
+        let ctx = &mut self.convert_ctx;
+        let res = match tp {
+            types::TINY | types::SHORT | types::INT24 | types::LONG | types::LONG_LONG |
+            types::YEAR => {
+                let unsigned = mysql::has_unsigned_flag(flag as u64);
+                convert::cast_as_int(ctx, d, unsigned)
+            }
+            types::FLOAT | types::DOUBLE => convert::cast_as_real(ctx, d),
+            types::NEW_DECIMAL => convert::cast_as_decimal(ctx, d, flen, decimal),
+            types::VARCHAR | types::VAR_STRING | types::STRING | types::TINY_BLOB |
+            types::MEDIUM_BLOB | types::BLOB | types::LONG_BLOB => {
+                convert::cast_as_string(ctx, d, flen)
+            }
+            tp @ types::DATE | tp @ types::DATETIME | tp @ types::TIMESTAMP => {
+                convert::cast_as_time(ctx, d, tp, try!(cast_fsp(decimal)))
+            }
+            types::DURATION => convert::cast_as_duration(ctx, d, try!(cast_fsp(decimal))),
+            tp => return Err(Error::Expr(format!("unsupported cast to type {}", tp))),
+        };
+        res.map_err(From::from)
+    }
+
+    fn decode_cast_type(&mut self, cast_expr: &Expr) -> Result<&ColumnInfo> {
+        let p = cast_expr as *const Expr as isize;
+        let col = try!(self.cached_cast_type.entry(p).or_try_insert_with(|| {
+            protobuf::parse_from_bytes(cast_expr.get_val())
+                .map_err(|e| Error::Expr(format!("invalid cast type: {:?}", e)))
+        }));
+        Ok(col)
+    }
+
+    /// Infer the result type of `expr` without evaluating it, and return a datum
+    /// of the type. Return None if it can't be inferred.
+    fn infer_type(&self, expr: &Expr) -> Option<Datum> {
+        let sample = match expr.get_tp() {
+            ExprType::Null => Datum::Null,
+            ExprType::Int64 => Datum::I64(0),
+            ExprType::Uint64 => Datum::U64(0),
+            ExprType::Float32 | ExprType::Float64 => Datum::F64(0f64),
+            ExprType::MysqlDecimal => Datum::Dec(0u64.into()),
+            ExprType::String | ExprType::Bytes => Datum::Bytes(vec![]),
+            ExprType::ColumnRef => {
+                let id = match expr.get_val().decode_i64() {
+                    Ok(id) => id,
+                    Err(_) => return None,
+                };
+                return self.col_samples.get(&id).cloned();
+            }
+            ExprType::LT | ExprType::LE | ExprType::EQ | ExprType::NE | ExprType::GE |
+            ExprType::GT | ExprType::NullEQ | ExprType::And | ExprType::Or | ExprType::Not |
+            ExprType::Like | ExprType::In | ExprType::IsNull | ExprType::IsTruth |
+            ExprType::IntDiv => Datum::I64(0),
+            ExprType::Plus | ExprType::Minus | ExprType::Mul | ExprType::Div | ExprType::Mod => {
+                let children = expr.get_children();
+                if children.len() != 2 {
+                    return None;
+                }
+                let (l, r) = match (self.infer_type(&children[0]), self.infer_type(&children[1])) {
+                    (Some(l), Some(r)) => Datum::coerce(l, r),
+                    _ => return None,
+                };
+                match (l, r) {
+                    (Datum::Null, _) | (_, Datum::Null) => Datum::Null,
+                    (d @ Datum::Dec(_), _) | (d @ Datum::F64(_), _) => d,
+                    (Datum::Bytes(_), _) | (_, Datum::Bytes(_)) => Datum::F64(0f64),
+                    _ if expr.get_tp() == ExprType::Div => Datum::Dec(0u64.into()),
+                    (d, _) => d,
+                }
+            }
+            _ => return None,
+        };
+        Some(sample)
+    }
+
+    /// Coerce the result of a control flow function to the type decided by all
+    /// the branches: a string if any branch is a string, otherwise the numeric
+    /// type that `Datum::coerce` converts the branches to. Branches whose types
+    /// can't be inferred are ignored.
+    fn coerce_branches<'a, I>(&self, res: Datum, branches: I) -> Result<Datum>
+        where I: IntoIterator<Item = &'a Expr>
+    {
+        let mut res = res;
+        if res == Datum::Null {
+            return Ok(res);
+        }
+        for branch in branches {
+            res = match (res, self.infer_type(branch)) {
+                (res @ Datum::Bytes(_), _) => res,
+                (res, Some(Datum::Bytes(_))) => Datum::Bytes(try!(into_bytes(res))),
+                (res, Some(sample)) => Datum::coerce(res, sample).0,
+                (res, None) => res,
+            };
+        }
+        Ok(res)
+    }
+}
+
+fn cast_fsp(decimal: i32) -> Result<u8> {
+    if decimal < 0 {
+        return Ok(mysql::DEFAULT_FSP);
+    }
+    if decimal > MAX_FSP as i32 {
+        return Err(Error::Expr(format!("invalid fsp {}", decimal)));
+    }
+    Ok(decimal as u8)
+}
+
+fn check_children_cnt(expr: &Expr, min: usize, max: usize) -> Result<()> {
+    let l = expr.get_children().len();
+    if l < min || l > max {
+        return Err(Error::Expr(format!("need {} to {} operands but got {}", min, max, l)));
+    }
+    Ok(())
+}
+
+/// Get a datum of the column type, None if it's not used to infer types.
+fn column_sample(col: &ColumnInfo) -> Option<Datum> {
+    let sample = match col.get_tp() as u8 {
+        types::TINY | types::SHORT | types::INT24 | types::LONG | types::LONG_LONG |
+        types::YEAR => {
+            if mysql::has_unsigned_flag(col.get_flag() as u64) {
+                Datum::U64(0)
+            } else {
+                Datum::I64(0)
+            }
+        }
+        types::FLOAT | types::DOUBLE => Datum::F64(0f64),
+        types::NEW_DECIMAL => Datum::Dec(0u64.into()),
+        types::VARCHAR | types::VAR_STRING | types::STRING | types::TINY_BLOB |
+        types::MEDIUM_BLOB | types::BLOB | types::LONG_BLOB => Datum::Bytes(vec![]),
+        _ => return None,
+    };
+    Some(sample)
 }
 
 #[inline]
@@ -281,6 +569,269 @@ pub fn eval_arith<F>(left: Datum, right: Datum, f: F) -> Result<Datum>
     f(left, right).map_err(From::from)
 }
 
+/// The directions of `TRIM`, keep compatible with TiDB's `TrimDirectionType`.
+const TRIM_BOTH_DEFAULT: i64 = 0;
+const TRIM_BOTH: i64 = 1;
+const TRIM_LEADING: i64 = 2;
+const TRIM_TRAILING: i64 = 3;
+
+fn into_bytes(d: Datum) -> Result<Vec<u8>> {
+    match d {
+        Datum::Bytes(bs) => Ok(bs),
+        d => d.into_string().map(String::into_bytes).map_err(From::from),
+    }
+}
+
+fn concat(args: Vec<Datum>) -> Result<Datum> {
+    let mut res = vec![];
+    for arg in args {
+        res.extend_from_slice(&try!(into_bytes(arg)));
+    }
+    Ok(Datum::Bytes(res))
+}
+
+/// `LENGTH` returns the length of a string in bytes.
+fn length(args: Vec<Datum>) -> Result<Datum> {
+    let bs = try!(into_bytes(args.into_iter().next().unwrap()));
+    Ok(Datum::I64(bs.len() as i64))
+}
+
+/// `CHAR_LENGTH` returns the length of a string in characters.
+fn char_length(args: Vec<Datum>) -> Result<Datum> {
+    let s = try!(args.into_iter().next().unwrap().into_string());
+    Ok(Datum::I64(s.chars().count() as i64))
+}
+
+/// `SUBSTRING(str, pos[, len])`, `pos` starts from 1, and counts from the end of
+/// the string if it's negative.
+fn substring(args: Vec<Datum>) -> Result<Datum> {
+    let mut args = args.into_iter();
+    let s = try!(args.next().unwrap().into_string());
+    let pos = try!(args.next().unwrap().into_i64());
+    let len = match args.next() {
+        Some(d) => try!(d.into_i64()),
+        None => i64::MAX,
+    };
+    let start = if pos > 0 {
+        pos - 1
+    } else {
+        s.chars().count() as i64 + pos
+    };
+    if pos == 0 || start < 0 || len <= 0 {
+        return Ok(Datum::Bytes(vec![]));
+    }
+    let res: String = s.chars().skip(start as usize).take(len as usize).collect();
+    Ok(Datum::Bytes(res.into_bytes()))
+}
+
+fn lower(args: Vec<Datum>) -> Result<Datum> {
+    let s = try!(args.into_iter().next().unwrap().into_string());
+    Ok(Datum::Bytes(s.to_lowercase().into_bytes()))
+}
+
+fn upper(args: Vec<Datum>) -> Result<Datum> {
+    let s = try!(args.into_iter().next().unwrap().into_string());
+    Ok(Datum::Bytes(s.to_uppercase().into_bytes()))
+}
+
+/// `TRIM(str[, remstr[, direction]])` removes the `remstr` prefixes and suffixes,
+/// `remstr` is a space by default.
+fn trim(args: Vec<Datum>) -> Result<Datum> {
+    let mut args = args.into_iter();
+    let s = try!(args.next().unwrap().into_string());
+    let remstr = match args.next() {
+        Some(d) => try!(d.into_string()),
+        None => " ".to_owned(),
+    };
+    let direction = match args.next() {
+        Some(d) => try!(d.into_i64()),
+        None => TRIM_BOTH_DEFAULT,
+    };
+    match direction {
+        TRIM_BOTH_DEFAULT | TRIM_BOTH | TRIM_LEADING | TRIM_TRAILING => {}
+        d => return Err(Error::Expr(format!("invalid trim direction {}", d))),
+    }
+
+    let mut res = s.as_str();
+    if !remstr.is_empty() {
+        if direction != TRIM_TRAILING {
+            while res.starts_with(remstr.as_str()) {
+                res = &res[remstr.len()..];
+            }
+        }
+        if direction != TRIM_LEADING {
+            while res.ends_with(remstr.as_str()) {
+                res = &res[..res.len() - remstr.len()];
+            }
+        }
+    }
+    Ok(Datum::Bytes(res.as_bytes().to_vec()))
+}
+
+/// `LEFT(str, len)` returns the leftmost `len` characters.
+fn left(args: Vec<Datum>) -> Result<Datum> {
+    let mut args = args.into_iter();
+    let s = try!(args.next().unwrap().into_string());
+    let len = try!(args.next().unwrap().into_i64());
+    if len <= 0 {
+        return Ok(Datum::Bytes(vec![]));
+    }
+    let res: String = s.chars().take(len as usize).collect();
+    Ok(Datum::Bytes(res.into_bytes()))
+}
+
+/// `RIGHT(str, len)` returns the rightmost `len` characters.
+fn right(args: Vec<Datum>) -> Result<Datum> {
+    let mut args = args.into_iter();
+    let s = try!(args.next().unwrap().into_string());
+    let len = try!(args.next().unwrap().into_i64());
+    if len <= 0 {
+        return Ok(Datum::Bytes(vec![]));
+    }
+    let skip = (s.chars().count() as u64).saturating_sub(len as u64);
+    let res: String = s.chars().skip(skip as usize).collect();
+    Ok(Datum::Bytes(res.into_bytes()))
+}
+
+/// `REPLACE(str, from_str, to_str)`, the match is case sensitive.
+fn replace(args: Vec<Datum>) -> Result<Datum> {
+    let mut args = args.into_iter();
+    let s = try!(args.next().unwrap().into_string());
+    let from = try!(args.next().unwrap().into_string());
+    let to = try!(args.next().unwrap().into_string());
+    if from.is_empty() {
+        return Ok(Datum::Bytes(s.into_bytes()));
+    }
+    Ok(Datum::Bytes(s.replace(from.as_str(), &to).into_bytes()))
+}
+
+/// `LOCATE(substr, str[, pos])` returns the position of the first occurrence of
+/// `substr` in `str` starting from `pos`, or 0 if not found. Positions start from 1,
+/// and the match is case sensitive.
+fn locate(args: Vec<Datum>) -> Result<Datum> {
+    let mut args = args.into_iter();
+    let substr = try!(args.next().unwrap().into_string());
+    let s = try!(args.next().unwrap().into_string());
+    let pos = match args.next() {
+        Some(d) => try!(d.into_i64()),
+        None => 1,
+    };
+    if pos < 1 {
+        return Ok(Datum::I64(0));
+    }
+    // An empty `substr` matches at the end of `str` too.
+    let start = match s.char_indices().map(|(i, _)| i).chain(Some(s.len())).nth(pos as usize - 1) {
+        Some(start) => start,
+        None => return Ok(Datum::I64(0)),
+    };
+    match s[start..].find(substr.as_str()) {
+        Some(i) => Ok(Datum::I64(pos + s[start..start + i].chars().count() as i64)),
+        None => Ok(Datum::I64(0)),
+    }
+}
+
+/// Convert a datum to a time, strings and numbers are parsed as datetimes.
+fn into_time(d: Datum) -> Result<Time> {
+    match d {
+        Datum::Time(t) => Ok(t),
+        d => {
+            let s = try!(d.into_string());
+            Time::parse_datetime(&s, MAX_FSP).map_err(From::from)
+        }
+    }
+}
+
+/// Get the hour, minute and second of a time or a duration, strings are parsed
+/// as durations first.
+fn into_hms(d: Datum) -> Result<(u64, u64, u64)> {
+    match d {
+        Datum::Dur(d) => Ok((d.hours(), d.minutes(), d.secs())),
+        Datum::Time(t) => Ok((t.hour() as u64, t.minute() as u64, t.second() as u64)),
+        d => {
+            let bs = try!(into_bytes(d));
+            match Duration::parse(&bs, MAX_FSP) {
+                Ok(d) => Ok((d.hours(), d.minutes(), d.secs())),
+                Err(_) => into_hms(Datum::Time(try!(into_time(Datum::Bytes(bs))))),
+            }
+        }
+    }
+}
+
+fn year(args: Vec<Datum>) -> Result<Datum> {
+    let t = try!(into_time(args.into_iter().next().unwrap()));
+    Ok(Datum::I64(t.year() as i64))
+}
+
+fn month(args: Vec<Datum>) -> Result<Datum> {
+    let t = try!(into_time(args.into_iter().next().unwrap()));
+    Ok(Datum::I64(t.month() as i64))
+}
+
+fn day(args: Vec<Datum>) -> Result<Datum> {
+    let t = try!(into_time(args.into_iter().next().unwrap()));
+    Ok(Datum::I64(t.day() as i64))
+}
+
+fn hour(args: Vec<Datum>) -> Result<Datum> {
+    let (h, _, _) = try!(into_hms(args.into_iter().next().unwrap()));
+    Ok(Datum::I64(h as i64))
+}
+
+fn minute(args: Vec<Datum>) -> Result<Datum> {
+    let (_, m, _) = try!(into_hms(args.into_iter().next().unwrap()));
+    Ok(Datum::I64(m as i64))
+}
+
+fn second(args: Vec<Datum>) -> Result<Datum> {
+    let (_, _, s) = try!(into_hms(args.into_iter().next().unwrap()));
+    Ok(Datum::I64(s as i64))
+}
+
+/// `DAYOFWEEK` returns 1 for Sunday and 7 for Saturday, NULL for the zero time.
+fn day_of_week(args: Vec<Datum>) -> Result<Datum> {
+    let t = try!(into_time(args.into_iter().next().unwrap()));
+    Ok(t.day_of_week().map(|d| d as i64).into())
+}
+
+fn date_format(args: Vec<Datum>) -> Result<Datum> {
+    let mut args = args.into_iter();
+    let t = try!(into_time(args.next().unwrap()));
+    let layout = try!(args.next().unwrap().into_string());
+    Ok(t.date_format(&layout).map(String::into_bytes).into())
+}
+
+fn date_add(args: Vec<Datum>) -> Result<Datum> {
+    date_arith(args, false)
+}
+
+fn date_sub(args: Vec<Datum>) -> Result<Datum> {
+    date_arith(args, true)
+}
+
+/// Evaluate `DATE_ADD(date, value, unit)` or `DATE_SUB`, where `value` and
+/// `unit` come from `INTERVAL value unit`. The result is NULL if it's out of range.
+fn date_arith(args: Vec<Datum>, sub: bool) -> Result<Datum> {
+    let mut args = args.into_iter();
+    let t = try!(into_time(args.next().unwrap()));
+    let value = args.next().unwrap();
+    let unit = try!(args.next().unwrap().into_string());
+    let is_frac = match value {
+        Datum::F64(_) | Datum::Dec(_) => true,
+        _ => false,
+    };
+    // Like MySQL, a fractional number is rounded unless the unit is `SECOND`.
+    let value = if is_frac && !unit.eq_ignore_ascii_case("SECOND") {
+        try!(value.into_i64()).to_string()
+    } else {
+        try!(value.into_string())
+    };
+    let mut interval = try!(Interval::parse(&value, &unit));
+    if sub {
+        interval = -interval;
+    }
+    Ok(t.add_interval(&interval).into())
+}
+
 /// Check if `target` is in `value_list`.
 fn check_in(target: Datum, value_list: &[Datum]) -> Result<bool> {
     let mut err = None;
@@ -301,13 +852,18 @@ fn check_in(target: Datum, value_list: &[Datum]) -> Result<bool> {
 
 #[cfg(test)]
 mod test {
+    use std::f64;
+
     use super::*;
+    use super::{TRIM_BOTH, TRIM_LEADING, TRIM_TRAILING};
     use util::codec::number::{self, NumberEncoder};
     use util::codec::{Datum, datum};
     use util::codec::mysql::{MAX_FSP, Decimal, Duration, DecimalEncoder};
 
+    use util::codec::mysql::types;
     use tipb::expression::{Expr, ExprType};
-    use protobuf::RepeatedField;
+    use tipb::schema::ColumnInfo;
+    use protobuf::{Message, RepeatedField};
 
     fn datum_expr(datum: Datum) -> Expr {
         let mut expr = Expr::new();
@@ -388,6 +944,37 @@ mod test {
         expr
     }
 
+    fn func_expr(tp: ExprType, args: Vec<Datum>) -> Expr {
+        let mut expr = Expr::new();
+        expr.set_tp(tp);
+        expr.set_children(RepeatedField::from_vec(args.into_iter().map(datum_expr).collect()));
+        expr
+    }
+
+    fn func_expr_r(tp: ExprType, children: Vec<Expr>) -> Expr {
+        let mut expr = Expr::new();
+        expr.set_tp(tp);
+        expr.set_children(RepeatedField::from_vec(children));
+        expr
+    }
+
+    fn cast_expr(value: Datum, tp: u8, flag: i32, flen: i32, decimal: i32) -> Expr {
+        let mut col = ColumnInfo::new();
+        col.set_tp(tp as i32);
+        col.set_flag(flag);
+        col.set_columnLen(flen);
+        col.set_decimal(decimal);
+        let mut expr = Expr::new();
+        expr.set_tp(ExprType::Cast);
+        expr.set_val(col.write_to_bytes().unwrap());
+        expr.mut_children().push(datum_expr(value));
+        expr
+    }
+
+    fn str_datum(s: &str) -> Datum {
+        Datum::Bytes(s.as_bytes().to_vec())
+    }
+
     macro_rules! test_eval {
         ($tag:ident, $cases:expr) => {
             #[test]
@@ -516,8 +1103,365 @@ mod test {
          ExprType::Plus), Datum::Dec(Decimal::from_f64(5040202.000000).unwrap())),
         (bin_expr(Datum::I64(2), Datum::Dur(Duration::parse(b"21 00:02:00.321", 2).unwrap()),
          ExprType::Plus), Datum::Dec(Decimal::from_f64(5040202.32).unwrap())),
+        (bin_expr(Datum::I64(3), Datum::I64(5), ExprType::Minus), Datum::I64(-2)),
+        (bin_expr(Datum::U64(3), Datum::I64(-2), ExprType::Minus), Datum::U64(5)),
+        (bin_expr(Datum::I64(3), Datum::U64(2), ExprType::Minus), Datum::U64(1)),
+        (bin_expr(Datum::F64(2.5), Datum::I64(1), ExprType::Minus), Datum::F64(1.5)),
+        (bin_expr(Datum::Dec("3.3".parse().unwrap()), Datum::I64(1), ExprType::Minus),
+         Datum::Dec("2.3".parse().unwrap())),
+        (bin_expr(Datum::Null, Datum::I64(1), ExprType::Minus), Datum::Null),
+        (bin_expr(Datum::I64(3), Datum::I64(-2), ExprType::Mul), Datum::I64(-6)),
+        (bin_expr(Datum::U64(3), Datum::I64(2), ExprType::Mul), Datum::U64(6)),
+        (bin_expr(Datum::U64(0), Datum::I64(-2), ExprType::Mul), Datum::U64(0)),
+        (bin_expr(Datum::F64(1.5), Datum::I64(2), ExprType::Mul), Datum::F64(3.0)),
+        (bin_expr(Datum::Dec("1.1".parse().unwrap()), Datum::I64(2), ExprType::Mul),
+         Datum::Dec("2.2".parse().unwrap())),
+        (bin_expr(Datum::I64(1), Datum::I64(3), ExprType::Div),
+         Datum::Dec("0.3333".parse().unwrap())),
+        (bin_expr(Datum::U64(6), Datum::I64(-4), ExprType::Div),
+         Datum::Dec("-1.5".parse().unwrap())),
+        (bin_expr(Datum::F64(3.0), Datum::I64(2), ExprType::Div), Datum::F64(1.5)),
+        (bin_expr(Datum::I64(1), Datum::I64(0), ExprType::Div), Datum::Null),
+        (bin_expr(Datum::F64(1.0), Datum::F64(0.0), ExprType::Div), Datum::Null),
+        (bin_expr(Datum::Dec("1.1".parse().unwrap()), Datum::I64(0), ExprType::Div),
+         Datum::Null),
+        (bin_expr(Datum::I64(7), Datum::I64(2), ExprType::IntDiv), Datum::I64(3)),
+        (bin_expr(Datum::I64(-7), Datum::I64(2), ExprType::IntDiv), Datum::I64(-3)),
+        (bin_expr(Datum::U64(7), Datum::I64(-8), ExprType::IntDiv), Datum::U64(0)),
+        (bin_expr(Datum::F64(7.5), Datum::I64(2), ExprType::IntDiv), Datum::I64(3)),
+        (bin_expr(Datum::Dec("7.5".parse().unwrap()), Datum::I64(2), ExprType::IntDiv),
+         Datum::I64(3)),
+        (bin_expr(Datum::I64(1), Datum::I64(0), ExprType::IntDiv), Datum::Null),
+        (bin_expr(Datum::I64(7), Datum::I64(-3), ExprType::Mod), Datum::I64(1)),
+        (bin_expr(Datum::I64(-7), Datum::I64(3), ExprType::Mod), Datum::I64(-1)),
+        (bin_expr(Datum::I64(-7), Datum::U64(3), ExprType::Mod), Datum::I64(-1)),
+        (bin_expr(Datum::U64(7), Datum::I64(-3), ExprType::Mod), Datum::U64(1)),
+        (bin_expr(Datum::I64(i64::min_value()), Datum::I64(-1), ExprType::Mod), Datum::I64(0)),
+        (bin_expr(Datum::F64(7.5), Datum::I64(2), ExprType::Mod), Datum::F64(1.5)),
+        (bin_expr(Datum::Dec("7.5".parse().unwrap()), Datum::I64(2), ExprType::Mod),
+         Datum::Dec("1.5".parse().unwrap())),
+        (bin_expr(Datum::I64(1), Datum::I64(0), ExprType::Mod), Datum::Null),
+    ]);
+
+    test_eval!(test_eval_string,
+               vec![
+        (func_expr(ExprType::Concat, vec![str_datum("ab"), Datum::I64(-1), Datum::F64(1.5)]),
+         str_datum("ab-11.5")),
+        (func_expr(ExprType::Concat, vec![str_datum("ab"), Datum::Null]), Datum::Null),
+        (func_expr(ExprType::Length, vec![str_datum("")]), Datum::I64(0)),
+        (func_expr(ExprType::Length, vec![str_datum("中文")]), Datum::I64(6)),
+        (func_expr(ExprType::Length, vec![Datum::I64(-12)]), Datum::I64(3)),
+        (func_expr(ExprType::Length, vec![Datum::Null]), Datum::Null),
+        (func_expr(ExprType::CharLength, vec![str_datum("中文a")]), Datum::I64(3)),
+        (func_expr(ExprType::CharLength, vec![Datum::Null]), Datum::Null),
+        (func_expr(ExprType::Substring, vec![str_datum("Quadratically"), Datum::I64(5)]),
+         str_datum("ratically")),
+        (func_expr(ExprType::Substring,
+                   vec![str_datum("Quadratically"), Datum::I64(5), Datum::I64(6)]),
+         str_datum("ratica")),
+        (func_expr(ExprType::Substring, vec![str_datum("Sakila"), Datum::I64(-3)]),
+         str_datum("ila")),
+        (func_expr(ExprType::Substring,
+                   vec![str_datum("Sakila"), Datum::I64(-5), Datum::I64(3)]),
+         str_datum("aki")),
+        (func_expr(ExprType::Substring, vec![str_datum("Sakila"), Datum::I64(0)]),
+         str_datum("")),
+        (func_expr(ExprType::Substring, vec![str_datum("Sakila"), Datum::I64(-7)]),
+         str_datum("")),
+        (func_expr(ExprType::Substring, vec![str_datum("Sakila"), Datum::I64(7)]),
+         str_datum("")),
+        (func_expr(ExprType::Substring,
+                   vec![str_datum("中文字符"), Datum::I64(2), Datum::I64(2)]),
+         str_datum("文字")),
+        (func_expr(ExprType::Substring, vec![str_datum("Sakila"), Datum::Null]), Datum::Null),
+        (func_expr(ExprType::Lower, vec![str_datum("AbC中")]), str_datum("abc中")),
+        (func_expr(ExprType::Lower, vec![Datum::Null]), Datum::Null),
+        (func_expr(ExprType::Upper, vec![str_datum("aBc中")]), str_datum("ABC中")),
+        (func_expr(ExprType::Upper, vec![Datum::Null]), Datum::Null),
+        (func_expr(ExprType::Trim, vec![str_datum("  bar   ")]), str_datum("bar")),
+        (func_expr(ExprType::Trim,
+                   vec![str_datum("xxxbarxxx"), str_datum("x"), Datum::I64(TRIM_LEADING)]),
+         str_datum("barxxx")),
+        (func_expr(ExprType::Trim,
+                   vec![str_datum("xxxbarxxx"), str_datum("x"), Datum::I64(TRIM_BOTH)]),
+         str_datum("bar")),
+        (func_expr(ExprType::Trim,
+                   vec![str_datum("barxxyz"), str_datum("xyz"), Datum::I64(TRIM_TRAILING)]),
+         str_datum("barx")),
+        (func_expr(ExprType::Trim, vec![str_datum("xbarx"), str_datum("")]), str_datum("xbarx")),
+        (func_expr(ExprType::Trim, vec![str_datum(" bar "), Datum::Null]), Datum::Null),
+        (func_expr(ExprType::Left, vec![str_datum("foobarbar"), Datum::I64(5)]),
+         str_datum("fooba")),
+        (func_expr(ExprType::Left, vec![str_datum("中文"), Datum::I64(1)]), str_datum("中")),
+        (func_expr(ExprType::Left, vec![str_datum("foobarbar"), Datum::I64(-1)]),
+         str_datum("")),
+        (func_expr(ExprType::Left, vec![str_datum("foobarbar"), Datum::Null]), Datum::Null),
+        (func_expr(ExprType::Right, vec![str_datum("foobarbar"), Datum::I64(4)]),
+         str_datum("rbar")),
+        (func_expr(ExprType::Right, vec![str_datum("中文"), Datum::I64(10)]),
+         str_datum("中文")),
+        (func_expr(ExprType::Right, vec![str_datum("foobarbar"), Datum::I64(0)]),
+         str_datum("")),
+        (func_expr(ExprType::Right, vec![Datum::Null, Datum::I64(1)]), Datum::Null),
+        (func_expr(ExprType::Replace,
+                   vec![str_datum("www.mysql.com"), str_datum("w"), str_datum("Ww")]),
+         str_datum("WwWwWw.mysql.com")),
+        (func_expr(ExprType::Replace,
+                   vec![str_datum("www.mysql.com"), str_datum(""), str_datum("Ww")]),
+         str_datum("www.mysql.com")),
+        (func_expr(ExprType::Replace,
+                   vec![str_datum("www.mysql.com"), Datum::Null, str_datum("Ww")]),
+         Datum::Null),
+        (func_expr(ExprType::Locate, vec![str_datum("bar"), str_datum("foobarbar")]),
+         Datum::I64(4)),
+        (func_expr(ExprType::Locate, vec![str_datum("xbar"), str_datum("foobar")]),
+         Datum::I64(0)),
+        (func_expr(ExprType::Locate,
+                   vec![str_datum("bar"), str_datum("foobarbar"), Datum::I64(5)]),
+         Datum::I64(7)),
+        (func_expr(ExprType::Locate,
+                   vec![str_datum("bar"), str_datum("foobarbar"), Datum::I64(0)]),
+         Datum::I64(0)),
+        (func_expr(ExprType::Locate, vec![str_datum("字"), str_datum("中文字符")]),
+         Datum::I64(3)),
+        (func_expr(ExprType::Locate, vec![str_datum(""), str_datum("abc"), Datum::I64(4)]),
+         Datum::I64(4)),
+        (func_expr(ExprType::Locate, vec![str_datum(""), str_datum("abc"), Datum::I64(5)]),
+         Datum::I64(0)),
+        (func_expr(ExprType::Locate, vec![Datum::Null, str_datum("abc")]), Datum::Null),
+    ]);
+
+    fn time_datum(s: &str) -> Datum {
+        Datum::Time(Time::parse_datetime(s, MAX_FSP).unwrap())
+    }
+
+    test_eval!(test_eval_time,
+               vec![
+        (func_expr(ExprType::Year, vec![str_datum("2010-01-02 03:04:05")]), Datum::I64(2010)),
+        (func_expr(ExprType::Year, vec![Datum::I64(20100102)]), Datum::I64(2010)),
+        (func_expr(ExprType::Year, vec![str_datum("0000-00-00")]), Datum::I64(0)),
+        (func_expr(ExprType::Year, vec![Datum::Null]), Datum::Null),
+        (func_expr(ExprType::Month, vec![str_datum("2010-01-02 03:04:05")]), Datum::I64(1)),
+        (func_expr(ExprType::Day, vec![str_datum("2010-01-02 03:04:05")]), Datum::I64(2)),
+        (func_expr(ExprType::Hour, vec![str_datum("2010-01-02 03:04:05")]), Datum::I64(3)),
+        (func_expr(ExprType::Hour, vec![str_datum("838:59:59")]), Datum::I64(838)),
+        (func_expr(ExprType::Hour, vec![Datum::Dur(Duration::parse(b"-10:11:12", 0).unwrap())]),
+         Datum::I64(10)),
+        (func_expr(ExprType::Minute, vec![str_datum("2010-01-02 03:04:05")]), Datum::I64(4)),
+        (func_expr(ExprType::Minute, vec![str_datum("10:11:12")]), Datum::I64(11)),
+        (func_expr(ExprType::Second, vec![str_datum("2010-01-02 03:04:05")]), Datum::I64(5)),
+        (func_expr(ExprType::Second, vec![Datum::Null]), Datum::Null),
+        (func_expr(ExprType::DayOfWeek, vec![str_datum("2010-01-02")]), Datum::I64(7)),
+        (func_expr(ExprType::DayOfWeek, vec![str_datum("2010-01-03")]), Datum::I64(1)),
+        (func_expr(ExprType::DayOfWeek, vec![str_datum("0000-00-00")]), Datum::Null),
+        (func_expr(ExprType::DateFormat,
+                   vec![str_datum("2009-10-04 22:23:00"), str_datum("%W %M %Y")]),
+         str_datum("Sunday October 2009")),
+        (func_expr(ExprType::DateFormat, vec![str_datum("0000-00-00"), str_datum("%Y")]),
+         Datum::Null),
+        (func_expr(ExprType::DateFormat, vec![str_datum("2009-10-04"), Datum::Null]),
+         Datum::Null),
+        (func_expr(ExprType::DateAdd,
+                   vec![str_datum("2010-01-31"), Datum::I64(1), str_datum("MONTH")]),
+         time_datum("2010-02-28")),
+        (func_expr(ExprType::DateAdd,
+                   vec![str_datum("2010-01-01"), Datum::F64(1.5), str_datum("DAY")]),
+         time_datum("2010-01-03")),
+        (func_expr(ExprType::DateAdd,
+                   vec![str_datum("2010-01-01"), Datum::F64(1.5), str_datum("second")]),
+         time_datum("2010-01-01 00:00:01.5")),
+        (func_expr(ExprType::DateAdd,
+                   vec![str_datum("9999-12-31"), Datum::I64(1), str_datum("DAY")]),
+         Datum::Null),
+        (func_expr(ExprType::DateAdd, vec![Datum::Null, Datum::I64(1), str_datum("DAY")]),
+         Datum::Null),
+        (func_expr(ExprType::DateSub,
+                   vec![str_datum("2010-01-01"), str_datum("1 1:1:1"), str_datum("DAY_SECOND")]),
+         time_datum("2009-12-30 22:58:59")),
+        (func_expr(ExprType::DateSub,
+                   vec![str_datum("2010-01-01"), Datum::I64(-1), str_datum("YEAR")]),
+         time_datum("2011-01-01")),
+    ]);
+
+    test_eval!(test_eval_control,
+               vec![
+        (func_expr(ExprType::If, vec![Datum::I64(1), Datum::I64(2), Datum::I64(3)]),
+            Datum::I64(2)),
+        (func_expr(ExprType::If, vec![Datum::I64(0), Datum::I64(2), Datum::I64(3)]),
+            Datum::I64(3)),
+        (func_expr(ExprType::If, vec![Datum::Null, Datum::I64(2), Datum::I64(3)]),
+            Datum::I64(3)),
+        (func_expr(ExprType::If, vec![Datum::I64(1), Datum::Null, Datum::I64(3)]), Datum::Null),
+        (func_expr(ExprType::If, vec![Datum::I64(1), Datum::I64(2), Datum::F64(3.5)]),
+            Datum::F64(2.0)),
+        (func_expr(ExprType::If, vec![Datum::I64(1), Datum::I64(2), Datum::Dec(3u64.into())]),
+            Datum::Dec(2u64.into())),
+        (func_expr(ExprType::If, vec![Datum::I64(1), Datum::I64(2), str_datum("a")]),
+            str_datum("2")),
+        (func_expr_r(ExprType::If,
+                     vec![bin_expr(Datum::I64(1), Datum::I64(2), ExprType::LT),
+                          col_expr(1),
+                          bin_expr(Datum::I64(i64::max_value()), Datum::I64(1), ExprType::Plus)]),
+            Datum::I64(100)),
+        (func_expr_r(ExprType::If,
+                     vec![datum_expr(Datum::I64(0)),
+                          bin_expr(Datum::I64(i64::max_value()), Datum::I64(1), ExprType::Plus),
+                          bin_expr(Datum::I64(1), Datum::F64(0.5), ExprType::Plus)]),
+            Datum::F64(1.5)),
+        (func_expr(ExprType::IfNull, vec![Datum::I64(1), Datum::I64(2)]), Datum::I64(1)),
+        (func_expr(ExprType::IfNull, vec![Datum::Null, Datum::I64(2)]), Datum::I64(2)),
+        (func_expr(ExprType::IfNull, vec![Datum::Null, Datum::Null]), Datum::Null),
+        (func_expr(ExprType::IfNull, vec![Datum::I64(1), Datum::F64(2.0)]), Datum::F64(1.0)),
+        (func_expr_r(ExprType::IfNull,
+                     vec![col_expr(1),
+                          bin_expr(Datum::I64(i64::max_value()), Datum::I64(1), ExprType::Plus)]),
+            Datum::I64(100)),
+        (func_expr(ExprType::Coalesce, vec![Datum::Null]), Datum::Null),
+        (func_expr(ExprType::Coalesce, vec![Datum::Null, Datum::I64(1), Datum::I64(2)]),
+            Datum::I64(1)),
+        (func_expr(ExprType::Coalesce, vec![Datum::Null, Datum::I64(1), str_datum("a")]),
+            str_datum("1")),
+        (func_expr_r(ExprType::Coalesce,
+                     vec![datum_expr(Datum::I64(1)),
+                          bin_expr(Datum::I64(i64::max_value()), Datum::I64(1), ExprType::Plus)]),
+            Datum::I64(1)),
+        (func_expr(ExprType::Case,
+                   vec![Datum::I64(0), Datum::I64(1), Datum::I64(1), Datum::I64(2)]),
+            Datum::I64(2)),
+        (func_expr(ExprType::Case,
+                   vec![Datum::I64(0), Datum::I64(1), Datum::Null, Datum::I64(2)]),
+            Datum::Null),
+        (func_expr(ExprType::Case,
+                   vec![Datum::I64(0), Datum::I64(1), Datum::Null, Datum::I64(2), Datum::I64(3)]),
+            Datum::I64(3)),
+        (func_expr(ExprType::Case,
+                   vec![Datum::I64(1), Datum::I64(1), Datum::I64(0), Datum::F64(2.5)]),
+            Datum::F64(1.0)),
+        (func_expr(ExprType::Case, vec![Datum::I64(0), Datum::I64(1), str_datum("a")]),
+            str_datum("a")),
+        (func_expr_r(ExprType::Case,
+                     vec![bin_expr_r(col_expr(1), datum_expr(Datum::I64(10)), ExprType::GT),
+                          datum_expr(Datum::I64(1)),
+                          bin_expr(Datum::I64(i64::max_value()), Datum::I64(1), ExprType::Plus)]),
+            Datum::I64(1)),
+        (func_expr(ExprType::IsNull, vec![Datum::Null]), Datum::I64(1)),
+        (func_expr(ExprType::IsNull, vec![Datum::I64(0)]), Datum::I64(0)),
+        (func_expr(ExprType::IsTruth, vec![Datum::I64(2)]), Datum::I64(1)),
+        (func_expr(ExprType::IsTruth, vec![Datum::I64(0)]), Datum::I64(0)),
+        (func_expr(ExprType::IsTruth, vec![Datum::Null]), Datum::I64(0)),
+        (func_expr(ExprType::IsTruth, vec![Datum::F64(1.5)]), Datum::I64(1)),
+    ]);
+
+    test_eval!(test_eval_cast,
+               vec![
+        (cast_expr(str_datum("-12.5a"), types::LONG_LONG, 0, -1, -1), Datum::I64(-12)),
+        (cast_expr(Datum::I64(-1), types::LONG_LONG, 32, -1, -1), Datum::U64(u64::max_value())),
+        (cast_expr(Datum::F64(2.5), types::LONG, 0, -1, -1), Datum::I64(3)),
+        (cast_expr(str_datum("1.5e1"), types::DOUBLE, 0, -1, -1), Datum::F64(15.0)),
+        (cast_expr(Datum::I64(1000), types::NEW_DECIMAL, 0, 4, 1),
+            Datum::Dec("999.9".parse().unwrap())),
+        (cast_expr(Datum::F64(1.25), types::NEW_DECIMAL, 0, 10, 1),
+            Datum::Dec("1.3".parse().unwrap())),
+        (cast_expr(Datum::F64(1.5), types::VAR_STRING, 0, -1, -1), str_datum("1.5")),
+        (cast_expr(str_datum("abc"), types::VAR_STRING, 0, 2, -1), str_datum("ab")),
+        (cast_expr(str_datum("2010-01-02 12:30:59"), types::DATE, 0, -1, -1),
+            time_datum("2010-01-02")),
+        (cast_expr(Datum::I64(20100102123059), types::DATETIME, 0, -1, -1),
+            time_datum("2010-01-02 12:30:59")),
+        (cast_expr(str_datum("2010-13-01"), types::DATETIME, 0, -1, -1), Datum::Null),
+        (cast_expr(Datum::Dur(Duration::parse(b"12:30:59.5", 1).unwrap()),
+                   types::DURATION,
+                   0,
+                   -1,
+                   0),
+            Datum::Dur(Duration::parse(b"12:31:00", 0).unwrap())),
+        (cast_expr(Datum::I64(123), types::DURATION, 0, -1, -1),
+            Datum::Dur(Duration::parse(b"00:01:23", 0).unwrap())),
+        (cast_expr(Datum::Null, types::LONG_LONG, 0, -1, -1), Datum::Null),
     ]);
 
+    #[test]
+    fn test_eval_cast_strict() {
+        let mut eval = Evaluator::default();
+        let expr = cast_expr(str_datum("12a"), types::LONG_LONG, 0, -1, -1);
+        assert_eq!(eval.eval(&expr).unwrap(), Datum::I64(12));
+        assert_eq!(eval.convert_ctx.warning_cnt, 1);
+
+        eval.convert_ctx.strict = true;
+        assert!(eval.eval(&expr).is_err());
+        let expr = cast_expr(str_datum("12"), types::LONG_LONG, 0, -1, -1);
+        assert_eq!(eval.eval(&expr).unwrap(), Datum::I64(12));
+    }
+
+    #[test]
+    fn test_eval_control_flow_col_type() {
+        let mut eval = Evaluator::default();
+        eval.row.insert(1, str_datum("a"));
+        let expr = func_expr_r(ExprType::If,
+                               vec![datum_expr(Datum::I64(1)),
+                                    datum_expr(Datum::I64(1)),
+                                    col_expr(1)]);
+        assert_eq!(eval.eval(&expr).unwrap(), Datum::I64(1));
+
+        // The result is a string since the column is.
+        let mut col = ColumnInfo::new();
+        col.set_column_id(1);
+        col.set_tp(types::VARCHAR as i32);
+        eval.set_column_infos(&[col]);
+        assert_eq!(eval.eval(&expr).unwrap(), str_datum("1"));
+    }
+
+    #[test]
+    fn test_eval_error() {
+        let mut count_expr = Expr::new();
+        count_expr.set_tp(ExprType::Count);
+        count_expr.mut_children().push(col_expr(1));
+        let cases = vec![
+            bin_expr(Datum::I64(i64::max_value()), Datum::I64(1), ExprType::Plus),
+            bin_expr(Datum::I64(i64::min_value()), Datum::I64(1), ExprType::Minus),
+            bin_expr(Datum::I64(-1), Datum::U64(1), ExprType::Minus),
+            bin_expr(Datum::U64(1), Datum::I64(2), ExprType::Minus),
+            bin_expr(Datum::I64(i64::max_value()), Datum::I64(2), ExprType::Mul),
+            bin_expr(Datum::I64(-1), Datum::U64(2), ExprType::Mul),
+            bin_expr(Datum::F64(f64::MAX), Datum::F64(2.0), ExprType::Mul),
+            bin_expr(Datum::F64(f64::MAX), Datum::F64(0.5), ExprType::Div),
+            bin_expr(Datum::I64(i64::min_value()), Datum::I64(-1), ExprType::IntDiv),
+            bin_expr(Datum::U64(10), Datum::I64(-2), ExprType::IntDiv),
+            bin_expr(Datum::F64(1e20), Datum::I64(1), ExprType::IntDiv),
+            count_expr,
+            func_expr(ExprType::Length, vec![]),
+            func_expr(ExprType::Replace, vec![str_datum("a"), str_datum("b")]),
+            func_expr(ExprType::Trim, vec![str_datum("a"), str_datum("b"), Datum::I64(4)]),
+            func_expr(ExprType::Year, vec![str_datum("2010-13-01")]),
+            func_expr(ExprType::DateAdd,
+                      vec![str_datum("2010-01-01"), Datum::I64(1), str_datum("FORTNIGHT")]),
+            func_expr(ExprType::If, vec![Datum::I64(1), Datum::I64(2)]),
+            func_expr(ExprType::IfNull, vec![Datum::I64(1)]),
+            func_expr(ExprType::Coalesce, vec![]),
+            func_expr(ExprType::IsNull, vec![Datum::I64(1), Datum::I64(2)]),
+            cast_expr(Datum::I64(1), types::ENUM, 0, -1, -1),
+            cast_expr(Datum::I64(1), types::DATETIME, 0, -1, 7),
+            cast_expr(Datum::Dur(Duration::parse(b"12:00:00", 0).unwrap()),
+                      types::DATETIME,
+                      0,
+                      -1,
+                      -1),
+            func_expr_r(ExprType::If,
+                        vec![datum_expr(Datum::I64(1)),
+                             bin_expr(Datum::I64(i64::max_value()), Datum::I64(1), ExprType::Plus),
+                             datum_expr(Datum::I64(1))]),
+        ];
+
+        let mut eval = Evaluator::default();
+        eval.row.insert(1, Datum::I64(100));
+        for expr in cases {
+            let res = eval.eval(&expr);
+            if res.is_ok() {
+                panic!("eval {:?} should fail, got {:?}", expr, res);
+            }
+        }
+    }
+
     fn in_expr(target: Datum, mut list: Vec<Datum>) -> Expr {
         let target_expr = datum_expr(target);
         list.sort_by(|l, r| l.cmp(r).unwrap());
@@ -4,7 +4,8 @@ use kvproto::kvrpcpb::Context;
 use tikv::util::codec::{table, Datum, datum};
 use tikv::util::codec::datum::DatumDecoder;
 use tikv::util::codec::number::*;
-use tikv::storage::{Dsn, Mutation, Key, DEFAULT_CFS};
+use tikv::util::codec::mysql::types;
+use tikv::storage::{Dsn, Mutation, Key, MaxReadTs, DEFAULT_CFS};
 use tikv::storage::engine::{self, Engine, TEMP_DIR};
 use tikv::util::event::Event;
 use tikv::util::worker::Worker;
@@ -295,6 +296,16 @@ impl<'a> Select<'a> {
         self.aggr_col(col, ExprType::Min)
     }
 
+    fn where_expr(mut self, expr: Expr) -> Select<'a> {
+        self.sel.set_field_where(expr);
+        self
+    }
+
+    fn flags(mut self, flags: u64) -> Select<'a> {
+        self.sel.set_flags(flags);
+        self
+    }
+
     fn group_by(mut self, cols: &[Column]) -> Select<'a> {
         for col in cols {
             let mut expr = Expr::new();
@@ -393,6 +404,10 @@ impl Store {
         self.store.get_engine()
     }
 
+    fn get_max_read_ts(&self) -> MaxReadTs {
+        self.store.get_max_read_ts()
+    }
+
     fn begin(&mut self) {
         self.current_ts = next_id() as u64;
         self.handles.clear();
@@ -478,7 +493,9 @@ fn init_with_data(tbl: &ProductTable,
     store.commit();
 
     let mut end_point = Worker::new("test select worker");
-    let runner = EndPointHost::new(store.get_engine(), end_point.scheduler());
+    let runner = EndPointHost::new(store.get_engine(),
+                                   store.get_max_read_ts(),
+                                   end_point.scheduler());
     end_point.start_batch(runner, 5).unwrap();
 
     (store, end_point)
@@ -1156,3 +1173,56 @@ fn test_index_aggr_extre() {
     }
     end_point.stop().unwrap();
 }
+
+#[test]
+fn test_select_cast_warnings() {
+    let data = vec![
+        (1, Some("name:0"), 2),
+        (2, Some("name:3"), 3),
+        (4, Some("name:1"), 1),
+    ];
+
+    let product = ProductTable::new();
+    let (_, mut end_point) = init_with_data(&product, &data);
+
+    // where cast(name as signed)
+    let mut col_expr = Expr::new();
+    col_expr.set_tp(ExprType::ColumnRef);
+    col_expr.mut_val().encode_i64(product.name.id).unwrap();
+    let mut tp = ColumnInfo::new();
+    tp.set_tp(types::LONG_LONG as i32);
+    tp.set_columnLen(-1);
+    tp.set_decimal(-1);
+    let mut cond = Expr::new();
+    cond.set_tp(ExprType::Cast);
+    cond.set_val(tp.write_to_bytes().unwrap());
+    cond.mut_children().push(col_expr);
+
+    let req = Select::from(&product.table)
+        .where_expr(cond.clone())
+        .flags(FLAG_TRUNCATE_AS_WARNING)
+        .build();
+    let resp = handle_select(&end_point, req);
+    assert!(!resp.has_error());
+    assert_eq!(resp.get_rows().len(), 0);
+    assert_eq!(resp.get_warning_count(), data.len() as i64);
+    assert_eq!(resp.get_warnings().len(), data.len());
+
+    // The warnings of a request are not carried to the next one.
+    let req = Select::from(&product.table)
+        .where_expr(cond.clone())
+        .flags(FLAG_IGNORE_TRUNCATE)
+        .build();
+    let resp = handle_select(&end_point, req);
+    assert_eq!(resp.get_rows().len(), 0);
+    assert_eq!(resp.get_warning_count(), 0);
+    assert!(resp.get_warnings().is_empty());
+
+    // A lossy cast fails the request in strict mode.
+    let req = Select::from(&product.table).where_expr(cond).build();
+    let resp = handle_select(&end_point, req);
+    assert!(resp.has_error());
+    assert!(resp.get_rows().is_empty());
+
+    end_point.stop().unwrap();
+}
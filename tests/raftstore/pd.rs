@@ -53,6 +53,9 @@ struct Cluster {
     split_count: usize,
 
     down_peers: HashMap<u64, pdpb::PeerStats>,
+
+    gc_safe_point: u64,
+    tso: u64,
 }
 
 impl Cluster {
@@ -71,6 +74,8 @@ impl Cluster {
             store_stats: HashMap::new(),
             split_count: 0,
             down_peers: HashMap::new(),
+            gc_safe_point: 0,
+            tso: 0,
         }
     }
 
@@ -473,6 +478,10 @@ impl TestPdClient {
     pub fn get_down_peers(&self) -> HashMap<u64, pdpb::PeerStats> {
         self.cluster.rl().down_peers.clone()
     }
+
+    pub fn set_gc_safe_point(&self, safe_point: u64) {
+        self.cluster.wl().gc_safe_point = safe_point;
+    }
 }
 
 impl PdClient for TestPdClient {
@@ -565,4 +574,15 @@ impl PdClient for TestPdClient {
         self.cluster.wl().split_count += 1;
         Ok(())
     }
+
+    fn get_gc_safe_point(&self) -> Result<u64> {
+        try!(self.check_bootstrap());
+        Ok(self.cluster.rl().gc_safe_point)
+    }
+
+    fn get_ts(&self) -> Result<u64> {
+        let mut cluster = self.cluster.wl();
+        cluster.tso += 1;
+        Ok(cluster.tso)
+    }
 }
@@ -173,7 +173,8 @@ impl Simulator for ServerCluster {
         self.store_chs.insert(node_id, node.get_sendch());
         self.sim_trans.insert(node_id, simulate_trans);
 
-        let mut store = create_raft_storage(sim_router.clone(), engine, &cfg).unwrap();
+        let mut store =
+            create_raft_storage(sim_router.clone(), engine, node.max_read_ts(), &cfg).unwrap();
         store.start(&cfg.storage).unwrap();
         self.storages.insert(node_id, store.get_engine());
 
@@ -183,7 +184,8 @@ impl Simulator for ServerCluster {
                                      store,
                                      sim_router.clone(),
                                      resolver,
-                                     snap_mgr)
+                                     snap_mgr,
+                                     Some(node.cdc_scheduler()))
             .unwrap();
 
         let ch = server.get_sendch();
@@ -0,0 +1,123 @@
+// Copyright 2016 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::File;
+use std::io::Read;
+use std::time::Duration;
+
+use crc::crc32;
+use rocksdb::{SstFileWriter, EnvOptions, Options};
+use tempdir::TempDir;
+use uuid::Uuid;
+use kvproto::metapb;
+use kvproto::importpb::SstMeta;
+use kvproto::raft_cmdpb::{Request, CmdType};
+
+use tikv::raftstore::store::{keys, new_snap_mgr, SstImporter};
+use tikv::util::HandyRwLock;
+
+use super::cluster::{Cluster, Simulator};
+use super::node::new_node_cluster;
+use super::server::new_server_cluster;
+use super::util::must_get_equal;
+
+fn new_sst(dir: &TempDir, kvs: &[(&[u8], &[u8])]) -> Vec<u8> {
+    let path = dir.path().join("gen.sst");
+    let mut writer = SstFileWriter::new(EnvOptions::new(), Options::new());
+    writer.open(path.to_str().unwrap()).unwrap();
+    for &(k, v) in kvs {
+        writer.add(&keys::data_key(k), v).unwrap();
+    }
+    writer.finish().unwrap();
+    let mut data = vec![];
+    File::open(&path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+fn new_sst_meta(region: &metapb::Region, data: &[u8]) -> SstMeta {
+    let mut meta = SstMeta::new();
+    meta.set_uuid(Uuid::new_v4().as_bytes().to_vec());
+    meta.set_region_id(region.get_id());
+    meta.set_region_epoch(region.get_region_epoch().clone());
+    meta.set_cf_name("default".to_owned());
+    meta.set_length(data.len() as u64);
+    meta.set_crc32(crc32::checksum_ieee(data));
+    meta.mut_range().set_start(region.get_start_key().to_vec());
+    meta.mut_range().set_end(region.get_end_key().to_vec());
+    meta
+}
+
+fn new_ingest_sst_cmd(meta: SstMeta) -> Request {
+    let mut cmd = Request::new();
+    cmd.set_cmd_type(CmdType::IngestSst);
+    cmd.mut_ingest_sst().set_sst(meta);
+    cmd
+}
+
+fn upload_sst<T: Simulator>(cluster: &Cluster<T>, node_id: u64, meta: &SstMeta, data: &[u8]) {
+    let snap_mgr = new_snap_mgr(cluster.get_snap_dir(node_id), None);
+    let importer = SstImporter::new(snap_mgr.rl().import_dir());
+    importer.upload(meta, data).unwrap();
+}
+
+fn test_ingest_sst<T: Simulator>(cluster: &mut Cluster<T>) {
+    cluster.run();
+    cluster.must_put(b"k0", b"v0");
+
+    let region = cluster.get_region(b"k1");
+    let leader = cluster.leader_of_region(region.get_id()).unwrap();
+    let node_ids: Vec<_> = cluster.engines.keys().cloned().collect();
+    let dir = TempDir::new("test-ingest-sst").unwrap();
+    let timeout = Duration::from_secs(5);
+
+    // The leader rejects the command if the file is not uploaded.
+    let data = new_sst(&dir, &[(b"k1", b"v1"), (b"k2", b"v2")]);
+    let meta = new_sst_meta(&region, &data);
+    let resp = cluster.request(b"k1", vec![new_ingest_sst_cmd(meta.clone())], false, timeout);
+    assert!(resp.get_header().has_error(), "{:?}", resp);
+
+    for &id in &node_ids {
+        upload_sst(cluster, id, &meta, &data);
+    }
+    let resp = cluster.request(b"k1", vec![new_ingest_sst_cmd(meta)], false, timeout);
+    assert!(!resp.get_header().has_error(), "{:?}", resp);
+    for engine in cluster.engines.values() {
+        must_get_equal(engine, b"k1", b"v1");
+        must_get_equal(engine, b"k2", b"v2");
+    }
+
+    // The followers without the file fail to apply the command, but keep working.
+    let data = new_sst(&dir, &[(b"k3", b"v3")]);
+    let meta = new_sst_meta(&region, &data);
+    upload_sst(cluster, leader.get_store_id(), &meta, &data);
+    let resp = cluster.request(b"k3", vec![new_ingest_sst_cmd(meta)], false, timeout);
+    assert!(!resp.get_header().has_error(), "{:?}", resp);
+    must_get_equal(&cluster.get_engine(leader.get_store_id()), b"k3", b"v3");
+
+    cluster.must_put(b"k4", b"v4");
+    for engine in cluster.engines.values() {
+        must_get_equal(engine, b"k4", b"v4");
+    }
+}
+
+#[test]
+fn test_node_ingest_sst() {
+    let mut cluster = new_node_cluster(0, 3);
+    test_ingest_sst(&mut cluster);
+}
+
+#[test]
+fn test_server_ingest_sst() {
+    let mut cluster = new_server_cluster(0, 3);
+    test_ingest_sst(&mut cluster);
+}
@@ -14,7 +14,8 @@
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-use tikv::storage::{Storage, Engine, Key, Value, KvPair, Mutation, Result};
+use tikv::storage::{Storage, Engine, Key, Value, KvPair, Mutation, Options, TxnStatus, MaxReadTs,
+                    Result};
 use tikv::storage::config::Config;
 use kvproto::kvrpcpb::{Context, LockInfo};
 
@@ -47,6 +48,10 @@ impl SyncStorage {
         self.store.get_engine()
     }
 
+    pub fn get_max_read_ts(&self) -> MaxReadTs {
+        self.store.get_max_read_ts()
+    }
+
     pub fn get(&self, ctx: Context, key: &Key, start_ts: u64) -> Result<Option<Value>> {
         wait_event!(|cb| self.store.async_get(ctx, key.to_owned(), start_ts, cb).unwrap()).unwrap()
     }
@@ -64,10 +69,30 @@ impl SyncStorage {
     pub fn scan(&self,
                 ctx: Context,
                 key: Key,
+                end_key: Option<Key>,
                 limit: usize,
+                key_only: bool,
                 start_ts: u64)
                 -> Result<Vec<Result<KvPair>>> {
-        wait_event!(|cb| self.store.async_scan(ctx, key, limit, start_ts, cb).unwrap()).unwrap()
+        wait_event!(|cb| {
+                self.store
+                    .async_scan(ctx, key, end_key, limit, key_only, start_ts, cb)
+                    .unwrap()
+            })
+            .unwrap()
+    }
+
+    pub fn reverse_scan(&self,
+                        ctx: Context,
+                        key: Key,
+                        end_key: Option<Key>,
+                        limit: usize,
+                        start_ts: u64)
+                        -> Result<Vec<Result<KvPair>>> {
+        wait_event!(|cb| {
+                self.store.async_reverse_scan(ctx, key, end_key, limit, start_ts, cb).unwrap()
+            })
+            .unwrap()
     }
 
     pub fn prewrite(&self,
@@ -76,7 +101,48 @@ impl SyncStorage {
                     primary: Vec<u8>,
                     start_ts: u64)
                     -> Result<Vec<Result<()>>> {
-        wait_event!(|cb| self.store.async_prewrite(ctx, mutations, primary, start_ts, cb).unwrap())
+        wait_event!(|cb| {
+                self.store
+                    .async_prewrite(ctx, mutations, primary, start_ts, Options::default(), cb)
+                    .unwrap()
+            })
+            .unwrap()
+    }
+
+    #[allow(dead_code)]
+    pub fn acquire_pessimistic_lock(&self,
+                                    ctx: Context,
+                                    keys: Vec<Key>,
+                                    primary: Vec<u8>,
+                                    start_ts: u64,
+                                    for_update_ts: u64)
+                                    -> Result<Vec<Result<()>>> {
+        wait_event!(|cb| {
+                self.store
+                    .async_acquire_pessimistic_lock(ctx,
+                                                    keys,
+                                                    primary,
+                                                    start_ts,
+                                                    for_update_ts,
+                                                    Options::default(),
+                                                    cb)
+                    .unwrap()
+            })
+            .unwrap()
+    }
+
+    #[allow(dead_code)]
+    pub fn pessimistic_rollback(&self,
+                                ctx: Context,
+                                keys: Vec<Key>,
+                                start_ts: u64,
+                                for_update_ts: u64)
+                                -> Result<()> {
+        wait_event!(|cb| {
+                self.store
+                    .async_pessimistic_rollback(ctx, keys, start_ts, for_update_ts, cb)
+                    .unwrap()
+            })
             .unwrap()
     }
 
@@ -103,6 +169,21 @@ impl SyncStorage {
         wait_event!(|cb| self.store.async_scan_lock(ctx, max_ts, cb).unwrap()).unwrap()
     }
 
+    #[allow(dead_code)]
+    pub fn check_txn_status(&self,
+                            ctx: Context,
+                            primary: Key,
+                            lock_ts: u64,
+                            current_ts: u64)
+                            -> Result<TxnStatus> {
+        wait_event!(|cb| {
+                self.store
+                    .async_check_txn_status(ctx, primary, lock_ts, current_ts, cb)
+                    .unwrap()
+            })
+            .unwrap()
+    }
+
     pub fn resolve_lock(&self, ctx: Context, start_ts: u64, commit_ts: Option<u64>) -> Result<()> {
         wait_event!(|cb| self.store.async_resolve_lock(ctx, start_ts, commit_ts, cb).unwrap())
             .unwrap()
@@ -31,6 +31,7 @@ fn test_raftkv(read_quorum: bool) {
     near_seek(&ctx, storage.as_ref());
     cf(&ctx, storage.as_ref());
     empty_write(&ctx, storage.as_ref());
+    delete_range(&ctx, storage.as_ref());
     // TODO: test multiple node
 }
 
@@ -221,3 +222,19 @@ fn cf(ctx: &Context, engine: &Engine) {
 fn empty_write(ctx: &Context, engine: &Engine) {
     engine.write(ctx, vec![]).unwrap();
 }
+
+fn delete_range(ctx: &Context, engine: &Engine) {
+    for key in &[b"a", b"b", b"c", b"d"] {
+        must_put(ctx, engine, *key, b"v");
+        must_put_cf(ctx, engine, "cf", *key, b"v");
+    }
+    engine.delete_range(ctx, make_key(b"b"), make_key(b"d")).unwrap();
+    for key in &[b"a", b"d"] {
+        assert_has(ctx, engine, *key, b"v");
+        assert_has_cf(ctx, engine, "cf", *key, b"v");
+    }
+    for key in &[b"b", b"c"] {
+        assert_none(ctx, engine, *key);
+        assert_none_cf(ctx, engine, "cf", *key);
+    }
+}
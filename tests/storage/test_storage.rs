@@ -18,8 +18,9 @@ use std::thread;
 use rand::random;
 use super::sync_storage::SyncStorage;
 use kvproto::kvrpcpb::{Context, LockInfo};
-use tikv::storage::{Mutation, Key, KvPair, make_key};
+use tikv::storage::{Mutation, Key, KvPair, TxnStatus, make_key};
 use tikv::storage::mvcc::TEST_TS_BASE;
+use tikv::storage::config::Config;
 
 #[derive(Clone)]
 struct AssertionStorage(SyncStorage);
@@ -66,8 +67,21 @@ impl AssertionStorage {
                limit: usize,
                ts: u64,
                expect: Vec<Option<(&[u8], &[u8])>>) {
+        self.scan_range_ok(start_key, None, limit, false, ts, expect);
+    }
+
+    fn scan_range_ok(&self,
+                     start_key: &[u8],
+                     end_key: Option<&[u8]>,
+                     limit: usize,
+                     key_only: bool,
+                     ts: u64,
+                     expect: Vec<Option<(&[u8], &[u8])>>) {
         let key_address = make_key(start_key);
-        let result = self.0.scan(Context::new(), key_address, limit, ts).unwrap();
+        let end_key = end_key.map(make_key);
+        let result = self.0
+            .scan(Context::new(), key_address, end_key, limit, key_only, ts)
+            .unwrap();
         let result: Vec<Option<KvPair>> = result.into_iter()
             .map(Result::ok)
             .collect();
@@ -77,22 +91,30 @@ impl AssertionStorage {
         assert_eq!(result, expect);
     }
 
-    #[allow(unused_variables)]
     fn reverse_scan_ok(&self,
                        start_key: &[u8],
                        limit: usize,
                        ts: u64,
                        expect: Vec<Option<(&[u8], &[u8])>>) {
-        // TODO: uncomment it after Storage support reverse_scan.
-        // let key_address = make_key(start_key);
-        // let result = self.0.reverse_scan(Context::new(), key_address, limit, ts).unwrap();
-        // let result: Vec<Option<KvPair>> = result.into_iter()
-        //     .map(Result::ok)
-        //     .collect();
-        // let expect: Vec<Option<KvPair>> = expect.into_iter()
-        //     .map(|x| x.map(|(k, v)| (k.to_vec(), v.to_vec())))
-        //     .collect();
-        // assert_eq!(result, expect);
+        self.reverse_scan_range_ok(start_key, None, limit, ts, expect);
+    }
+
+    fn reverse_scan_range_ok(&self,
+                             start_key: &[u8],
+                             end_key: Option<&[u8]>,
+                             limit: usize,
+                             ts: u64,
+                             expect: Vec<Option<(&[u8], &[u8])>>) {
+        let key_address = make_key(start_key);
+        let end_key = end_key.map(make_key);
+        let result = self.0.reverse_scan(Context::new(), key_address, end_key, limit, ts).unwrap();
+        let result: Vec<Option<KvPair>> = result.into_iter()
+            .map(Result::ok)
+            .collect();
+        let expect: Vec<Option<KvPair>> = expect.into_iter()
+            .map(|x| x.map(|(k, v)| (k.to_vec(), v.to_vec())))
+            .collect();
+        assert_eq!(result, expect);
     }
 
     fn prewrite_ok(&self, mutations: Vec<Mutation>, primary: &[u8], start_ts: u64) {
@@ -118,6 +140,17 @@ impl AssertionStorage {
         assert_eq!(self.0.scan_lock(Context::new(), max_ts).unwrap(), expect);
     }
 
+    fn check_txn_status_ok(&self,
+                           primary: &[u8],
+                           lock_ts: u64,
+                           current_ts: u64,
+                           expect: TxnStatus) {
+        assert_eq!(self.0
+                       .check_txn_status(Context::new(), make_key(primary), lock_ts, current_ts)
+                       .unwrap(),
+                   expect);
+    }
+
     fn resolve_lock_ok(&self, start_ts: u64, commit_ts: Option<u64>) {
         self.0.resolve_lock(Context::new(), start_ts, commit_ts).unwrap();
     }
@@ -217,6 +250,26 @@ fn test_txn_store_scan() {
                       vec![Some((b"C", b"C10")), Some((b"E", b"E10"))]);
         store.scan_ok(b"F", 1, 10, vec![]);
 
+        store.scan_range_ok(b"",
+                            Some(b"E"),
+                            4,
+                            false,
+                            10,
+                            vec![Some((b"A", b"A10")), Some((b"C", b"C10"))]);
+        store.scan_range_ok(b"B", Some(b"C"), 4, false, 10, vec![]);
+        store.scan_range_ok(b"",
+                            None,
+                            4,
+                            true,
+                            10,
+                            vec![Some((b"A", b"")), Some((b"C", b"")), Some((b"E", b""))]);
+        store.scan_range_ok(b"A\x00",
+                            Some(b"E"),
+                            4,
+                            true,
+                            10,
+                            vec![Some((b"C", b""))]);
+
         store.reverse_scan_ok(b"F", 0, 10, vec![]);
         store.reverse_scan_ok(b"F", 1, 10, vec![Some((b"E", b"E10"))]);
         store.reverse_scan_ok(b"F",
@@ -247,6 +300,14 @@ fn test_txn_store_scan() {
                               vec![Some((b"C", b"C10")), Some((b"A", b"A10"))]);
         store.reverse_scan_ok(b"C", 4, 10, vec![Some((b"A", b"A10"))]);
         store.reverse_scan_ok(b"0", 1, 10, vec![]);
+
+        store.reverse_scan_range_ok(b"F",
+                                    Some(b"C"),
+                                    4,
+                                    10,
+                                    vec![Some((b"E", b"E10")), Some((b"C", b"C10"))]);
+        store.reverse_scan_range_ok(b"F", Some(b"D"), 4, 10, vec![Some((b"E", b"E10"))]);
+        store.reverse_scan_range_ok(b"F", Some(b"F"), 4, 10, vec![]);
     };
     check_v10();
 
@@ -393,13 +454,105 @@ fn test_txn_store_resolve_lock() {
                            Mutation::Put((make_key(b"s2"), b"v10".to_vec()))],
                       b"p2",
                       10);
+    // A pessimistic lock left by a committed txn doesn't fail the resolving.
+    store.0
+        .acquire_pessimistic_lock(Context::new(), vec![make_key(b"s3")], b"p2".to_vec(), 10, 10)
+        .unwrap();
     store.resolve_lock_ok(5, None);
     store.resolve_lock_ok(10, Some(20));
     store.get_none(b"p1", 20);
     store.get_none(b"s1", 30);
     store.get_ok(b"p2", 20, b"v10");
     store.get_ok(b"s2", 30, b"v10");
+    store.get_none(b"s3", 30);
     store.scan_lock_ok(30, vec![]);
+    // Nothing to resolve.
+    store.resolve_lock_ok(15, Some(25));
+}
+
+#[test]
+fn test_txn_store_check_txn_status() {
+    let store = new_assertion_storage();
+
+    store.prewrite_ok(vec![Mutation::Put((make_key(b"p1"), b"v5".to_vec())),
+                           Mutation::Put((make_key(b"s1"), b"v5".to_vec()))],
+                      b"p1",
+                      5);
+    // Locks without ttl never expire.
+    store.check_txn_status_ok(b"p1", 5, u64::max_value(), TxnStatus::Locked { ttl: 0 });
+    store.commit_ok(vec![b"p1", b"s1"], 5, 10);
+    store.check_txn_status_ok(b"p1", 5, 20, TxnStatus::Committed { commit_ts: 10 });
+
+    store.prewrite_ok(vec![Mutation::Put((make_key(b"p2"), b"v15".to_vec()))],
+                      b"p2",
+                      15);
+    store.rollback_ok(vec![b"p2"], 15);
+    store.check_txn_status_ok(b"p2", 15, 20, TxnStatus::RolledBack);
+}
+
+fn new_lock_wait_storage(timeout: u64) -> SyncStorage {
+    let mut config = Config::default();
+    config.sched_lock_wait_timeout = timeout;
+    SyncStorage::new(&config)
+}
+
+#[test]
+fn test_txn_store_lock_wait() {
+    let store = new_lock_wait_storage(3000);
+    let k = make_key(b"k");
+    let put = |v: &[u8]| vec![Mutation::Put((make_key(b"k"), v.to_vec()))];
+
+    store.prewrite(Context::new(), put(b"v10"), b"k".to_vec(), 10).unwrap();
+    let finished = Arc::new(AtomicUsize::new(0));
+    let (store2, finished2) = (store.clone(), finished.clone());
+    let t = thread::spawn(move || {
+        let res = store2.prewrite(Context::new(), put(b"v20"), b"k".to_vec(), 20).unwrap();
+        finished2.fetch_add(1, Ordering::SeqCst);
+        res.into_iter().all(|r| r.is_ok())
+    });
+    // The second prewrite waits until the lock is released.
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(finished.load(Ordering::SeqCst), 0);
+    store.commit(Context::new(), vec![k.clone()], 10, 15).unwrap();
+    assert!(t.join().unwrap());
+    store.commit(Context::new(), vec![k.clone()], 20, 25).unwrap();
+    assert_eq!(store.get(Context::new(), &k, 30).unwrap().unwrap(), b"v20".to_vec());
+}
+
+#[test]
+fn test_txn_store_lock_wait_timeout() {
+    let store = new_lock_wait_storage(100);
+    let put = |v: &[u8]| vec![Mutation::Put((make_key(b"k"), v.to_vec()))];
+
+    store.prewrite(Context::new(), put(b"v10"), b"k".to_vec(), 10).unwrap();
+    // The lock is returned to the client after timeout.
+    let res = store.prewrite(Context::new(), put(b"v20"), b"k".to_vec(), 20).unwrap();
+    assert_eq!(res.len(), 1);
+    assert!(res[0].is_err());
+}
+
+#[test]
+fn test_txn_store_deadlock() {
+    let store = new_lock_wait_storage(3000);
+    let (a, b) = (make_key(b"a"), make_key(b"b"));
+
+    store.acquire_pessimistic_lock(Context::new(), vec![a.clone()], b"a".to_vec(), 10, 10)
+        .unwrap();
+    store.acquire_pessimistic_lock(Context::new(), vec![b.clone()], b"b".to_vec(), 20, 20)
+        .unwrap();
+    // Txn 10 waits for txn 20.
+    let (store2, b2) = (store.clone(), b.clone());
+    let t = thread::spawn(move || {
+        let res = store2.acquire_pessimistic_lock(Context::new(), vec![b2], b"a".to_vec(), 10, 30)
+            .unwrap();
+        res.into_iter().all(|r| r.is_ok())
+    });
+    thread::sleep(Duration::from_millis(200));
+    // Txn 20 waiting for txn 10 causes a deadlock.
+    assert!(store.acquire_pessimistic_lock(Context::new(), vec![a.clone()], b"b".to_vec(), 20, 30)
+        .is_err());
+    store.pessimistic_rollback(Context::new(), vec![b.clone()], 20, 20).unwrap();
+    assert!(t.join().unwrap());
 }
 
 #[test]
@@ -26,6 +26,7 @@ extern crate protobuf;
 #[macro_use]
 extern crate tikv;
 extern crate rand;
+extern crate crc;
 extern crate rocksdb;
 extern crate tempdir;
 extern crate uuid;